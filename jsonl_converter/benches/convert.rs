@@ -0,0 +1,80 @@
+//! Benchmarks converting a synthetic JSON array with the line processor, as
+//! without `--messy`, against the byte processor, as with `--messy`.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo bench --bench convert
+//! JSONL_BENCH_MB=10 cargo bench --bench convert
+//! ```
+//!
+//! The array is pretty printed, so that both paths can convert it, and is
+//! about `JSONL_BENCH_MB` megabytes, 100 unless set. A smaller size gives a
+//! quicker run. Each path converts it to
+//! a sink that discards the output, and criterion reports the throughput of
+//! each in bytes of input per second.
+
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use jsonl_converter::{
+    cli::Args,
+    generator::{generate, GeneratorOptions},
+    pipeline::convert,
+    report::Report,
+};
+
+/// The number of records generated to estimate the size of each one.
+const SAMPLE_RECORDS: usize = 1000;
+
+/// Writes a pretty printed array of about `megabytes` megabytes to `path`.
+fn write_input(path: &Path, megabytes: usize) -> io::Result<()> {
+    let mut options = GeneratorOptions {
+        records: SAMPLE_RECORDS,
+        ..GeneratorOptions::default()
+    };
+    let mut sample = Vec::new();
+    generate(&mut sample, &options)?;
+    options.records = megabytes * 1_000_000 / (sample.len() / SAMPLE_RECORDS).max(1);
+
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    generate(&mut writer, &options)?;
+    writer.flush()
+}
+
+fn bench_convert(c: &mut Criterion) {
+    let megabytes = match env::var("JSONL_BENCH_MB") {
+        Ok(value) => value
+            .parse()
+            .expect("JSONL_BENCH_MB is a whole number of megabytes"),
+        Err(_) => 100,
+    };
+    let dir = tempfile::tempdir().expect("a temporary directory can be created");
+    let path = dir.path().join("input.json");
+    write_input(&path, megabytes).expect("the input can be written");
+    let size = fs::metadata(&path).expect("the input exists").len();
+
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(size));
+    group.sample_size(10);
+    for (name, is_messy) in [("line", false), ("byte", true)] {
+        let args = Args {
+            filepath: path.to_string_lossy().into_owned(),
+            is_messy,
+            ..Args::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &args, |b, args| {
+            b.iter(|| {
+                convert(args, io::sink(), &mut Report::default()).expect("the input is valid")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_convert);
+criterion_main!(benches);
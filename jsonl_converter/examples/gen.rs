@@ -0,0 +1,61 @@
+//! Writes a synthetic JSON array for benchmarking and stress testing.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo run --release --example gen -- [RECORDS] [--depth N] [--string-len N]
+//!     [--unicode] [--compact] [--seed N] [--output PATH]
+//! ```
+//!
+//! The array is written to stdout unless `--output` is provided.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    process,
+};
+
+use jsonl_converter::generator::{generate, GeneratorOptions};
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    value.and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+        eprintln!("{} expects a number.", flag);
+        process::exit(2);
+    })
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> String {
+    match value {
+        Some(value) if !value.starts_with("--") => value,
+        _ => {
+            eprintln!("{} expects a value.", flag);
+            process::exit(2);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut options = GeneratorOptions::default();
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => options.depth = parse_number(&arg, args.next()),
+            "--string-len" => options.string_len = parse_number(&arg, args.next()),
+            "--seed" => options.seed = parse_number(&arg, args.next()),
+            "--unicode" => options.unicode = true,
+            "--compact" => options.pretty = false,
+            "--output" => output = Some(expect_value(&arg, args.next())),
+            _ => options.records = parse_number("RECORDS", Some(arg)),
+        }
+    }
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    generate(&mut writer, &options)?;
+    writer.flush()
+}
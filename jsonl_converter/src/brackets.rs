@@ -2,7 +2,15 @@
 //! closed. This is useful for parsing JSON objects, which can contain nested
 //! objects.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, fmt, ops::Deref};
+
+use smallvec::SmallVec;
+
+use crate::errors::ConversionError;
+
+/// The number of brackets a `BracketStack` holds inline, without allocating
+/// on the heap. Records nested deeper than this spill the stack to the heap.
+pub const INLINE_BRACKETS: usize = 32;
 
 /// Checks if a character is an opening bracket. Note: this function does not
 /// consider '(' to be an opening bracket because it is not used in JSON.
@@ -27,10 +35,7 @@ use std::collections::HashMap;
 /// assert_eq!(is_opening_bracket(&'{'), true);
 /// ```
 pub fn is_opening_bracket(c: &char) -> bool {
-    match c {
-        '[' | '{' => true,
-        _ => false,
-    }
+    matches!(c, '[' | '{')
 }
 
 /// Checks if a character is a closing bracket. Note: this function does not
@@ -57,10 +62,7 @@ pub fn is_opening_bracket(c: &char) -> bool {
 ///
 /// ```
 pub fn is_closing_bracket(c: &char) -> bool {
-    match c {
-        ']' | '}' => true,
-        _ => false,
-    }
+    matches!(c, ']' | '}')
 }
 
 /// Returns a map of brackets with their corresponding opening and closing
@@ -87,14 +89,94 @@ pub fn brackets_map() -> HashMap<char, char> {
     map
 }
 
+/// Errors that can occur when closing a bracket with `BracketStack::pop_pair`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BracketError {
+    /// There is no open bracket to close, as with a stray closing bracket.
+    Underflow,
+    /// The last open bracket is closed by `expected`, not by `got`.
+    Mismatch { expected: char, got: char },
+}
+
+impl BracketError {
+    /// Returns the error as a `ConversionError` for a closing bracket
+    /// `got` at `offset` in the input.
+    pub fn at(self, got: char, offset: u64) -> ConversionError {
+        let expected = match self {
+            BracketError::Underflow => None,
+            BracketError::Mismatch { expected, .. } => Some(expected),
+        };
+        ConversionError::MismatchedBracket {
+            expected,
+            got,
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BracketError::Underflow => write!(f, "There is no open bracket to close."),
+            BracketError::Mismatch { expected, got } => {
+                write!(f, "Expected `{}` but found `{}`.", expected, got)
+            }
+        }
+    }
+}
+
+impl Error for BracketError {}
+
+/// The brackets held by a `BracketStack`, inline for up to
+/// `INLINE_BRACKETS` and on the heap beyond that, so that shallow records
+/// never allocate.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Brackets(SmallVec<[char; INLINE_BRACKETS]>);
+
+impl Brackets {
+    /// Returns whether the brackets have spilled from inline storage to the
+    /// heap.
+    pub fn spilled(&self) -> bool {
+        self.0.spilled()
+    }
+
+    /// Returns the number of brackets that can be held without allocating.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl Deref for Brackets {
+    type Target = [char];
+
+    fn deref(&self) -> &[char] {
+        &self.0
+    }
+}
+
+impl PartialEq<Vec<char>> for Brackets {
+    fn eq(&self, other: &Vec<char>) -> bool {
+        self.0[..] == other[..]
+    }
+}
+
+impl<const N: usize> PartialEq<[char; N]> for Brackets {
+    fn eq(&self, other: &[char; N]) -> bool {
+        self.0[..] == other[..]
+    }
+}
+
 /// This struct is used to keep track of brackets that have been opened but not
 /// closed.
 ///
 /// # Fields
 ///
 /// * `stack` - A stack of brackets that have been opened but not closed.
+/// * `capacity` - If set, the most brackets the stack holds.
+#[derive(Clone)]
 pub struct BracketStack {
-    pub stack: Vec<char>,
+    pub stack: Brackets,
+    capacity: Option<usize>,
     _map: HashMap<char, char>,
 }
 
@@ -102,11 +184,44 @@ impl BracketStack {
     /// Creates a new `BracketStack`.
     pub fn new() -> Self {
         BracketStack {
-            stack: Vec::new(),
+            stack: Brackets::default(),
+            capacity: None,
             _map: brackets_map(),
         }
     }
 
+    /// Sets the most brackets the `BracketStack` holds. `None` lets it grow
+    /// without limit. Nothing is allocated up front, as the capacity may be
+    /// far larger than any record needs: the stack is held inline up to
+    /// `INLINE_BRACKETS` deep, and grows on the heap as needed beyond that.
+    ///
+    /// The stack does not stop brackets being pushed past its capacity
+    /// itself. Callers check `is_full` first, so that they can report the
+    /// nesting being too deep in their own way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::brackets::BracketStack;
+    ///
+    /// let mut stack = BracketStack::new();
+    /// stack.set_capacity(Some(2));
+    /// stack.push(&'[');
+    /// assert!(!stack.is_full());
+    /// stack.push(&'{');
+    /// assert!(stack.is_full());
+    /// ```
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+    }
+
+    /// Checks if the `BracketStack` holds as many brackets as its capacity.
+    /// A stack without a capacity is never full.
+    pub fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.stack.len() >= capacity)
+    }
+
     /// Checks if the `BracketStack` is empty.
     ///
     /// # Returns
@@ -117,40 +232,128 @@ impl BracketStack {
         self.stack.is_empty()
     }
 
+    /// Removes all brackets from the `BracketStack`, keeping the memory that
+    /// has been allocated for them so that the stack can be reused.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::brackets::BracketStack;
+    ///
+    /// let mut stack = BracketStack::new();
+    /// stack.push(&'[');
+    /// stack.clear();
+    /// assert!(stack.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.stack.0.clear();
+    }
+
     /// Pushes a bracket onto the `BracketStack`.
     ///
     /// # Arguments
     ///
     /// * `c` - A bracket.
     pub fn push(&mut self, c: &char) {
-        self.stack.push(*c);
+        self.stack.0.push(*c);
     }
 
     /// Pops a bracket off of the `BracketStack`.
     ///
     /// # Arguments
     ///
-    /// * `c` - A bracket.
+    /// * `c` - A closing bracket.
     ///
     /// # Returns
     ///
-    /// * `Some(c)` if the `BracketStack` if the popped bracket matches the
-    /// corresponding opening bracket.
+    /// * The last opening bracket on the `BracketStack`, which is popped, if
+    ///   it matches `c`.
+    ///
+    /// # Errors
     ///
-    /// # Panics
+    /// * `BracketError::Underflow` if the `BracketStack` is empty.
+    /// * `BracketError::Mismatch` if the last opening bracket does not match
+    ///   `c`, in which case it is left on the `BracketStack`.
     ///
-    /// * If the `BracketStack` is empty.
-    /// * If the popped bracket does not match the corresponding opening bracket.
-    pub fn pop_pair(&mut self, c: &char) -> Option<char> {
-        let popped = self.stack.pop().unwrap();
-        if popped == self._map[&c] {
-            Some(popped)
-        } else {
-            panic!(
-                "BracketStack::pop() called on mismatched brackets - expected {:?}, got {:?}",
-                self._map[&c], popped
-            );
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::brackets::{BracketError, BracketStack};
+    ///
+    /// let mut stack = BracketStack::new();
+    /// stack.push(&'[');
+    /// assert_eq!(
+    ///     stack.pop_pair(&'}'),
+    ///     Err(BracketError::Mismatch { expected: ']', got: '}' })
+    /// );
+    /// assert_eq!(stack.pop_pair(&']'), Ok('['));
+    /// assert_eq!(stack.pop_pair(&']'), Err(BracketError::Underflow));
+    /// ```
+    pub fn pop_pair(&mut self, c: &char) -> Result<char, BracketError> {
+        let expected = self.expected_closing().ok_or(BracketError::Underflow)?;
+        if expected != *c {
+            return Err(BracketError::Mismatch { expected, got: *c });
         }
+        self.stack.0.pop().ok_or(BracketError::Underflow)
+    }
+
+    /// Recovers from `c` failing to close the last opening bracket, by
+    /// popping the brackets it leaves unclosed. These are the brackets down
+    /// to and including the last one that `c` closes, or if none above the
+    /// first does, all but the first, which is the root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::brackets::BracketStack;
+    ///
+    /// let mut stack = BracketStack::new();
+    /// for c in ['[', '{', '[', '['] {
+    ///     stack.push(&c);
+    /// }
+    /// stack.pop_through(&'}');
+    /// assert_eq!(stack.stack, ['[']);
+    ///
+    /// stack.push(&'{');
+    /// stack.pop_through(&']');
+    /// assert_eq!(stack.stack, ['[']);
+    /// ```
+    pub fn pop_through(&mut self, c: &char) {
+        let opening = self._map.get(c).copied();
+        let keep = self
+            .stack
+            .iter()
+            .skip(1)
+            .rposition(|&bracket| Some(bracket) == opening)
+            .map_or(1, |i| i + 1);
+        self.stack.0.truncate(keep);
+    }
+
+    /// Returns the closing bracket that matches the last opening bracket on
+    /// the `BracketStack`, or `None` if it is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::brackets::BracketStack;
+    ///
+    /// let mut stack = BracketStack::new();
+    /// stack.push(&'[');
+    /// stack.push(&'{');
+    /// assert_eq!(stack.expected_closing(), Some('}'));
+    /// ```
+    pub fn expected_closing(&self) -> Option<char> {
+        let last = self.stack.last()?;
+        self._map
+            .iter()
+            .find(|(_, opening)| *opening == last)
+            .map(|(closing, _)| *closing)
+    }
+}
+
+impl Default for BracketStack {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -158,7 +361,7 @@ impl Iterator for BracketStack {
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.stack.pop()
+        self.stack.0.pop()
     }
 }
 
@@ -169,24 +372,23 @@ impl ExactSizeIterator for BracketStack {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
     #[test]
     fn test_is_opening_bracket_returns_true_for_opening_bracket() {
-        assert_eq!(is_opening_bracket(&'['), true);
-        assert_eq!(is_opening_bracket(&'{'), true);
-        assert_eq!(is_opening_bracket(&']'), false);
-        assert_eq!(is_opening_bracket(&'}'), false);
+        assert!(is_opening_bracket(&'['));
+        assert!(is_opening_bracket(&'{'));
+        assert!(!is_opening_bracket(&']'));
+        assert!(!is_opening_bracket(&'}'));
     }
 
     #[test]
     fn test_is_closing_bracket_returns_true_for_closing_bracket() {
-        assert_eq!(is_closing_bracket(&']'), true);
-        assert_eq!(is_closing_bracket(&'}'), true);
-        assert_eq!(is_closing_bracket(&'['), false);
-        assert_eq!(is_closing_bracket(&'{'), false);
+        assert!(is_closing_bracket(&']'));
+        assert!(is_closing_bracket(&'}'));
+        assert!(!is_closing_bracket(&'['));
+        assert!(!is_closing_bracket(&'{'));
     }
 
     #[test]
@@ -199,14 +401,14 @@ mod tests {
     #[test]
     fn test_bracket_stack_is_empty_returns_true_for_empty_bracket_stack() {
         let stack = BracketStack::new();
-        assert_eq!(stack.is_empty(), true);
+        assert!(stack.is_empty());
     }
 
     #[test]
     fn test_bracket_stack_is_empty_returns_false_for_non_empty_bracket_stack() {
         let mut stack = BracketStack::new();
         stack.push(&'[');
-        assert_eq!(stack.is_empty(), false);
+        assert!(!stack.is_empty());
     }
 
     #[test]
@@ -217,18 +419,94 @@ mod tests {
     }
 
     #[test]
-    fn test_bracket_stack_pop_pair_returns_correct_bracket() {
+    fn test_bracket_stack_clear_empties_stack_and_keeps_capacity() {
         let mut stack = BracketStack::new();
         stack.push(&'[');
-        assert_eq!(stack.pop_pair(&']'), Some('['));
+        stack.push(&'{');
+        let capacity = stack.stack.capacity();
+        stack.clear();
+        assert!(stack.is_empty());
+        assert_eq!(stack.stack.capacity(), capacity);
     }
 
     #[test]
-    #[should_panic]
-    fn test_bracket_stack_pop_pair_panics_on_mismatched_brackets() {
+    fn test_bracket_stack_is_full_at_its_capacity() {
         let mut stack = BracketStack::new();
         stack.push(&'[');
-        stack.pop_pair(&'{');
+        stack.set_capacity(Some(3));
+        stack.push(&'{');
+        assert!(!stack.is_full());
+        stack.push(&'[');
+        assert!(stack.is_full());
+        stack.set_capacity(None);
+        assert!(!stack.is_full());
+    }
+
+    #[test]
+    fn test_bracket_stack_does_not_allocate_for_a_huge_capacity() {
+        let mut stack = BracketStack::new();
+        stack.set_capacity(Some(usize::MAX));
+        assert!(!stack.stack.spilled());
+        assert_eq!(stack.stack.capacity(), INLINE_BRACKETS);
+    }
+
+    #[test]
+    fn test_bracket_stack_is_inline_until_it_is_deep() {
+        let mut stack = BracketStack::new();
+        for _ in 0..INLINE_BRACKETS {
+            stack.push(&'[');
+        }
+        assert!(!stack.stack.spilled());
+        stack.push(&'{');
+        assert!(stack.stack.spilled());
+        assert_eq!(stack.len(), INLINE_BRACKETS + 1);
+        assert_eq!(stack.pop_pair(&'}'), Ok('{'));
+    }
+
+    #[test]
+    fn test_bracket_stack_pop_pair_returns_correct_bracket() {
+        let mut stack = BracketStack::new();
+        stack.push(&'[');
+        assert_eq!(stack.pop_pair(&']'), Ok('['));
+    }
+
+    #[test]
+    fn test_bracket_stack_pop_pair_errors_on_mismatched_brackets() {
+        let mut stack = BracketStack::new();
+        stack.push(&'{');
+        assert_eq!(
+            stack.pop_pair(&']'),
+            Err(BracketError::Mismatch {
+                expected: '}',
+                got: ']'
+            })
+        );
+        assert_eq!(stack.stack, ['{']);
+        assert_eq!(stack.pop_pair(&'}'), Ok('{'));
+    }
+
+    #[test]
+    fn test_bracket_stack_pop_through_keeps_the_root() {
+        let mut stack = BracketStack::new();
+        for c in ['[', '[', '{', '['] {
+            stack.push(&c);
+        }
+        stack.pop_through(&'}');
+        assert_eq!(stack.stack, ['[', '[']);
+        stack.pop_through(&'}');
+        assert_eq!(stack.stack, ['[']);
+        stack.pop_through(&'}');
+        assert_eq!(stack.stack, ['[']);
+        let mut stack = BracketStack::new();
+        stack.pop_through(&']');
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_bracket_stack_pop_pair_errors_when_empty() {
+        let mut stack = BracketStack::new();
+        assert_eq!(stack.pop_pair(&']'), Err(BracketError::Underflow));
+        assert_eq!(stack.expected_closing(), None);
     }
 
     #[test]
@@ -238,7 +516,7 @@ mod tests {
         stack.push(&'{');
         stack.push(&'}');
         stack.push(&']');
-        let mut iter = stack.into_iter();
+        let mut iter = stack;
         assert_eq!(iter.next(), Some(']'));
         assert_eq!(iter.next(), Some('}'));
         assert_eq!(iter.next(), Some('{'));
@@ -0,0 +1,143 @@
+//! This module contains the size of the buffer the input is read through,
+//! set with `--buffer-size`, and the calibration behind `--auto-buffer`,
+//! which picks the size by timing a few reads of the input.
+//!
+//! The best size depends on the CPU, its caches and the storage the input is
+//! on, so rather than guessing, `--auto-buffer` reads the start of the input
+//! through a buffer of each of `AUTO_BUFFER_SIZES` in turn and keeps the
+//! fastest. The start of the input is read once beforehand, so that it is
+//! cached for every timed read rather than only the later ones.
+//!
+//! Calibration takes a few reads of `CALIBRATION_BYTES`, so it is skipped
+//! for inputs smaller than `MIN_AUTO_BUFFER_BYTES`, which are read with
+//! `DEFAULT_BUFFER_SIZE`.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    time::{Duration, Instant},
+};
+
+use crate::readers::byte_iter::ByteIterator;
+
+/// The size of the buffer the input is read through by default, the same as
+/// `BufReader`'s.
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// The largest buffer the input can be read through, as the buffer is
+/// allocated in full before anything is read.
+pub const MAX_BUFFER_SIZE: usize = 1024 * 1024 * 1024;
+
+/// The buffer sizes that `--auto-buffer` picks from.
+pub const AUTO_BUFFER_SIZES: [usize; 3] = [8 * 1024, 64 * 1024, 1024 * 1024];
+
+/// How much of the input is read to time each buffer size.
+pub const CALIBRATION_BYTES: u64 = 4 * 1024 * 1024;
+
+/// The smallest input worth calibrating for.
+pub const MIN_AUTO_BUFFER_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Returns the buffer size to read the file at `path` through: the fastest
+/// of `AUTO_BUFFER_SIZES`, or `DEFAULT_BUFFER_SIZE` if the file is too small
+/// to be worth calibrating for.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+/// * `by_char` - Whether the file is read a character at a time, as with
+///   `--messy`, rather than a line at a time.
+///
+/// # Errors
+///
+/// * If the file cannot be read.
+pub fn auto_buffer_size(path: &str, by_char: bool) -> io::Result<usize> {
+    if File::open(path)?.metadata()?.len() < MIN_AUTO_BUFFER_BYTES {
+        return Ok(DEFAULT_BUFFER_SIZE);
+    }
+    calibrate_buffer_size(path, by_char, &AUTO_BUFFER_SIZES)
+}
+
+/// Returns whichever of `sizes` reads the first `CALIBRATION_BYTES` of the
+/// file at `path` the fastest, the first of them on a tie.
+///
+/// # Errors
+///
+/// * If the file cannot be read.
+/// * If `sizes` is empty.
+pub fn calibrate_buffer_size(path: &str, by_char: bool, sizes: &[usize]) -> io::Result<usize> {
+    read_head(path, DEFAULT_BUFFER_SIZE, by_char)?;
+    fastest(sizes, |size| read_head(path, size, by_char))
+}
+
+/// Returns whichever of `sizes` takes the least time according to `time`.
+///
+/// # Errors
+///
+/// * If `time` fails.
+/// * If `sizes` is empty.
+fn fastest(
+    sizes: &[usize],
+    mut time: impl FnMut(usize) -> io::Result<Duration>,
+) -> io::Result<usize> {
+    let mut best = None;
+    for &size in sizes {
+        let elapsed = time(size)?;
+        if best.is_none_or(|(_, fastest)| elapsed < fastest) {
+            best = Some((size, elapsed));
+        }
+    }
+    best.map(|(size, _)| size)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No buffer sizes to pick from."))
+}
+
+/// Reads the first `CALIBRATION_BYTES` of the file at `path` through a
+/// buffer of `size` bytes in the same way as it is converted, returning how
+/// long it took.
+fn read_head(path: &str, size: usize, by_char: bool) -> io::Result<Duration> {
+    let head = File::open(path)?.take(CALIBRATION_BYTES);
+    let mut reader = BufReader::with_capacity(size, head);
+    let started = Instant::now();
+    if by_char {
+        let mut bytes_iter = ByteIterator::from_reader(reader);
+        while bytes_iter.read_char().transpose()?.is_some() {}
+    } else {
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            line.clear();
+        }
+    }
+    Ok(started.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastest_picks_the_quickest_size() {
+        let times = [(1, 30), (2, 10), (3, 10), (4, 20)];
+        let picked = fastest(&[1, 2, 3, 4], |size| {
+            let (_, millis) = times.iter().find(|(s, _)| *s == size).unwrap();
+            Ok(Duration::from_millis(*millis))
+        });
+        assert_eq!(picked.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_fastest_of_no_sizes_is_an_error() {
+        let error = fastest(&[], |_| Ok(Duration::ZERO)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let error = calibrate_buffer_size("tests/fixtures/pretty.json", false, &[]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_small_files_are_not_calibrated() {
+        let size = auto_buffer_size("tests/fixtures/pretty.json", true).unwrap();
+        assert_eq!(size, DEFAULT_BUFFER_SIZE);
+        for by_char in [false, true] {
+            let size = calibrate_buffer_size("tests/fixtures/pretty.json", by_char, &[16, 4096]);
+            assert!([16, 4096].contains(&size.unwrap()));
+        }
+    }
+}
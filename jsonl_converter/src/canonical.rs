@@ -0,0 +1,223 @@
+//! This module contains the canonical form of records for `--canonical`,
+//! which writes records that are equal as JSON byte for byte the same, so
+//! that the output of different producers can be diffed and hashed.
+//!
+//! # Rules
+//!
+//! * Whitespace outside of strings is removed.
+//! * The keys of each object are sorted by their code points, at every
+//!   depth. A record with a key that appears more than once in an object is
+//!   an error.
+//! * Strings are written with only `"`, `\` and control characters escaped,
+//!   so `"\u00e9"` and `"é"` are both written as `"é"`. Control characters
+//!   are written as `\b`, `\f`, `\n`, `\r` and `\t`, or as `\u001f` and so
+//!   on for the rest.
+//! * Numbers are written in the form that JavaScript, and so RFC 8785,
+//!   writes them in, but from the exact decimal value written in the record
+//!   rather than from the nearest 64-bit float, so no digits are lost. See
+//!   `Decimal`.
+//!   Numbers with no fractional part are written as integers with up to 21
+//!   digits, so `1.0`, `1e0` and `1` are all written as `1`, and `1e20` as
+//!   `100000000000000000000`. Trailing zeros are dropped, so `1.50` is
+//!   written as `1.5`, and numbers below `1e-6` or of `1e21` and above are
+//!   written with an exponent, so `1E300` is written as `1e+300`. `-0` is
+//!   written as `0`.
+//! * With `trim_strings`, whitespace at the start and end of each string
+//!   value is removed. Keys are left alone.
+//!
+//! Canonicalizing happens after every other change to the record, other than
+//! wrapping it in an envelope.
+
+use crate::{decimal::Decimal, raw::RawJson};
+
+/// Writes records in canonical form.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Canonicalizer {
+    pub trim_strings: bool,
+}
+
+impl Canonicalizer {
+    /// Returns `record` in canonical form.
+    ///
+    /// # Errors
+    ///
+    /// * If `record` is not valid JSON.
+    /// * If a key appears more than once in the same object of `record`.
+    /// * If a number in `record` has an exponent that does not fit a 64-bit
+    ///   integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::canonical::Canonicalizer;
+    ///
+    /// let canonicalizer = Canonicalizer::default();
+    /// assert_eq!(
+    ///     canonicalizer.canonicalize(r#"{"b": 1.0, "a": ["\u00e9", 2.50]}"#).unwrap(),
+    ///     r#"{"a":["é",2.5],"b":1}"#
+    /// );
+    /// ```
+    pub fn canonicalize(&self, record: &str) -> Result<String, String> {
+        let value = RawJson::parse(record)?;
+        let mut canonical = String::with_capacity(record.len());
+        self.write_value(value, &mut canonical)?;
+        Ok(canonical)
+    }
+
+    /// Writes `value` onto `canonical` in canonical form.
+    ///
+    /// # Errors
+    ///
+    /// * If a number in `value` cannot be written canonically.
+    fn write_value(&self, value: RawJson, canonical: &mut String) -> Result<(), String> {
+        match value {
+            RawJson::Literal(literal) if is_number(literal) => {
+                canonical.push_str(&canonical_number(literal)?);
+            }
+            RawJson::Literal(literal) => canonical.push_str(literal),
+            RawJson::String(string) if self.trim_strings => {
+                let trimmed = RawJson::String(string.trim().to_string());
+                canonical.push_str(&trimmed.to_string());
+            }
+            string @ RawJson::String(_) => canonical.push_str(&string.to_string()),
+            RawJson::Array(array) => {
+                canonical.push('[');
+                for (i, value) in array.into_iter().enumerate() {
+                    if i > 0 {
+                        canonical.push(',');
+                    }
+                    self.write_value(value, canonical)?;
+                }
+                canonical.push(']');
+            }
+            RawJson::Object(mut object) => {
+                object.sort_by(|(a, _), (b, _)| a.cmp(b));
+                canonical.push('{');
+                for (i, (key, value)) in object.into_iter().enumerate() {
+                    if i > 0 {
+                        canonical.push(',');
+                    }
+                    canonical.push_str(&RawJson::String(key).to_string());
+                    canonical.push(':');
+                    self.write_value(value, canonical)?;
+                }
+                canonical.push('}');
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks if the literal is a number rather than `true`, `false` or `null`.
+fn is_number(literal: &str) -> bool {
+    literal.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+}
+
+/// Returns the JSON number `number` in canonical form, computed exactly
+/// from its decimal digits.
+///
+/// # Errors
+///
+/// * If its exponent does not fit a 64-bit integer.
+fn canonical_number(number: &str) -> Result<String, String> {
+    Decimal::parse(number)
+        .map(|decimal| decimal.to_string())
+        .ok_or_else(|| {
+            format!(
+                "The number `{}` has too large an exponent to be written canonically.",
+                number
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_records_are_written_the_same() {
+        let canonicalizer = Canonicalizer::default();
+        let records = [
+            "{\"id\": 1, \"tags\": [\"a\", \"\\u00e9\"], \"n\": {\"y\": 2.50, \"x\": -0.0}}",
+            "{\n  \"n\" : {\"x\":0,\"y\":25e-1},\n  \"tags\":[\"a\",\"é\"],\"id\":1.0\n}",
+            "{\"id\": 1E0, \"n\": {\"x\": 0, \"y\": 2.5}, \"tags\": [\"\\u0061\", \"é\"]}",
+        ];
+        for record in records {
+            assert_eq!(
+                canonicalizer.canonicalize(record).unwrap(),
+                "{\"id\":1,\"n\":{\"x\":0,\"y\":2.5},\"tags\":[\"a\",\"é\"]}",
+                "{}",
+                record
+            );
+        }
+    }
+
+    #[test]
+    fn test_numbers_are_normalized() {
+        let canonicalizer = Canonicalizer::default();
+        let cases = [
+            ("[1.50, 1E+300, 100e-2, -3.0]", "[1.5,1e+300,1,-3]"),
+            (
+                "[18446744073709551615, -9223372036854775808]",
+                "[18446744073709551615,-9223372036854775808]",
+            ),
+            ("[1e20, 1e21, 0.1]", "[100000000000000000000,1e+21,0.1]"),
+            ("[0.000001, 1.5e-7, -0.0e5, 0e-3]", "[0.000001,1.5e-7,0,0]"),
+            ("[123.456e1, 12e-1, 0.0120]", "[1234.56,1.2,0.012]"),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(canonicalizer.canonicalize(record).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_numbers_keep_every_digit() {
+        let canonicalizer = Canonicalizer::default();
+        let cases = [
+            // More digits than a 64-bit float holds.
+            (
+                "[9007199254740993, 0.1000000000000000055511151231257827]",
+                "[9007199254740993,0.1000000000000000055511151231257827]",
+            ),
+            // Too large and too small for a 64-bit float.
+            ("[1e400, -2.50e-400]", "[1e+400,-2.5e-400]"),
+            (
+                "[123456789012345678901234567890, 1.5e20]",
+                "[1.2345678901234567890123456789e+29,150000000000000000000]",
+            ),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(canonicalizer.canonicalize(record).unwrap(), expected);
+        }
+        assert!(canonicalizer
+            .canonicalize("[1e99999999999999999999]")
+            .unwrap_err()
+            .contains("too large an exponent"));
+    }
+
+    #[test]
+    fn test_repeated_keys_are_errors() {
+        assert_eq!(
+            Canonicalizer::default()
+                .canonicalize("{\"id\": 1E0, \"id\": 1}")
+                .unwrap_err(),
+            "The key `id` appears more than once in the same object."
+        );
+    }
+
+    #[test]
+    fn test_trim_strings_only_trims_values() {
+        let record = "{\" a \": \"  b\\t\", \"c\": [\" d\"]}";
+        let trimmed = Canonicalizer { trim_strings: true }.canonicalize(record);
+        assert_eq!(trimmed.unwrap(), "{\" a \":\"b\",\"c\":[\"d\"]}");
+        let untrimmed = Canonicalizer::default().canonicalize(record);
+        assert_eq!(untrimmed.unwrap(), "{\" a \":\"  b\\t\",\"c\":[\" d\"]}");
+    }
+
+    #[test]
+    fn test_invalid_records_are_errors() {
+        assert!(Canonicalizer::default()
+            .canonicalize("{\"a\": 1,}")
+            .is_err());
+    }
+}
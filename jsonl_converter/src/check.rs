@@ -0,0 +1,109 @@
+//! This module contains the validation of JSONL input, which checks that each
+//! line of a file is a JSON value of its own.
+
+use std::io::BufRead;
+
+use crate::{errors::ConversionError, readers::line_iter::LineIterator, report::Report};
+
+/// Checks that each line read by `line_iter` is a valid JSON value.
+///
+/// # Arguments
+///
+/// * `line_iter` - The lines to check.
+/// * `report` - Updated with the number of valid lines and bytes read, even
+///   if an invalid line is found.
+///
+/// # Errors
+///
+/// * If a line is blank, as a `ConversionError::EmptyLine`, or is not valid
+///   JSON, giving the number of the first such line.
+/// * If the file cannot be read.
+pub fn check_lines<R: BufRead>(
+    line_iter: &mut LineIterator<R>,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let result = check_each_line(line_iter, report);
+    report.bytes_in = line_iter.bytes_read();
+    result
+}
+
+fn check_each_line<R: BufRead>(
+    line_iter: &mut LineIterator<R>,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let mut line_number = 0;
+    while let Some(line) = line_iter.try_next_line_ref()? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            return Err(ConversionError::EmptyLine { line: line_number });
+        }
+        if let Err(error) = serde_json::from_str::<serde_json::Value>(line) {
+            return Err(ConversionError::InvalidJsonl {
+                line: line_number,
+                error,
+            });
+        }
+        report.records += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn check(contents: &str) -> (Result<(), ConversionError>, Report) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.jsonl");
+        fs::write(&path, contents).unwrap();
+        let mut line_iter = LineIterator::new(path.to_str().unwrap()).unwrap();
+        let mut report = Report::default();
+        let result = check_lines(&mut line_iter, &mut report);
+        (result, report)
+    }
+
+    #[test]
+    fn test_check_lines_accepts_valid_jsonl() {
+        let contents = "{\"a\": 1}\r\n[1, 2]\n\"s\"\n3\n";
+        let (result, report) = check(contents);
+        assert!(result.is_ok());
+        assert_eq!(report.records, 4);
+        assert_eq!(report.bytes_in, contents.len() as u64);
+    }
+
+    #[test]
+    fn test_check_lines_reports_first_invalid_line() {
+        let (result, report) = check("{\"a\": 1}\n{\"b\":\n2}\n[}\n");
+        assert!(matches!(
+            result,
+            Err(ConversionError::InvalidJsonl { line: 2, .. })
+        ));
+        assert_eq!(report.records, 1);
+    }
+
+    #[test]
+    fn test_check_lines_rejects_two_values_on_a_line() {
+        let (result, _) = check("{\"a\": 1} {\"b\": 2}\n");
+        assert!(matches!(
+            result,
+            Err(ConversionError::InvalidJsonl { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_lines_rejects_blank_line() {
+        let (result, report) = check("1\n\n2\n");
+        assert!(matches!(
+            result,
+            Err(ConversionError::EmptyLine { line: 2 })
+        ));
+        assert_eq!(report.records, 1);
+
+        let (result, _) = check("1\n \t\r\n");
+        assert!(matches!(
+            result,
+            Err(ConversionError::EmptyLine { line: 2 })
+        ));
+    }
+}
@@ -1,32 +1,2305 @@
 //! Contains CLI related code.
 
-use std::env;
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    fmt,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
 
-/// Returns the filepath from the command line arguments assuming that the
-/// filepath is the first argument.
+use crate::{
+    buffer::MAX_BUFFER_SIZE,
+    envelope::EnvelopeFields,
+    json_object::NewlinePolicy,
+    key_case::KeyCase,
+    partition::DEFAULT_MAX_OPEN_FILES,
+    pointer::JsonPointer,
+    readers::line_iter::DEFAULT_MAX_LINE_BYTES,
+    repair::Repairs,
+    unicode::NormalizationForm,
+    writer::{LengthPrefix, LineEnding, OutputFormat},
+};
+
+/// The options provided on the command line.
+///
+/// # Fields
+///
+/// * `filepath` - The path of the JSON file to convert.
+/// * `is_messy` - Whether the JSON file is not well formed.
+/// * `output` - Where the JSONL output is written to: `-` for stdout,
+///   `/dev/stderr` for stderr or a path.
+/// * `pipe` - A shell command that the JSONL output is piped to instead of
+///   being written to stdout.
+/// * `in_place` - Whether the JSONL output replaces the input file once the
+///   conversion has succeeded.
+/// * `tee` - A path that a copy of the JSONL output is also written to.
+/// * `report_json` - Whether to print a JSON summary of the conversion to
+///   stderr once it has finished.
+/// * `count` - Whether to print the number of records converted to stderr
+///   once the conversion has finished.
+/// * `progress_records` - Whether to periodically print the number of
+///   records converted so far to stderr.
+/// * `start_offset` - A byte offset to resume the conversion from.
+/// * `root_pointers` - JSON pointers to arrays nested within the input, which
+///   are converted instead of the root array if there are any.
+/// * `emit_array_path` - Whether to prefix each record with the JSON pointer
+///   to the array it came from.
+/// * `with_offset` - Whether to prefix each record with the byte offset that
+///   the record started at in the input.
+/// * `partition_by` - A top level key whose value decides which file in
+///   `partition_dir` each record is written to.
+/// * `partition_dir` - The directory partition files are written to.
+/// * `max_open_files` - The maximum number of partition files kept open at
+///   once.
+/// * `order_by` - A top level key whose value the records are sorted by
+///   before being written.
+/// * `descending` - Whether records are sorted in descending order when
+///   using `order_by`.
+/// * `chunk_records` - If set, an empty line is written between each group
+///   of this many records.
+/// * `flush_every` - If set, the output is flushed after this many records
+///   rather than only once the conversion has finished.
+/// * `output_eol` - The separator written after each record.
+/// * `no_final_eol` - Whether `output_eol` is only written between records,
+///   rather than after each one.
+/// * `prefix` - Text written verbatim before each record.
+/// * `suffix` - Text written verbatim after each record.
+/// * `format` - The format records are written in.
+/// * `length_prefixed` - Whether each record is written as its length
+///   followed by the record, instead of being followed by `output_eol`.
+/// * `varint` - Whether the length of each record is written as a varint
+///   rather than 4 bytes when using `length_prefixed`.
+/// * `input_list` - A file listing the paths of JSON files to convert, one
+///   per line, used instead of `filepath`.
+/// * `input_glob` - A glob pattern matching the paths of JSON files to
+///   convert, used instead of `filepath`.
+/// * `fd` - An already open file descriptor to read the JSON from, used
+///   instead of `filepath`. Only supported on Unix.
+/// * `concat` - Whether arrays or objects following the root, as when files
+///   have been concatenated, are converted too.
+/// * `strip_jsonp` - Whether JSONP padding around the root array, as in
+///   `callback([...]);`, is stripped.
+/// * `continue_on_error` - Whether to carry on with the next file in
+///   `input_list` or `input_glob` when a file cannot be converted.
+/// * `max_line_bytes` - The maximum length of a line when converting in line
+///   mode, rather than with `is_messy`.
+/// * `head_bytes` - If set, the most bytes read from each input, beyond
+///   which the conversion fails.
+/// * `buffer_size` - If set, the size in bytes of the buffer each input is
+///   read through.
+/// * `auto_buffer` - Whether the size of the buffer each input is read
+///   through is picked by timing reads of the input.
+/// * `max_depth` - If set, the deepest nesting of objects and arrays allowed
+///   within a record, beyond which the conversion fails.
+/// * `verify` - Whether to parse the whole input once it has been converted,
+///   to check that its root has as many elements as records were converted.
+/// * `diff` - If set, a JSON file whose array the input is compared with,
+///   writing the elements that differ instead of the records.
+/// * `preserve_whitespace` - Whether to keep the whitespace within records,
+///   only removing line breaks.
+/// * `minify` - Whether to remove all whitespace outside of strings within
+///   records.
+/// * `newline_in_values` - If set, how line breaks within strings are
+///   handled, rather than by the cleanup of the rest of the whitespace.
+/// * `allow_control_chars` - Whether raw control characters inside strings
+///   are passed through rather than stopping the conversion.
+/// * `escape_control_chars` - Whether raw control characters allowed with
+///   `allow_control_chars` are escaped.
+/// * `index` - A path to write an index of where each record is in the
+///   output to.
+/// * `normalize_unicode` - If set, the form that the strings in each record
+///   are normalized to.
+/// * `normalize_keys` - Whether object keys are normalized as well as
+///   values.
+/// * `strict_utf8` - Whether invalid UTF-8 in the input is an error rather
+///   than being replaced with `U+FFFD`.
+/// * `check_jsonl` - Whether to check that the input is valid JSONL instead
+///   of converting it.
+/// * `columns` - Whether to write how many records each top level key
+///   appears in instead of the records.
+/// * `reverse` - Whether to convert the input from JSONL back to a JSON
+///   array instead.
+/// * `pretty` - Whether the array written with `reverse` is pretty printed.
+/// * `strict` - Whether each record is checked to be valid JSON.
+/// * `emit_errors_as_records` - Whether a record that is not valid JSON is
+///   written as an error record instead of stopping the conversion.
+/// * `fields_required` - Top level keys that each record must contain.
+/// * `replace_nan_inf` - Whether `NaN`, `Infinity` and `-Infinity` in each
+///   record are replaced with `null`.
+/// * `repair` - Whether every best-effort repair is made to each record.
+/// * `repair_trailing_commas` - Whether trailing commas in each record are
+///   removed.
+/// * `repair_missing_commas` - Whether missing commas in each record are
+///   added.
+/// * `single_quotes` - Whether single-quoted keys and strings in each record
+///   are double-quoted.
+/// * `json5` - Whether the input is read as JSON5, with comments, trailing
+///   commas, single quotes, unquoted keys, and the numbers and string
+///   escapes that JSON does not have.
+/// * `map_values` - Whether the root is an object whose values are written
+///   as records, rather than an array.
+/// * `inject_key` - A name to add each value's key to it under when using
+///   `map_values`.
+/// * `retain_key_order` - Whether to refuse options that would change the
+///   order of the keys in each record.
+/// * `flatten` - Whether nested objects and arrays in each record are
+///   flattened into a single object.
+/// * `flatten_separator` - The separator between the parts of each flattened
+///   key.
+/// * `canonical` - Whether each record is written in canonical form.
+/// * `trim_strings` - Whether whitespace at the start and end of each string
+///   value is removed when writing records in canonical form.
+/// * `dedupe_window` - If set, a record identical to one of this many
+///   records written before it is dropped.
+/// * `envelope` - Whether each record is wrapped in an envelope of metadata.
+/// * `envelope_fields` - The metadata included in each envelope, if not all
+///   of it.
+/// * `key_case` - If set, the case that the keys of each record are
+///   converted to.
+/// * `recursive` - Whether the keys of nested objects are converted as well
+///   as top level keys when using `key_case`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Args {
+    pub filepath: String,
+    pub is_messy: bool,
+    pub output: Option<String>,
+    pub pipe: Option<String>,
+    pub in_place: bool,
+    pub tee: Option<String>,
+    pub report_json: bool,
+    pub count: bool,
+    pub progress_records: bool,
+    pub start_offset: Option<u64>,
+    pub root_pointers: Vec<JsonPointer>,
+    pub emit_array_path: bool,
+    pub with_offset: bool,
+    pub partition_by: Option<String>,
+    pub partition_dir: Option<String>,
+    pub max_open_files: Option<usize>,
+    pub order_by: Option<String>,
+    pub descending: bool,
+    pub chunk_records: Option<usize>,
+    pub flush_every: Option<usize>,
+    pub output_eol: LineEnding,
+    pub no_final_eol: bool,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub format: OutputFormat,
+    pub length_prefixed: bool,
+    pub varint: bool,
+    pub input_list: Option<String>,
+    pub input_glob: Option<String>,
+    pub fd: Option<i32>,
+    pub continue_on_error: bool,
+    pub concat: bool,
+    pub strip_jsonp: bool,
+    pub max_line_bytes: Option<u64>,
+    pub head_bytes: Option<u64>,
+    pub buffer_size: Option<usize>,
+    pub auto_buffer: bool,
+    pub max_depth: Option<usize>,
+    pub verify: bool,
+    pub diff: Option<String>,
+    pub preserve_whitespace: bool,
+    pub minify: bool,
+    pub newline_in_values: Option<NewlinePolicy>,
+    pub allow_control_chars: bool,
+    pub escape_control_chars: bool,
+    pub index: Option<String>,
+    pub normalize_unicode: Option<NormalizationForm>,
+    pub normalize_keys: bool,
+    pub strict_utf8: bool,
+    pub check_jsonl: bool,
+    pub columns: bool,
+    pub reverse: bool,
+    pub pretty: bool,
+    pub strict: bool,
+    pub emit_errors_as_records: bool,
+    pub fields_required: Vec<String>,
+    pub replace_nan_inf: bool,
+    pub repair: bool,
+    pub repair_trailing_commas: bool,
+    pub repair_missing_commas: bool,
+    pub single_quotes: bool,
+    pub json5: bool,
+    pub map_values: bool,
+    pub inject_key: Option<String>,
+    pub retain_key_order: bool,
+    pub flatten: bool,
+    pub flatten_separator: Option<String>,
+    pub canonical: bool,
+    pub trim_strings: bool,
+    pub dedupe_window: Option<usize>,
+    pub envelope: bool,
+    pub envelope_fields: Option<EnvelopeFields>,
+    pub key_case: Option<KeyCase>,
+    pub recursive: bool,
+}
+
+impl Args {
+    /// Returns the directory partition files are written to, defaulting to
+    /// `out`.
+    pub fn partition_dir(&self) -> &str {
+        self.partition_dir.as_deref().unwrap_or("out")
+    }
+
+    /// Returns the maximum number of partition files kept open at once,
+    /// defaulting to `DEFAULT_MAX_OPEN_FILES`.
+    pub fn max_open_files(&self) -> usize {
+        self.max_open_files.unwrap_or(DEFAULT_MAX_OPEN_FILES)
+    }
+
+    /// Returns where the JSONL output is written to, defaulting to `-` for
+    /// stdout.
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or("-")
+    }
+
+    /// Returns the name of the input, which is its path, `-` for stdin, or
+    /// `/dev/fd/N` for another file descriptor given with `--fd`.
+    pub fn input(&self) -> String {
+        match self.fd {
+            Some(0) => String::from("-"),
+            Some(fd) => format!("/dev/fd/{}", fd),
+            None => self.filepath.clone(),
+        }
+    }
+
+    /// Returns how the length of each record is written, if records are
+    /// length-prefixed.
+    pub fn length_prefix(&self) -> Option<LengthPrefix> {
+        match (self.length_prefixed, self.varint) {
+            (false, _) => None,
+            (true, false) => Some(LengthPrefix::U32),
+            (true, true) => Some(LengthPrefix::Varint),
+        }
+    }
+
+    /// Returns the metadata included in each envelope, defaulting to all of
+    /// it.
+    pub fn envelope_fields(&self) -> EnvelopeFields {
+        self.envelope_fields.unwrap_or(EnvelopeFields::ALL)
+    }
+
+    /// Returns the maximum length of a line when converting in line mode,
+    /// defaulting to `DEFAULT_MAX_LINE_BYTES`.
+    pub fn max_line_bytes(&self) -> u64 {
+        self.max_line_bytes.unwrap_or(DEFAULT_MAX_LINE_BYTES)
+    }
+
+    /// Returns the separator between the parts of each flattened key,
+    /// defaulting to `.`.
+    pub fn flatten_separator(&self) -> &str {
+        self.flatten_separator.as_deref().unwrap_or(".")
+    }
+
+    /// Returns the best-effort repairs made to each record, which is all of
+    /// them with `repair`, and those that turn JSON5 into JSON with `json5`.
+    pub fn repairs(&self) -> Repairs {
+        Repairs {
+            trailing_commas: self.repair || self.json5 || self.repair_trailing_commas,
+            missing_commas: self.repair || self.repair_missing_commas,
+            single_quotes: self.repair || self.json5 || self.single_quotes,
+            unquoted_keys: self.repair || self.json5,
+        }
+    }
+
+    /// Returns whether `NaN`, `Infinity` and `-Infinity` in each record are
+    /// replaced with `null`, which they are with `repair` and `json5`.
+    pub fn replace_non_finite(&self) -> bool {
+        self.repair || self.json5 || self.replace_nan_inf
+    }
+}
+
+/// Parses the command line arguments assuming that the filepath is the first
+/// argument.
 ///
 /// Optionally, a `--messy` flag can be provided to indicate that the JSONL
 /// file is not well formed. This is useful if the JSONL file contains
-/// multiple JSON objects on a single line.
+/// multiple JSON objects on a single line. It also recovers elements that are
+/// missing the comma between them, such as `[{"a":1} {"b":2}]`.
+///
+/// An `--output TARGET` option can be provided to choose where the JSONL
+/// output is written to. `TARGET` is `-` for stdout, which is the default,
+/// `/dev/stderr` for stderr, or the path of a file, which is truncated. Paths
+/// may be special files such as named pipes or `/dev/fd/3`.
+///
+/// A `--pipe 'command'` option can be provided to stream the JSONL output to
+/// the stdin of a shell command, for example `--pipe 'gzip > out.jsonl.gz'`.
+///
+/// An `--in-place` flag can be provided to replace the input file with the
+/// output. The output is written to a temporary file in the same directory,
+/// which is renamed over the input once the conversion has succeeded. If the
+/// conversion fails, the input is left untouched.
+///
+/// A `--tee FILE` option can be provided to also write a copy of the JSONL
+/// output to `FILE`, while it is still written to stdout, `--output` or
+/// `--pipe` as usual. The copy is byte for byte the same, so an `--index`
+/// applies to both. If either cannot be written to, the conversion stops.
+///
+/// A `--report-json` flag can be provided to print a summary of the
+/// conversion to stderr as a single JSON object once it has finished. A
+/// `--count` flag can be provided to print just the number of records
+/// converted to stderr instead, which is `0` for an empty array. To only
+/// count the records, the output can be discarded with `--output /dev/null`.
+///
+/// A `--progress-records` flag can be provided to print the number of records
+/// converted so far to stderr every couple of seconds, and the total once the
+/// conversion has finished. This works even when the length of the input is
+/// not known, such as when reading from a pipe.
+///
+/// A `--start-offset BYTES` option can be provided to resume a conversion from
+/// a byte offset. The offset must be the boundary of an element of the root
+/// array, such as the offset reported for a previously emitted record.
+///
+/// A `--root-pointer POINTER` option can be provided to convert an array
+/// nested within the input, such as the `items` of `{"data": {"items": [...]}}`
+/// with `--root-pointer /data/items`, instead of the root array. The input is
+/// scanned to find the array first, and anything after it is ignored. See the
+/// `pointer` module for the syntax.
+///
+/// `--root-pointer` can be provided more than once to convert several arrays,
+/// such as `--root-pointer /a/items --root-pointer /b/items`. The input is
+/// scanned for all of them at once, and their records are written one array
+/// after another, in the order the arrays appear in the input. An array
+/// nested within another is converted too, after the array it is nested in,
+/// whose records already contain it. A pointer given twice is only
+/// converted once.
+///
+/// An `--emit-array-path` flag can be provided to prefix each record with the
+/// JSON pointer to the array it came from, followed by a tab. This is the
+/// `--root-pointer`, or empty for the root array, and tells the records of
+/// several `--root-pointer` arrays apart.
+///
+/// A `--with-offset` flag can be provided to prefix each record with the byte
+/// offset of its first character in the input, separated by a tab.
+///
+/// `--prefix TEXT` and `--suffix TEXT` options can be provided to write
+/// `TEXT` before or after each record, such as `--prefix "INSERT INTO t
+/// VALUES('" --suffix "');"` to turn the records into statements for a
+/// script. The text is written verbatim, with no escapes interpreted, and
+/// the suffix comes before the line ending. As the lines are no longer JSON,
+/// they cannot be used with options that read the records back.
+///
+/// A `--partition-by KEY` option can be provided to write each record to
+/// `<DIR>/<value>.jsonl` based on the value of a top level key, where `DIR`
+/// is set with `--partition-dir` and defaults to `out`. The value is
+/// percent-encoded where it is not a safe file name, and values other than
+/// non-empty strings start with `@`, so `"a b"` is written to `a%20b.jsonl`
+/// and `1` to `@1.jsonl`, and no two values share a file. Records without
+/// the key are written to `<DIR>/@default.jsonl`. At most
+/// `--max-open-files N` partition files, 64 by default, are kept open at
+/// once. When another is needed, the least recently used one is closed and
+/// is reopened to append to if needed again.
+///
+/// An `--order-by KEY` option can be provided to sort the records by the
+/// value of a top level key before they are written, with the `--descending`
+/// flag to reverse the order. Numbers come before strings, and records
+/// without the key come last. Records are sorted in memory and spilled to
+/// temporary files once they take up more than 64 MiB, so any number of
+/// records can be sorted. See the `sort` module for the full order.
+///
+/// A `--chunk-records N` option can be provided to write an empty line after
+/// every `N` records, for consumers that read JSONL in groups. No empty line
+/// follows the last group, whether or not it has `N` records. Groups carry
+/// on from one file to the next with `--input-list` and `--input-glob`.
+///
+/// Output is buffered and flushed once the conversion has finished. A
+/// `--flush-every N` option can be provided to also flush after every `N`
+/// records, which lowers latency for live consumers at the cost of
+/// throughput.
+///
+/// An `--output-eol lf|crlf` option can be provided to choose the separator
+/// written after each record, regardless of the line endings of the input.
+/// With `--reverse`, it is the line break written after the array and
+/// between its lines when pretty printed. Defaults to `lf`. A `--no-final-eol` flag can be provided to write it
+/// only between records, so that nothing follows the last one, for readers
+/// that reject a trailing empty line. As the output is then not made of
+/// whole lines, it cannot be used with the options that read the records
+/// back as lines, or that write more than one input to the same output.
+///
+/// A `--length-prefixed` flag can be provided to write each record as a
+/// frame for length-delimited readers: the length of the UTF-8 record in
+/// bytes as a 4 byte big-endian unsigned integer, followed by the record,
+/// with no line ending. A `--varint` flag can also be provided to write the
+/// length as an unsigned LEB128 varint instead, as used by Protocol Buffers.
+///
+/// With the `msgpack` feature, a `--format msgpack` option can be provided
+/// to write each record as a MessagePack value instead of a line of JSON,
+/// for consumers that would rather not parse JSON text. Each record is
+/// checked as with `--strict`, then written with its keys in the order they
+/// appear, so `--retain-key-order` holds. The values are written one after
+/// another, as MessagePack values carry their own length, or with
+/// `--length-prefixed`, each after its length in bytes. As the output is not
+/// lines of text, it cannot be used with options that add to or count the
+/// lines. See the `msgpack` module for the encoding. `--format jsonl` writes
+/// JSONL, as happens without the option.
+///
+/// Instead of a filepath, an `--input-list FILE` option can be provided to
+/// convert each of the files listed in `FILE`, one path per line, writing all
+/// of their records to the same output. Relative paths are resolved from the
+/// working directory. Conversion stops at the first file that fails unless
+/// the `--continue-on-error` flag is provided, in which case the error is
+/// reported and the next file is converted.
+///
+/// Similarly, an `--input-glob PATTERN` option can be provided to convert
+/// each of the files matching `PATTERN`, such as `'data/*.json'`, in sorted
+/// order. It is an error for no files to match. Within each `/`-separated
+/// component, `*` matches any run of characters and `?` a single one, and
+/// `[a-z]` matches a character in the set while `[!a-z]` matches one outside
+/// it. `**` as a component of its own matches any number of directories, so
+/// `'data/**/*.json'` searches `data` and every directory below it. There
+/// is no escape character, so `\` is matched as itself; to match a literal
+/// `*`, `?` or `[`, put it in a set, such as `[*]`.
+///
+/// On Unix, an `--fd N` option can also be provided instead of a filepath to
+/// read from the already open file descriptor `N`, such as a pipe passed down
+/// by a supervisor, or `3` with `3< data.json` in a shell. As the descriptor
+/// may not be seekable, or read from twice, `--fd` cannot be used with
+/// `--start-offset`, `--root-pointer` or `--in-place`.
+///
+/// On Unix, a filepath of `-` reads from stdin, as does providing no input
+/// at all when stdin is not a terminal, such as when piping with
+/// `curl ... | jsonl_converter`. Either is the same as `--fd 0`, so has the
+/// same limits.
+///
+/// A file containing more than one array, such as `[...][...]` when two
+/// exports have been concatenated, is an error, as is one containing more
+/// than one object, such as `{...}{...}` from tools that emit values back to
+/// back. A `--concat` flag can be provided to instead convert each root in
+/// turn, writing the elements of an array or an object as a single record.
+/// Without `--messy`, each root must start on a new line.
+///
+/// A `--strip-jsonp` flag can be provided to convert an array wrapped in
+/// JSONP padding, such as `callback([...]);`, as returned by some legacy
+/// APIs. Input that starts with the array itself is converted as usual.
+/// Without `--messy`, the callback and its `(` must be on the first line,
+/// along with the opening bracket of the array.
+///
+/// Without `--messy`, the file is read a line at a time, and a line longer
+/// than 64 MiB is an error suggesting `--messy`, so that a file without line
+/// breaks is not read into memory whole. A `--max-line-bytes N` option can be
+/// provided to change the limit.
+///
+/// A `--head-bytes N` option can be provided to read at most `N` bytes of
+/// each input, as a safety cap when converting untrusted files. If the input
+/// goes on past the cap, even with whitespace after the root array, the
+/// conversion stops with an error, having written the records completed
+/// before it.
+///
+/// Each input is read through an 8 KiB buffer. A `--buffer-size N` option
+/// can be provided to change its size, up to 1 GiB, or an `--auto-buffer` flag to time
+/// reads of the start of each input through buffers of a few sizes and use
+/// the fastest for the rest of it. As the timed reads take a moment,
+/// `--auto-buffer` only does so for inputs of 64 MiB or more, and as it reads
+/// the input more than once, it cannot be used with `--fd`.
+///
+/// Records may nest objects and arrays to any depth. A `--max-depth N` option
+/// can be provided to fail the conversion at the first bracket nested more
+/// than `N` deep within a record, where a flat object has a depth of 1, as a
+/// safety cap when converting untrusted files. The records completed before
+/// it are kept. Either way, the brackets that are still open are held inline
+/// up to 32 deep, and only allocated on the heap for records nested deeper.
+///
+/// A `--verify` flag can be provided to check the conversion once it is done
+/// by parsing the whole input with `serde_json` and comparing the number of
+/// elements in its root with the number of records converted, counting those
+/// dropped as duplicates. The conversion fails if they differ, or if the
+/// input is not valid JSON. As this holds the whole input in memory, it is
+/// meant for tests and spot checks rather than every conversion. It cannot be
+/// used with options that convert only part of the input, or input that is
+/// not a single JSON document.
+///
+/// A `--diff OTHER` option can be provided to compare the array in the input
+/// with the one in the `OTHER` file instead of converting it, such as to
+/// check a new export against the last one. The elements of both are read
+/// side by side and compared by index in canonical form, as with
+/// `--canonical`, so only one of each is held in memory. Each index whose
+/// elements differ is written as a line such as
+/// `{"index":3,"change":"modified","old":{...},"new":{...}}`. When one array
+/// is longer, each element past the end of the other is written as
+/// `"removed"` if it is in the input, or `"added"` if it is in `OTHER`. Both
+/// files are read as with `--messy`, so their elements must be objects or
+/// arrays. The options for how records are written do not apply, and options
+/// that read only part of the input cannot be used with it. See the `diff`
+/// module for the format.
+///
+/// By default, line breaks within a record are removed along with the
+/// whitespace around them, so `{\n  "a": 1\n}` becomes `{"a": 1}`. A
+/// `--preserve-whitespace` flag can be provided to remove only the line
+/// breaks, keeping the indentation, so that it becomes `{  "a": 1}`. A
+/// `--minify` flag can be provided instead to remove all whitespace outside
+/// of strings, so that `{ "a b" :  1 }` becomes `{"a b":1}`.
+///
+/// Line breaks within strings, which only non-strict input contains, are part
+/// of the values, so are kept by default and with `--minify`, in which case
+/// the record spans lines. With `--preserve-whitespace`, they are removed
+/// too, silently changing the values. A
+/// `--newline-in-values escape|preserve|strip` option can be provided to
+/// choose what happens to them instead: `escape` writes them as `\n` and
+/// `\r`, `preserve` keeps them, so that the record spans lines and is not
+/// valid JSONL, and `strip` removes them along with the whitespace around
+/// them.
+/// Only `--messy` reads strings that span lines, so it requires `--messy`.
+///
+/// Other control characters, such as a literal tab, must also be escaped
+/// inside strings, so the conversion stops at the first one with its byte
+/// offset. An `--allow-control-chars` flag can be provided to pass them
+/// through instead, in which case the records are not valid JSON, or with
+/// the `--escape-control-chars` flag as well, to escape them as `\t`,
+/// `\u0001` and so on. See the `control` module for details.
+///
+/// An `--index FILE` option can be provided to write the offset and length
+/// of each record in the output to `FILE`, so that consumers can seek to a
+/// record directly. See the `index` module for the format.
+///
+/// A `--normalize-unicode nfc|nfd` option can be provided to normalize the
+/// Unicode of string values, so that equal text is also equal byte for byte.
+/// Object keys are only normalized if the `--normalize-keys` flag is also
+/// provided, and a record with two keys that would become the same key is
+/// rejected as invalid.
+///
+/// Invalid UTF-8 in the input is replaced with `U+FFFD`. A `--strict-utf8`
+/// flag can be provided to instead stop with an error giving the byte offset
+/// of the first invalid sequence.
+///
+/// A `--check-jsonl` flag can be provided to check that the input is already
+/// valid JSONL, where every line is a JSON value of its own, instead of
+/// converting it. Nothing is written to the output and the number of the
+/// first invalid line is reported as an error.
+///
+/// A `--columns` flag can be provided to write a summary of the top level
+/// keys of the records instead of the records themselves, with a line such as
+/// `id\t120` for each key giving the number of records it appears in. Keys
+/// are sorted with the most common first. See the `columns` module for
+/// details.
+///
+/// A `--reverse` flag can be provided to convert the input from JSONL back to
+/// JSON, writing each line as an element of a single array. The array is
+/// written on one line unless the `--pretty` flag is also provided, in which
+/// case each element is indented on lines of its own. Elements are written as
+/// they are read, so the array is never held in memory. The options for how
+/// records are written, such as `--flatten`, do not apply.
+///
+/// A `--strict` flag can be provided to check that each record is valid JSON
+/// before it is written. Numbers must follow the JSON grammar, so `+1`, `01`,
+/// `.5` and `1.` are rejected. Conversion stops at the first invalid record.
+///
+/// An `--emit-errors-as-records` flag can be provided so that no record is
+/// lost when some are not valid JSON. Each record is checked as with
+/// `--strict`, but an invalid one is written in its place as an error
+/// record holding why it is invalid and the record as it was read, such as
+/// `{"_error":"...","_raw":"{\"a\": .5}"}`, and the conversion carries on.
+/// The same goes for records that cannot be flattened with `--flatten`, and
+/// records missing a key required with `--fields-required`.
+/// An element whose brackets do not match, or with a raw control character
+/// in a string, is written as an error record too, holding the element up
+/// to where it ends, and the conversion resumes at the next element. An
+/// element ends at a closing bracket that closes no bracket within it, and
+/// a stray closing bracket between elements is an error record of its own.
+/// Input that is cut short before the root is closed ends the conversion
+/// without an error, with the element that was cut short, if any, written
+/// as an error record. Other errors in the structure of the input, such as
+/// content after the root array or nesting deeper than `--max-depth`, still
+/// stop the conversion. The number of error records is reported as
+/// `errors` with `--report-json`.
+///
+/// A `--fields-required KEY,KEY` option can be provided to check that each
+/// record is an object with all of the given top level keys, stopping the
+/// conversion at the first that is missing any, or with
+/// `--emit-errors-as-records`, writing it as an error record naming them.
+/// Records are scanned for their keys rather than parsed. The keys are
+/// checked as they are written, after `--flatten`, `--key-case` and so on,
+/// and are compared as written in the record, escape sequences and all.
+///
+/// A `--replace-nan-inf` flag can be provided to replace the bare `NaN`,
+/// `Infinity` and `-Infinity` literals that some producers write, which are
+/// not valid JSON, with `null`. Strings such as `"NaN"` are left alone.
+/// Without it, `--strict` rejects records containing these literals.
+///
+/// A `--repair` flag can be provided to salvage records from JSON written by
+/// hand or by a lax producer. It removes trailing commas, adds missing
+/// commas between elements and members, double-quotes single-quoted keys
+/// and strings, quotes keys that are unquoted identifiers, and replaces
+/// `NaN`, `Infinity` and `-Infinity` with `null`.
+/// The repairs can also be made one at a time with
+/// `--repair-trailing-commas`, `--repair-missing-commas`, `--single-quotes`
+/// and `--replace-nan-inf`. Double quotes inside single-quoted strings are
+/// escaped, while apostrophes inside double-quoted strings are left alone.
+/// Repairs are best-effort: they cannot fix every defect and may guess
+/// wrongly, so using `--strict` as well is recommended to stop at any record
+/// that is still not valid JSON.
+///
+/// A `--json5` flag can be provided to convert input written in JSON5, such
+/// as a configuration dump, to strict JSONL. It drops `//` and `/* */`
+/// comments, including any before and after the root array, and makes the
+/// repairs for trailing commas, single quotes and unquoted keys, and for
+/// `NaN` and `Infinity`. Hexadecimal numbers, numbers with a `+` sign or a
+/// leading or trailing decimal point, line continuations and the escapes
+/// that JSON does not have are rewritten as JSON, keeping their exact value.
+/// Each record is then checked as with `--strict`; see the `json5` module
+/// for what is not supported. As comments can span lines, it requires
+/// `--messy`, and it cannot be used with `--strip-jsonp` or
+/// `--root-pointer`.
+///
+/// A `--map-values` flag can be provided when the root is an object, such as
+/// `{"id1": {...}, "id2": {...}}`, to write each of its values as a record.
+/// Values must be objects or arrays. An `--inject-key NAME` option can also
+/// be provided to add each key to its value, e.g. `{"NAME": "id1", ...}`.
+/// A value that is not an object, or that already has a `NAME` key, is then
+/// rejected as invalid rather than written without its key.
+///
+/// The keys of each record are written in the order they appear in the
+/// input, unless `--flatten` or `--canonical` is provided. A
+/// `--retain-key-order` flag can be provided to guarantee this, refusing any
+/// option that would reorder keys.
+///
+/// A `--flatten` flag can be provided to flatten each record into a single
+/// object, so `{"a": {"b": [1]}}` becomes `{"a.b.0": 1}`. A
+/// `--flatten-separator SEP` option can be provided to join the parts of each
+/// key with `SEP` instead of `.`. Numbers are written exactly as they are
+/// in the input, and a record with a repeated key is rejected as invalid.
+/// See the `flatten` module for how empty objects, empty arrays and clashing
+/// keys are handled.
+///
+/// A `--key-case snake|camel|kebab` option can be provided to convert the top
+/// level keys of each record to a case, so `firstName` becomes `first_name`
+/// with `snake`. The `--recursive` flag can also be provided to convert the
+/// keys of nested objects too. Values are left alone. A record with two keys
+/// that would become the same key, such as `first_name` and `firstName` with
+/// `camel`, is an error. See the `key_case` module for how keys are split
+/// into words.
+///
+/// A `--canonical` flag can be provided to write each record in a canonical
+/// form, so that records that are equal as JSON are written byte for byte
+/// the same, ready to be diffed or hashed. Whitespace is removed, keys are
+/// sorted, escapes are written the same way and numbers such as `1.0` and
+/// `2.50` are written as `1` and `2.5`, keeping every digit. A record with a
+/// repeated key is rejected as invalid. A `--trim-strings` flag can also be
+/// provided to remove whitespace at the start and end of each string value.
+/// See the `canonical` module for the exact rules.
+///
+/// A `--dedupe-window N` option can be provided to drop records that are
+/// identical to one of the last `N` records written, such as those repeated
+/// by retrying producers. Only the hashes of those `N` records are kept, so
+/// identical records further apart are both written. The window starts
+/// afresh with each input file.
+///
+/// An `--envelope` flag can be provided to wrap each record in an object of
+/// metadata, such as `{"source":"data.json","index":0,"ts":"...","data":{...}}`,
+/// where `data` is the record exactly as it would otherwise be written. An
+/// `--envelope-fields source,index,ts` option can also be provided to choose
+/// which metadata is included. See the `envelope` module for the format.
 ///
 /// # Returns
 ///
-/// * The filepath from the command line arguments.
-/// * A boolean indicating whether the JSONL file is not well formed.
+/// * The parsed `Args`.
+///
+/// # Errors
 ///
-/// # Panics
+/// Each error is returned as a `CliError` holding a message for the user.
 ///
-/// * If the filepath is not provided.
-pub fn parse_args() -> (String, bool) {
+/// * If an argument is not valid UTF-8.
+/// * If an option that takes a value is not followed by one, or its value
+///   cannot be parsed.
+/// * If not exactly one of a filepath, `--input-list`, `--input-glob` and
+///   `--fd` is provided.
+/// * If `--input-list` or `--input-glob` is provided with `--start-offset`.
+/// * If `--fd` is negative, or is provided with `--start-offset`,
+///   `--root-pointer` or `--in-place`.
+/// * If an unknown argument is provided.
+/// * If `--output-eol` is not `lf` or `crlf`.
+/// * If `--normalize-unicode` is not `nfc` or `nfd`.
+/// * If `--key-case` is not `snake`, `camel` or `kebab`.
+/// * If `--recursive` is provided without `--key-case`.
+/// * If `--envelope-fields` contains anything other than `source`, `index`
+///   and `ts`, or is provided without `--envelope`.
+/// * If `--pretty` is provided without `--reverse`.
+/// * If `--columns` is provided with `--reverse`, `--check-jsonl`,
+///   `--length-prefixed`, `--index`, `--partition-by`, `--chunk-records` or
+///   `--order-by`.
+/// * If `--strip-jsonp` is provided with `--reverse`, `--check-jsonl`,
+///   `--start-offset` or `--root-pointer`.
+/// * If `--progress-records` is provided with `--reverse`, `--check-jsonl` or
+///   `--length-prefixed`.
+/// * If `--dedupe-window` is 0, or is provided with `--reverse` or
+///   `--check-jsonl`.
+/// * If `--repair`, `--repair-trailing-commas`, `--repair-missing-commas` or
+///   `--single-quotes` is provided with `--reverse` or `--check-jsonl`.
+/// * If `--json5` is provided without `--messy`, or with `--strip-jsonp` or
+///   `--root-pointer`.
+/// * If `--emit-errors-as-records` is provided with `--reverse` or
+///   `--check-jsonl`.
+/// * If `--fields-required` names no keys, or is provided with `--reverse`
+///   or `--check-jsonl`.
+/// * If `--descending` is provided without `--order-by`.
+/// * If `--max-line-bytes` is provided with `--messy`.
+/// * If `--escape-control-chars` is provided without
+///   `--allow-control-chars`.
+/// * If `--allow-control-chars` is provided with `--reverse` or
+///   `--check-jsonl`.
+/// * If `--head-bytes` is 0, or is provided with `--reverse` or
+///   `--check-jsonl`.
+/// * If `--buffer-size` is 0 or more than `MAX_BUFFER_SIZE`.
+/// * If `--auto-buffer` is provided with `--buffer-size` or `--fd`.
+/// * If `--max-depth` is 0, or is provided with `--reverse` or
+///   `--check-jsonl`.
+/// * If `--verify` is provided with `--reverse`, `--check-jsonl`, `--fd`,
+///   `--start-offset`, `--root-pointer`, `--concat`, `--strip-jsonp` or
+///   `--head-bytes`.
+/// * If `--diff` is provided with `--reverse`, `--check-jsonl`,
+///   `--input-list`, `--input-glob`, `--start-offset`, `--root-pointer`,
+///   `--map-values`, `--strip-jsonp`, `--json5`, `--verify`, `--head-bytes`,
+///   `--max-depth`, `--allow-control-chars` or `--in-place`.
+/// * If `--chunk-records` is 0, or is provided with `--reverse`,
+///   `--length-prefixed`, `--index` or `--partition-by`.
+/// * If `--order-by` is provided with `--reverse`, `--length-prefixed` or
+///   `--flush-every`.
+/// * If `--root-pointer` is not empty or does not start with `/`.
+/// * If `--root-pointer` is provided with `--start-offset` or `--map-values`.
+/// * If `--emit-array-path` is provided with `--length-prefixed`.
+/// * If `--reverse` is provided with `--messy`, `--check-jsonl`,
+///   `--input-list`, `--input-glob`, `--start-offset`, `--root-pointer`,
+///   `--with-offset`, `--emit-array-path`, `--partition-by`, `--index` or
+///   `--length-prefixed`.
+/// * If `--normalize-keys` is provided without `--normalize-unicode`.
+/// * If `--inject-key` is provided without `--map-values`.
+/// * If `--flatten-separator` is provided without `--flatten`.
+/// * If `--varint` is provided without `--length-prefixed`.
+/// * If `--format` is not `jsonl` or `msgpack`, or is `msgpack` without the
+///   `msgpack` feature.
+/// * If `--format msgpack` is provided with `--reverse`, `--check-jsonl`,
+///   `--diff`, `--columns`, `--partition-by`, `--with-offset`,
+///   `--emit-array-path`, `--prefix`, `--suffix`, `--index`,
+///   `--chunk-records` or `--progress-records`.
+/// * If `--length-prefixed` is provided with `--with-offset` or `--index`.
+/// * If `--prefix` or `--suffix` is provided with `--reverse`,
+///   `--check-jsonl`, `--length-prefixed`, `--columns`, `--partition-by` or
+///   `--order-by`.
+/// * If `--no-final-eol` is provided with `--reverse`, `--check-jsonl`,
+///   `--diff`, `--columns`, `--length-prefixed`, `--format msgpack`,
+///   `--partition-by`, `--order-by`, `--index`, `--chunk-records`,
+///   `--progress-records`, `--input-list`, `--input-glob` or more than one
+///   `--root-pointer`.
+/// * If `--max-open-files` is provided without `--partition-by`.
+/// * If `--retain-key-order` is provided with `--flatten` or `--canonical`.
+/// * If `--canonical` is provided with `--reverse`, `--check-jsonl` or
+///   `--preserve-whitespace`.
+/// * If `--trim-strings` is provided without `--canonical`.
+/// * If `--minify` is provided with `--preserve-whitespace`, `--reverse` or
+///   `--check-jsonl`.
+/// * If more than one of `--output`, `--pipe` and `--partition-by` are
+///   provided.
+/// * If both `--index` and `--partition-by` are provided.
+/// * If both `--tee` and `--partition-by` are provided.
+/// * If `--in-place` is provided with `--output`, `--pipe`, `--partition-by`,
+///   `--input-list`, `--input-glob` or `--check-jsonl`.
+pub fn parse_args() -> Result<Args, CliError> {
     let mut args = env::args_os();
     args.next(); // Skip the program name.
+    parse_args_with_stdin(args, !io::stdin().is_terminal())
+}
+
+/// Parses `args` the same way as `parse_args`, where `args` does not include
+/// the program name. Stdin is only read from if the filepath is `-`, rather
+/// than whenever no input is provided and stdin is not a terminal.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::cli::parse_args_from;
+///
+/// let args = parse_args_from(["data.json", "--messy"].map(Into::into).into_iter()).unwrap();
+/// assert_eq!(args.filepath, "data.json");
+/// assert!(args.is_messy);
+///
+/// let error = parse_args_from(["data.json", "--help"].map(Into::into).into_iter());
+/// assert_eq!(error.unwrap_err().to_string(), "Unknown argument: --help");
+/// ```
+///
+/// # Errors
+///
+/// * For the same reasons as `parse_args`.
+pub fn parse_args_from<I: Iterator<Item = OsString>>(args: I) -> Result<Args, CliError> {
+    parse_args_with_stdin(args, false)
+}
+
+/// Parses `args` as `parse_args_from` does, reading from stdin if no input
+/// is provided and `stdin_piped` is set.
+fn parse_args_with_stdin<I: Iterator<Item = OsString>>(
+    args: I,
+    stdin_piped: bool,
+) -> Result<Args, CliError> {
+    let mut args = args.peekable();
+    let mut parsed = Args::default();
+    if let Some(filepath) = args.next_if(|arg| !arg.to_string_lossy().starts_with("--")) {
+        parsed.filepath = into_string(filepath)?;
+    }
+
+    while let Some(arg) = args.next() {
+        let arg = into_string(arg)?;
+        match arg.as_str() {
+            "--messy" => parsed.is_messy = true,
+            "--output" => parsed.output = Some(expect_value(&mut args, &arg)?),
+            "--pipe" => parsed.pipe = Some(expect_value(&mut args, &arg)?),
+            "--in-place" => parsed.in_place = true,
+            "--tee" => parsed.tee = Some(expect_value(&mut args, &arg)?),
+            "--report-json" => parsed.report_json = true,
+            "--count" => parsed.count = true,
+            "--progress-records" => parsed.progress_records = true,
+            "--start-offset" => parsed.start_offset = Some(expect_number(&mut args, &arg)?),
+            "--root-pointer" => parsed.root_pointers.push(expect_parsed(&mut args, &arg)?),
+            "--emit-array-path" => parsed.emit_array_path = true,
+            "--with-offset" => parsed.with_offset = true,
+            "--prefix" => parsed.prefix = Some(expect_value(&mut args, &arg)?),
+            "--suffix" => parsed.suffix = Some(expect_value(&mut args, &arg)?),
+            "--partition-by" => parsed.partition_by = Some(expect_value(&mut args, &arg)?),
+            "--partition-dir" => parsed.partition_dir = Some(expect_value(&mut args, &arg)?),
+            "--max-open-files" => parsed.max_open_files = Some(expect_number(&mut args, &arg)?),
+            "--order-by" => parsed.order_by = Some(expect_value(&mut args, &arg)?),
+            "--descending" => parsed.descending = true,
+            "--chunk-records" => parsed.chunk_records = Some(expect_number(&mut args, &arg)?),
+            "--flush-every" => parsed.flush_every = Some(expect_number(&mut args, &arg)?),
+            "--output-eol" => parsed.output_eol = expect_parsed(&mut args, &arg)?,
+            "--no-final-eol" => parsed.no_final_eol = true,
+            "--format" => parsed.format = expect_parsed(&mut args, &arg)?,
+            "--length-prefixed" => parsed.length_prefixed = true,
+            "--varint" => parsed.varint = true,
+            "--input-list" => parsed.input_list = Some(expect_value(&mut args, &arg)?),
+            "--input-glob" => parsed.input_glob = Some(expect_value(&mut args, &arg)?),
+            #[cfg(unix)]
+            "--fd" => parsed.fd = Some(expect_number(&mut args, &arg)?),
+            "--continue-on-error" => parsed.continue_on_error = true,
+            "--concat" => parsed.concat = true,
+            "--strip-jsonp" => parsed.strip_jsonp = true,
+            "--max-line-bytes" => parsed.max_line_bytes = Some(expect_number(&mut args, &arg)?),
+            "--head-bytes" => parsed.head_bytes = Some(expect_number(&mut args, &arg)?),
+            "--buffer-size" => parsed.buffer_size = Some(expect_number(&mut args, &arg)?),
+            "--auto-buffer" => parsed.auto_buffer = true,
+            "--max-depth" => parsed.max_depth = Some(expect_number(&mut args, &arg)?),
+            "--verify" => parsed.verify = true,
+            "--diff" => parsed.diff = Some(expect_value(&mut args, &arg)?),
+            "--preserve-whitespace" => parsed.preserve_whitespace = true,
+            "--minify" => parsed.minify = true,
+            "--newline-in-values" => {
+                parsed.newline_in_values = Some(expect_parsed(&mut args, &arg)?)
+            }
+            "--allow-control-chars" => parsed.allow_control_chars = true,
+            "--escape-control-chars" => parsed.escape_control_chars = true,
+            "--index" => parsed.index = Some(expect_value(&mut args, &arg)?),
+            "--normalize-unicode" => {
+                parsed.normalize_unicode = Some(expect_parsed(&mut args, &arg)?);
+            }
+            "--normalize-keys" => parsed.normalize_keys = true,
+            "--strict-utf8" => parsed.strict_utf8 = true,
+            "--check-jsonl" => parsed.check_jsonl = true,
+            "--columns" => parsed.columns = true,
+            "--reverse" => parsed.reverse = true,
+            "--pretty" => parsed.pretty = true,
+            "--strict" => parsed.strict = true,
+            "--emit-errors-as-records" => parsed.emit_errors_as_records = true,
+            "--fields-required" => {
+                parsed.fields_required = expect_value(&mut args, &arg)?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(String::from)
+                    .collect();
+                if parsed.fields_required.is_empty() {
+                    return Err(CliError::new(
+                        "--fields-required must name at least one key.",
+                    ));
+                }
+            }
+            "--replace-nan-inf" => parsed.replace_nan_inf = true,
+            "--repair" => parsed.repair = true,
+            "--repair-trailing-commas" => parsed.repair_trailing_commas = true,
+            "--repair-missing-commas" => parsed.repair_missing_commas = true,
+            "--single-quotes" => parsed.single_quotes = true,
+            "--json5" => parsed.json5 = true,
+            "--map-values" => parsed.map_values = true,
+            "--inject-key" => parsed.inject_key = Some(expect_value(&mut args, &arg)?),
+            "--retain-key-order" => parsed.retain_key_order = true,
+            "--flatten" => parsed.flatten = true,
+            "--canonical" => parsed.canonical = true,
+            "--trim-strings" => parsed.trim_strings = true,
+            "--flatten-separator" => {
+                parsed.flatten_separator = Some(expect_value(&mut args, &arg)?);
+            }
+            "--dedupe-window" => parsed.dedupe_window = Some(expect_number(&mut args, &arg)?),
+            "--envelope" => parsed.envelope = true,
+            "--envelope-fields" => parsed.envelope_fields = Some(expect_parsed(&mut args, &arg)?),
+            "--key-case" => parsed.key_case = Some(expect_parsed(&mut args, &arg)?),
+            "--recursive" => parsed.recursive = true,
+            _ => return Err(CliError::new(format!("Unknown argument: {}", arg))),
+        }
+    }
+
+    // Stdin is always open as file descriptor 0.
+    if parsed.filepath == "-" && parsed.fd.is_none() {
+        parsed.filepath.clear();
+        parsed.fd = Some(0);
+    }
+    let inputs = [
+        !parsed.filepath.is_empty(),
+        parsed.input_list.is_some(),
+        parsed.input_glob.is_some(),
+        parsed.fd.is_some(),
+    ];
+    match inputs.into_iter().filter(|&given| given).count() {
+        0 if stdin_piped => parsed.fd = Some(0),
+        0 => return Err(CliError::new("No filepath provided.")),
+        1 => {}
+        _ => {
+            return Err(CliError::new(
+                "Only one of a filepath, --input-list, --input-glob and --fd can be used.",
+            ))
+        }
+    }
+    let several_inputs = parsed.input_list.is_some() || parsed.input_glob.is_some();
+    if several_inputs && parsed.start_offset.is_some() {
+        return Err(CliError::new(
+            "--input-list and --input-glob cannot be used with --start-offset.",
+        ));
+    }
+    if parsed.fd.is_some_and(|fd| fd < 0) {
+        return Err(CliError::new(
+            "--fd must be a file descriptor, which cannot be negative.",
+        ));
+    }
+    let fd_conflicts = [
+        parsed.start_offset.is_some(),
+        !parsed.root_pointers.is_empty(),
+        parsed.in_place,
+    ];
+    if parsed.fd.is_some() && fd_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--fd cannot be used with --start-offset, --root-pointer or --in-place.",
+        ));
+    }
+
+    let destinations = [
+        parsed.output.is_some(),
+        parsed.pipe.is_some(),
+        parsed.partition_by.is_some(),
+    ];
+    if destinations.into_iter().filter(|&given| given).count() > 1 {
+        return Err(CliError::new(
+            "Only one of --output, --pipe and --partition-by can be used.",
+        ));
+    }
+    if parsed.normalize_keys && parsed.normalize_unicode.is_none() {
+        return Err(CliError::new(
+            "--normalize-keys requires --normalize-unicode.",
+        ));
+    }
+    if parsed.inject_key.is_some() && !parsed.map_values {
+        return Err(CliError::new("--inject-key requires --map-values."));
+    }
+    if parsed.max_open_files.is_some() && parsed.partition_by.is_none() {
+        return Err(CliError::new("--max-open-files requires --partition-by."));
+    }
+    if parsed.flatten_separator.is_some() && !parsed.flatten {
+        return Err(CliError::new("--flatten-separator requires --flatten."));
+    }
+    if parsed.pretty && !parsed.reverse {
+        return Err(CliError::new("--pretty requires --reverse."));
+    }
+    if parsed.descending && parsed.order_by.is_none() {
+        return Err(CliError::new("--descending requires --order-by."));
+    }
+    if parsed.order_by.is_some()
+        && (parsed.reverse || parsed.length_prefixed || parsed.flush_every.is_some())
+    {
+        return Err(CliError::new(
+            "--order-by cannot be used with --reverse, --length-prefixed or --flush-every.",
+        ));
+    }
+    if parsed.max_line_bytes.is_some() && parsed.is_messy {
+        return Err(CliError::new(
+            "--max-line-bytes cannot be used with --messy, which does not read lines.",
+        ));
+    }
+    if parsed.newline_in_values.is_some() && !parsed.is_messy {
+        return Err(CliError::new(
+            "--newline-in-values requires --messy, as only it reads strings that span lines.",
+        ));
+    }
+    if parsed.escape_control_chars && !parsed.allow_control_chars {
+        return Err(CliError::new(
+            "--escape-control-chars requires --allow-control-chars.",
+        ));
+    }
+    if parsed.allow_control_chars && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--allow-control-chars cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    if parsed.head_bytes == Some(0) {
+        return Err(CliError::new("--head-bytes must be at least 1."));
+    }
+    if parsed.head_bytes.is_some() && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--head-bytes cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    if parsed.buffer_size == Some(0) {
+        return Err(CliError::new("--buffer-size must be at least 1."));
+    }
+    if parsed
+        .buffer_size
+        .is_some_and(|size| size > MAX_BUFFER_SIZE)
+    {
+        return Err(CliError::new(format!(
+            "--buffer-size must be at most {} (1 GiB).",
+            MAX_BUFFER_SIZE
+        )));
+    }
+    if parsed.auto_buffer && (parsed.buffer_size.is_some() || parsed.fd.is_some()) {
+        return Err(CliError::new(
+            "--auto-buffer cannot be used with --buffer-size or --fd.",
+        ));
+    }
+    if parsed.max_depth == Some(0) {
+        return Err(CliError::new("--max-depth must be at least 1."));
+    }
+    if parsed.max_depth.is_some() && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--max-depth cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    let verify_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.fd.is_some(),
+        parsed.start_offset.is_some(),
+        !parsed.root_pointers.is_empty(),
+        parsed.concat,
+        parsed.strip_jsonp,
+        parsed.head_bytes.is_some(),
+    ];
+    if parsed.verify && verify_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--verify cannot be used with --reverse, --check-jsonl, --fd, --start-offset, \
+             --root-pointer, --concat, --strip-jsonp or --head-bytes.",
+        ));
+    }
+    let diff_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.input_list.is_some(),
+        parsed.input_glob.is_some(),
+        parsed.start_offset.is_some(),
+        !parsed.root_pointers.is_empty(),
+        parsed.map_values,
+        parsed.strip_jsonp,
+        parsed.json5,
+        parsed.verify,
+        parsed.head_bytes.is_some(),
+        parsed.max_depth.is_some(),
+        parsed.allow_control_chars,
+        parsed.in_place,
+    ];
+    if parsed.diff.is_some() && diff_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--diff cannot be used with --reverse, --check-jsonl, --input-list, --input-glob, \
+             --start-offset, --root-pointer, --map-values, --strip-jsonp, --json5, --verify, \
+             --head-bytes, --max-depth, --allow-control-chars or --in-place.",
+        ));
+    }
+    if parsed.chunk_records == Some(0) {
+        return Err(CliError::new("--chunk-records must be at least 1."));
+    }
+    let chunk_conflicts = [
+        parsed.reverse,
+        parsed.length_prefixed,
+        parsed.index.is_some(),
+        parsed.partition_by.is_some(),
+    ];
+    if parsed.chunk_records.is_some() && chunk_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--chunk-records cannot be used with --reverse, --length-prefixed, --index or \
+             --partition-by.",
+        ));
+    }
+    let columns_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.length_prefixed,
+        parsed.index.is_some(),
+        parsed.partition_by.is_some(),
+        parsed.chunk_records.is_some(),
+        parsed.order_by.is_some(),
+    ];
+    if parsed.columns && columns_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--columns cannot be used with --reverse, --check-jsonl, --length-prefixed, --index, \
+             --partition-by, --chunk-records or --order-by.",
+        ));
+    }
+    let jsonp_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.start_offset.is_some(),
+        !parsed.root_pointers.is_empty(),
+    ];
+    if parsed.strip_jsonp && jsonp_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--strip-jsonp cannot be used with --reverse, --check-jsonl, --start-offset or \
+             --root-pointer.",
+        ));
+    }
+    if parsed.progress_records && (parsed.reverse || parsed.check_jsonl || parsed.length_prefixed) {
+        return Err(CliError::new(
+            "--progress-records cannot be used with --reverse, --check-jsonl or --length-prefixed.",
+        ));
+    }
+    if parsed.dedupe_window == Some(0) {
+        return Err(CliError::new("--dedupe-window must be at least 1."));
+    }
+    if parsed.dedupe_window.is_some() && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--dedupe-window cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    if !parsed.repairs().is_empty() && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--repair cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    if parsed.json5 && !parsed.is_messy {
+        return Err(CliError::new(
+            "--json5 requires --messy, as comments can span lines.",
+        ));
+    }
+    if parsed.json5 && (parsed.strip_jsonp || !parsed.root_pointers.is_empty()) {
+        return Err(CliError::new(
+            "--json5 cannot be used with --strip-jsonp or --root-pointer.",
+        ));
+    }
+    if parsed.emit_errors_as_records && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--emit-errors-as-records cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    if !parsed.fields_required.is_empty() && (parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--fields-required cannot be used with --reverse or --check-jsonl.",
+        ));
+    }
+    let reverse_conflicts = [
+        parsed.is_messy,
+        parsed.check_jsonl,
+        several_inputs,
+        parsed.start_offset.is_some(),
+        !parsed.root_pointers.is_empty(),
+        parsed.with_offset,
+        parsed.emit_array_path,
+        parsed.partition_by.is_some(),
+        parsed.index.is_some(),
+        parsed.length_prefixed,
+    ];
+    if parsed.reverse && reverse_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--reverse cannot be used with --messy, --check-jsonl, --input-list, --input-glob, \
+             --start-offset, --root-pointer, --with-offset, --emit-array-path, --partition-by, \
+             --index or --length-prefixed.",
+        ));
+    }
+    if !parsed.root_pointers.is_empty() && (parsed.start_offset.is_some() || parsed.map_values) {
+        return Err(CliError::new(
+            "--root-pointer cannot be used with --start-offset or --map-values.",
+        ));
+    }
+    if parsed.emit_array_path && parsed.length_prefixed {
+        return Err(CliError::new(
+            "--emit-array-path cannot be used with --length-prefixed.",
+        ));
+    }
+    if parsed.envelope_fields.is_some() && !parsed.envelope {
+        return Err(CliError::new("--envelope-fields requires --envelope."));
+    }
+    if parsed.recursive && parsed.key_case.is_none() {
+        return Err(CliError::new("--recursive requires --key-case."));
+    }
+    if parsed.varint && !parsed.length_prefixed {
+        return Err(CliError::new("--varint requires --length-prefixed."));
+    }
+    if parsed.length_prefixed && (parsed.with_offset || parsed.index.is_some()) {
+        return Err(CliError::new(
+            "--length-prefixed cannot be used with --with-offset or --index.",
+        ));
+    }
+    if cfg!(not(feature = "msgpack")) && parsed.format == OutputFormat::Msgpack {
+        return Err(CliError::new(
+            "--format msgpack requires the msgpack feature.",
+        ));
+    }
+    let msgpack_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.diff.is_some(),
+        parsed.columns,
+        parsed.partition_by.is_some(),
+        parsed.with_offset,
+        parsed.emit_array_path,
+        parsed.prefix.is_some(),
+        parsed.suffix.is_some(),
+        parsed.index.is_some(),
+        parsed.chunk_records.is_some(),
+        parsed.progress_records,
+    ];
+    if parsed.format == OutputFormat::Msgpack && msgpack_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--format msgpack cannot be used with --reverse, --check-jsonl, --diff, --columns, \
+             --partition-by, --with-offset, --emit-array-path, --prefix, --suffix, --index, \
+             --chunk-records or --progress-records.",
+        ));
+    }
+    let wrapper_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.length_prefixed,
+        parsed.columns,
+        parsed.partition_by.is_some(),
+        parsed.order_by.is_some(),
+    ];
+    if (parsed.prefix.is_some() || parsed.suffix.is_some()) && wrapper_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--prefix and --suffix cannot be used with --reverse, --check-jsonl, \
+             --length-prefixed, --columns, --partition-by or --order-by.",
+        ));
+    }
+    let no_final_eol_conflicts = [
+        parsed.reverse,
+        parsed.check_jsonl,
+        parsed.diff.is_some(),
+        parsed.columns,
+        parsed.length_prefixed,
+        parsed.format == OutputFormat::Msgpack,
+        parsed.partition_by.is_some(),
+        parsed.order_by.is_some(),
+        parsed.index.is_some(),
+        parsed.chunk_records.is_some(),
+        parsed.progress_records,
+        parsed.input_list.is_some(),
+        parsed.input_glob.is_some(),
+        parsed.root_pointers.len() > 1,
+    ];
+    if parsed.no_final_eol && no_final_eol_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--no-final-eol cannot be used with --reverse, --check-jsonl, --diff, --columns, \
+             --length-prefixed, --format msgpack, --partition-by, --order-by, --index, \
+             --chunk-records, --progress-records, --input-list, --input-glob or more than one \
+             --root-pointer.",
+        ));
+    }
+    if parsed.retain_key_order && parsed.flatten {
+        return Err(CliError::new(
+            "--retain-key-order and --flatten cannot be used together, as --flatten sorts keys.",
+        ));
+    }
+    if parsed.retain_key_order && parsed.canonical {
+        return Err(CliError::new(
+            "--retain-key-order and --canonical cannot be used together, as --canonical sorts \
+             keys.",
+        ));
+    }
+    if parsed.canonical && (parsed.reverse || parsed.check_jsonl || parsed.preserve_whitespace) {
+        return Err(CliError::new(
+            "--canonical cannot be used with --reverse, --check-jsonl or --preserve-whitespace.",
+        ));
+    }
+    if parsed.trim_strings && !parsed.canonical {
+        return Err(CliError::new("--trim-strings requires --canonical."));
+    }
+    if parsed.minify && (parsed.preserve_whitespace || parsed.reverse || parsed.check_jsonl) {
+        return Err(CliError::new(
+            "--minify cannot be used with --preserve-whitespace, --reverse or --check-jsonl.",
+        ));
+    }
+    if parsed.index.is_some() && parsed.partition_by.is_some() {
+        return Err(CliError::new(
+            "--index and --partition-by cannot be used together.",
+        ));
+    }
+    if parsed.tee.is_some() && parsed.partition_by.is_some() {
+        return Err(CliError::new(
+            "--tee and --partition-by cannot be used together.",
+        ));
+    }
+    let in_place_conflicts = [
+        parsed.output.is_some(),
+        parsed.pipe.is_some(),
+        parsed.partition_by.is_some(),
+        parsed.filepath.is_empty(),
+        parsed.check_jsonl,
+    ];
+    if parsed.in_place && in_place_conflicts.contains(&true) {
+        return Err(CliError::new(
+            "--in-place cannot be used with --output, --pipe, --partition-by, --input-list, \
+             --input-glob or --check-jsonl.",
+        ));
+    }
+
+    Ok(parsed)
+}
+
+/// An invalid command line, such as an unknown argument or two options that
+/// cannot be used together. It holds the message shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliError(String);
+
+impl CliError {
+    /// Creates a new `CliError` with the message shown to the user.
+    pub fn new(message: impl Into<String>) -> Self {
+        CliError(message.into())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CliError {}
+
+/// Returns `arg` as a `String`.
+///
+/// # Errors
+///
+/// * If `arg` is not valid UTF-8.
+fn into_string(arg: OsString) -> Result<String, CliError> {
+    arg.into_string().map_err(|arg| {
+        CliError::new(format!(
+            "Arguments must be valid UTF-8, but {:?} is not.",
+            arg
+        ))
+    })
+}
+
+/// Returns the value following an option.
+///
+/// # Errors
+///
+/// * If no value follows the option, or it is not valid UTF-8.
+fn expect_value<I: Iterator<Item = OsString>>(
+    args: &mut I,
+    option: &str,
+) -> Result<String, CliError> {
+    let value = args
+        .next()
+        .ok_or_else(|| CliError::new(format!("{} requires a value.", option)))?;
+    into_string(value)
+}
+
+/// Returns the numeric value following an option.
+///
+/// # Errors
+///
+/// * If no value follows the option or the value is not a number.
+fn expect_number<T: FromStr, I: Iterator<Item = OsString>>(
+    args: &mut I,
+    option: &str,
+) -> Result<T, CliError> {
+    expect_value(args, option)?
+        .parse()
+        .map_err(|_| CliError::new(format!("{} requires a number.", option)))
+}
+
+/// Returns the value following an option, parsed with its `FromStr`
+/// implementation.
+///
+/// # Errors
+///
+/// * If no value follows the option or the value cannot be parsed.
+fn expect_parsed<T, I>(args: &mut I, option: &str) -> Result<T, CliError>
+where
+    T: FromStr<Err = String>,
+    I: Iterator<Item = OsString>,
+{
+    expect_value(args, option)?.parse().map_err(CliError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        parse_args_from(args.iter().map(OsString::from)).unwrap()
+    }
+
+    /// Returns the message of the error that parsing `args` fails with.
+    fn parse_err(args: &[&str]) -> String {
+        parse_args_from(args.iter().map(OsString::from))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_args_from_filepath_only() {
+        assert_eq!(
+            parse(&["data.json"]),
+            Args {
+                filepath: String::from("data.json"),
+                ..Args::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_messy_and_pipe() {
+        let args = parse(&["data.json", "--pipe", "gzip > out.gz", "--messy"]);
+        assert!(args.is_messy);
+        assert_eq!(args.pipe, Some(String::from("gzip > out.gz")));
+        assert!(!args.report_json);
+    }
+
+    #[test]
+    fn test_parse_args_from_in_place() {
+        assert!(parse(&["data.json", "--in-place"]).in_place);
+        assert!(!parse(&["data.json"]).in_place);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_in_place_and_output() {
+        parse_err(&["data.json", "--in-place", "--output", "out.jsonl"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_in_place_and_input_glob() {
+        parse_err(&["--input-glob", "*.json", "--in-place"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_tee() {
+        let args = parse(&[
+            "data.json",
+            "--tee",
+            "copy.jsonl",
+            "--pipe",
+            "gzip > out.gz",
+        ]);
+        assert_eq!(args.tee, Some(String::from("copy.jsonl")));
+        assert_eq!(args.pipe, Some(String::from("gzip > out.gz")));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_tee_and_partition_by() {
+        parse_err(&["data.json", "--tee", "copy.jsonl", "--partition-by", "lang"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_report_json() {
+        assert!(parse(&["data.json", "--report-json"]).report_json);
+    }
+
+    #[test]
+    fn test_parse_args_from_count() {
+        assert!(parse(&["data.json", "--count"]).count);
+        assert!(!parse(&["data.json"]).count);
+    }
+
+    #[test]
+    fn test_parse_args_from_columns() {
+        assert!(parse(&["data.json", "--columns"]).columns);
+        assert!(!parse(&["data.json"]).columns);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_columns_and_order_by() {
+        parse_err(&["data.json", "--columns", "--order-by", "id"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_strip_jsonp() {
+        assert!(parse(&["data.json", "--strip-jsonp"]).strip_jsonp);
+        assert!(!parse(&["data.json"]).strip_jsonp);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_strip_jsonp_and_root_pointer() {
+        parse_err(&["data.json", "--strip-jsonp", "--root-pointer", "/items"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_progress_records() {
+        assert!(parse(&["data.json", "--progress-records"]).progress_records);
+        assert!(!parse(&["data.json"]).progress_records);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_progress_records_and_length_prefixed() {
+        parse_err(&["data.json", "--progress-records", "--length-prefixed"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_start_offset() {
+        assert_eq!(
+            parse(&["data.json", "--start-offset", "42"]).start_offset,
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_with_offset() {
+        assert!(parse(&["data.json", "--with-offset"]).with_offset);
+    }
+
+    #[test]
+    fn test_parse_args_from_prefix_and_suffix() {
+        let args = parse(&[
+            "data.json",
+            "--prefix",
+            "INSERT INTO t VALUES('",
+            "--suffix",
+            "');",
+        ]);
+        assert_eq!(args.prefix.as_deref(), Some("INSERT INTO t VALUES('"));
+        assert_eq!(args.suffix.as_deref(), Some("');"));
+        assert_eq!(parse(&["data.json", "--suffix", ","]).prefix, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_suffix_and_order_by() {
+        assert!(
+            parse_err(&["data.json", "--suffix", ";", "--order-by", "id"])
+                .contains("--prefix and --suffix cannot be used with --reverse")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_partition_by() {
+        let args = parse(&["data.json", "--partition-by", "lang"]);
+        assert_eq!(args.partition_by, Some(String::from("lang")));
+        assert_eq!(args.partition_dir(), "out");
+
+        let args = parse(&[
+            "data.json",
+            "--partition-by",
+            "lang",
+            "--partition-dir",
+            "p",
+        ]);
+        assert_eq!(args.partition_dir(), "p");
+        assert_eq!(args.max_open_files(), DEFAULT_MAX_OPEN_FILES);
+
+        let args = parse(&[
+            "data.json",
+            "--partition-by",
+            "lang",
+            "--max-open-files",
+            "8",
+        ]);
+        assert_eq!(args.max_open_files(), 8);
+    }
+
+    #[test]
+    fn test_parse_args_from_order_by() {
+        let args = parse(&["data.json", "--order-by", "id"]);
+        assert_eq!(args.order_by, Some(String::from("id")));
+        assert!(!args.descending);
+        assert!(parse(&["data.json", "--order-by", "id", "--descending"]).descending);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_descending_alone() {
+        parse_err(&["data.json", "--descending"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_order_by_and_flush_every() {
+        parse_err(&["data.json", "--order-by", "id", "--flush-every", "10"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_chunk_records() {
+        assert_eq!(
+            parse(&["data.json", "--chunk-records", "100"]).chunk_records,
+            Some(100)
+        );
+        assert_eq!(parse(&["data.json"]).chunk_records, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_zero_chunk_records() {
+        parse_err(&["data.json", "--chunk-records", "0"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_chunk_records_and_index() {
+        parse_err(&["data.json", "--chunk-records", "2", "--index", "out.idx"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_flush_every() {
+        assert_eq!(
+            parse(&["data.json", "--flush-every", "10"]).flush_every,
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_output_eol() {
+        assert_eq!(parse(&["data.json"]).output_eol, LineEnding::Lf);
+        assert_eq!(
+            parse(&["data.json", "--output-eol", "crlf"]).output_eol,
+            LineEnding::Crlf
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_no_final_eol() {
+        assert!(parse(&["data.json", "--no-final-eol"]).no_final_eol);
+        assert!(!parse(&["data.json"]).no_final_eol);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_no_final_eol_and_chunk_records() {
+        assert!(
+            parse_err(&["data.json", "--no-final-eol", "--chunk-records", "2"])
+                .contains("--no-final-eol cannot be used with --reverse, --check-jsonl")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_length_prefixed() {
+        assert_eq!(parse(&["data.json"]).length_prefix(), None);
+        assert_eq!(
+            parse(&["data.json", "--length-prefixed"]).length_prefix(),
+            Some(LengthPrefix::U32)
+        );
+        assert_eq!(
+            parse(&["data.json", "--length-prefixed", "--varint"]).length_prefix(),
+            Some(LengthPrefix::Varint)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn test_parse_args_from_format() {
+        assert_eq!(parse(&["data.json"]).format, OutputFormat::Jsonl);
+        let args = parse(&["data.json", "--format", "msgpack", "--length-prefixed"]);
+        assert_eq!(args.format, OutputFormat::Msgpack);
+        assert_eq!(args.length_prefix(), Some(LengthPrefix::U32));
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn test_parse_args_from_rejects_msgpack_with_offset() {
+        assert!(
+            parse_err(&["data.json", "--format", "msgpack", "--with-offset"])
+                .contains("--format msgpack cannot be used with --reverse, --check-jsonl")
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "msgpack"))]
+    fn test_parse_args_from_rejects_msgpack_without_the_feature() {
+        assert!(parse_err(&["data.json", "--format", "msgpack"])
+            .contains("--format msgpack requires the msgpack feature."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_unknown_format() {
+        assert!(parse_err(&["data.json", "--format", "csv"])
+            .contains("Unknown format `csv`. Expected `jsonl` or `msgpack`."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_varint_alone() {
+        parse_err(&["data.json", "--varint"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_length_prefixed_with_offset() {
+        parse_err(&["data.json", "--length-prefixed", "--with-offset"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_dedupe_window() {
+        assert_eq!(
+            parse(&["data.json", "--dedupe-window", "1000"]).dedupe_window,
+            Some(1000)
+        );
+        assert_eq!(parse(&["data.json"]).dedupe_window, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_zero_dedupe_window() {
+        parse_err(&["data.json", "--dedupe-window", "0"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_dedupe_window_and_reverse() {
+        parse_err(&["data.json", "--dedupe-window", "2", "--reverse"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_envelope() {
+        let args = parse(&["data.json", "--envelope"]);
+        assert!(args.envelope);
+        assert_eq!(args.envelope_fields(), EnvelopeFields::ALL);
+
+        let args = parse(&["data.json", "--envelope", "--envelope-fields", "ts,source"]);
+        assert_eq!(
+            args.envelope_fields(),
+            EnvelopeFields {
+                source: true,
+                index: false,
+                timestamp: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_envelope_fields_alone() {
+        parse_err(&["data.json", "--envelope-fields", "ts"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_key_case() {
+        let args = parse(&["data.json", "--key-case", "camel"]);
+        assert_eq!(args.key_case, Some(KeyCase::Camel));
+        assert!(!args.recursive);
+        assert!(parse(&["data.json", "--key-case", "snake", "--recursive"]).recursive);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_recursive_alone() {
+        parse_err(&["data.json", "--recursive"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_root_pointer_and_emit_array_path() {
+        let args = parse(&["data.json", "--root-pointer", "/data/items"]);
+        assert_eq!(args.root_pointers, ["/data/items".parse().unwrap()]);
+        let args = parse(&["data.json", "--root-pointer", "/a", "--root-pointer", "/b"]);
+        assert_eq!(
+            args.root_pointers,
+            ["/a".parse().unwrap(), "/b".parse().unwrap()]
+        );
+        assert!(!args.emit_array_path);
+        assert!(parse(&["data.json", "--emit-array-path"]).emit_array_path);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_invalid_root_pointer() {
+        parse_err(&["data.json", "--root-pointer", "data/items"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_concat() {
+        assert!(parse(&["data.json", "--concat"]).concat);
+        assert!(!parse(&["data.json"]).concat);
+    }
+
+    #[test]
+    fn test_parse_args_from_reverse() {
+        let args = parse(&["data.jsonl", "--reverse", "--pretty"]);
+        assert!(args.reverse);
+        assert!(args.pretty);
+        assert!(!parse(&["data.jsonl", "--reverse"]).pretty);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_pretty_alone() {
+        parse_err(&["data.json", "--pretty"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_reverse_and_messy() {
+        parse_err(&["data.jsonl", "--reverse", "--messy"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_unknown_output_eol() {
+        parse_err(&["data.json", "--output-eol", "cr"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_input_list() {
+        let args = parse(&["--input-list", "files.txt", "--continue-on-error"]);
+        assert_eq!(args.filepath, "");
+        assert_eq!(args.input_list, Some(String::from("files.txt")));
+        assert!(args.continue_on_error);
+    }
+
+    #[test]
+    fn test_parse_args_from_input_glob() {
+        let args = parse(&["--input-glob", "data/*.json"]);
+        assert_eq!(args.filepath, "");
+        assert_eq!(args.input_glob, Some(String::from("data/*.json")));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_input_list_and_input_glob() {
+        parse_err(&["--input-list", "files.txt", "--input-glob", "*.json"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_max_line_bytes() {
+        assert_eq!(
+            parse(&["data.json"]).max_line_bytes(),
+            DEFAULT_MAX_LINE_BYTES
+        );
+        assert_eq!(
+            parse(&["data.json", "--max-line-bytes", "1024"]).max_line_bytes(),
+            1024
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_max_line_bytes_and_messy() {
+        parse_err(&["data.json", "--messy", "--max-line-bytes", "1024"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_head_bytes() {
+        assert_eq!(
+            parse(&["data.json", "--head-bytes", "1000000"]).head_bytes,
+            Some(1_000_000)
+        );
+        assert_eq!(parse(&["data.json"]).head_bytes, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_zero_head_bytes() {
+        assert!(parse_err(&["data.json", "--head-bytes", "0"])
+            .contains("--head-bytes must be at least 1."));
+    }
+
+    #[test]
+    fn test_parse_args_from_buffer_size() {
+        let args = parse(&["data.json", "--buffer-size", "65536"]);
+        assert_eq!(args.buffer_size, Some(65536));
+        assert!(!args.auto_buffer);
+        assert!(parse(&["data.json", "--auto-buffer"]).auto_buffer);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_huge_buffer_size() {
+        assert_eq!(
+            parse(&["data.json", "--buffer-size", "1073741824"]).buffer_size,
+            Some(MAX_BUFFER_SIZE)
+        );
+        assert!(parse_err(&["data.json", "--buffer-size", "1073741825"])
+            .contains("--buffer-size must be at most 1073741824 (1 GiB)."));
+        assert!(
+            parse_err(&["data.json", "--buffer-size", "18446744073709551615"])
+                .contains("--buffer-size must be at most")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_auto_buffer_and_buffer_size() {
+        assert!(
+            parse_err(&["data.json", "--auto-buffer", "--buffer-size", "4096"])
+                .contains("--auto-buffer cannot be used with --buffer-size or --fd.")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_max_depth() {
+        assert_eq!(
+            parse(&["data.json", "--max-depth", "64"]).max_depth,
+            Some(64)
+        );
+        assert_eq!(parse(&["data.json"]).max_depth, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_zero_max_depth() {
+        assert!(parse_err(&["data.json", "--max-depth", "0"])
+            .contains("--max-depth must be at least 1."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_max_depth_and_reverse() {
+        assert!(parse_err(&["data.jsonl", "--reverse", "--max-depth", "8"])
+            .contains("--max-depth cannot be used with --reverse or --check-jsonl."));
+    }
+
+    #[test]
+    fn test_parse_args_from_verify() {
+        assert!(parse(&["data.json", "--verify"]).verify);
+        assert!(!parse(&["data.json"]).verify);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_verify_and_concat() {
+        assert!(parse_err(&["data.json", "--verify", "--concat"])
+            .contains("--verify cannot be used with --reverse, --check-jsonl, --fd"));
+    }
+
+    #[test]
+    fn test_parse_args_from_diff() {
+        let args = parse(&["old.json", "--diff", "new.json"]);
+        assert_eq!(args.diff.as_deref(), Some("new.json"));
+        assert_eq!(parse(&["data.json"]).diff, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_diff_and_map_values() {
+        assert!(
+            parse_err(&["data.json", "--diff", "other.json", "--map-values"])
+                .contains("--diff cannot be used with --reverse, --check-jsonl")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_preserve_whitespace() {
+        assert!(parse(&["data.json", "--preserve-whitespace"]).preserve_whitespace);
+    }
+
+    #[test]
+    fn test_parse_args_from_minify() {
+        assert!(parse(&["data.json", "--minify"]).minify);
+        assert!(!parse(&["data.json"]).minify);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_minify_and_preserve_whitespace() {
+        assert!(
+            parse_err(&["data.json", "--minify", "--preserve-whitespace"])
+                .contains("--minify cannot be used with --preserve-whitespace")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_newline_in_values() {
+        let args = parse(&["data.json", "--messy", "--newline-in-values", "escape"]);
+        assert_eq!(args.newline_in_values, Some(NewlinePolicy::Escape));
+        assert_eq!(parse(&["data.json"]).newline_in_values, None);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_newline_in_values_without_messy() {
+        assert!(parse_err(&["data.json", "--newline-in-values", "preserve"])
+            .contains("--newline-in-values requires --messy"));
+    }
+
+    #[test]
+    fn test_parse_args_from_allow_control_chars() {
+        let args = parse(&["data.json", "--allow-control-chars"]);
+        assert!(args.allow_control_chars);
+        assert!(!args.escape_control_chars);
+        let args = parse(&[
+            "data.json",
+            "--allow-control-chars",
+            "--escape-control-chars",
+        ]);
+        assert!(args.escape_control_chars);
+        assert!(!parse(&["data.json"]).allow_control_chars);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_escape_control_chars_without_allow() {
+        assert!(parse_err(&["data.json", "--escape-control-chars"])
+            .contains("--escape-control-chars requires --allow-control-chars"));
+    }
+
+    #[test]
+    fn test_parse_args_from_index() {
+        assert_eq!(
+            parse(&["data.json", "--index", "out.jsonl.idx"]).index,
+            Some(String::from("out.jsonl.idx"))
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_normalize_unicode() {
+        let args = parse(&["data.json", "--normalize-unicode", "nfd"]);
+        assert_eq!(args.normalize_unicode, Some(NormalizationForm::Nfd));
+        assert!(!args.normalize_keys);
+
+        let args = parse(&[
+            "data.json",
+            "--normalize-keys",
+            "--normalize-unicode",
+            "nfc",
+        ]);
+        assert_eq!(args.normalize_unicode, Some(NormalizationForm::Nfc));
+        assert!(args.normalize_keys);
+    }
+
+    #[test]
+    fn test_parse_args_from_strict_utf8() {
+        assert!(parse(&["data.json", "--strict-utf8"]).strict_utf8);
+    }
+
+    #[test]
+    fn test_parse_args_from_check_jsonl() {
+        assert!(parse(&["data.jsonl", "--check-jsonl"]).check_jsonl);
+    }
+
+    #[test]
+    fn test_parse_args_from_strict() {
+        assert!(parse(&["data.json", "--strict"]).strict);
+    }
+
+    #[test]
+    fn test_parse_args_from_emit_errors_as_records() {
+        assert!(parse(&["data.json", "--emit-errors-as-records"]).emit_errors_as_records);
+        assert!(!parse(&["data.json"]).emit_errors_as_records);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_emit_errors_as_records_and_reverse() {
+        assert!(
+            parse_err(&["data.jsonl", "--emit-errors-as-records", "--reverse"])
+                .contains("--emit-errors-as-records cannot be used with --reverse")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_fields_required() {
+        let args = parse(&["data.json", "--fields-required", "id, name,,"]);
+        assert_eq!(args.fields_required, ["id", "name"]);
+        assert!(parse(&["data.json"]).fields_required.is_empty());
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_empty_fields_required() {
+        assert!(parse_err(&["data.json", "--fields-required", " , "])
+            .contains("--fields-required must name at least one key."));
+    }
+
+    #[test]
+    fn test_parse_args_from_replace_nan_inf() {
+        assert!(parse(&["data.json", "--replace-nan-inf"]).replace_nan_inf);
+    }
+
+    #[test]
+    fn test_parse_args_from_repair() {
+        let args = parse(&["data.json", "--repair"]);
+        assert_eq!(args.repairs(), Repairs::ALL);
+        assert!(args.replace_non_finite());
+
+        let args = parse(&["data.json", "--repair-missing-commas"]);
+        assert_eq!(
+            args.repairs(),
+            Repairs {
+                missing_commas: true,
+                ..Repairs::default()
+            }
+        );
+        assert!(!args.replace_non_finite());
+        assert!(
+            parse(&["data.json", "--single-quotes"])
+                .repairs()
+                .single_quotes
+        );
+        assert!(parse(&["data.json"]).repairs().is_empty());
+    }
+
+    #[test]
+    fn test_parse_args_from_json5() {
+        let args = parse(&["data.json5", "--messy", "--json5"]);
+        assert!(args.json5);
+        assert_eq!(
+            args.repairs(),
+            Repairs {
+                missing_commas: false,
+                ..Repairs::ALL
+            }
+        );
+        assert!(args.replace_non_finite());
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_json5_without_messy() {
+        assert!(parse_err(&["data.json5", "--json5"])
+            .contains("--json5 requires --messy, as comments can span lines."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_repair_and_check_jsonl() {
+        assert!(
+            parse_err(&["data.jsonl", "--repair-trailing-commas", "--check-jsonl"])
+                .contains("--repair cannot be used with --reverse or --check-jsonl.")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_map_values() {
+        let args = parse(&["data.json", "--map-values", "--inject-key", "_key"]);
+        assert!(args.map_values);
+        assert_eq!(args.inject_key, Some(String::from("_key")));
+    }
+
+    #[test]
+    fn test_parse_args_from_flatten() {
+        let args = parse(&["data.json", "--flatten"]);
+        assert!(args.flatten);
+        assert_eq!(args.flatten_separator(), ".");
+
+        let args = parse(&["data.json", "--flatten", "--flatten-separator", "__"]);
+        assert_eq!(args.flatten_separator(), "__");
+    }
+
+    #[test]
+    fn test_parse_args_from_retain_key_order() {
+        assert!(parse(&["data.json", "--retain-key-order"]).retain_key_order);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_retain_key_order_and_flatten() {
+        parse_err(&["data.json", "--retain-key-order", "--flatten"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_canonical() {
+        let args = parse(&["data.json", "--canonical", "--trim-strings"]);
+        assert!(args.canonical);
+        assert!(args.trim_strings);
+        assert!(!parse(&["data.json", "--canonical"]).trim_strings);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_trim_strings_without_canonical() {
+        assert!(parse_err(&["data.json", "--trim-strings"])
+            .contains("--trim-strings requires --canonical."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_retain_key_order_and_canonical() {
+        assert!(
+            parse_err(&["data.json", "--canonical", "--retain-key-order"])
+                .contains("--retain-key-order and --canonical cannot be used together")
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_max_open_files_alone() {
+        parse_err(&["data.json", "--max-open-files", "8"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_flatten_separator_alone() {
+        parse_err(&["data.json", "--flatten-separator", "__"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_inject_key_alone() {
+        parse_err(&["data.json", "--inject-key", "_key"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_normalize_keys_alone() {
+        parse_err(&["data.json", "--normalize-keys"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_index_and_partition_by() {
+        parse_err(&["data.json", "--partition-by", "lang", "--index", "out.idx"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_dash_reads_stdin() {
+        let args = parse(&["-", "--messy"]);
+        assert_eq!(args.fd, Some(0));
+        assert!(args.filepath.is_empty());
+        assert_eq!(args.input(), "-");
+        assert_eq!(parse(&["--fd", "3"]).input(), "/dev/fd/3");
+        assert_eq!(parse(&["data.json"]).input(), "data.json");
+    }
+
+    #[test]
+    fn test_parse_args_reads_stdin_without_an_input_when_piped() {
+        let args =
+            parse_args_with_stdin(["--messy"].map(OsString::from).into_iter(), true).unwrap();
+        assert_eq!(args.fd, Some(0));
+        let args =
+            parse_args_with_stdin(["data.json"].map(OsString::from).into_iter(), true).unwrap();
+        assert_eq!((args.filepath.as_str(), args.fd), ("data.json", None));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_unknown_arguments_and_missing_values() {
+        assert_eq!(
+            parse_err(&["data.json", "--help"]),
+            "Unknown argument: --help"
+        );
+        assert_eq!(
+            parse_err(&["data.json", "--output"]),
+            "--output requires a value."
+        );
+        assert_eq!(
+            parse_err(&["data.json", "--max-depth", "x"]),
+            "--max-depth requires a number."
+        );
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_without_an_input() {
+        assert!(parse_err(&["--messy"]).contains("No filepath provided."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_dash_and_fd() {
+        assert!(parse_err(&["-", "--fd", "3"])
+            .contains("Only one of a filepath, --input-list, --input-glob and --fd"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parse_args_from_fd() {
+        let args = parse(&["--fd", "3", "--messy"]);
+        assert_eq!(args.fd, Some(3));
+        assert_eq!(args.filepath, "");
+        assert!(args.is_messy);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parse_args_from_rejects_fd_and_root_pointer() {
+        assert!(parse_err(&["--fd", "3", "--root-pointer", "/items"])
+            .contains("--fd cannot be used with --start-offset, --root-pointer or --in-place."));
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_filepath_and_input_list() {
+        parse_err(&["data.json", "--input-list", "files.txt"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_pipe_and_partition_by() {
+        parse_err(&["data.json", "--partition-by", "lang", "--pipe", "cat"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_output() {
+        assert_eq!(parse(&["data.json"]).output(), "-");
+        let args = parse(&["data.json", "--output", "/dev/stderr"]);
+        assert_eq!(args.output(), "/dev/stderr");
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_output_and_pipe() {
+        parse_err(&["data.json", "--output", "out.jsonl", "--pipe", "cat"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_non_numeric_start_offset() {
+        parse_err(&["data.json", "--start-offset", "abc"]);
+    }
+
+    #[test]
+    fn test_parse_args_from_rejects_without_filepath() {
+        parse_err(&[]);
+    }
 
-    let filepath = args.next().expect("No filepath provided.");
-    let is_messy = if let Some(arg) = args.next() {
-        arg == "--messy"
-    } else {
-        false
-    };
+    #[test]
+    fn test_parse_args_from_rejects_when_option_missing_value() {
+        parse_err(&["data.json", "--pipe"]);
+    }
 
-    (filepath.into_string().unwrap(), is_messy)
+    #[test]
+    fn test_parse_args_from_rejects_unknown_argument() {
+        parse_err(&["data.json", "--nope"]);
+    }
 }
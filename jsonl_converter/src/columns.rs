@@ -0,0 +1,144 @@
+//! This module contains the analysis behind `--columns`, which tallies how
+//! many records each top level key appears in, to help design a schema for
+//! the records before loading them anywhere.
+//!
+//! # Summary
+//!
+//! The summary has a line for each key, with the key and the number of
+//! records it appears in separated by a tab, such as `id\t120`. Keys are
+//! sorted by that number, most common first, and then by the key. A key that
+//! appears more than once in a record is only counted once for it. Keys are
+//! written as they appear in the records, escape sequences and all.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{keys::top_level_keys, writer::LineEnding};
+
+/// Tallies the top level keys of the JSONL records written to it, passing
+/// nothing on until `finish` writes the summary to the wrapped writer.
+///
+/// # Fields
+///
+/// * `inner` - Where the summary is written.
+/// * `line_ending` - The separator written after each line of the summary.
+/// * `counts` - The number of records each key has appeared in.
+/// * `line` - The part of the current line written so far.
+pub struct ColumnsWriter<W: Write> {
+    inner: W,
+    line_ending: LineEnding,
+    counts: HashMap<String, usize>,
+    line: Vec<u8>,
+}
+
+impl<W: Write> ColumnsWriter<W> {
+    /// Creates a new `ColumnsWriter` that writes the summary to `inner`, with
+    /// each line ending with `line_ending`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::{columns::ColumnsWriter, writer::LineEnding};
+    ///
+    /// let mut writer = ColumnsWriter::new(Vec::new(), LineEnding::Lf);
+    /// writer.write_all(b"{\"id\":1,\"a\":2}\n{\"id\":3}\n").unwrap();
+    /// assert_eq!(writer.finish().unwrap(), b"id\t2\na\t1\n");
+    /// ```
+    pub fn new(inner: W, line_ending: LineEnding) -> Self {
+        ColumnsWriter {
+            inner,
+            line_ending,
+            counts: HashMap::new(),
+            line: Vec::new(),
+        }
+    }
+
+    /// Returns the keys seen so far and the number of records each has
+    /// appeared in, in the order of the summary.
+    pub fn columns(&self) -> Vec<(&str, usize)> {
+        let mut columns: Vec<(&str, usize)> = self
+            .counts
+            .iter()
+            .map(|(key, &count)| (key.as_str(), count))
+            .collect();
+        columns.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        columns
+    }
+
+    /// Tallies a final line without a line ending, writes the summary and
+    /// returns the wrapped writer.
+    ///
+    /// # Errors
+    ///
+    /// * If the summary cannot be written.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.tally_line();
+        let mut summary = String::new();
+        for (key, count) in self.columns() {
+            summary.push_str(&format!("{}\t{}{}", key, count, self.line_ending.as_str()));
+        }
+        self.inner.write_all(summary.as_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Adds the keys of the current line to the tally and clears it.
+    fn tally_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line);
+        let mut keys = top_level_keys(&line);
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            match self.counts.get_mut(key) {
+                Some(count) => *count += 1,
+                None => {
+                    self.counts.insert(key.to_string(), 1);
+                }
+            }
+        }
+        self.line.clear();
+    }
+}
+
+impl<W: Write> Write for ColumnsWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for part in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(part);
+            if part.ends_with(b"\n") {
+                self.tally_line();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Does nothing, as nothing is written until `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_each_key_once_per_record() {
+        let mut writer = ColumnsWriter::new(Vec::new(), LineEnding::Lf);
+        writer
+            .write_all(b"{\"a\": 1, \"a\": 2, \"b\": {\"a\": 3}}\n[1]\n{\"b\"")
+            .unwrap();
+        writer.write_all(b": \"a\", \"c\": null}").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"b\t2\na\t1\nc\t1\n");
+    }
+
+    #[test]
+    fn test_summary_uses_the_line_ending() {
+        let mut writer = ColumnsWriter::new(Vec::new(), LineEnding::Crlf);
+        writer.write_all(b"{\"z\": 1, \"y\": 2}\r\n").unwrap();
+        assert_eq!(writer.columns(), [("y", 1), ("z", 1)]);
+        assert_eq!(writer.finish().unwrap(), b"y\t1\r\nz\t1\r\n");
+    }
+}
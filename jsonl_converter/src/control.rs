@@ -0,0 +1,94 @@
+//! This module contains the handling of raw control characters inside
+//! strings, which JSON requires to be escaped but some producers write as
+//! they are, such as a literal tab in a value.
+//!
+//! By default, a control character inside a string stops the conversion at
+//! its offset in the input. With `--allow-control-chars` it is passed
+//! through, and with `--escape-control-chars` as well it is escaped so that
+//! the record is valid JSON. Line breaks are not covered, as
+//! `--newline-in-values` decides how they are handled.
+
+use std::borrow::Cow;
+
+/// Checks if `c` is a control character that must be escaped inside a JSON
+/// string, other than a line break.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::control::is_control_char;
+///
+/// assert!(is_control_char('\t'));
+/// assert!(is_control_char('\u{1f}'));
+/// assert!(!is_control_char('\n'));
+/// assert!(!is_control_char(' '));
+/// ```
+pub fn is_control_char(c: char) -> bool {
+    c < ' ' && c != '\n' && c != '\r'
+}
+
+/// Returns `record` with each control character inside its strings escaped,
+/// as `\b`, `\f` or `\t`, or otherwise as `\u001f` and so on. Control
+/// characters outside of strings and line breaks are left as they are.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::control::escape_control_chars;
+///
+/// assert_eq!(
+///     escape_control_chars("{\"a\":\t\"b\tc\u{1}\"}"),
+///     "{\"a\":\t\"b\\tc\\u0001\"}"
+/// );
+/// ```
+pub fn escape_control_chars(record: &str) -> Cow<'_, str> {
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut inside_string = false;
+    let mut last_char_escape = false;
+    for (i, c) in record.char_indices() {
+        if c == '"' && !last_char_escape {
+            inside_string = !inside_string;
+        } else if inside_string && is_control_char(c) {
+            result.push_str(&record[copied..i]);
+            push_escaped(&mut result, c);
+            copied = i + 1;
+        }
+        last_char_escape = inside_string && c == '\\' && !last_char_escape;
+    }
+
+    if copied == 0 {
+        return Cow::Borrowed(record);
+    }
+    result.push_str(&record[copied..]);
+    Cow::Owned(result)
+}
+
+/// Pushes the JSON escape of the control character `c` to `result`.
+fn push_escaped(result: &mut String, c: char) {
+    match c {
+        '\u{8}' => result.push_str("\\b"),
+        '\u{c}' => result.push_str("\\f"),
+        '\t' => result.push_str("\\t"),
+        _ => result.push_str(&format!("\\u{:04x}", c as u32)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_without_control_chars_are_borrowed() {
+        let record = "{\"a\": \"b\\tc\"}";
+        assert!(matches!(escape_control_chars(record), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_only_control_chars_inside_strings_are_escaped() {
+        assert_eq!(
+            escape_control_chars("{\t\"a\u{8}\": \"\\\"\u{c}\u{0}\",\n\"b\": \"x\ny\"}"),
+            "{\t\"a\\b\": \"\\\"\\f\\u0000\",\n\"b\": \"x\ny\"}"
+        );
+    }
+}
@@ -0,0 +1,625 @@
+//! This module contains the `Converter`, the core that every conversion runs
+//! through: the CLI, `convert_with_sink` and the record iterators alike. It
+//! reads the root bracket, sets up a processor from the `ConverterOptions`
+//! and feeds it the input a character or a line at a time, so that an option
+//! behaves the same whichever entry point it is given to.
+//!
+//! ```
+//! use jsonl_converter::{
+//!     converter::{Converter, ConverterOptions},
+//!     sink::CountingSink,
+//! };
+//!
+//! let options = ConverterOptions {
+//!     lines: true,
+//!     ..ConverterOptions::default()
+//! };
+//! let mut converter = Converter::new("[\n  1,\n  2\n]\n".as_bytes(), CountingSink::new(), options);
+//! converter.run().unwrap();
+//! assert_eq!(converter.count(), 2);
+//! ```
+
+use std::io::BufRead;
+
+use crate::{
+    errors::{guarded, ConversionError},
+    json5::read_root,
+    json_object::{CleanupPolicy, NewlinePolicy},
+    jsonp::read_padded_root,
+    processors::{byte_processor::ByteProcessor, line_processor::LineProcessor},
+    readers::{byte_iter::ByteIterator, line_iter::LineIterator, utils::verify_first_char},
+    sink::EmitSink,
+};
+
+/// Options for how the input is read and how records are cleaned up. The
+/// defaults convert a JSON array that may be formatted in any way.
+///
+/// # Fields
+///
+/// * `cleanup_policy` - How the whitespace of each record is cleaned up.
+/// * `newline_policy` - If set, how line breaks within strings are handled,
+///   rather than by the `cleanup_policy`. Only used when not reading `lines`.
+/// * `strict_utf8` - Whether invalid UTF-8 in the input is an error rather
+///   than being replaced with `U+FFFD`.
+/// * `concat` - Whether arrays following the root array, as in `[...][...]`,
+///   are converted too rather than being an error.
+/// * `lines` - Whether the input is read a line at a time, as without
+///   `--messy`, rather than a character at a time. Each element must then
+///   end at the end of a line or where the next element on it starts.
+/// * `max_depth` - If set, the deepest that brackets may be nested.
+/// * `max_bytes` - If set, how many bytes of the input are read at most.
+/// * `max_line_bytes` - If set, how long a line may be when reading `lines`.
+/// * `start_offset` - If set, the offset in the file that the reader has
+///   already been moved to, just inside the root bracket, which is then not
+///   read.
+/// * `map_values` - Whether the root must be an object, whose members are
+///   converted rather than the object being one record.
+/// * `json5` - Whether comments are allowed, before the root and between
+///   elements. Only used when not reading `lines`.
+/// * `strip_jsonp` - Whether the root may be wrapped in a JSONP callback,
+///   as in `callback([...]);`.
+/// * `single_quotes` - Whether strings may be quoted with `'`.
+/// * `allow_control_chars` - Whether raw control characters are allowed in
+///   strings.
+/// * `stop_at_root_end` - Whether reading stops once the root is closed,
+///   ignoring anything after it.
+/// * `recover` - Whether a malformed element is sent to the sink as an
+///   error, and the input is resynced at the next element, rather than
+///   stopping the conversion. Input cut short then ends the conversion
+///   without an error, other than for the element that was cut short.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConverterOptions {
+    pub cleanup_policy: CleanupPolicy,
+    pub newline_policy: Option<NewlinePolicy>,
+    pub strict_utf8: bool,
+    pub concat: bool,
+    pub lines: bool,
+    pub max_depth: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_line_bytes: Option<u64>,
+    pub start_offset: Option<u64>,
+    pub map_values: bool,
+    pub json5: bool,
+    pub strip_jsonp: bool,
+    pub single_quotes: bool,
+    pub allow_control_chars: bool,
+    pub stop_at_root_end: bool,
+    pub recover: bool,
+}
+
+/// The reader and processor for the mode that the input is read in.
+enum Engine<R: BufRead, S: EmitSink> {
+    Bytes(ByteIterator<R>, ByteProcessor<S>),
+    Lines(LineIterator<R>, LineProcessor<S>),
+}
+
+/// Converts a JSON array read from a `BufRead` to JSONL, sending each record
+/// to an `EmitSink`. The conversion can be run to the end with `run`, or a
+/// step at a time with `step`, as the iterators do to yield records lazily.
+///
+/// # Fields
+///
+/// * `engine` - The reader and processor.
+/// * `options` - How the input is read.
+/// * `started` - Whether the root bracket has been read.
+/// * `done` - Whether the input has ended or an error has been returned.
+/// * `single_value` - Whether the root is an object that is written whole as
+///   the only record.
+pub struct Converter<R: BufRead, S: EmitSink> {
+    engine: Engine<R, S>,
+    options: ConverterOptions,
+    started: bool,
+    done: bool,
+    single_value: bool,
+}
+
+impl<R: BufRead, S: EmitSink> Converter<R, S> {
+    /// Creates a new `Converter` that reads from `reader` and sends records
+    /// to `sink`. Nothing is read until the first `step`.
+    pub fn new(reader: R, sink: S, options: ConverterOptions) -> Self {
+        let engine = if options.lines {
+            let mut line_iter = LineIterator::from_reader(reader);
+            line_iter.set_strict_utf8(options.strict_utf8);
+            line_iter.set_max_bytes(options.max_bytes);
+            line_iter.set_max_line_bytes(options.max_line_bytes);
+            if let Some(offset) = options.start_offset {
+                line_iter.set_start_offset(offset);
+            }
+            let mut processor = LineProcessor::with_sink(sink);
+            processor.set_cleanup_policy(options.cleanup_policy);
+            processor.set_single_quotes(options.single_quotes);
+            processor.set_allow_control_chars(options.allow_control_chars);
+            processor.set_concat(options.concat);
+            processor.set_stop_at_root_end(options.stop_at_root_end);
+            processor.set_max_depth(options.max_depth);
+            processor.set_recover(options.recover);
+            Engine::Lines(line_iter, processor)
+        } else {
+            let mut bytes_iter = ByteIterator::from_reader(reader);
+            bytes_iter.set_strict_utf8(options.strict_utf8);
+            bytes_iter.set_max_bytes(options.max_bytes);
+            if let Some(offset) = options.start_offset {
+                bytes_iter.set_start_offset(offset);
+            }
+            let mut processor = ByteProcessor::with_sink(sink);
+            processor.set_cleanup_policy(options.cleanup_policy);
+            processor.set_newline_policy(options.newline_policy);
+            processor.set_comments(options.json5);
+            processor.set_single_quotes(options.single_quotes);
+            processor.set_allow_control_chars(options.allow_control_chars);
+            processor.set_concat(options.concat);
+            processor.set_stop_at_root_end(options.stop_at_root_end);
+            processor.set_max_depth(options.max_depth);
+            processor.set_recover(options.recover);
+            Engine::Bytes(bytes_iter, processor)
+        };
+        Converter {
+            engine,
+            options,
+            started: false,
+            done: false,
+            single_value: false,
+        }
+    }
+
+    /// Converts the rest of the input. The sink is not finished, so that the
+    /// caller can still inspect it.
+    ///
+    /// # Errors
+    ///
+    /// * As for `step`.
+    pub fn run(&mut self) -> Result<(), ConversionError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Reads the root bracket on the first call, and the next character or
+    /// line of the input on each call after it, sending any record that it
+    /// completes to the sink.
+    ///
+    /// # Returns
+    ///
+    /// * Whether there is more of the input to convert. Once this is false,
+    ///   or an error has been returned, every later call returns false.
+    ///
+    /// # Errors
+    ///
+    /// * If the input is empty, cannot be read or is malformed.
+    /// * If the first character of the input that is not whitespace is not a
+    ///   `[` or a `{`, or not a `{` with `map_values`, as a
+    ///   `ConversionError::InvalidStart`.
+    /// * If the input ends before the root is closed, or has content after
+    ///   it.
+    /// * If the sink does not accept a record.
+    /// * If the conversion panics, which is caught and returned as
+    ///   `ConversionError::Internal`. The panic hook still runs.
+    pub fn step(&mut self) -> Result<bool, ConversionError> {
+        if self.done {
+            return Ok(false);
+        }
+        let result = guarded(|| {
+            if self.started {
+                self.advance()
+            } else {
+                self.started = true;
+                self.start().map(|_| true)
+            }
+        });
+        if !matches!(result, Ok(true)) {
+            self.done = true;
+        }
+        result
+    }
+
+    /// Returns the number of records completed so far.
+    pub fn count(&self) -> usize {
+        match &self.engine {
+            Engine::Bytes(_, processor) => processor.count(),
+            Engine::Lines(_, processor) => processor.count(),
+        }
+    }
+
+    /// Returns the deepest that brackets have been nested so far.
+    pub fn max_depth(&self) -> usize {
+        match &self.engine {
+            Engine::Bytes(_, processor) => processor.max_depth(),
+            Engine::Lines(_, processor) => processor.max_depth(),
+        }
+    }
+
+    /// Returns the number of bytes of the input read so far.
+    pub fn bytes_read(&self) -> u64 {
+        match &self.engine {
+            Engine::Bytes(bytes_iter, _) => bytes_iter.bytes_read(),
+            Engine::Lines(line_iter, _) => line_iter.bytes_read(),
+        }
+    }
+
+    /// Returns whether the root is an object that is written whole as the
+    /// only record, which is known once the root bracket has been read.
+    pub fn is_single_value(&self) -> bool {
+        self.single_value
+    }
+
+    /// Returns the sink that records are sent to.
+    pub fn sink_mut(&mut self) -> &mut S {
+        match &mut self.engine {
+            Engine::Bytes(_, processor) => processor.sink_mut(),
+            Engine::Lines(_, processor) => processor.sink_mut(),
+        }
+    }
+
+    /// Consumes the converter, returning the sink that records were sent to.
+    pub fn into_sink(self) -> S {
+        match self.engine {
+            Engine::Bytes(_, processor) => processor.into_sink(),
+            Engine::Lines(_, processor) => processor.into_sink(),
+        }
+    }
+
+    /// Reads the root bracket and starts the processor at it. When resuming
+    /// from an offset, the root bracket has already been passed, so a
+    /// synthetic one is used instead.
+    fn start(&mut self) -> Result<(), ConversionError> {
+        if self.options.start_offset.is_some() {
+            let root = if self.options.map_values { '{' } else { '[' };
+            return match &mut self.engine {
+                Engine::Bytes(_, processor) => processor.push_bracket(&root),
+                Engine::Lines(_, processor) => processor.push_bracket(&root),
+            };
+        }
+        match self.engine {
+            Engine::Bytes(..) => self.start_bytes(),
+            Engine::Lines(..) => self.start_lines(),
+        }
+    }
+
+    /// Reads the root bracket a character at a time, skipping whitespace,
+    /// comments with `json5` and the callback with `strip_jsonp`.
+    fn start_bytes(&mut self) -> Result<(), ConversionError> {
+        let Engine::Bytes(bytes_iter, _) = &mut self.engine else {
+            unreachable!("start_bytes is only called when reading bytes");
+        };
+        let mut padded = false;
+        let root = if self.options.json5 {
+            read_root(|| Ok(bytes_iter.read_char().transpose()?))?
+        } else if self.options.strip_jsonp {
+            let root = read_padded_root(|| Ok(bytes_iter.read_char().transpose()?))?;
+            padded = root.is_some_and(|(_, padded)| padded);
+            root.map(|(root, _)| root)
+        } else {
+            bytes_iter.next_non_whitespace_char()?
+        };
+        let root = root.ok_or(ConversionError::EmptyInput)?;
+        self.single_value = check_root(root, self.options.map_values)?;
+
+        let Engine::Bytes(bytes_iter, processor) = &mut self.engine else {
+            unreachable!("start_bytes is only called when reading bytes");
+        };
+        processor.set_jsonp(padded);
+        if !self.single_value {
+            return processor.push_bracket(&root);
+        }
+        // The opening brace has already been read, and starts the record.
+        processor.start_single_value();
+        processor.set_position(bytes_iter.offset() - 1);
+        processor.process_char(&root)
+    }
+
+    /// Reads the root bracket from the first line that is not blank,
+    /// skipping whitespace and the callback with `strip_jsonp`, and processes
+    /// the rest of that line.
+    fn start_lines(&mut self) -> Result<(), ConversionError> {
+        let map_values = self.options.map_values;
+        let strip_jsonp = self.options.strip_jsonp;
+        let Engine::Lines(line_iter, processor) = &mut self.engine else {
+            unreachable!("start_lines is only called when reading lines");
+        };
+        loop {
+            let line_start = line_iter.offset();
+            let Some(line) = line_iter.try_next_line_ref()? else {
+                return Err(ConversionError::EmptyInput);
+            };
+            let mut chars = line.chars();
+            let mut padded = false;
+            let root = if strip_jsonp {
+                let root = read_padded_root(|| Ok(chars.next()))?;
+                padded = root.is_some_and(|(_, padded)| padded);
+                root.map(|(root, _)| root)
+            } else {
+                chars.find(|c| !c.is_whitespace())
+            };
+            let Some(root) = root else {
+                continue;
+            };
+            let rest = chars.as_str();
+            let root_index = line.len() - rest.len() - root.len_utf8();
+            self.single_value = check_root(root, map_values)?;
+
+            processor.set_jsonp(padded);
+            if self.single_value {
+                // The opening brace starts the record.
+                processor.start_single_value();
+                processor.set_position(line_start + root_index as u64);
+                return processor.process_line(&line[root_index..]);
+            }
+            processor.push_bracket(&root)?;
+            processor.set_position(line_start + (root_index + root.len_utf8()) as u64);
+            return processor.process_line(rest);
+        }
+    }
+
+    /// Processes the next character or line of the input, returning whether
+    /// there may be more to convert. Once the input ends, or the processor is
+    /// done, checks that the root and any JSONP callback were closed.
+    fn advance(&mut self) -> Result<bool, ConversionError> {
+        let more = match &mut self.engine {
+            Engine::Bytes(bytes_iter, processor) => {
+                // Keep the processor in step with the reader, as invalid
+                // bytes are replaced with a character that is longer than the
+                // bytes themselves.
+                processor.set_position(bytes_iter.offset());
+                if processor.is_done() {
+                    false
+                } else if let Some(c) = bytes_iter.read_char() {
+                    processor.process_char(&c?)?;
+                    true
+                } else {
+                    false
+                }
+            }
+            Engine::Lines(line_iter, processor) => {
+                processor.set_position(line_iter.offset());
+                if processor.is_done() {
+                    false
+                } else if let Some(line) = line_iter.try_next_line_ref()? {
+                    processor.process_line(line)?;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        if !more {
+            self.check_closed()?;
+        }
+        Ok(more)
+    }
+
+    /// Checks that the root and any JSONP callback around it were closed,
+    /// once the element cut short, if any, has been sent as an error when
+    /// recovering from malformed elements.
+    fn check_closed(&mut self) -> Result<(), ConversionError> {
+        match &mut self.engine {
+            Engine::Bytes(_, processor) => {
+                processor.close_truncated_root()?;
+                processor.check_root_closed()?;
+                processor.check_jsonp_closed()
+            }
+            Engine::Lines(_, processor) => {
+                processor.close_truncated_root()?;
+                processor.check_root_closed()?;
+                processor.check_jsonp_closed()
+            }
+        }
+    }
+}
+
+/// Checks that `root` can be converted, returning whether it is an object
+/// that is written whole as the only record, as it is without `map_values`.
+///
+/// # Errors
+///
+/// * If `root` is not a `[` or a `{`, or not a `{` with `map_values`.
+fn check_root(root: char, map_values: bool) -> Result<bool, ConversionError> {
+    if !map_values {
+        verify_first_char(&root)?;
+    } else if root != '{' {
+        return Err(ConversionError::InvalidStart {
+            found: root,
+            map_values: true,
+        });
+    }
+    Ok(root == '{' && !map_values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::{CallbackSink, CountingSink};
+
+    fn count(input: &str, options: ConverterOptions) -> Result<usize, ConversionError> {
+        let mut converter = Converter::new(input.as_bytes(), CountingSink::new(), options);
+        converter.run()?;
+        Ok(converter.count())
+    }
+
+    fn records(input: &str, options: ConverterOptions) -> Result<Vec<String>, ConversionError> {
+        let mut records = Vec::new();
+        let sink = CallbackSink::new(|record: &str| {
+            records.push(record.to_string());
+            Ok(())
+        });
+        Converter::new(input.as_bytes(), sink, options).run()?;
+        Ok(records)
+    }
+
+    fn both_modes(options: ConverterOptions) -> [ConverterOptions; 2] {
+        [
+            ConverterOptions {
+                lines: false,
+                ..options
+            },
+            ConverterOptions {
+                lines: true,
+                ..options
+            },
+        ]
+    }
+
+    #[test]
+    fn test_converts_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            assert_eq!(count("\n  [\n  1,\n  [2]\n]\n", options).unwrap(), 2);
+            assert_eq!(count("[]", options).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_arrays_on_one_line_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            for input in ["[ {\"a\":1} ]", "[{\"a\":1}\t]\n", "[ {\"a\":1},  ]"] {
+                assert_eq!(
+                    records(input, options).unwrap(),
+                    ["{\"a\":1}"],
+                    "{:?}",
+                    input
+                );
+            }
+            for input in ["[ ]", "[\n]", "[]\n", "[\r\n]\r\n"] {
+                assert!(records(input, options).unwrap().is_empty(), "{:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_elements_on_the_line_of_the_root_bracket_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            assert_eq!(
+                records("[{\"a\": 1},\n{\"b\": 2}]\n", options).unwrap(),
+                ["{\"a\": 1}", "{\"b\": 2}"]
+            );
+            for (input, unexpected) in [("[]]", ']'), ("[ ]\n{\"a\": 1}", '{')] {
+                assert!(
+                    matches!(
+                        records(input, options),
+                        Err(ConversionError::TrailingContent(c)) if c == unexpected
+                    ),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_object_is_a_single_value_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            let mut converter =
+                Converter::new("\n {\"a\": 1}\n".as_bytes(), CountingSink::new(), options);
+            converter.run().unwrap();
+            assert!(converter.is_single_value());
+            assert_eq!(converter.count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_invalid_start_is_an_error_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            assert!(matches!(
+                count("\n42\n", options),
+                Err(ConversionError::InvalidStart {
+                    found: '4',
+                    map_values: false
+                })
+            ));
+            let map_values = ConverterOptions {
+                map_values: true,
+                ..options
+            };
+            assert!(matches!(
+                count("[1]", map_values),
+                Err(ConversionError::InvalidStart {
+                    found: '[',
+                    map_values: true
+                })
+            ));
+            assert_eq!(count("{\n\"a\": 1,\n\"b\": 2\n}", map_values).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn test_empty_input_is_an_error_in_both_modes() {
+        for options in both_modes(ConverterOptions::default()) {
+            assert!(matches!(
+                count(" \n\n", options),
+                Err(ConversionError::EmptyInput)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_error_before_the_root_is_not_empty_input_in_both_modes() {
+        let options = ConverterOptions {
+            strict_utf8: true,
+            ..ConverterOptions::default()
+        };
+        for options in both_modes(options) {
+            let mut converter = Converter::new(&b" \xff[1]"[..], CountingSink::new(), options);
+            assert!(matches!(converter.run(), Err(ConversionError::Io(_))));
+        }
+    }
+
+    #[test]
+    fn test_unclosed_root_and_callback_are_errors_in_both_modes() {
+        let jsonp = ConverterOptions {
+            strip_jsonp: true,
+            ..ConverterOptions::default()
+        };
+        for options in both_modes(jsonp) {
+            assert_eq!(count("cb([1,\n2]);\n", options).unwrap(), 2);
+            assert!(matches!(
+                count("[1,\n2\n", options),
+                Err(ConversionError::UnexpectedEof)
+            ));
+            assert!(count("cb([1,\n2]\n", options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_stop_at_root_end_ignores_the_rest_in_both_modes() {
+        let options = ConverterOptions {
+            stop_at_root_end: true,
+            ..ConverterOptions::default()
+        };
+        for options in both_modes(options) {
+            assert_eq!(count("[1,\n2]\n, not json", options).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn test_start_offset_uses_a_synthetic_root() {
+        let input = "{\"data\": [1,\n2\n]}";
+        for options in both_modes(ConverterOptions::default()) {
+            let options = ConverterOptions {
+                start_offset: Some(10),
+                stop_at_root_end: true,
+                ..options
+            };
+            let mut converter =
+                Converter::new(&input.as_bytes()[10..], CountingSink::new(), options);
+            converter.run().unwrap();
+            assert_eq!(converter.count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_step_stops_after_an_error() {
+        let mut converter = Converter::new(
+            "42".as_bytes(),
+            CountingSink::new(),
+            ConverterOptions::default(),
+        );
+        assert!(converter.step().is_err());
+        assert!(!converter.step().unwrap());
+    }
+
+    #[test]
+    fn test_json5_comments_before_the_root() {
+        let options = ConverterOptions {
+            json5: true,
+            ..ConverterOptions::default()
+        };
+        assert_eq!(count("// config\n[1, /* two */ 2]", options).unwrap(), 2);
+    }
+}
@@ -0,0 +1,251 @@
+//! This module contains `Decimal`, the exact value of a JSON number, so that
+//! numbers can be compared and rewritten without being rounded to a 64-bit
+//! float, which holds integers exactly only up to 2^53 and turns numbers of
+//! `1e309` and above into infinity.
+
+use std::{cmp::Ordering, fmt};
+
+use crate::strict::is_json_number;
+
+/// The number of digits up to which integers are written without an
+/// exponent, as in JavaScript.
+const MAX_PLAIN_DIGITS: i128 = 21;
+
+/// The exact value of a JSON number.
+///
+/// # Fields
+///
+/// * `negative` - Whether the number is below zero. Zero, however it is
+///   written, is not negative.
+/// * `digits` - The significant digits of the number, without leading or
+///   trailing zeros, which are empty for zero.
+/// * `point` - Where the decimal point falls, counting from the first of
+///   `digits`, so the value is `0.<digits>` times ten to the power of
+///   `point`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal {
+    negative: bool,
+    digits: String,
+    point: i128,
+}
+
+impl Decimal {
+    /// Parses a JSON number.
+    ///
+    /// Returns `None` if `number` is not a JSON number, or if its exponent
+    /// does not fit a 64-bit integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::decimal::Decimal;
+    ///
+    /// let a = Decimal::parse("9007199254740993").unwrap();
+    /// let b = Decimal::parse("9007199254740992").unwrap();
+    /// assert!(a > b);
+    /// assert_eq!(Decimal::parse("2.50e1"), Decimal::parse("25"));
+    /// assert!(Decimal::parse("+1").is_none());
+    /// ```
+    pub fn parse(number: &str) -> Option<Decimal> {
+        if !is_json_number(number) {
+            return None;
+        }
+        let (negative, unsigned) = match number.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, number),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(e) => (&unsigned[..e], &unsigned[e + 1..]),
+            None => (unsigned, "0"),
+        };
+        let exponent: i64 = exponent
+            .strip_prefix('+')
+            .unwrap_or(exponent)
+            .parse()
+            .ok()?;
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        let digits = format!("{}{}", integer, fraction);
+        let leading = digits.len() - digits.trim_start_matches('0').len();
+        let digits = digits.trim_matches('0');
+        if digits.is_empty() {
+            return Some(Decimal {
+                negative: false,
+                digits: String::new(),
+                point: 0,
+            });
+        }
+        let point = i128::from(exponent) + integer.len() as i128 - leading as i128;
+        Some(Decimal {
+            negative,
+            digits: digits.to_string(),
+            point,
+        })
+    }
+
+    /// Returns -1, 0 or 1 as the number is below, equal to or above zero.
+    fn signum(&self) -> i8 {
+        match (self.digits.is_empty(), self.negative) {
+            (true, _) => 0,
+            (false, true) => -1,
+            (false, false) => 1,
+        }
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_sign = self.signum().cmp(&other.signum());
+        if by_sign != Ordering::Equal || self.signum() == 0 {
+            return by_sign;
+        }
+        // Neither has leading zeros, so the one whose point falls later is
+        // the larger, and if they fall in the same place, the digits decide.
+        let by_magnitude = self
+            .point
+            .cmp(&other.point)
+            .then_with(|| self.digits.cmp(&other.digits));
+        if self.negative {
+            by_magnitude.reverse()
+        } else {
+            by_magnitude
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Writes the number in the form that JavaScript, and so RFC 8785,
+    /// writes numbers in, with every digit kept: integers of up to 21 digits
+    /// without an exponent, numbers below `1e-6` or of `1e21` and above with
+    /// one, and other numbers with a decimal point.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::decimal::Decimal;
+    ///
+    /// let written: Vec<String> = ["1.0", "-2.50", "1e20", "1E21", "0.0000015"]
+    ///     .iter()
+    ///     .map(|number| Decimal::parse(number).unwrap().to_string())
+    ///     .collect();
+    /// assert_eq!(written, ["1", "-2.5", "100000000000000000000", "1e+21", "0.0000015"]);
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.digits.is_empty() {
+            return f.write_str("0");
+        }
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let digits = self.digits.as_str();
+        let count = digits.len() as i128;
+        let point = self.point;
+        if count <= point && point <= MAX_PLAIN_DIGITS {
+            write!(f, "{}{}", digits, "0".repeat((point - count) as usize))
+        } else if 0 < point && point <= MAX_PLAIN_DIGITS {
+            let (whole, part) = digits.split_at(point as usize);
+            write!(f, "{}.{}", whole, part)
+        } else if -6 < point && point <= 0 {
+            write!(f, "0.{}{}", "0".repeat((-point) as usize), digits)
+        } else {
+            let (first, rest) = digits.split_at(1);
+            f.write_str(first)?;
+            if !rest.is_empty() {
+                write!(f, ".{}", rest)?;
+            }
+            let exponent = point - 1;
+            let sign = if exponent < 0 { "-" } else { "+" };
+            write!(f, "e{}{}", sign, exponent.abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(number: &str) -> Decimal {
+        Decimal::parse(number).unwrap()
+    }
+
+    #[test]
+    fn test_equal_values_are_equal_however_written() {
+        for (a, b) in [
+            ("1", "1.0"),
+            ("1", "10e-1"),
+            ("0", "-0.0e5"),
+            ("120", "1.2E2"),
+            ("-0.05", "-5e-2"),
+        ] {
+            assert_eq!(decimal(a), decimal(b), "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_order_is_exact() {
+        let ordered = [
+            "-1e400",
+            "-18446744073709551617",
+            "-1.5",
+            "-1e-400",
+            "0",
+            "1e-400",
+            "0.1",
+            "0.10000000000000000000001",
+            "1",
+            "9007199254740992",
+            "9007199254740993",
+            "18446744073709551616",
+            "1e400",
+            "1.0000001e400",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(decimal(pair[0]) < decimal(pair[1]), "{:?}", pair);
+            assert!(decimal(pair[1]) > decimal(pair[0]), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            ("0.0", "0"),
+            ("-0", "0"),
+            ("123.456e1", "1234.56"),
+            ("12e-1", "1.2"),
+            ("0.0120", "0.012"),
+            ("0.000001", "0.000001"),
+            ("1.5e-7", "1.5e-7"),
+            (
+                "123456789012345678901234567890",
+                "1.2345678901234567890123456789e+29",
+            ),
+            ("-2.50e-400", "-2.5e-400"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(decimal(number).to_string(), expected, "{}", number);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_what_is_not_a_json_number() {
+        for number in [
+            "",
+            "+1",
+            ".5",
+            "1.",
+            "01",
+            "0x10",
+            "NaN",
+            "1e",
+            "1e99999999999999999999",
+        ] {
+            assert!(Decimal::parse(number).is_none(), "{}", number);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! This module contains the suppression of repeated records for
+//! `--dedupe-window`, which drops a record when an identical one was written
+//! shortly before it.
+//!
+//! Only the hashes of the most recent records are kept, so memory use is
+//! bounded by the size of the window rather than the size of the input. The
+//! window grows as records are written, so a window far larger than the
+//! input costs no more than the input.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+/// Remembers the hashes of the last few records written.
+///
+/// # Fields
+///
+/// * `capacity` - The number of records remembered.
+/// * `order` - The hashes of the remembered records, oldest first.
+/// * `seen` - The same hashes, for quick lookup.
+#[derive(Debug, Clone)]
+pub struct DedupeWindow {
+    capacity: usize,
+    order: VecDeque<u64>,
+    seen: HashSet<u64>,
+}
+
+impl DedupeWindow {
+    /// Creates a new `DedupeWindow` that remembers the last `capacity`
+    /// records. Nothing is allocated until records are inserted.
+    pub fn new(capacity: usize) -> Self {
+        DedupeWindow {
+            capacity,
+            order: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Records `record` as written, returning `false` instead if an identical
+    /// record is still within the window. Duplicates are not remembered
+    /// again, so the window only moves on when a new record is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record about to be written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::dedupe::DedupeWindow;
+    ///
+    /// let mut window = DedupeWindow::new(1);
+    /// assert!(window.insert("{\"a\":1}"));
+    /// assert!(!window.insert("{\"a\":1}"));
+    /// assert!(window.insert("{\"a\":2}"));
+    /// assert!(window.insert("{\"a\":1}"));
+    /// ```
+    pub fn insert(&mut self, record: &str) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        let hash = hash(record);
+        if !self.seen.insert(hash) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(hash);
+        true
+    }
+}
+
+fn hash(record: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    record.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kept(window: usize, records: &[&str]) -> Vec<String> {
+        let mut window = DedupeWindow::new(window);
+        records
+            .iter()
+            .filter(|record| window.insert(record))
+            .map(|record| record.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicates_within_the_window_are_dropped() {
+        assert_eq!(kept(3, &["a", "b", "a", "c", "b"]), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_duplicates_beyond_the_window_are_kept() {
+        assert_eq!(kept(2, &["a", "b", "c", "a"]), ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_a_run_of_duplicates_does_not_move_the_window() {
+        assert_eq!(kept(2, &["a", "b", "b", "b", "a"]), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_a_huge_window_is_not_allocated_up_front() {
+        let window = DedupeWindow::new(usize::MAX);
+        assert_eq!(window.order.capacity(), 0);
+        assert_eq!(window.seen.capacity(), 0);
+        assert_eq!(kept(usize::MAX, &["a", "b", "a"]), ["a", "b"]);
+    }
+}
@@ -0,0 +1,296 @@
+//! This module contains the comparison behind `--diff`, which streams the
+//! elements of two JSON arrays side by side and reports those that differ,
+//! for regression testing data exports.
+//!
+//! Elements are compared by index: the first element of one array with the
+//! first of the other, and so on. Each is compared in canonical form, as
+//! with `--canonical`, so differences in whitespace, key order, escapes and
+//! how numbers are written are not reported. Only one element of each array
+//! is held at a time, so arrays of any size can be compared.
+//!
+//! # Differences
+//!
+//! Each difference is written as a JSON object on a line of its own, with
+//! the elements in canonical form:
+//!
+//! * `{"index":1,"change":"modified","old":{...},"new":{...}}` when the
+//!   elements at an index differ.
+//! * `{"index":5,"change":"removed","old":{...}}` for each element past the
+//!   end of the other array, when the input is longer.
+//! * `{"index":5,"change":"added","new":{...}}` for each element past the
+//!   end of the input, when the other array is longer.
+//!
+//! As elements are only compared by index, inserting an element near the
+//! start of an array reports every element after it as modified.
+//!
+//! Both arrays are split into elements as with `--messy`, so their elements
+//! need not each be on a line of their own.
+
+use crate::{canonical::Canonicalizer, errors::ConversionError, records::JsonlRecord};
+
+/// How an element differs between the two arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The element is only in the other array, past the end of the input.
+    Added,
+    /// The element is only in the input, past the end of the other array.
+    Removed,
+    /// The elements at the index differ.
+    Modified,
+}
+
+impl Change {
+    /// Returns the name of the change as written in a difference.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Modified => "modified",
+        }
+    }
+}
+
+/// An element that differs between the two arrays.
+///
+/// # Fields
+///
+/// * `index` - The index of the element, from 0.
+/// * `change` - How the element differs.
+/// * `old` - The element of the input in canonical form, unless it was
+///   added.
+/// * `new` - The element of the other array in canonical form, unless it was
+///   removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub index: usize,
+    pub change: Change,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Difference {
+    /// Returns the difference as a single line JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::diff::{Change, Difference};
+    ///
+    /// let difference = Difference {
+    ///     index: 2,
+    ///     change: Change::Removed,
+    ///     old: Some("{\"a\":1}".to_string()),
+    ///     new: None,
+    /// };
+    /// assert_eq!(
+    ///     difference.to_json(),
+    ///     "{\"index\":2,\"change\":\"removed\",\"old\":{\"a\":1}}"
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"index\":{},\"change\":\"{}\"",
+            self.index,
+            self.change.as_str()
+        );
+        // The elements are canonical JSON already, so are written as they are.
+        if let Some(old) = &self.old {
+            json.push_str(",\"old\":");
+            json.push_str(old);
+        }
+        if let Some(new) = &self.new {
+            json.push_str(",\"new\":");
+            json.push_str(new);
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Returns `records` in canonical form, for comparing with `diff_records`.
+///
+/// # Errors
+///
+/// * Any error yielded by `records`.
+/// * If a record is not valid JSON or repeats a key in an object, numbering
+///   the records from 1.
+pub fn canonical_records<I>(records: I) -> impl Iterator<Item = Result<String, ConversionError>>
+where
+    I: Iterator<Item = Result<JsonlRecord, ConversionError>>,
+{
+    let canonicalizer = Canonicalizer::default();
+    records.enumerate().map(move |(index, record)| {
+        canonicalizer
+            .canonicalize(&record?.text)
+            .map_err(|error| ConversionError::InvalidRecord {
+                record: index + 1,
+                message: error,
+            })
+    })
+}
+
+/// Returns an iterator over the elements that differ between `old` and
+/// `new`, which are compared by index as they are read.
+///
+/// The iterator yields the first error of either input and then ends.
+///
+/// # Arguments
+///
+/// * `old` - The elements of the input, in canonical form.
+/// * `new` - The elements of the array it is compared with, in canonical
+///   form.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::diff::{diff_records, Change};
+///
+/// let old = ["1", "2", "3"].map(|element| Ok(element.to_string()));
+/// let new = ["1", "4"].map(|element| Ok(element.to_string()));
+/// let changes: Vec<(usize, Change)> = diff_records(old.into_iter(), new.into_iter())
+///     .map(|difference| difference.unwrap())
+///     .map(|difference| (difference.index, difference.change))
+///     .collect();
+/// assert_eq!(changes, [(1, Change::Modified), (2, Change::Removed)]);
+/// ```
+pub fn diff_records<O, N>(old: O, new: N) -> RecordDiff<O, N>
+where
+    O: Iterator<Item = Result<String, ConversionError>>,
+    N: Iterator<Item = Result<String, ConversionError>>,
+{
+    RecordDiff {
+        old,
+        new,
+        index: 0,
+        done: false,
+    }
+}
+
+/// The iterator returned by `diff_records`.
+///
+/// # Fields
+///
+/// * `old` - The elements of the input.
+/// * `new` - The elements of the array it is compared with.
+/// * `index` - The index of the next elements to compare.
+/// * `done` - Whether both inputs have ended or an error has been yielded.
+pub struct RecordDiff<O, N> {
+    old: O,
+    new: N,
+    index: usize,
+    done: bool,
+}
+
+impl<O, N> Iterator for RecordDiff<O, N>
+where
+    O: Iterator<Item = Result<String, ConversionError>>,
+    N: Iterator<Item = Result<String, ConversionError>>,
+{
+    type Item = Result<Difference, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (old, new) = match (self.old.next().transpose(), self.new.next().transpose()) {
+                (Ok(old), Ok(new)) => (old, new),
+                (Err(error), _) | (_, Err(error)) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            };
+            let index = self.index;
+            self.index += 1;
+            let change = match (&old, &new) {
+                (None, None) => {
+                    self.done = true;
+                    return None;
+                }
+                (Some(old), Some(new)) if old == new => continue,
+                (Some(_), Some(_)) => Change::Modified,
+                (Some(_), None) => Change::Removed,
+                (None, Some(_)) => Change::Added,
+            };
+            return Some(Ok(Difference {
+                index,
+                change,
+                old,
+                new,
+            }));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::{jsonl_records, ConverterOptions};
+
+    fn diff(old: &str, new: &str) -> Vec<String> {
+        let old = canonical_records(jsonl_records(old.as_bytes(), ConverterOptions::default()));
+        let new = canonical_records(jsonl_records(new.as_bytes(), ConverterOptions::default()));
+        diff_records(old, new)
+            .map(|difference| difference.unwrap().to_json())
+            .collect()
+    }
+
+    #[test]
+    fn test_equal_arrays_have_no_differences() {
+        let old = "[{\"a\": 1, \"b\": [1.0, \"\\u00e9\"]},\n [2]]";
+        let new = "[\n  {\"b\": [1, \"é\"], \"a\": 1},\n  [ 2 ]\n]";
+        assert!(diff(old, new).is_empty());
+        assert!(diff("[]", "[ ]").is_empty());
+    }
+
+    #[test]
+    fn test_modified_elements_are_reported() {
+        assert_eq!(
+            diff("[{\"a\": 1}, [2], [3]]", "[{\"a\": 2}, [2], [3, 4]]"),
+            [
+                "{\"index\":0,\"change\":\"modified\",\"old\":{\"a\":1},\"new\":{\"a\":2}}",
+                "{\"index\":2,\"change\":\"modified\",\"old\":[3],\"new\":[3,4]}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_added_elements_are_reported() {
+        assert_eq!(
+            diff("[[1]]", "[[1], {\"b\": true}, [null]]"),
+            [
+                "{\"index\":1,\"change\":\"added\",\"new\":{\"b\":true}}",
+                "{\"index\":2,\"change\":\"added\",\"new\":[null]}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_removed_elements_are_reported() {
+        assert_eq!(
+            diff("[[1], [2], {\"c\": \"d\"}]", "[[0]]"),
+            [
+                "{\"index\":0,\"change\":\"modified\",\"old\":[1],\"new\":[0]}",
+                "{\"index\":1,\"change\":\"removed\",\"old\":[2]}",
+                "{\"index\":2,\"change\":\"removed\",\"old\":{\"c\":\"d\"}}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_element_stops_the_diff() {
+        let old = canonical_records(jsonl_records(
+            "[[1], [2], {a}, [4]]".as_bytes(),
+            ConverterOptions::default(),
+        ));
+        let new = canonical_records(jsonl_records(
+            "[[0], [2], [3], [4]]".as_bytes(),
+            ConverterOptions::default(),
+        ));
+        let mut differences = diff_records(old, new);
+        assert_eq!(differences.next().unwrap().unwrap().index, 0);
+        assert!(matches!(
+            differences.next(),
+            Some(Err(ConversionError::InvalidRecord { record: 3, .. }))
+        ));
+        assert!(differences.next().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! This module contains the wrapping of records in a metadata envelope for
+//! `--envelope`, so that pipelines can track where each record came from.
+//!
+//! # Format
+//!
+//! Each record is written as an object with these members, in this order:
+//!
+//! * `source` - The path of the file the record came from, or `-` when it
+//!   was read from stdin.
+//! * `index` - The position of the record in that file, counting from 0.
+//! * `ts` - When the record was written, as an ISO 8601 UTC timestamp such
+//!   as `2024-05-01T12:30:00Z`.
+//! * `data` - The record itself, exactly as it would otherwise be written.
+//!
+//! Any of the first three can be left out with `--envelope-fields`.
+
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The metadata members included in each envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvelopeFields {
+    pub source: bool,
+    pub index: bool,
+    pub timestamp: bool,
+}
+
+impl EnvelopeFields {
+    /// Every metadata member, which is the default.
+    pub const ALL: EnvelopeFields = EnvelopeFields {
+        source: true,
+        index: true,
+        timestamp: true,
+    };
+}
+
+impl FromStr for EnvelopeFields {
+    type Err = String;
+
+    /// Parses a comma separated list of `source`, `index` and `ts`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::envelope::EnvelopeFields;
+    ///
+    /// let fields: EnvelopeFields = "source,ts".parse().unwrap();
+    /// assert!(fields.source && fields.timestamp && !fields.index);
+    /// assert!("source,time".parse::<EnvelopeFields>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = EnvelopeFields {
+            source: false,
+            index: false,
+            timestamp: false,
+        };
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            match field {
+                "source" => fields.source = true,
+                "index" => fields.index = true,
+                "ts" => fields.timestamp = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown envelope field `{}`. Expected `source`, `index` or `ts`.",
+                        field
+                    ))
+                }
+            }
+        }
+        Ok(fields)
+    }
+}
+
+/// Wraps each record in an envelope of metadata.
+///
+/// # Fields
+///
+/// * `source` - The path of the file that records come from, or `-` for
+///   stdin.
+/// * `fields` - The metadata members included in each envelope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub source: String,
+    pub fields: EnvelopeFields,
+}
+
+impl Envelope {
+    /// Returns `record` wrapped in an envelope. The record is inserted as
+    /// it is, without being parsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record to wrap.
+    /// * `index` - The position of the record in its file.
+    /// * `now` - The time the record is being written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::UNIX_EPOCH;
+    /// use jsonl_converter::envelope::{Envelope, EnvelopeFields};
+    ///
+    /// let envelope = Envelope {
+    ///     source: String::from("data.json"),
+    ///     fields: EnvelopeFields::ALL,
+    /// };
+    /// assert_eq!(
+    ///     envelope.wrap("{\"a\": 1}", 3, UNIX_EPOCH),
+    ///     r#"{"source":"data.json","index":3,"ts":"1970-01-01T00:00:00Z","data":{"a": 1}}"#
+    /// );
+    /// ```
+    pub fn wrap(&self, record: &str, index: usize, now: SystemTime) -> String {
+        let mut wrapped = String::from("{");
+        if self.fields.source {
+            wrapped.push_str("\"source\":");
+            wrapped.push_str(&serde_json::to_string(&self.source).unwrap());
+            wrapped.push(',');
+        }
+        if self.fields.index {
+            wrapped.push_str(&format!("\"index\":{},", index));
+        }
+        if self.fields.timestamp {
+            wrapped.push_str(&format!("\"ts\":\"{}\",", iso8601(now)));
+        }
+        wrapped.push_str("\"data\":");
+        wrapped.push_str(record);
+        wrapped.push('}');
+        wrapped
+    }
+}
+
+/// Formats `time` as an ISO 8601 UTC timestamp to the second. Times before
+/// the Unix epoch are written as the epoch.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use jsonl_converter::envelope::iso8601;
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// assert_eq!(iso8601(time), "2023-11-14T22:13:20Z");
+/// ```
+pub fn iso8601(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Returns the year, month and day of the date `days` days after the Unix
+/// epoch, using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so that leap days fall at the end of
+    // each year, and count in 400 year eras of 146097 days.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_iso8601() {
+        let cases = [
+            (0, "1970-01-01T00:00:00Z"),
+            (951_782_400, "2000-02-29T00:00:00Z"),
+            (951_868_799, "2000-02-29T23:59:59Z"),
+            (951_868_800, "2000-03-01T00:00:00Z"),
+            (1_704_067_199, "2023-12-31T23:59:59Z"),
+            (4_107_542_400, "2100-03-01T00:00:00Z"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(iso8601(at(secs)), expected, "{}", secs);
+        }
+        assert_eq!(
+            iso8601(UNIX_EPOCH - Duration::from_secs(1)),
+            "1970-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_wrap_keeps_record_verbatim() {
+        let envelope = Envelope {
+            source: String::from("dir/\"quoted\".json"),
+            fields: EnvelopeFields::ALL,
+        };
+        let record = "{\"b\": [1, 2.50], \"a\" : \"\\u00e9\"}";
+        let wrapped = envelope.wrap(record, 7, at(1_700_000_000));
+
+        let value: serde_json::Value = serde_json::from_str(&wrapped).unwrap();
+        assert_eq!(value["source"], "dir/\"quoted\".json");
+        assert_eq!(value["index"], 7);
+        assert_eq!(value["ts"], "2023-11-14T22:13:20Z");
+        assert!(wrapped.ends_with(&format!(",\"data\":{}}}", record)));
+    }
+
+    #[test]
+    fn test_wrap_with_selected_fields() {
+        let mut envelope = Envelope {
+            source: String::from("data.json"),
+            fields: "index".parse().unwrap(),
+        };
+        assert_eq!(envelope.wrap("[1]", 0, at(0)), "{\"index\":0,\"data\":[1]}");
+
+        envelope.fields = "".parse().unwrap();
+        assert_eq!(envelope.wrap("[1]", 0, at(0)), "{\"data\":[1]}");
+    }
+}
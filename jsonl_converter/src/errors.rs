@@ -0,0 +1,406 @@
+//! This module contains the errors that can occur whilst converting JSON to
+//! JSONL.
+
+use std::{
+    any::Any,
+    error::Error,
+    fmt, io,
+    panic::{self, AssertUnwindSafe},
+    process::ExitStatus,
+};
+
+/// Errors that can occur whilst converting JSON to JSONL.
+#[derive(Debug)]
+pub enum ConversionError {
+    /// The input contains nothing but whitespace.
+    EmptyInput,
+    /// The input starts with `found` rather than the bracket of a root that
+    /// can be converted, which is a `[` or a `{`, or only a `{` when
+    /// converting the members of an object with `--map-values`.
+    InvalidStart { found: char, map_values: bool },
+    /// Non-whitespace content was found after the root array was closed.
+    TrailingContent(char),
+    /// Non-whitespace content was found after the root object was closed,
+    /// when converting its members with `--map-values`.
+    TrailingObjectContent(char),
+    /// Another array was found after the root array was closed, as when two
+    /// files are concatenated, without `--concat`.
+    ConcatenatedArrays,
+    /// The JSONP padding around the root array is malformed when using
+    /// `--strip-jsonp`.
+    InvalidJsonp(String),
+    /// Objects and arrays are nested deeper than the limit set with
+    /// `--max-depth`, at a bracket that starts at `offset` in the input.
+    TooDeep { max_depth: usize, offset: u64 },
+    /// A closing bracket at `offset` in the input does not match the last
+    /// opening bracket, which `expected` would have closed. `expected` is
+    /// `None` if no bracket was open.
+    MismatchedBracket {
+        expected: Option<char>,
+        got: char,
+        offset: u64,
+    },
+    /// The input ended before the root was closed, as when it has been cut
+    /// short.
+    UnexpectedEof,
+    /// A string contains a raw control character, other than a line break,
+    /// at `offset` in the input, without `--allow-control-chars`.
+    ControlCharacter { character: char, offset: u64 },
+    /// Reading the input or writing the output failed.
+    Io(io::Error),
+    /// The command given to `--pipe` could not be started.
+    PipeSpawn { command: String, error: io::Error },
+    /// The command given to `--pipe` exited unsuccessfully.
+    PipeExit { command: String, status: ExitStatus },
+    /// A line of the input is not valid JSON when checking JSONL.
+    InvalidJsonl {
+        line: usize,
+        error: serde_json::Error,
+    },
+    /// A line of the input is blank when checking JSONL with
+    /// `--check-jsonl`, where every line must be a value. Lines are numbered
+    /// from 1.
+    EmptyLine { line: usize },
+    /// A record is not valid JSON when converting with `--strict` or
+    /// `--flatten`. Records are numbered from 1.
+    InvalidRecord { record: usize, message: String },
+    /// A record does not contain some of the top level keys given to
+    /// `--fields-required`. Records are numbered from 1.
+    MissingFields { record: usize, fields: Vec<String> },
+    /// A record has the same value of `key` as an earlier record when
+    /// collecting records into a map that refuses duplicates. Records are
+    /// numbered from 1.
+    DuplicateKey {
+        record: usize,
+        key: String,
+        value: String,
+    },
+    /// The file given to `--output` could not be opened.
+    OutputOpen { path: String, error: io::Error },
+    /// The pattern given to `--input-glob` does not match any files.
+    NoGlobMatches(String),
+    /// There is no array at the pointer given to `--root-pointer`.
+    PointerNotFound(String),
+    /// The input could not be parsed as a whole to check the number of
+    /// records with `--verify`.
+    Unverifiable(serde_json::Error),
+    /// Parsing the input as a whole with `--verify` found a different number
+    /// of elements in its root than the records converted.
+    CountMismatch { parsed: usize, converted: usize },
+    /// The conversion panicked, and the panic was caught at the boundary of
+    /// the library rather than unwinding into the caller. The message is
+    /// that of the panic.
+    Internal(String),
+    /// A file listed with `--input-list` could not be converted.
+    Input {
+        path: String,
+        error: Box<ConversionError>,
+    },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::EmptyInput => {
+                write!(f, "The file is empty. It must contain a JSON array.")
+            }
+            ConversionError::InvalidStart {
+                found,
+                map_values: false,
+            } => {
+                write!(
+                    f,
+                    "The input must start with a '[' or a '{{', not {:?}.",
+                    found
+                )?;
+                write_gzip_hint(f, *found)
+            }
+            ConversionError::InvalidStart {
+                found,
+                map_values: true,
+            } => {
+                write!(
+                    f,
+                    "The input must start with a '{{' when using --map-values, not {:?}.",
+                    found
+                )?;
+                write_gzip_hint(f, *found)
+            }
+            ConversionError::TrailingContent(c) => write!(
+                f,
+                "Unexpected {:?} after the root array was closed. Only whitespace may follow the closing bracket.",
+                c
+            ),
+            ConversionError::TrailingObjectContent(c) => write!(
+                f,
+                "Unexpected {:?} after the root object was closed. Only whitespace may follow the closing brace.",
+                c
+            ),
+            ConversionError::ConcatenatedArrays => write!(
+                f,
+                "Another array follows the root array, as if two files were concatenated. Use --concat to convert the elements of both."
+            ),
+            ConversionError::InvalidJsonp(message) => write!(f, "{}", message),
+            ConversionError::TooDeep { max_depth, offset } => write!(
+                f,
+                "Objects and arrays are nested deeper than {}, the limit set with --max-depth, at byte offset {}.",
+                max_depth, offset
+            ),
+            ConversionError::MismatchedBracket {
+                expected: Some(expected),
+                got,
+                offset,
+            } => write!(
+                f,
+                "Expected `{}` but found `{}` at byte offset {}.",
+                expected, got, offset
+            ),
+            ConversionError::MismatchedBracket {
+                expected: None,
+                got,
+                offset,
+            } => write!(
+                f,
+                "Found `{}` at byte offset {} with no bracket open for it to close.",
+                got, offset
+            ),
+            ConversionError::UnexpectedEof => write!(
+                f,
+                "The input ended before the root was closed, so it may have been cut short."
+            ),
+            ConversionError::ControlCharacter { character, offset } => write!(
+                f,
+                "A string contains the control character U+{:04X} at byte offset {}, which must be escaped in JSON. Use --allow-control-chars to pass it through, or --escape-control-chars as well to escape it.",
+                *character as u32, offset
+            ),
+            ConversionError::Io(error) => write!(f, "{}", error),
+            ConversionError::PipeSpawn { command, error } => {
+                write!(f, "Could not run the pipe command `{}`: {}", command, error)
+            }
+            ConversionError::PipeExit { command, status } => {
+                write!(f, "The pipe command `{}` failed with {}.", command, status)
+            }
+            ConversionError::InvalidJsonl { line, error } => {
+                write!(f, "Line {} is not valid JSON: {}", line, error)
+            }
+            ConversionError::EmptyLine { line } => write!(
+                f,
+                "Line {} is empty, but every line of JSONL must hold a record.",
+                line
+            ),
+            ConversionError::InvalidRecord { record, message } => {
+                write!(f, "Record {} is not valid JSON: {}", record, message)
+            }
+            ConversionError::MissingFields { record, fields } => write!(
+                f,
+                "Record {} is missing the required fields {}.",
+                record,
+                quote_fields(fields)
+            ),
+            ConversionError::DuplicateKey { record, key, value } => write!(
+                f,
+                "Record {} has the same `{}` as an earlier record: {}.",
+                record, key, value
+            ),
+            ConversionError::OutputOpen { path, error } => {
+                write!(f, "Could not open the output `{}`: {}", path, error)
+            }
+            ConversionError::NoGlobMatches(pattern) => {
+                write!(f, "No files match the pattern `{}`.", pattern)
+            }
+            ConversionError::PointerNotFound(pointer) => {
+                write!(f, "There is no array at `{}` to convert.", pointer)
+            }
+            ConversionError::Unverifiable(error) => {
+                write!(f, "Could not parse the input to --verify it: {}", error)
+            }
+            ConversionError::CountMismatch { parsed, converted } => write!(
+                f,
+                "Converted {} records, but the root of the input has {} elements, so records were split or joined in the wrong place.",
+                converted, parsed
+            ),
+            ConversionError::Internal(message) => {
+                write!(f, "The conversion stopped unexpectedly: {}", message)
+            }
+            ConversionError::Input { path, error } => {
+                write!(f, "Could not convert `{}`: {}", path, error)
+            }
+        }
+    }
+}
+
+/// Returns `fields` quoted with backticks and separated by commas, as in
+/// "`a`, `b`".
+pub fn quote_fields<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| format!("`{}`", field.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The first byte of gzip input, read as a character.
+const GZIP_FIRST_CHAR: char = '\u{1f}';
+
+/// Writes a hint after an `InvalidStart` error if the input looks like it
+/// is compressed with gzip, which can only be read with the `gzip` feature.
+fn write_gzip_hint(f: &mut fmt::Formatter<'_>, found: char) -> fmt::Result {
+    if found != GZIP_FIRST_CHAR || cfg!(feature = "gzip") {
+        return Ok(());
+    }
+    write!(
+        f,
+        " The input looks compressed with gzip, which can only be read when \
+         built with the `gzip` feature. Decompress it first, such as with `gunzip -c`."
+    )
+}
+
+impl ConversionError {
+    /// Returns an `Internal` error with the message of a caught panic, as
+    /// returned by `std::panic::catch_unwind`.
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => String::from("the panic had no message."),
+            },
+        };
+        ConversionError::Internal(message)
+    }
+
+    /// Returns whether the error was caused by the reader of the output
+    /// closing it early, such as when the output is piped to `head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        match self {
+            ConversionError::Io(error) => error.kind() == io::ErrorKind::BrokenPipe,
+            ConversionError::Input { error, .. } => error.is_broken_pipe(),
+            _ => false,
+        }
+    }
+}
+
+/// Runs `f`, returning a panic as `ConversionError::Internal`. Invalid input
+/// is reported as an error rather than a panic, so this is only a backstop
+/// against bugs.
+pub(crate) fn guarded<T>(
+    f: impl FnOnce() -> Result<T, ConversionError>,
+) -> Result<T, ConversionError> {
+    // Whatever `f` was working on is only left part of the way through, as
+    // it is when `f` fails with an error.
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(ConversionError::from_panic(payload)))
+}
+
+impl Error for ConversionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConversionError::Io(error)
+            | ConversionError::PipeSpawn { error, .. }
+            | ConversionError::OutputOpen { error, .. } => Some(error),
+            ConversionError::InvalidJsonl { error, .. } | ConversionError::Unverifiable(error) => {
+                Some(error)
+            }
+            ConversionError::Input { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConversionError {
+    fn from(error: io::Error) -> Self {
+        ConversionError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailing_content_display_names_the_char() {
+        let error = ConversionError::TrailingContent('x');
+        assert!(error
+            .to_string()
+            .starts_with("Unexpected 'x' after the root"));
+        let error = ConversionError::TrailingObjectContent('[');
+        assert!(error
+            .to_string()
+            .starts_with("Unexpected '[' after the root object"));
+    }
+
+    #[test]
+    fn test_invalid_start_display_hints_at_gzip_without_the_feature() {
+        for map_values in [false, true] {
+            let error = ConversionError::InvalidStart {
+                found: '\u{1f}',
+                map_values,
+            };
+            assert_eq!(
+                error.to_string().contains("compressed with gzip"),
+                !cfg!(feature = "gzip")
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_start_display_escapes_the_char() {
+        let error = ConversionError::InvalidStart {
+            found: '\u{7f}',
+            map_values: false,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The input must start with a '[' or a '{', not '\\u{7f}'."
+        );
+        let error = ConversionError::InvalidStart {
+            found: '[',
+            map_values: true,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The input must start with a '{' when using --map-values, not '['."
+        );
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let error = ConversionError::Io(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(error.is_broken_pipe());
+        let error = ConversionError::Io(io::Error::from(io::ErrorKind::NotFound));
+        assert!(!error.is_broken_pipe());
+        assert!(!ConversionError::TrailingContent('x').is_broken_pipe());
+        let error = ConversionError::Input {
+            path: String::from("a.json"),
+            error: Box::new(ConversionError::Io(io::Error::from(
+                io::ErrorKind::BrokenPipe,
+            ))),
+        };
+        assert!(error.is_broken_pipe());
+    }
+
+    #[test]
+    fn test_guarded_catches_panics() {
+        let error = guarded::<()>(|| panic!("Bad bracket.")).unwrap_err();
+        assert!(matches!(
+            &error,
+            ConversionError::Internal(message) if message == "Bad bracket."
+        ));
+        assert_eq!(guarded(|| Ok(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_from_panic_keeps_the_message() {
+        let payload = std::panic::catch_unwind(|| panic!("Bad {}.", "bracket")).unwrap_err();
+        let error = ConversionError::from_panic(payload);
+        assert_eq!(
+            error.to_string(),
+            "The conversion stopped unexpectedly: Bad bracket."
+        );
+        let payload = std::panic::catch_unwind(|| panic!("Bad bracket.")).unwrap_err();
+        assert!(matches!(
+            ConversionError::from_panic(payload),
+            ConversionError::Internal(message) if message == "Bad bracket."
+        ));
+    }
+}
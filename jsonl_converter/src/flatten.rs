@@ -0,0 +1,207 @@
+//! This module contains the flattening of records for `--flatten`, which
+//! turns nested objects and arrays into a single object with one key per
+//! leaf value, ready to be loaded into a columnar store.
+//!
+//! # Rules
+//!
+//! * The key of each leaf is the path to it, joined by the separator, with
+//!   array elements named by their index, e.g. `{"a": {"b": [1]}}` becomes
+//!   `{"a.b.0": 1}`.
+//! * Empty objects and arrays are leaves themselves, so `{"a": {}}` stays as
+//!   it is rather than losing the key.
+//! * If two paths give the same key, such as the `a.b` key and the `b` key
+//!   under `a`, the record is an error rather than one value being dropped.
+//! * A record that is not an object or an array with members is written
+//!   unchanged.
+//! * Keys are written in sorted order.
+//! * Numbers are written exactly as they are in the record, however many
+//!   digits they have, and a record with a key that appears twice in the
+//!   same object is an error.
+
+use std::collections::BTreeMap;
+
+use crate::raw::RawJson;
+
+/// Flattens records, joining the parts of each key with `separator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flattener {
+    pub separator: String,
+}
+
+impl Default for Flattener {
+    fn default() -> Self {
+        Flattener {
+            separator: String::from("."),
+        }
+    }
+}
+
+impl Flattener {
+    /// Returns `record` flattened.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A JSON value.
+    ///
+    /// # Errors
+    ///
+    /// * If `record` is not valid JSON.
+    /// * If a key appears twice in the same object of `record`.
+    /// * If two paths in `record` give the same key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::flatten::Flattener;
+    ///
+    /// let flattener = Flattener::default();
+    /// assert_eq!(
+    ///     flattener.flatten(r#"{"a": {"b": 1, "c": [true]}}"#).unwrap(),
+    ///     r#"{"a.b":1,"a.c.0":true}"#
+    /// );
+    /// ```
+    pub fn flatten(&self, record: &str) -> Result<String, String> {
+        let value = RawJson::parse(record)?;
+        if !value.has_members() {
+            return Ok(record.to_string());
+        }
+
+        let mut flat = BTreeMap::new();
+        self.flatten_into(&mut flat, None, value)?;
+        Ok(RawJson::Object(flat.into_iter().collect()).to_string())
+    }
+
+    /// Inserts the leaves of `value` into `flat`, with keys prefixed by
+    /// `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// * If a leaf's key is already in `flat`.
+    fn flatten_into<'a>(
+        &self,
+        flat: &mut BTreeMap<String, RawJson<'a>>,
+        prefix: Option<&str>,
+        value: RawJson<'a>,
+    ) -> Result<(), String> {
+        let members: Vec<(String, RawJson)> = match value {
+            RawJson::Object(object) if !object.is_empty() => object,
+            RawJson::Array(array) if !array.is_empty() => array
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (i.to_string(), value))
+                .collect(),
+            leaf => {
+                let key = prefix.unwrap_or_default();
+                if flat.contains_key(key) {
+                    return Err(format!(
+                        "More than one value would be flattened to the key `{}`.",
+                        key
+                    ));
+                }
+                flat.insert(key.to_string(), leaf);
+                return Ok(());
+            }
+        };
+
+        for (key, value) in members {
+            let key = match prefix {
+                Some(prefix) => format!("{}{}{}", prefix, self.separator, key),
+                None => key,
+            };
+            self.flatten_into(flat, Some(&key), value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flatten(record: &str) -> String {
+        Flattener::default().flatten(record).unwrap()
+    }
+
+    #[test]
+    fn test_flatten_nested_objects() {
+        assert_eq!(
+            flatten(r#"{"a": {"b": {"c": 1}, "d": "x"}, "e": null}"#),
+            r#"{"a.b.c":1,"a.d":"x","e":null}"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_nested_arrays() {
+        assert_eq!(flatten("[[1, 2], [3]]"), r#"{"0.0":1,"0.1":2,"1.0":3}"#);
+    }
+
+    #[test]
+    fn test_flatten_mixed() {
+        assert_eq!(
+            flatten(r#"{"a": [{"b": 1}, [2]], "c": {"d": [3]}}"#),
+            r#"{"a.0.b":1,"a.1.0":2,"c.d.0":3}"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_keeps_empty_objects_and_arrays() {
+        assert_eq!(
+            flatten(r#"{"a": {}, "b": {"c": []}}"#),
+            r#"{"a":{},"b.c":[]}"#
+        );
+        assert_eq!(flatten("{}"), "{}");
+        assert_eq!(flatten("[]"), "[]");
+    }
+
+    #[test]
+    fn test_flatten_rejects_paths_that_collide() {
+        assert_eq!(
+            Flattener::default()
+                .flatten(r#"{"a.b": 1, "a": {"b": 2}}"#)
+                .unwrap_err(),
+            "More than one value would be flattened to the key `a.b`."
+        );
+    }
+
+    #[test]
+    fn test_flatten_keeps_numbers_exactly() {
+        assert_eq!(
+            flatten(
+                r#"{"a": {"big": 18446744073709551617, "dec": 0.1000000000000000055511151231257827}, "b": [1.0, 1e400]}"#
+            ),
+            r#"{"a.big":18446744073709551617,"a.dec":0.1000000000000000055511151231257827,"b.0":1.0,"b.1":1e400}"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_rejects_repeated_keys() {
+        assert_eq!(
+            Flattener::default()
+                .flatten(r#"{"a": {"b": 1, "b": 2}}"#)
+                .unwrap_err(),
+            "The key `b` appears more than once in the same object."
+        );
+    }
+
+    #[test]
+    fn test_flatten_rejects_invalid_json() {
+        assert!(Flattener::default().flatten("{").is_err());
+    }
+
+    #[test]
+    fn test_flatten_with_separator() {
+        let flattener = Flattener {
+            separator: String::from("__"),
+        };
+        assert_eq!(
+            flattener.flatten(r#"{"a": {"b": [1]}}"#).unwrap(),
+            r#"{"a__b__0":1}"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_scalars_are_unchanged() {
+        assert_eq!(flatten(" 1.50 "), " 1.50 ");
+        assert!(Flattener::default().flatten("{").is_err());
+    }
+}
@@ -0,0 +1,326 @@
+//! This module generates synthetic JSON arrays. It is used to produce
+//! repeatable inputs for benchmarking and stress testing the converter, and
+//! does not share any code with the conversion path itself.
+
+use std::io::{self, Write};
+
+/// Characters used when building ASCII strings.
+const ASCII_CHARS: &[char] = &[
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ' ',
+];
+
+/// Multibyte characters mixed into strings when unicode is enabled.
+const UNICODE_CHARS: &[char] = &['é', 'ß', 'Ж', 'λ', '漢', '字', '🦀', '✓'];
+
+/// Options controlling the shape of the generated JSON.
+///
+/// # Fields
+///
+/// * `records` - The number of objects in the root array.
+/// * `depth` - How many levels of nested objects each record contains.
+/// * `string_len` - The number of characters in each generated string.
+/// * `unicode` - Whether strings should contain multibyte characters.
+/// * `pretty` - Whether to write one token per line (suitable for line mode)
+///   or the entire array on a single line (suitable for `--messy` mode).
+/// * `seed` - The seed for the pseudo-random generator, so that the same
+///   options always produce the same output.
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    pub records: usize,
+    pub depth: usize,
+    pub string_len: usize,
+    pub unicode: bool,
+    pub pretty: bool,
+    pub seed: u64,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            records: 100,
+            depth: 2,
+            string_len: 16,
+            unicode: false,
+            pretty: true,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// A small xorshift generator. It is not suitable for anything other than
+/// producing varied test data, but it keeps the output deterministic without
+/// pulling in an extra dependency.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // A zero state would only ever produce zeros.
+        XorShift(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Writes JSON values to a writer, either pretty printed or on one line.
+struct JsonWriter<'a, W: Write> {
+    writer: &'a mut W,
+    rng: XorShift,
+    options: &'a GeneratorOptions,
+}
+
+impl<'a, W: Write> JsonWriter<'a, W> {
+    fn newline(&mut self, indent: usize) -> io::Result<()> {
+        if self.options.pretty {
+            write!(self.writer, "\n{:width$}", "", width = indent * 2)?;
+        }
+        Ok(())
+    }
+
+    fn separator(&self) -> &'static str {
+        if self.options.pretty {
+            ": "
+        } else {
+            ":"
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut s = String::with_capacity(self.options.string_len);
+        for _ in 0..self.options.string_len {
+            let c = if self.options.unicode && self.rng.below(4) == 0 {
+                UNICODE_CHARS[self.rng.below(UNICODE_CHARS.len())]
+            } else {
+                ASCII_CHARS[self.rng.below(ASCII_CHARS.len())]
+            };
+            s.push(c);
+        }
+        s
+    }
+
+    fn key_value(&mut self, indent: usize, key: &str, value: &str, last: bool) -> io::Result<()> {
+        self.newline(indent)?;
+        let sep = self.separator();
+        write!(self.writer, "\"{}\"{}{}", key, sep, value)?;
+        if !last {
+            write!(self.writer, ",")?;
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, indent: usize, level: usize) -> io::Result<()> {
+        write!(self.writer, "{{")?;
+        let value = format!("\"{}\"", self.string());
+        self.key_value(indent + 1, "level", &level.to_string(), false)?;
+        self.key_value(indent + 1, "value", &value, level == self.options.depth)?;
+        if level < self.options.depth {
+            self.newline(indent + 1)?;
+            write!(self.writer, "\"child\"{}", self.separator())?;
+            self.nested(indent + 1, level + 1)?;
+        }
+        self.newline(indent)?;
+        write!(self.writer, "}}")
+    }
+
+    fn record(&mut self, id: usize) -> io::Result<()> {
+        let indent = 1;
+        self.newline(indent)?;
+        write!(self.writer, "{{")?;
+
+        let name = format!("\"{}\"", self.string());
+        let score = format!("{}.{}", self.rng.below(1000), self.rng.below(100));
+        let active = (self.rng.below(2) == 0).to_string();
+        self.key_value(indent + 1, "id", &id.to_string(), false)?;
+        self.key_value(indent + 1, "name", &name, false)?;
+        self.key_value(indent + 1, "score", &score, false)?;
+        self.key_value(indent + 1, "active", &active, false)?;
+        self.key_value(indent + 1, "empty", "[]", false)?;
+
+        self.newline(indent + 1)?;
+        write!(self.writer, "\"tags\"{}[", self.separator())?;
+        for i in 0..3 {
+            self.newline(indent + 2)?;
+            let tag = self.string();
+            write!(self.writer, "\"{}\"", tag)?;
+            if i < 2 {
+                write!(self.writer, ",")?;
+            }
+        }
+        self.newline(indent + 1)?;
+        write!(self.writer, "]")?;
+
+        if self.options.depth > 0 {
+            write!(self.writer, ",")?;
+            self.newline(indent + 1)?;
+            write!(self.writer, "\"nested\"{}", self.separator())?;
+            self.nested(indent + 1, 1)?;
+        }
+
+        self.newline(indent)?;
+        write!(self.writer, "}}")
+    }
+}
+
+/// Writes a synthetic JSON array to `writer` according to `options`.
+///
+/// # Arguments
+///
+/// * `writer` - Where the generated JSON is written to.
+/// * `options` - The options controlling the generated JSON.
+///
+/// # Errors
+///
+/// * If writing to `writer` fails.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::generator::{generate, GeneratorOptions};
+///
+/// let options = GeneratorOptions {
+///     records: 2,
+///     ..GeneratorOptions::default()
+/// };
+/// let mut output = Vec::new();
+/// generate(&mut output, &options).unwrap();
+///
+/// assert!(output.starts_with(b"["));
+/// assert!(output.ends_with(b"]\n"));
+/// ```
+pub fn generate<W: Write>(writer: &mut W, options: &GeneratorOptions) -> io::Result<()> {
+    write!(writer, "[")?;
+    let mut json_writer = JsonWriter {
+        writer,
+        rng: XorShift::new(options.seed),
+        options,
+    };
+    for id in 0..options.records {
+        json_writer.record(id)?;
+        if id + 1 < options.records {
+            write!(json_writer.writer, ",")?;
+        }
+    }
+    if options.records > 0 {
+        json_writer.newline(0)?;
+    }
+    writeln!(writer, "]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        processors::{byte_processor::ByteProcessor, line_processor::LineProcessor},
+        writer::RecordWriter,
+    };
+
+    fn generate_string(options: &GeneratorOptions) -> String {
+        let mut output = Vec::new();
+        generate(&mut output, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_generate_produces_valid_json_array() {
+        let options = GeneratorOptions {
+            records: 10,
+            depth: 3,
+            unicode: true,
+            ..GeneratorOptions::default()
+        };
+        let value: serde_json::Value = serde_json::from_str(&generate_string(&options)).unwrap();
+        let array = value.as_array().unwrap();
+        assert_eq!(array.len(), 10);
+        assert_eq!(array[3]["id"], 3);
+        assert_eq!(array[3]["nested"]["child"]["child"]["level"], 3);
+    }
+
+    #[test]
+    fn test_generate_compact_produces_single_line() {
+        let options = GeneratorOptions {
+            records: 5,
+            pretty: false,
+            ..GeneratorOptions::default()
+        };
+        let output = generate_string(&options);
+        assert_eq!(output.lines().count(), 1);
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+    }
+
+    #[test]
+    fn test_generate_is_deterministic_for_seed() {
+        let options = GeneratorOptions::default();
+        assert_eq!(generate_string(&options), generate_string(&options));
+
+        let reseeded = GeneratorOptions {
+            seed: 42,
+            ..GeneratorOptions::default()
+        };
+        assert_ne!(generate_string(&options), generate_string(&reseeded));
+    }
+
+    #[test]
+    fn test_generate_zero_records_is_empty_array() {
+        let options = GeneratorOptions {
+            records: 0,
+            ..GeneratorOptions::default()
+        };
+        assert_eq!(generate_string(&options), "[]\n");
+    }
+
+    #[test]
+    fn test_generated_output_converts_cleanly_in_line_mode() {
+        let options = GeneratorOptions {
+            records: 20,
+            depth: 4,
+            unicode: true,
+            ..GeneratorOptions::default()
+        };
+        let output = generate_string(&options);
+        let mut lines = output.lines();
+        let first_char = lines.next().unwrap().chars().next().unwrap();
+
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&first_char).unwrap();
+        for line in lines {
+            processor.process_line(line).unwrap();
+        }
+
+        assert_eq!(processor.bracket_stack.len(), 0);
+        let converted = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+        assert_eq!(converted.lines().count(), 20);
+    }
+
+    #[test]
+    fn test_generated_output_converts_cleanly_in_byte_mode() {
+        let options = GeneratorOptions {
+            records: 20,
+            depth: 4,
+            pretty: false,
+            ..GeneratorOptions::default()
+        };
+        let output = generate_string(&options);
+        let mut chars = output.chars();
+
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&chars.next().unwrap()).unwrap();
+        for c in chars {
+            processor.process_char(&c).unwrap();
+        }
+
+        assert_eq!(processor.bracket_stack.len(), 0);
+        let converted = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+        assert_eq!(converted.lines().count(), 20);
+    }
+}
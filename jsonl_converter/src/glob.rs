@@ -0,0 +1,141 @@
+//! This module contains the expansion of the glob patterns given to
+//! `--input-glob` into the paths of the files that they match, using the
+//! `glob` crate.
+//!
+//! # Syntax
+//!
+//! Patterns are made up of components separated by `/`, each of which may
+//! contain the following wildcards:
+//!
+//! * `*` matches any number of characters.
+//! * `?` matches a single character.
+//! * `[abc]` and `[a-z]` match a single character in the set, and `[!abc]`
+//!   matches a single character not in it.
+//! * `**`, as a component of its own, matches any number of directories, so
+//!   `data/**/*.json` matches `.json` files in `data` and every directory
+//!   below it.
+//!
+//! Wildcards never match `/`, and only match a leading `.` if the pattern
+//! has one there, so hidden files are skipped as they are by shells.
+//!
+//! There is no escape character, and `\` is matched as itself. A literal
+//! `*`, `?` or `[` is matched by putting it in a set, as in `[*]`, `[?]` or
+//! `[[]`.
+
+use std::io;
+
+use ::glob::{glob_with, MatchOptions};
+
+/// Returns the paths of the files matching `pattern`, in sorted order.
+/// Directories that match are skipped.
+///
+/// # Errors
+///
+/// * If the pattern is not valid, such as one with `**` in the middle of a
+///   component.
+/// * If a directory being searched cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jsonl_converter::glob::expand;
+///
+/// for path in expand("data/**/*.json").unwrap() {
+///     println!("{}", path);
+/// }
+/// ```
+pub fn expand(pattern: &str) -> io::Result<Vec<String>> {
+    let options = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+    };
+    let paths = glob_with(pattern, options).map_err(|error| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid glob pattern `{}`: {}.", pattern, error),
+        )
+    })?;
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(io::Error::from)?;
+        if !path.is_file() {
+            continue;
+        }
+        if let Ok(path) = path.into_os_string().into_string() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_expand_matches_files_in_sorted_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.json", "a.json", "c.jsonl", ".hidden.json", "[x].json"] {
+            fs::write(dir.path().join(name), "[]").unwrap();
+        }
+        fs::create_dir(dir.path().join("d.json")).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("e.json"), "[]").unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let path = |name: &str| format!("{}/{}", root, name);
+        assert_eq!(
+            expand(&format!("{}/*.json", root)).unwrap(),
+            [path("[x].json"), path("a.json"), path("b.json")]
+        );
+        assert_eq!(
+            expand(&format!("{}/[[]?].json", root)).unwrap(),
+            [path("[x].json")]
+        );
+        assert_eq!(
+            expand(&format!("{}/[!a-b].json*", root)).unwrap(),
+            [path("c.jsonl")]
+        );
+        assert_eq!(
+            expand(&format!("{}/*/e.json", root)).unwrap(),
+            [path("sub/e.json")]
+        );
+        assert!(expand(&format!("{}/*.csv", root)).unwrap().is_empty());
+        assert!(expand(&format!("{}/missing/*.json", root))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_expand_double_star_searches_nested_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("sub").join("deeper");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("a.json"), "[]").unwrap();
+        fs::write(dir.path().join("sub").join("b.json"), "[]").unwrap();
+        fs::write(nested.join("c.json"), "[]").unwrap();
+        fs::write(nested.join("d.csv"), "").unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let path = |name: &str| format!("{}/{}", root, name);
+        assert_eq!(
+            expand(&format!("{}/**/*.json", root)).unwrap(),
+            [
+                path("a.json"),
+                path("sub/b.json"),
+                path("sub/deeper/c.json")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_invalid_pattern_is_an_error() {
+        let error = expand("data/a**b/*.json").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,159 @@
+//! This module contains the `IndexWriter`, which writes a sidecar index of
+//! where each record is in the JSONL output, allowing consumers to seek
+//! straight to a record.
+//!
+//! # Format
+//!
+//! The index is a sequence of fixed-width entries, one per record in the
+//! order that the records were written. Each entry is `ENTRY_LEN` bytes long
+//! and holds two little-endian `u64`s:
+//!
+//! 1. The byte offset in the output of the start of the record's line.
+//! 2. The length in bytes of the record's line, including its line ending.
+//!
+//! The entry for record `k` (counting from 0) therefore starts at byte
+//! `k * ENTRY_LEN` of the index.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::output::NamedWriter;
+
+/// The length in bytes of each entry in an index.
+pub const ENTRY_LEN: u64 = 16;
+
+/// Wraps the writer that JSONL is written to, writing an index entry to
+/// `index` for each line written through it.
+///
+/// # Fields
+///
+/// * `inner` - The wrapped writer.
+/// * `index` - Where index entries are written to.
+/// * `position` - The number of bytes written to `inner` so far.
+/// * `line_start` - The offset in the output of the start of the current
+///   line.
+pub struct IndexWriter<W: Write, I: Write = BufWriter<NamedWriter<File>>> {
+    inner: W,
+    index: I,
+    position: u64,
+    line_start: u64,
+}
+
+impl<W: Write> IndexWriter<W> {
+    /// Creates a new `IndexWriter` around `inner` that writes the index to a
+    /// file at `path`, truncating it if it already exists. Errors writing to
+    /// the index name its path.
+    ///
+    /// # Errors
+    ///
+    /// * If the index file cannot be created.
+    pub fn create(inner: W, path: &Path) -> io::Result<Self> {
+        let index = NamedWriter::new(File::create(path)?, &path.to_string_lossy());
+        Ok(IndexWriter::new(inner, BufWriter::new(index)))
+    }
+}
+
+impl<W: Write, I: Write> IndexWriter<W, I> {
+    /// Creates a new `IndexWriter` around `inner` that writes the index to
+    /// `index`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::index::IndexWriter;
+    ///
+    /// let mut writer = IndexWriter::new(Vec::new(), Vec::new());
+    /// writer.write_all(b"{}\n[1]\n").unwrap();
+    /// let (_, index) = writer.into_inner();
+    /// assert_eq!(index[16..24], 3u64.to_le_bytes());
+    /// ```
+    pub fn new(inner: W, index: I) -> Self {
+        IndexWriter {
+            inner,
+            index,
+            position: 0,
+            line_start: 0,
+        }
+    }
+
+    /// Consumes the `IndexWriter`, returning the wrapped writer and the
+    /// index.
+    pub fn into_inner(self) -> (W, I) {
+        (self.inner, self.index)
+    }
+}
+
+impl<W: Write, I: Write> Write for IndexWriter<W, I> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for (i, _) in buf[..written]
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b == b'\n')
+        {
+            let line_end = self.position + i as u64 + 1;
+            self.index.write_all(&self.line_start.to_le_bytes())?;
+            self.index
+                .write_all(&(line_end - self.line_start).to_le_bytes())?;
+            self.line_start = line_end;
+        }
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.index.flush()
+    }
+}
+
+/// Reads the entry for record `k` (counting from 0) from an index, returning
+/// the offset and length of the record's line in the output.
+///
+/// # Errors
+///
+/// * If the index cannot be read or has no entry for record `k`.
+pub fn read_entry<R: Read + Seek>(index: &mut R, k: u64) -> io::Result<(u64, u64)> {
+    index.seek(SeekFrom::Start(k * ENTRY_LEN))?;
+    let mut entry = [0; ENTRY_LEN as usize];
+    index.read_exact(&mut entry)?;
+    let (offset, len) = entry.split_at(8);
+    Ok((
+        u64::from_le_bytes(offset.try_into().unwrap()),
+        u64::from_le_bytes(len.try_into().unwrap()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_index_allows_seeking_to_a_record() {
+        let mut writer = IndexWriter::new(Vec::new(), Vec::new());
+        writer.write_all(b"{\"a\":1}\n").unwrap();
+        // A record may be written in more than one call.
+        writer.write_all(b"12\t{\"b\"").unwrap();
+        writer.write_all(b":[2]}\r\n[]\n").unwrap();
+        let (output, index) = writer.into_inner();
+        assert_eq!(index.len() as u64, 3 * ENTRY_LEN);
+
+        let mut index = Cursor::new(index);
+        assert_eq!(read_entry(&mut index, 0).unwrap(), (0, 8));
+        assert_eq!(read_entry(&mut index, 2).unwrap(), (22, 3));
+
+        let (offset, len) = read_entry(&mut index, 1).unwrap();
+        let mut output = Cursor::new(output);
+        output.seek(SeekFrom::Start(offset)).unwrap();
+        let mut line = vec![0; len as usize];
+        output.read_exact(&mut line).unwrap();
+        assert_eq!(line, b"12\t{\"b\":[2]}\r\n");
+
+        assert!(read_entry(&mut index, 3).is_err());
+    }
+}
@@ -0,0 +1,461 @@
+//! This module contains the handling of `--json5`, which converts input
+//! written in JSON5, such as configuration dumps, to strict JSONL.
+//!
+//! # Supported
+//!
+//! * `//` line comments and `/* */` block comments, anywhere outside of
+//!   strings, including before and after the root array. They are dropped
+//!   by the processor, so brackets and quotes inside them are ignored.
+//! * Trailing commas in objects and arrays, which are removed as with
+//!   `--repair-trailing-commas`.
+//! * Single-quoted strings, which are double-quoted as with
+//!   `--single-quotes`.
+//! * Object keys that are unquoted identifiers, such as `{id: 1}`, which are
+//!   quoted.
+//! * Hexadecimal numbers, numbers with a leading or trailing decimal point,
+//!   and numbers with an explicit `+` sign, which `to_json` writes as JSON
+//!   numbers of exactly the same value, so `0xFF` becomes `255` and `+.5`
+//!   becomes `0.5`.
+//! * Strings that span lines with a `\` line continuation, which `to_json`
+//!   joins, and the escapes that JSON does not have, `\x41`, `\v`, `\0` and
+//!   `\'`, which it rewrites.
+//! * `NaN`, `Infinity` and `-Infinity`, with or without a sign, which are
+//!   written as `null` as with `--replace-nan-inf`, as JSON has no way to
+//!   write them.
+//!
+//! # Not supported
+//!
+//! * Unquoted keys with Unicode escapes.
+//!
+//! As every record is checked as with `--strict`, a record using these stops
+//! the conversion, or with `--emit-errors-as-records`, is written as an
+//! error record, rather than being written as invalid JSON.
+//!
+//! The `json5` crate is not used to read the records, as it reads every
+//! number as an `i64` or an `f64`. Decimals would be rounded to the nearest
+//! `f64`, and integers that do not fit an `i64`, or hexadecimal numbers that
+//! do not fit a `u32`, would be rejected, whereas every other option of the
+//! converter writes numbers exactly as they were read. Instead, each record
+//! is rewritten token by token, leaving the JSON in it untouched.
+
+use std::borrow::Cow;
+
+use crate::errors::ConversionError;
+
+/// Where the input is in relation to comments.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Outside of any comment.
+    #[default]
+    Code,
+    /// Just after a `/`, which may start a comment.
+    Slash,
+    /// Inside a `//` comment.
+    Line,
+    /// Inside a `/* */` comment.
+    Block,
+    /// Just after a `*` inside a `/* */` comment, which may end it.
+    BlockStar,
+}
+
+/// What to do with a character given to `Comments::accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStep {
+    /// The character is not part of a comment.
+    Keep,
+    /// The character is part of a comment, so is dropped.
+    Drop,
+    /// The `/` before the character did not start a comment after all, so it
+    /// is kept, followed by the character.
+    KeepSlash,
+}
+
+/// Tracks the comments in the input one character at a time, so that they
+/// can be dropped. Only characters outside of strings are given to it.
+///
+/// The newline that ends a `//` comment is not part of the comment, so is
+/// kept as whitespace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Comments {
+    state: State,
+}
+
+impl Comments {
+    /// Advances past `c`, returning what to do with it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::json5::{CommentStep, Comments};
+    ///
+    /// let mut comments = Comments::default();
+    /// let kept: String = "[1, /* [2] */ 3] // end"
+    ///     .chars()
+    ///     .filter(|&c| comments.accept(c) == CommentStep::Keep)
+    ///     .collect();
+    /// assert_eq!(kept, "[1,  3] ");
+    /// ```
+    pub fn accept(&mut self, c: char) -> CommentStep {
+        let (state, step) = match (self.state, c) {
+            (State::Code, '/') => (State::Slash, CommentStep::Drop),
+            (State::Code, _) => (State::Code, CommentStep::Keep),
+            (State::Slash, '/') => (State::Line, CommentStep::Drop),
+            (State::Slash, '*') => (State::Block, CommentStep::Drop),
+            (State::Slash, _) => (State::Code, CommentStep::KeepSlash),
+            (State::Line, '\n') => (State::Code, CommentStep::Keep),
+            (State::Line, _) => (State::Line, CommentStep::Drop),
+            (State::Block | State::BlockStar, '*') => (State::BlockStar, CommentStep::Drop),
+            (State::BlockStar, '/') => (State::Code, CommentStep::Drop),
+            (State::Block | State::BlockStar, _) => (State::Block, CommentStep::Drop),
+        };
+        self.state = state;
+        step
+    }
+}
+
+/// Reads the root bracket of the input, skipping the whitespace and
+/// comments in front of it.
+///
+/// # Arguments
+///
+/// * `next_char` - Returns the next character of the input, or `None` once
+///   it has ended.
+///
+/// # Returns
+///
+/// * The first character that is neither whitespace nor part of a comment,
+///   or `None` if the input ends before it.
+///
+/// # Errors
+///
+/// * If `next_char` fails to read the input.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::json5::read_root;
+///
+/// let mut chars = "// Exported config\n/* [old] */ [1]".chars();
+/// assert_eq!(read_root(|| Ok(chars.next())).unwrap(), Some('['));
+/// assert_eq!(chars.as_str(), "1]");
+/// ```
+pub fn read_root(
+    mut next_char: impl FnMut() -> Result<Option<char>, ConversionError>,
+) -> Result<Option<char>, ConversionError> {
+    let mut comments = Comments::default();
+    while let Some(c) = next_char()? {
+        match comments.accept(c) {
+            CommentStep::Keep if c.is_whitespace() => {}
+            CommentStep::Keep => return Ok(Some(c)),
+            CommentStep::Drop => {}
+            CommentStep::KeepSlash => return Ok(Some('/')),
+        }
+    }
+    Ok(None)
+}
+
+/// Returns `record` with the JSON5 numbers and string escapes that JSON
+/// does not have rewritten as JSON, or borrowed as it is if it has none.
+///
+/// Records are given to it after their comments have been dropped and their
+/// single-quoted strings double-quoted, so only double-quoted strings are
+/// expected. Signed `NaN` and `Infinity` lose their `+` sign, or the `-` sign
+/// of `-NaN`, and are left for `replace_non_finite` to replace.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::json5::to_json;
+///
+/// assert_eq!(
+///     to_json("[0x1F, +1, .5, 5., \"a\\\nb\\x41\"]"),
+///     "[31, 1, 0.5, 5, \"ab\\u0041\"]"
+/// );
+/// assert_eq!(to_json("{\"a\": 1.5e3}"), "{\"a\": 1.5e3}");
+/// ```
+pub fn to_json(record: &str) -> Cow<'_, str> {
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut rest = record;
+    while let Some(c) = rest.chars().next() {
+        let start = record.len() - rest.len();
+        let token_len = match c {
+            '"' => string_len(rest),
+            _ if c.is_whitespace() || "{}[]:,".contains(c) => c.len_utf8(),
+            _ => rest
+                .find(|c: char| c.is_whitespace() || "{}[]:,\"".contains(c))
+                .unwrap_or(rest.len()),
+        };
+        let token = &rest[..token_len];
+        rest = &rest[token_len..];
+        let rewritten = if c == '"' {
+            string_to_json(token)
+        } else {
+            number_to_json(token)
+        };
+        if let Some(rewritten) = rewritten {
+            result.push_str(&record[copied..start]);
+            result.push_str(&rewritten);
+            copied = start + token_len;
+        }
+    }
+
+    if copied == 0 {
+        return Cow::Borrowed(record);
+    }
+    result.push_str(&record[copied..]);
+    Cow::Owned(result)
+}
+
+/// Returns the length of the double-quoted string at the start of `s`,
+/// including its quotes, or the rest of `s` if the string is not closed.
+fn string_len(s: &str) -> usize {
+    let mut last_char_escape = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if c == '"' && !last_char_escape {
+            return i + 1;
+        }
+        last_char_escape = c == '\\' && !last_char_escape;
+    }
+    s.len()
+}
+
+/// Returns the double-quoted `string` with its line continuations joined
+/// and its JSON5 escapes rewritten, or `None` if it has neither.
+fn string_to_json(string: &str) -> Option<String> {
+    let mut result = String::with_capacity(string.len());
+    let mut changed = false;
+    let mut chars = string.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let Some(escaped) = chars.next() else {
+            result.push(c);
+            break;
+        };
+        match escaped {
+            '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' | 'u' => {
+                result.push(c);
+                result.push(escaped);
+                continue;
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => {}
+            '\r' => {
+                chars.next_if_eq(&'\n');
+            }
+            'v' => result.push_str("\\u000b"),
+            '0' if !chars.peek().is_some_and(char::is_ascii_digit) => result.push_str("\\u0000"),
+            'x' => {
+                let digits: String = chars.clone().take(2).collect();
+                if digits.len() == 2 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    chars.nth(1);
+                    result.push_str("\\u00");
+                    result.push_str(&digits);
+                } else {
+                    // Left for `--strict` to reject.
+                    result.push(c);
+                    result.push(escaped);
+                    continue;
+                }
+            }
+            // Digits cannot be escaped in JSON5 either, so are left for
+            // `--strict` to reject.
+            _ if escaped.is_ascii_digit() => {
+                result.push(c);
+                result.push(escaped);
+                continue;
+            }
+            _ if escaped.is_control() => {
+                result.push_str(&format!("\\u{:04x}", escaped as u32));
+            }
+            // Any other character stands for itself, such as the `'` of
+            // `\'`.
+            _ => result.push(escaped),
+        }
+        changed = true;
+    }
+    changed.then_some(result)
+}
+
+/// Returns `token` written as a JSON number if it is a JSON5 number that
+/// JSON does not allow, or `None` if it is not.
+fn number_to_json(token: &str) -> Option<String> {
+    let (sign, unsigned) = match token.as_bytes().first() {
+        Some(b'+') => ("", &token[1..]),
+        Some(b'-') => ("-", &token[1..]),
+        _ => ("", token),
+    };
+    if unsigned == "Infinity" || unsigned == "NaN" {
+        let sign = if unsigned == "NaN" { "" } else { sign };
+        return (token.len() != sign.len() + unsigned.len())
+            .then(|| format!("{}{}", sign, unsigned));
+    }
+    if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        return hex_to_decimal(hex).map(|decimal| format!("{}{}", sign, decimal));
+    }
+
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(e) => unsigned.split_at(e),
+        None => (unsigned, ""),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let exponent_digits = exponent
+        .get(1..)
+        .map(|e| e.strip_prefix(['+', '-']).unwrap_or(e));
+    if !is_digits(integer)
+        || !fraction.is_none_or(is_digits)
+        || (integer.is_empty() && fraction.is_none_or(str::is_empty))
+        || exponent_digits.is_some_and(|e| e.is_empty() || !is_digits(e))
+    {
+        return None;
+    }
+
+    let integer = if integer.is_empty() { "0" } else { integer };
+    let fraction = match fraction {
+        Some("") | None => String::new(),
+        Some(fraction) => format!(".{}", fraction),
+    };
+    let number = format!("{}{}{}{}", sign, integer, fraction, exponent);
+    (number != token).then_some(number)
+}
+
+/// Returns the hexadecimal digits `hex` as a decimal integer, exactly, or
+/// `None` if they are not all hexadecimal digits.
+fn hex_to_decimal(hex: &str) -> Option<String> {
+    if hex.is_empty() {
+        return None;
+    }
+    // The decimal digits, least significant first.
+    let mut digits: Vec<u32> = vec![0];
+    for c in hex.chars() {
+        let mut carry = c.to_digit(16)?;
+        for digit in digits.iter_mut() {
+            let value = *digit * 16 + carry;
+            *digit = value % 10;
+            carry = value / 10;
+        }
+        while carry > 0 {
+            digits.push(carry % 10);
+            carry /= 10;
+        }
+    }
+    while digits.len() > 1 && digits.last() == Some(&0) {
+        digits.pop();
+    }
+    Some(
+        digits
+            .iter()
+            .rev()
+            .map(|&digit| char::from_digit(digit, 10).unwrap_or('0'))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(input: &str) -> String {
+        let mut comments = Comments::default();
+        let mut kept = String::new();
+        for c in input.chars() {
+            match comments.accept(c) {
+                CommentStep::Keep => kept.push(c),
+                CommentStep::Drop => {}
+                CommentStep::KeepSlash => {
+                    kept.push('/');
+                    kept.push(c);
+                }
+            }
+        }
+        kept
+    }
+
+    #[test]
+    fn test_comments_are_dropped() {
+        assert_eq!(strip("{a: 1, // one\n b: 2}"), "{a: 1, \n b: 2}");
+        assert_eq!(strip("[1, /* a **/ 2 /*/ b */]"), "[1,  2 ]");
+        assert_eq!(strip("/* {\n[ */[]"), "[]");
+    }
+
+    #[test]
+    fn test_slash_that_does_not_start_a_comment_is_kept() {
+        assert_eq!(strip("[1 / 2, 3/4]"), "[1 / 2, 3/4]");
+    }
+
+    #[test]
+    fn test_read_root_without_comments() {
+        let mut chars = " \n[1]".chars();
+        assert_eq!(read_root(|| Ok(chars.next())).unwrap(), Some('['));
+        let mut chars = "// only a comment".chars();
+        assert_eq!(read_root(|| Ok(chars.next())).unwrap(), None);
+        let mut chars = "/x".chars();
+        assert_eq!(read_root(|| Ok(chars.next())).unwrap(), Some('/'));
+    }
+
+    #[test]
+    fn test_numbers_are_written_as_json() {
+        let cases = [
+            ("0x1F", "31"),
+            ("-0XaB", "-171"),
+            // Larger than a `u64`, and kept exactly.
+            ("0x1ffffffffffffffffff", "9444732965739290427391"),
+            ("0x0", "0"),
+            ("+1", "1"),
+            (".5", "0.5"),
+            ("-.5e-3", "-0.5e-3"),
+            ("5.", "5"),
+            ("+5.E2", "5E2"),
+            ("+Infinity", "Infinity"),
+            ("-NaN", "NaN"),
+            ("+NaN", "NaN"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(to_json(number), expected, "{}", number);
+        }
+    }
+
+    #[test]
+    fn test_json_is_untouched() {
+        for record in [
+            "{\"a\": [1, -2.5, 3e10, 1E-2, 0], \"b\": \"0x1F +1 .5\"}",
+            "[true, null, \"\\\"\\\\\\/\\b\\f\\n\\r\\t\\u00e9\"]",
+            "[-Infinity, Infinity, NaN]",
+            // Not numbers, so left for `--strict` to reject.
+            "[0x, 0xG, ., +, 1e, 1.2.3, +-1, 1_000]",
+        ] {
+            assert!(matches!(to_json(record), Cow::Borrowed(_)), "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_strings_are_written_as_json() {
+        let cases = [
+            // Line continuations, with each line terminator.
+            ("\"a\\\nb\"", "\"ab\""),
+            ("\"a\\\r\nb\"", "\"ab\""),
+            ("\"a\\\rb\"", "\"ab\""),
+            ("\"a\\\u{2028}b\"", "\"ab\""),
+            ("\"\\x41\\x7e\"", "\"\\u0041\\u007e\""),
+            ("\"\\v\\0\"", "\"\\u000b\\u0000\""),
+            ("\"it\\'s \\a\"", "\"it's a\""),
+            ("{\"k\\x41\": \"v\"}", "{\"k\\u0041\": \"v\"}"),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(to_json(record), expected, "{}", record);
+        }
+        // Invalid in JSON5 too, so left for `--strict` to reject.
+        for record in ["\"\\x4\"", "\"\\01\"", "\"\\1\""] {
+            assert!(matches!(to_json(record), Cow::Borrowed(_)), "{}", record);
+        }
+    }
+}
@@ -3,18 +3,110 @@
 
 use core::fmt;
 use regex::Regex;
-use std::ops::Deref;
+use std::{borrow::Cow, ops::Deref, str::FromStr, sync::OnceLock};
+
+/// How the whitespace of a JSONL string is cleaned up when it is displayed.
+/// Separator commas before and after the record are always removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    /// Removes newlines and the whitespace around them outside of strings,
+    /// leaving other whitespace, and line breaks within strings, as they were
+    /// in the input.
+    #[default]
+    CollapseNewlines,
+    /// Leaves the whitespace as it was in the input.
+    None,
+    /// Removes line breaks, leaving all other whitespace as it was in the
+    /// input, including indentation. Records are displayed on a single line.
+    StripNewlines,
+    /// Removes all whitespace outside of strings.
+    Compact,
+    /// Indents nested objects and arrays by two spaces per level. The output
+    /// spans multiple lines.
+    Pretty,
+}
+
+/// The format of a JSONL string when it is displayed, for choosing between
+/// the two single-line cleanup policies. See `JSONLString::set_format`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Removes line breaks and the whitespace around them, keeping other
+    /// whitespace as it was in the input, as `CleanupPolicy::CollapseNewlines`
+    /// does.
+    #[default]
+    Compact,
+    /// Removes all whitespace outside of strings, as `CleanupPolicy::Compact`
+    /// does.
+    Minified,
+}
+
+impl From<Format> for CleanupPolicy {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Compact => CleanupPolicy::CollapseNewlines,
+            Format::Minified => CleanupPolicy::Compact,
+        }
+    }
+}
+
+/// How line breaks within the strings of a JSONL string are handled when it
+/// is displayed, which can only appear in non-strict input. Without one, they
+/// are left to the `CleanupPolicy`, which keeps them unless it removes every
+/// line break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlinePolicy {
+    /// Escapes line breaks as `\n` and `\r`, so that the string is valid
+    /// JSON.
+    Escape,
+    /// Keeps line breaks, so that the record spans multiple lines and is not
+    /// valid JSON.
+    Preserve,
+    /// Removes line breaks and the whitespace around them, as the default
+    /// `CleanupPolicy` does outside of strings.
+    Strip,
+}
+
+impl FromStr for NewlinePolicy {
+    type Err = String;
+
+    /// Parses `escape`, `preserve` or `strip`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "escape" => Ok(NewlinePolicy::Escape),
+            "preserve" => Ok(NewlinePolicy::Preserve),
+            "strip" => Ok(NewlinePolicy::Strip),
+            _ => Err(format!(
+                "Unknown newline policy `{}`. Expected `escape`, `preserve` or `strip`.",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the regular expression that matches a line break along with the
+/// whitespace around it, compiling it on first use.
+fn clean_re_pattern() -> &'static Regex {
+    static CLEAN_RE_PATTERN: OnceLock<Regex> = OnceLock::new();
+    CLEAN_RE_PATTERN.get_or_init(|| Regex::new(r"\s{0,}\n\s{0,}").unwrap())
+}
 
 /// This struct represents a JSONL string being built.
 ///
 /// # Fields
 ///
 /// * `string` - The JSONL string being built.
-/// * `clean_re_pattern` - A regular expression pattern used to clean the
-/// JSONL string.
+/// * `policy` - How the JSONL string is cleaned up when it is displayed.
+/// * `newlines` - If set, how line breaks within strings are handled when it
+///   is displayed, rather than by the `policy`.
+///
+/// Cloning copies the `string`. The regular expression used to collapse
+/// newlines is compiled once for the whole process and shared by every
+/// instance, so creating one, as is done for every input, is cheap.
+#[derive(Clone)]
 pub struct JSONLString {
     string: String,
-    clean_re_pattern: Regex,
+    policy: CleanupPolicy,
+    newlines: Option<NewlinePolicy>,
 }
 
 impl Deref for JSONLString {
@@ -26,11 +118,117 @@ impl Deref for JSONLString {
 }
 
 impl JSONLString {
-    /// Creates a new instance of `JSONLString`.
+    /// Creates a new instance of `JSONLString` that collapses newlines when
+    /// displayed.
     pub fn new() -> Self {
+        JSONLString::with_policy(CleanupPolicy::default())
+    }
+
+    /// Creates a new instance of `JSONLString` that is cleaned up according
+    /// to `policy` when displayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - How the string is cleaned up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::json_object::{CleanupPolicy, JSONLString};
+    ///
+    /// let mut jsonl_string = JSONLString::with_policy(CleanupPolicy::Compact);
+    /// jsonl_string.push_str("{\"a b\": [1, 2]},");
+    /// assert_eq!(jsonl_string.to_string(), "{\"a b\":[1,2]}");
+    /// ```
+    pub fn with_policy(policy: CleanupPolicy) -> Self {
         JSONLString {
             string: String::new(),
-            clean_re_pattern: Regex::new(r"\s{0,}\n\s{0,}").unwrap(),
+            policy,
+            newlines: None,
+        }
+    }
+
+    /// Returns how the string is cleaned up when displayed.
+    pub fn policy(&self) -> CleanupPolicy {
+        self.policy
+    }
+
+    /// Sets how the string is cleaned up when displayed.
+    pub fn set_policy(&mut self, policy: CleanupPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets the format of the string when displayed, which replaces its
+    /// `CleanupPolicy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::json_object::{Format, JSONLString};
+    ///
+    /// let mut jsonl_string = JSONLString::new();
+    /// jsonl_string.push_str("{ \"a b\" :  1 }");
+    /// assert_eq!(jsonl_string.to_string(), "{ \"a b\" :  1 }");
+    /// jsonl_string.set_format(Format::Minified);
+    /// assert_eq!(jsonl_string.to_string(), "{\"a b\":1}");
+    /// ```
+    pub fn set_format(&mut self, format: Format) {
+        self.policy = format.into();
+    }
+
+    /// Sets how line breaks within strings are handled when displayed. `None`
+    /// leaves them to the `CleanupPolicy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::json_object::{JSONLString, NewlinePolicy};
+    ///
+    /// let mut jsonl_string = JSONLString::new();
+    /// jsonl_string.set_newlines(Some(NewlinePolicy::Escape));
+    /// jsonl_string.push_str("{\n  \"a\": \"b\nc\"\n}");
+    /// assert_eq!(jsonl_string.to_string(), "{\"a\": \"b\\nc\"}");
+    /// ```
+    pub fn set_newlines(&mut self, newlines: Option<NewlinePolicy>) {
+        self.newlines = newlines;
+    }
+
+    /// Cleans up the whitespace outside of strings according to the `policy`
+    /// and the line breaks inside of them according to `newlines`.
+    fn clean_by_string(&self, newlines: NewlinePolicy) -> String {
+        let mut result = String::with_capacity(self.string.len());
+        let mut run = String::new();
+        let mut run_in_string = false;
+        for_each_char(&self.string, |c, in_string| {
+            if in_string != run_in_string {
+                self.push_run(&mut result, &run, run_in_string, newlines);
+                run.clear();
+                run_in_string = in_string;
+            }
+            run.push(c);
+        });
+        self.push_run(&mut result, &run, run_in_string, newlines);
+        result
+    }
+
+    /// Pushes a run of characters that are all inside or all outside of
+    /// strings to `result`, cleaned up as `clean_by_string` describes.
+    fn push_run(&self, result: &mut String, run: &str, in_string: bool, newlines: NewlinePolicy) {
+        match (in_string, newlines, self.policy) {
+            (true, NewlinePolicy::Escape, _) => {
+                result.push_str(&run.replace('\r', "\\r").replace('\n', "\\n"))
+            }
+            (true, NewlinePolicy::Preserve, _) => result.push_str(run),
+            (true, NewlinePolicy::Strip, _) | (false, _, CleanupPolicy::CollapseNewlines) => {
+                result.push_str(&clean_re_pattern().replace_all(run, ""))
+            }
+            (false, _, CleanupPolicy::None) => result.push_str(run),
+            (false, _, CleanupPolicy::StripNewlines) => {
+                result.extend(run.chars().filter(|&c| c != '\r' && c != '\n'))
+            }
+            (false, _, CleanupPolicy::Compact | CleanupPolicy::Pretty) => {
+                result.extend(run.chars().filter(|c| !c.is_whitespace()))
+            }
         }
     }
 
@@ -84,18 +282,134 @@ impl JSONLString {
     pub fn clear(&mut self) {
         self.string.clear();
     }
+
+    /// Removes the whitespace at the end of the `string`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::json_object::JSONLString;
+    ///
+    /// let mut jsonl_string = JSONLString::new();
+    /// jsonl_string.push_str(&"\"abc\" \n");
+    /// jsonl_string.trim_end();
+    /// assert_eq!(jsonl_string.as_str(), "\"abc\"");
+    /// ```
+    pub fn trim_end(&mut self) {
+        let len = self.string.trim_end().len();
+        self.string.truncate(len);
+    }
+}
+
+impl Default for JSONLString {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Display for JSONLString {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let result = self.clean_re_pattern.replace_all(&self.string, "");
-        write!(
-            f,
-            "{}",
-            result
-                .trim_start_matches(',')
-                .trim_end_matches(',')
-        )
+        let result = match self.policy {
+            _ if self.newlines.is_some() && self.string.contains(['\r', '\n']) => {
+                Cow::Owned(self.clean_by_string(self.newlines.unwrap()))
+            }
+            // The pattern only matches around a newline, and most records,
+            // such as those built from trimmed lines, have none.
+            CleanupPolicy::CollapseNewlines if !self.string.contains('\n') => {
+                Cow::Borrowed(self.string.as_str())
+            }
+            // Line breaks within strings are part of the value, so are kept.
+            CleanupPolicy::CollapseNewlines => {
+                Cow::Owned(self.clean_by_string(NewlinePolicy::Preserve))
+            }
+            CleanupPolicy::None => Cow::Borrowed(self.string.as_str()),
+            CleanupPolicy::StripNewlines => Cow::Owned(self.string.replace(['\r', '\n'], "")),
+            CleanupPolicy::Compact | CleanupPolicy::Pretty => Cow::Owned(compact(&self.string)),
+        };
+        let result = match self.policy {
+            CleanupPolicy::StripNewlines => {
+                result.trim_matches(|c: char| c == ',' || c.is_whitespace())
+            }
+            _ => result.trim_start_matches(',').trim_end_matches(','),
+        };
+        if self.policy == CleanupPolicy::Pretty {
+            write!(f, "{}", pretty(result))
+        } else {
+            write!(f, "{}", result)
+        }
+    }
+}
+
+/// Calls `f` with each character of `s` and whether that character is part
+/// of a string, including its quotes.
+fn for_each_char(s: &str, mut f: impl FnMut(char, bool)) {
+    let mut inside_string = false;
+    let mut last_char_escape = false;
+    for c in s.chars() {
+        let is_quote = c == '"' && !last_char_escape;
+        f(c, inside_string || is_quote);
+        if is_quote {
+            inside_string = !inside_string;
+        }
+        last_char_escape = inside_string && c == '\\' && !last_char_escape;
+    }
+}
+
+/// Returns `s` without any whitespace outside of strings.
+fn compact(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for_each_char(s, |c, in_string| {
+        if in_string || !c.is_whitespace() {
+            result.push(c);
+        }
+    });
+    result
+}
+
+/// Returns the compacted JSON `s` spread over multiple lines, with nested
+/// objects and arrays indented by two spaces per level. Empty objects and
+/// arrays are kept on a single line.
+fn pretty(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() * 2);
+    let mut depth = 0usize;
+    let mut just_opened = false;
+    for_each_char(s, |c, in_string| {
+        if just_opened {
+            just_opened = false;
+            if !in_string && (c == '}' || c == ']') {
+                result.push(c);
+                return;
+            }
+            depth += 1;
+            push_newline(&mut result, depth);
+        }
+        match c {
+            _ if in_string => result.push(c),
+            '{' | '[' => {
+                result.push(c);
+                just_opened = true;
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                push_newline(&mut result, depth);
+                result.push(c);
+            }
+            ',' => {
+                result.push(c);
+                push_newline(&mut result, depth);
+            }
+            ':' => result.push_str(": "),
+            _ => result.push(c),
+        }
+    });
+    result
+}
+
+/// Pushes a newline followed by the indent for `depth`.
+fn push_newline(result: &mut String, depth: usize) {
+    result.push('\n');
+    for _ in 0..depth {
+        result.push_str("  ");
     }
 }
 
@@ -138,6 +452,18 @@ mod tests {
         assert_eq!(jsonl_string.to_string(), "{\"a\": 1}\"");
     }
 
+    #[test]
+    fn test_jsonl_string_instances_share_the_cleanup_pattern() {
+        let input = "{\n  \"a\": [\n    1,\n    2\n  ]\n},";
+        let mut first = JSONLString::new();
+        first.push_str(input);
+        let mut second = JSONLString::new();
+        second.push_str(input);
+        assert_eq!(first.to_string(), "{\"a\": [1,2]}");
+        assert_eq!(first.to_string(), second.to_string());
+        assert!(std::ptr::eq(clean_re_pattern(), clean_re_pattern()));
+    }
+
     #[test]
     fn test_jsonl_string_display_removes_leading_comma() {
         let mut jsonl_string = JSONLString::new();
@@ -145,6 +471,195 @@ mod tests {
         assert_eq!(jsonl_string.to_string(), "{\"a\": 1}");
     }
 
+    fn display_with(policy: CleanupPolicy, s: &str) -> String {
+        let mut jsonl_string = JSONLString::with_policy(policy);
+        jsonl_string.push_str(s);
+        jsonl_string.to_string()
+    }
+
+    #[test]
+    fn test_new_collapses_newlines() {
+        assert_eq!(JSONLString::new().policy(), CleanupPolicy::CollapseNewlines);
+        assert_eq!(
+            display_with(CleanupPolicy::CollapseNewlines, "{\n  \"a\": [1, 2]\n},"),
+            "{\"a\": [1, 2]}"
+        );
+    }
+
+    #[test]
+    fn test_collapse_newlines_without_newlines_is_unchanged() {
+        assert_eq!(
+            display_with(CleanupPolicy::CollapseNewlines, "{\"a\":  [1,\t2]},"),
+            "{\"a\":  [1,\t2]}"
+        );
+    }
+
+    #[test]
+    fn test_none_policy_keeps_whitespace() {
+        assert_eq!(
+            display_with(CleanupPolicy::None, ",{\n  \"a\": 1\n},"),
+            "{\n  \"a\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn test_strip_newlines_policy_keeps_other_whitespace() {
+        assert_eq!(
+            display_with(
+                CleanupPolicy::StripNewlines,
+                "{\r\n    \"a\":  1,\n    \"b\": [ 2 ]\n  }, \n"
+            ),
+            "{    \"a\":  1,    \"b\": [ 2 ]  }"
+        );
+    }
+
+    #[test]
+    fn test_compact_policy_keeps_whitespace_inside_strings() {
+        assert_eq!(
+            display_with(
+                CleanupPolicy::Compact,
+                "{ \"a b\" : [1, \" \\\" , \"],\n \"c\": {} },"
+            ),
+            "{\"a b\":[1,\" \\\" , \"],\"c\":{}}"
+        );
+    }
+
+    #[test]
+    fn test_only_compact_policy_removes_spaces_around_keys() {
+        assert_eq!(
+            display_with(CleanupPolicy::Compact, "{ \"a\" :  1 }"),
+            "{\"a\":1}"
+        );
+        assert_eq!(
+            display_with(CleanupPolicy::CollapseNewlines, "{ \"a\" :  1 }"),
+            "{ \"a\" :  1 }"
+        );
+    }
+
+    #[test]
+    fn test_only_minified_format_removes_insignificant_whitespace() {
+        let display = |format: Format, s: &str| {
+            let mut jsonl_string = JSONLString::new();
+            jsonl_string.set_format(format);
+            jsonl_string.push_str(s);
+            jsonl_string.to_string()
+        };
+        assert_eq!(display(Format::Minified, "{ \"a\" :  1 }"), "{\"a\":1}");
+        assert_eq!(display(Format::Compact, "{ \"a\" :  1 }"), "{ \"a\" :  1 }");
+        assert_eq!(
+            display(Format::Minified, "{\n  \"a b\" : [ \"c  d\" ]\n}"),
+            "{\"a b\":[\"c  d\"]}"
+        );
+        assert_eq!(
+            display(Format::Compact, "{\n  \"a b\" : [ \"c  d\" ]\n}"),
+            "{\"a b\" : [ \"c  d\" ]}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_policy_indents_nested_values() {
+        assert_eq!(
+            display_with(
+                CleanupPolicy::Pretty,
+                "{\"a\": [1, 2], \"b\": {}, \"c\": {\"d\": \"{x, y}\"}},"
+            ),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {},\n  \"c\": {\n    \"d\": \"{x, y}\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_policy_keeps_empty_array_on_one_line() {
+        assert_eq!(display_with(CleanupPolicy::Pretty, "[ ]"), "[]");
+    }
+
+    fn display_newlines(policy: CleanupPolicy, newlines: NewlinePolicy, s: &str) -> String {
+        let mut jsonl_string = JSONLString::with_policy(policy);
+        jsonl_string.set_newlines(Some(newlines));
+        jsonl_string.push_str(s);
+        jsonl_string.to_string()
+    }
+
+    const MULTILINE_VALUE: &str = "{\n  \"a\": \"b\n  c\",\n  \"d\": \"e\\\"\r\n\"\n},";
+
+    #[test]
+    fn test_newlines_in_strings_are_escaped() {
+        assert_eq!(
+            display_newlines(
+                CleanupPolicy::default(),
+                NewlinePolicy::Escape,
+                MULTILINE_VALUE
+            ),
+            "{\"a\": \"b\\n  c\",\"d\": \"e\\\"\\r\\n\"}"
+        );
+        assert_eq!(
+            display_newlines(
+                CleanupPolicy::Compact,
+                NewlinePolicy::Escape,
+                MULTILINE_VALUE
+            ),
+            "{\"a\":\"b\\n  c\",\"d\":\"e\\\"\\r\\n\"}"
+        );
+    }
+
+    #[test]
+    fn test_newlines_in_strings_are_preserved() {
+        assert_eq!(
+            display_newlines(
+                CleanupPolicy::default(),
+                NewlinePolicy::Preserve,
+                MULTILINE_VALUE
+            ),
+            "{\"a\": \"b\n  c\",\"d\": \"e\\\"\r\n\"}"
+        );
+        assert_eq!(
+            display_newlines(
+                CleanupPolicy::StripNewlines,
+                NewlinePolicy::Preserve,
+                MULTILINE_VALUE
+            ),
+            "{  \"a\": \"b\n  c\",  \"d\": \"e\\\"\r\n\"}"
+        );
+    }
+
+    #[test]
+    fn test_newlines_in_strings_are_stripped_with_the_strip_policy() {
+        assert_eq!(
+            display_newlines(
+                CleanupPolicy::default(),
+                NewlinePolicy::Strip,
+                MULTILINE_VALUE
+            ),
+            "{\"a\": \"bc\",\"d\": \"e\\\"\"}"
+        );
+    }
+
+    #[test]
+    fn test_newlines_in_strings_are_kept_by_default() {
+        assert_eq!(
+            display_with(CleanupPolicy::default(), MULTILINE_VALUE),
+            display_newlines(
+                CleanupPolicy::default(),
+                NewlinePolicy::Preserve,
+                MULTILINE_VALUE
+            )
+        );
+        assert_eq!(
+            display_with(
+                CleanupPolicy::default(),
+                "{\n  \"bio\": \"line1\nline2\",\n  \"n\": 1\n}"
+            ),
+            "{\"bio\": \"line1\nline2\",\"n\": 1}"
+        );
+    }
+
+    #[test]
+    fn test_newline_policy_from_str() {
+        assert_eq!("Escape".parse(), Ok(NewlinePolicy::Escape));
+        assert_eq!("preserve".parse(), Ok(NewlinePolicy::Preserve));
+        assert_eq!("strip".parse(), Ok(NewlinePolicy::Strip));
+        assert!("keep".parse::<NewlinePolicy>().is_err());
+    }
+
     #[test]
     fn test_jsonl_len_returns_string_length() {
         let mut jsonl_string = JSONLString::new();
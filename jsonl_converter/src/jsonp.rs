@@ -0,0 +1,173 @@
+//! This module contains the handling of JSONP padding for `--strip-jsonp`,
+//! as returned by some legacy APIs, where the root array is passed to a
+//! callback: `callback([...]);`.
+//!
+//! The padding is only recognised when the input starts with a callback
+//! name, which cannot be mistaken for an array as arrays start with `[`.
+//! Input without padding is converted as usual.
+
+use crate::errors::ConversionError;
+
+/// Reads the JSONP padding in front of the root bracket, if there is any.
+///
+/// # Arguments
+///
+/// * `next_char` - Returns the next character of the input, or `None` once
+///   it has ended.
+///
+/// # Returns
+///
+/// * The root bracket, or `None` if the input ends before it, along with
+///   whether it was padded.
+///
+/// # Errors
+///
+/// * If `next_char` fails to read the input.
+/// * If a callback name is not followed by `(`.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::jsonp::read_padded_root;
+///
+/// let mut chars = " window.cb ([1]);".chars();
+/// let root = read_padded_root(|| Ok(chars.next())).unwrap();
+/// assert_eq!(root, Some(('[', true)));
+/// assert_eq!(chars.as_str(), "1]);");
+///
+/// let mut chars = "[1]".chars();
+/// assert_eq!(read_padded_root(|| Ok(chars.next())).unwrap(), Some(('[', false)));
+/// ```
+pub fn read_padded_root(
+    mut next_char: impl FnMut() -> Result<Option<char>, ConversionError>,
+) -> Result<Option<(char, bool)>, ConversionError> {
+    let Some(first) = skip_whitespace(&mut next_char, None)? else {
+        return Ok(None);
+    };
+    if !is_callback_start(first) {
+        return Ok(Some((first, false)));
+    }
+
+    let mut callback = String::from(first);
+    let mut c = next_char()?;
+    while let Some(next) = c.filter(|&c| is_callback_char(c)) {
+        callback.push(next);
+        c = next_char()?;
+    }
+    if skip_whitespace(&mut next_char, c)? != Some('(') {
+        return Err(ConversionError::InvalidJsonp(format!(
+            "Expected `(` after the JSONP callback `{}`.",
+            callback
+        )));
+    }
+    Ok(skip_whitespace(&mut next_char, None)?.map(|root| (root, true)))
+}
+
+/// Returns `c`, or if it is whitespace or `None`, the next character that is
+/// not whitespace.
+fn skip_whitespace(
+    next_char: &mut impl FnMut() -> Result<Option<char>, ConversionError>,
+    c: Option<char>,
+) -> Result<Option<char>, ConversionError> {
+    let mut c = match c {
+        Some(c) => Some(c),
+        None => next_char()?,
+    };
+    while c.is_some_and(char::is_whitespace) {
+        c = next_char()?;
+    }
+    Ok(c)
+}
+
+/// Checks if a character can start a callback name.
+fn is_callback_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '$'
+}
+
+/// Checks if a character can be part of a callback name, including the dots
+/// of names such as `window.callback`.
+fn is_callback_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.')
+}
+
+/// Tracks the `);` that closes JSONP padding once the root array has been
+/// closed. The `;` is optional.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JsonpTail {
+    closed: bool,
+    terminated: bool,
+}
+
+impl JsonpTail {
+    /// Accepts `c` if it is the next character of the `);`, returning whether
+    /// it was accepted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::jsonp::JsonpTail;
+    ///
+    /// let mut tail = JsonpTail::default();
+    /// assert!(!tail.accept(';'));
+    /// assert!(tail.accept(')'));
+    /// assert!(tail.accept(';'));
+    /// assert!(!tail.accept(')'));
+    /// assert!(tail.is_closed());
+    /// ```
+    pub fn accept(&mut self, c: char) -> bool {
+        match c {
+            ')' if !self.closed => self.closed = true,
+            ';' if self.closed && !self.terminated => self.terminated = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns whether the `)` has been read.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Root = Result<Option<(char, bool)>, ConversionError>;
+
+    fn read(input: &str) -> (Root, &str) {
+        let mut chars = input.chars();
+        let root = read_padded_root(|| Ok(chars.next()));
+        (root, chars.as_str())
+    }
+
+    #[test]
+    fn test_padding_is_stripped() {
+        for input in ["cb([1]);", "\n  cb  (\n [1]);", "$jQuery_1.x([1])"] {
+            let (root, rest) = read(input);
+            assert_eq!(root.unwrap(), Some(('[', true)), "{}", input);
+            assert!(rest.starts_with("1]"), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_input_without_padding_is_untouched() {
+        for input in ["[[{\"a\": [1]}], 2]", "  {\"a\": 1}", "\"cb(\""] {
+            let (root, rest) = read(input);
+            let first = input.trim_start().chars().next().unwrap();
+            assert_eq!(root.unwrap(), Some((first, false)), "{}", input);
+            assert_eq!(rest, &input.trim_start()[1..]);
+        }
+        assert_eq!(read(" \n").0.unwrap(), None);
+    }
+
+    #[test]
+    fn test_callback_must_be_followed_by_a_paren() {
+        let (root, _) = read("callback [1]");
+        assert_eq!(
+            root.unwrap_err().to_string(),
+            "Expected `(` after the JSONP callback `callback`."
+        );
+        assert!(read("cb").0.is_err());
+    }
+}
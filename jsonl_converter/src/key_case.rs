@@ -0,0 +1,388 @@
+//! This module contains the rewriting of object keys for `--key-case`, so
+//! that records from producers with different conventions can be loaded into
+//! a target that expects consistent casing.
+//!
+//! # Rules
+//!
+//! * A key is split into words at `_`, `-` and spaces, and where the case
+//!   changes, so `firstName`, `first_name`, `first-name` and `FirstName` all
+//!   have the words `first` and `name`. A run of capitals is one word, so
+//!   `HTTPServer` has the words `http` and `server`. Digits stay with the
+//!   word before them.
+//! * Leading and trailing runs of other characters are kept, so `_id` stays
+//!   `_id`. Other characters within a key, such as the `.` of a flattened
+//!   key, are kept and each side is cased on its own.
+//! * Keys containing escapes are left alone.
+//! * If two keys of the same object would become the same key, such as
+//!   `first_name` and `firstName` in camel case, the record is an error
+//!   rather than being written with a duplicate key.
+//! * Converting a key that is already in the case leaves it unchanged. The
+//!   exception is camel case with single letter words next to each other,
+//!   such as the `b` and `c` of `a_b_c`, which becomes `aBC` and is then read
+//!   back as the words `a` and `bc`.
+
+use std::{borrow::Cow, collections::HashMap, str::FromStr};
+
+/// A convention for casing object keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    /// `first_name`
+    Snake,
+    /// `firstName`
+    Camel,
+    /// `first-name`
+    Kebab,
+}
+
+impl FromStr for KeyCase {
+    type Err = String;
+
+    /// Parses `snake`, `camel` or `kebab`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "snake" => Ok(KeyCase::Snake),
+            "camel" => Ok(KeyCase::Camel),
+            "kebab" => Ok(KeyCase::Kebab),
+            _ => Err(format!(
+                "Unknown key case `{}`. Expected `snake`, `camel` or `kebab`.",
+                s
+            )),
+        }
+    }
+}
+
+impl KeyCase {
+    /// Returns `key` converted to the case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::key_case::KeyCase;
+    ///
+    /// assert_eq!(KeyCase::Snake.convert("firstName"), "first_name");
+    /// assert_eq!(KeyCase::Camel.convert("first_name"), "firstName");
+    /// assert_eq!(KeyCase::Kebab.convert("_userID"), "_user-id");
+    /// ```
+    pub fn convert(&self, key: &str) -> String {
+        let start = key.find(char::is_alphanumeric).unwrap_or(key.len());
+        let end = key
+            .rfind(char::is_alphanumeric)
+            .map_or(start, |i| i + key[i..].chars().next().unwrap().len_utf8());
+
+        let mut result = String::with_capacity(key.len());
+        result.push_str(&key[..start]);
+        let mut words = Vec::new();
+        for c in key[start..end].chars() {
+            if c.is_alphanumeric() {
+                words.push(c);
+            } else if matches!(c, '_' | '-' | ' ') {
+                words.push(' ');
+            } else {
+                self.push_words(&mut result, &words.drain(..).collect::<String>());
+                result.push(c);
+            }
+        }
+        self.push_words(&mut result, &words.into_iter().collect::<String>());
+        result.push_str(&key[end..]);
+        result
+    }
+
+    /// Pushes the words of `s`, which are separated by spaces and changes of
+    /// case, onto `result` in the case.
+    fn push_words(&self, result: &mut String, s: &str) {
+        let words = s.split(' ').flat_map(split_case).filter(|w| !w.is_empty());
+        for (i, word) in words.enumerate() {
+            match self {
+                KeyCase::Snake | KeyCase::Kebab => {
+                    if i > 0 {
+                        result.push(if *self == KeyCase::Snake { '_' } else { '-' });
+                    }
+                    result.extend(word.chars().flat_map(char::to_lowercase));
+                }
+                KeyCase::Camel => {
+                    let mut chars = word.chars();
+                    if i > 0 {
+                        result.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+                    }
+                    result.extend(chars.flat_map(char::to_lowercase));
+                }
+            }
+        }
+    }
+}
+
+/// Splits `s` into words where its case changes: before a capital that
+/// follows a lowercase letter or digit, and before the last capital of a run
+/// of them that is followed by a lowercase letter.
+fn split_case(s: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut words = Vec::new();
+    let mut word_start = 0;
+    for (j, &(i, c)) in chars.iter().enumerate().skip(1) {
+        let prev = chars[j - 1].1;
+        let next = chars.get(j + 1).map(|&(_, c)| c);
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase()
+                || prev.is_numeric()
+                || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+        if boundary {
+            words.push(&s[word_start..i]);
+            word_start = i;
+        }
+    }
+    words.push(&s[word_start..]);
+    words
+}
+
+/// Rewrites the object keys of records into a case.
+///
+/// # Fields
+///
+/// * `case` - The case to convert keys to.
+/// * `recursive` - Whether the keys of nested objects are converted as well
+///   as those at the top level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCaser {
+    pub case: KeyCase,
+    pub recursive: bool,
+}
+
+impl KeyCaser {
+    /// Returns `record` with its keys converted, leaving values and the
+    /// layout of the record untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A JSON value.
+    ///
+    /// # Errors
+    ///
+    /// * If two keys of the same object would become the same key, with a
+    ///   message naming them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::key_case::{KeyCase, KeyCaser};
+    ///
+    /// let caser = KeyCaser {
+    ///     case: KeyCase::Snake,
+    ///     recursive: false,
+    /// };
+    /// assert_eq!(
+    ///     caser.rewrite("{\"userId\": {\"firstName\": \"fooBar\"}}").unwrap(),
+    ///     "{\"user_id\": {\"firstName\": \"fooBar\"}}"
+    /// );
+    /// assert!(caser.rewrite("{\"user_id\": 1, \"userId\": 2}").is_err());
+    /// ```
+    pub fn rewrite<'a>(&self, record: &'a str) -> Result<Cow<'a, str>, String> {
+        let mut result = String::new();
+        let mut copied = 0;
+        // For each object or array that is open, the converted keys seen in
+        // it so far, each with the key it was converted from. Arrays have
+        // none.
+        let mut open: Vec<Option<HashMap<String, &str>>> = Vec::new();
+        let mut string_start = None;
+        let mut last_char_escape = false;
+        for (i, c) in record.char_indices() {
+            let Some(start) = string_start else {
+                match c {
+                    '"' => string_start = Some(i + 1),
+                    '{' => open.push(Some(HashMap::new())),
+                    '[' => open.push(None),
+                    '}' | ']' => {
+                        open.pop();
+                    }
+                    _ => {}
+                }
+                continue;
+            };
+            if c == '"' && !last_char_escape {
+                let key = &record[start..i];
+                if (self.recursive || open.len() == 1) && is_key(&record[i + 1..]) {
+                    let converted = if key.contains('\\') {
+                        key.to_string()
+                    } else {
+                        self.case.convert(key)
+                    };
+                    if converted != key {
+                        result.push_str(&record[copied..start]);
+                        result.push_str(&converted);
+                        copied = i;
+                    }
+                    if let Some(Some(keys)) = open.last_mut() {
+                        check_collision(keys, converted, key)?;
+                    }
+                }
+                string_start = None;
+            }
+            last_char_escape = c == '\\' && !last_char_escape;
+        }
+
+        if copied == 0 {
+            return Ok(Cow::Borrowed(record));
+        }
+        result.push_str(&record[copied..]);
+        Ok(Cow::Owned(result))
+    }
+}
+
+/// Records that `key` of an object was converted to `converted`, where
+/// `keys` holds the keys of the object converted so far.
+///
+/// # Errors
+///
+/// * If a different key of the object was already converted to the same
+///   key. A key repeated as it is in the input is left to the reader.
+fn check_collision<'a>(
+    keys: &mut HashMap<String, &'a str>,
+    converted: String,
+    key: &'a str,
+) -> Result<(), String> {
+    match keys.get(&converted) {
+        Some(&previous) if previous != key => Err(format!(
+            "The keys `{}` and `{}` would both become `{}`.",
+            previous, key, converted
+        )),
+        Some(_) => Ok(()),
+        None => {
+            keys.insert(converted, key);
+            Ok(())
+        }
+    }
+}
+
+/// Checks if a string is an object key, given the part of the record that
+/// follows its closing quote.
+fn is_key(rest: &str) -> bool {
+    rest.trim_start().starts_with(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_representative_keys() {
+        let cases = [
+            ("firstName", "first_name", "firstName", "first-name"),
+            ("first_name", "first_name", "firstName", "first-name"),
+            ("first-name", "first_name", "firstName", "first-name"),
+            ("FirstName", "first_name", "firstName", "first-name"),
+            ("HTTPServer", "http_server", "httpServer", "http-server"),
+            ("userID", "user_id", "userId", "user-id"),
+            (
+                "address2Line",
+                "address2_line",
+                "address2Line",
+                "address2-line",
+            ),
+            ("_id", "_id", "_id", "_id"),
+            ("__meta__Key", "__meta_key", "__metaKey", "__meta-key"),
+            ("first  name", "first_name", "firstName", "first-name"),
+            ("a.bC", "a.b_c", "a.bC", "a.b-c"),
+            ("caféOwner", "café_owner", "caféOwner", "café-owner"),
+            ("", "", "", ""),
+            ("$", "$", "$", "$"),
+        ];
+        for (key, snake, camel, kebab) in cases {
+            assert_eq!(KeyCase::Snake.convert(key), snake, "{}", key);
+            assert_eq!(KeyCase::Camel.convert(key), camel, "{}", key);
+            assert_eq!(KeyCase::Kebab.convert(key), kebab, "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_convert_is_idempotent() {
+        for key in ["firstName", "HTTPServer", "_user-ID", "a.b_cat", "x2Y"] {
+            for case in [KeyCase::Snake, KeyCase::Camel, KeyCase::Kebab] {
+                let once = case.convert(key);
+                assert_eq!(case.convert(&once), once, "{:?} {}", case, key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewrite_only_changes_keys() {
+        let caser = KeyCaser {
+            case: KeyCase::Snake,
+            recursive: true,
+        };
+        assert_eq!(
+            caser
+                .rewrite("{\"userId\" : \"someValue\", \"tags\": [\"aB\", {\"innerKey\": 1}]}")
+                .unwrap(),
+            "{\"user_id\" : \"someValue\", \"tags\": [\"aB\", {\"inner_key\": 1}]}"
+        );
+        assert!(matches!(
+            caser.rewrite("[\"firstName\", {\"a\": \"b:\"}]").unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_top_level_only() {
+        let caser = KeyCaser {
+            case: KeyCase::Camel,
+            recursive: false,
+        };
+        assert_eq!(
+            caser
+                .rewrite("{\"user_id\": {\"first_name\": 1}, \"a_b\": [{\"c_d\": 2}]}")
+                .unwrap(),
+            "{\"userId\": {\"first_name\": 1}, \"aB\": [{\"c_d\": 2}]}"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_leaves_escaped_keys_alone() {
+        let caser = KeyCaser {
+            case: KeyCase::Snake,
+            recursive: false,
+        };
+        assert_eq!(
+            caser.rewrite("{\"a\\\"B\": 1, \"cD\": \"}\"}").unwrap(),
+            "{\"a\\\"B\": 1, \"c_d\": \"}\"}"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_rejects_keys_that_collide() {
+        let caser = KeyCaser {
+            case: KeyCase::Camel,
+            recursive: true,
+        };
+        assert_eq!(
+            caser.rewrite("{\"first_name\": 1, \"firstName\": 2}"),
+            Err(String::from(
+                "The keys `first_name` and `firstName` would both become `firstName`."
+            ))
+        );
+        assert!(caser
+            .rewrite("{\"a\": {\"user_id\": 1, \"user-id\": 2}}")
+            .is_err());
+        // The same keys in different objects, and a key repeated as it is in
+        // the input, do not collide.
+        assert_eq!(
+            caser
+                .rewrite("[{\"first_name\": 1}, {\"firstName\": 2, \"b\": {\"firstName\": 3}}]")
+                .unwrap(),
+            "[{\"firstName\": 1}, {\"firstName\": 2, \"b\": {\"firstName\": 3}}]"
+        );
+        assert!(caser.rewrite("{\"a_b\": 1, \"a_b\": 2}").is_ok());
+
+        let top_level = KeyCaser {
+            recursive: false,
+            ..caser
+        };
+        assert!(top_level
+            .rewrite("{\"a\": {\"user_id\": 1, \"userId\": 2}}")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_key_case_from_str() {
+        assert_eq!("Kebab".parse(), Ok(KeyCase::Kebab));
+        assert!("pascal".parse::<KeyCase>().is_err());
+    }
+}
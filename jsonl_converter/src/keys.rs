@@ -0,0 +1,460 @@
+//! This module contains functions for looking up the top level keys of a
+//! completed JSONL record without parsing the whole record.
+
+use std::{borrow::Cow, ops::ControlFlow};
+
+/// Returns the raw JSON text of the value of the top level `key` in `record`,
+/// or `None` if `record` is not an object or does not contain `key`.
+///
+/// The record is scanned rather than parsed, keeping track of strings so that
+/// brackets, colons and commas inside strings are ignored. Keys are compared
+/// to `key` exactly as they are written in the record, so a key containing
+/// escape sequences will only match if `key` contains the same escapes.
+///
+/// Anything before the opening `{` is skipped, so records that have been
+/// prefixed (for example with `--with-offset`) can be looked up too.
+///
+/// # Arguments
+///
+/// * `record` - A single JSON object.
+/// * `key` - The key to look up.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::keys::top_level_value;
+///
+/// let record = r#"{"a": {"id": 1}, "id": "x,y"}"#;
+/// assert_eq!(top_level_value(record, "id"), Some(r#""x,y""#));
+/// assert_eq!(top_level_value(record, "b"), None);
+/// ```
+pub fn top_level_value<'a>(record: &'a str, key: &str) -> Option<&'a str> {
+    for_each_member(record, |member_key, value| {
+        if member_key == key {
+            ControlFlow::Break(value)
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+}
+
+/// Returns the top level keys of `record` in the order they are written,
+/// without their quotes, or nothing if `record` is not an object. As with
+/// `top_level_value`, keys are returned exactly as they are written, escape
+/// sequences and all, and anything before the opening `{` is skipped.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::keys::top_level_keys;
+///
+/// let record = r#"{"a": {"b": 1}, "c,d": "e:f"}"#;
+/// assert_eq!(top_level_keys(record), ["a", "c,d"]);
+/// assert!(top_level_keys("[1]").is_empty());
+/// ```
+pub fn top_level_keys(record: &str) -> Vec<&str> {
+    let mut keys = Vec::new();
+    for_each_member(record, |key, _| {
+        keys.push(key);
+        ControlFlow::<()>::Continue(())
+    });
+    keys
+}
+
+/// Calls `f` with the key, without its quotes, and the trimmed raw JSON text
+/// of the value of each top level member of `record`, stopping early if `f`
+/// breaks.
+///
+/// The record is scanned rather than parsed, keeping track of strings so that
+/// brackets, colons and commas inside strings are ignored.
+fn for_each_member<'a, T>(
+    record: &'a str,
+    mut f: impl FnMut(&'a str, &'a str) -> ControlFlow<T>,
+) -> Option<T> {
+    let start = record.find(['{', '[', '"'])?;
+    let bytes = record.as_bytes();
+    if bytes[start] != b'{' {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut inside_string = false;
+    let mut last_char_escape = false;
+    let mut string_start = 0;
+    let mut expecting_key = false;
+    let mut last_key: Option<&str> = None;
+    let mut value_start: Option<usize> = None;
+
+    for (i, &b) in bytes.iter().enumerate().skip(start) {
+        if inside_string {
+            if b == b'"' && !last_char_escape {
+                inside_string = false;
+                if depth == 1 && expecting_key {
+                    last_key = Some(&record[string_start + 1..i]);
+                }
+            }
+            last_char_escape = b == b'\\' && !last_char_escape;
+            continue;
+        }
+
+        match b {
+            b'"' => {
+                inside_string = true;
+                string_start = i;
+            }
+            b'{' | b'[' => {
+                depth += 1;
+                if depth == 1 {
+                    expecting_key = true;
+                }
+            }
+            b':' if depth == 1 => {
+                expecting_key = false;
+                value_start = Some(i + 1);
+            }
+            b',' | b'}' if depth == 1 => {
+                if let (Some(key), Some(value_start)) = (last_key, value_start) {
+                    if let ControlFlow::Break(result) = f(key, record[value_start..i].trim()) {
+                        return Some(result);
+                    }
+                }
+                expecting_key = true;
+                last_key = None;
+                value_start = None;
+            }
+            _ => {}
+        }
+
+        if b == b'}' || b == b']' {
+            depth -= 1;
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+    None
+}
+
+/// Returns the contents of a JSON string value without its surrounding
+/// quotes, or the value unchanged if it is not a string.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::keys::unquote;
+///
+/// assert_eq!(unquote(r#""abc""#), "abc");
+/// assert_eq!(unquote("12"), "12");
+/// ```
+pub fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+/// Splits a member of an object, such as `"id": {"a": 1}`, into the raw
+/// JSON text of its key, including the quotes, and of its value. Returns
+/// `None` if `member` does not start with a string followed by a colon.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::keys::split_member;
+///
+/// assert_eq!(
+///     split_member(r#""a\"b": [1]"#),
+///     Some((r#""a\"b""#, "[1]"))
+/// );
+/// assert_eq!(split_member("[1]"), None);
+/// ```
+pub fn split_member(member: &str) -> Option<(&str, &str)> {
+    let member = member.trim_start();
+    if !member.starts_with('"') {
+        return None;
+    }
+
+    let mut last_char_escape = false;
+    let key_end = member
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| {
+            let is_end = c == '"' && !last_char_escape;
+            last_char_escape = c == '\\' && !last_char_escape;
+            is_end
+        })
+        .map(|(i, _)| i + 1)?;
+
+    let value = member[key_end..].trim_start().strip_prefix(':')?;
+    Some((&member[..key_end], value.trim()))
+}
+
+/// Turns the members of a root object into records, as with `--map-values`.
+///
+/// # Fields
+///
+/// * `inject_key` - If set, the member's key is added to its value under
+///   this name, so every value must be an object without a member of that
+///   name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MapValues {
+    pub inject_key: Option<String>,
+}
+
+impl MapValues {
+    /// Returns the record for a member of the root object. If `member` is
+    /// not a member, it is returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `member` - A member of the root object, such as `"id": {"a": 1}`.
+    ///
+    /// # Errors
+    ///
+    /// * If `inject_key` is set and the value is not an object, or already
+    ///   has a member named `inject_key`, naming the member's key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::keys::MapValues;
+    ///
+    /// let map_values = MapValues {
+    ///     inject_key: Some(String::from("_key")),
+    /// };
+    /// assert_eq!(
+    ///     map_values.record(r#""id1": {"a": 1}"#).unwrap(),
+    ///     r#"{"_key":"id1","a": 1}"#
+    /// );
+    /// assert!(map_values.record(r#""id2": {"_key": 1}"#).is_err());
+    /// assert!(map_values.record(r#""id3": [1]"#).is_err());
+    /// ```
+    pub fn record<'a>(&self, member: &'a str) -> Result<Cow<'a, str>, String> {
+        let Some((key, value)) = split_member(member) else {
+            return Ok(Cow::Borrowed(member));
+        };
+        let Some(name) = &self.inject_key else {
+            return Ok(Cow::Borrowed(value));
+        };
+        let Some(rest) = value.strip_prefix('{') else {
+            return Err(format!(
+                "The value of {} is not an object, so its key cannot be added to it as `{}`.",
+                key, name
+            ));
+        };
+        if top_level_keys(value)
+            .into_iter()
+            .any(|existing| is_same_key(existing, name))
+        {
+            return Err(format!(
+                "The value of {} already has a `{}` key, so its key cannot be added to it.",
+                key, name
+            ));
+        }
+
+        let separator = if rest.trim_start().starts_with('}') {
+            ""
+        } else {
+            ","
+        };
+        Ok(Cow::Owned(format!(
+            "{{{}:{}{}{}",
+            serde_json::Value::from(name.as_str()),
+            key,
+            separator,
+            rest
+        )))
+    }
+}
+
+/// Checks if the key `written`, as it is written in a record without its
+/// quotes, is `key` once its escapes are decoded.
+fn is_same_key(written: &str, key: &str) -> bool {
+    written == key
+        || serde_json::from_str::<String>(&format!("\"{}\"", written))
+            .is_ok_and(|decoded| decoded == key)
+}
+
+/// Checks that records contain each of a set of top level keys, as with
+/// `--fields-required`.
+///
+/// # Fields
+///
+/// * `keys` - The keys each record must contain.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RequiredFields {
+    pub keys: Vec<String>,
+}
+
+impl RequiredFields {
+    /// Returns the required keys that `record` does not contain, in the
+    /// order they were given. Keys are compared as `top_level_keys` returns
+    /// them, and a record that is not an object contains none of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::keys::RequiredFields;
+    ///
+    /// let required = RequiredFields {
+    ///     keys: vec![String::from("id"), String::from("name")],
+    /// };
+    /// assert_eq!(required.missing(r#"{"id": 1, "tags": {"name": "x"}}"#), ["name"]);
+    /// assert!(required.missing(r#"{"name": "x", "id": 1}"#).is_empty());
+    /// ```
+    pub fn missing(&self, record: &str) -> Vec<&str> {
+        let present = top_level_keys(record);
+        self.keys
+            .iter()
+            .map(String::as_str)
+            .filter(|key| !present.contains(key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_level_value_finds_scalar_values() {
+        let record = r#"{"a": 1, "b": true, "c": null, "d": "x"}"#;
+        assert_eq!(top_level_value(record, "a"), Some("1"));
+        assert_eq!(top_level_value(record, "b"), Some("true"));
+        assert_eq!(top_level_value(record, "c"), Some("null"));
+        assert_eq!(top_level_value(record, "d"), Some("\"x\""));
+    }
+
+    #[test]
+    fn test_top_level_value_finds_nested_values() {
+        let record = r#"{"a": {"b": [1, {"c": 2}]}, "d": [3]}"#;
+        assert_eq!(
+            top_level_value(record, "a"),
+            Some(r#"{"b": [1, {"c": 2}]}"#)
+        );
+        assert_eq!(top_level_value(record, "d"), Some("[3]"));
+    }
+
+    #[test]
+    fn test_top_level_value_ignores_nested_keys() {
+        let record = r#"{"a": {"id": 1}, "b": [{"id": 2}]}"#;
+        assert_eq!(top_level_value(record, "id"), None);
+    }
+
+    #[test]
+    fn test_top_level_value_ignores_keys_and_brackets_in_strings() {
+        let record = r#"{"a": "\"id\": {[", "id": "}\","}"#;
+        assert_eq!(top_level_value(record, "id"), Some(r#""}\",""#));
+    }
+
+    #[test]
+    fn test_top_level_value_does_not_match_string_values() {
+        let record = r#"{"a": "id", "b": 1}"#;
+        assert_eq!(top_level_value(record, "id"), None);
+    }
+
+    #[test]
+    fn test_top_level_value_skips_prefix() {
+        assert_eq!(top_level_value("12\t{\"id\": 3}", "id"), Some("3"));
+    }
+
+    #[test]
+    fn test_top_level_keys_ignore_nested_keys_and_strings() {
+        let record = r#"7 {"a": {"x": 1}, "b\"c": "d\": e", "f": [{"g": 2}], "h": {}}"#;
+        assert_eq!(top_level_keys(record), ["a", "b\\\"c", "f", "h"]);
+        assert!(top_level_keys("{}").is_empty());
+        assert!(top_level_keys("\"a\"").is_empty());
+    }
+
+    #[test]
+    fn test_split_member_ignores_colons_in_key() {
+        assert_eq!(
+            split_member("  \"a:b\" :\n{\"c\": 1}"),
+            Some(("\"a:b\"", "{\"c\": 1}"))
+        );
+        assert_eq!(split_member("\"a\" 1"), None);
+        assert_eq!(split_member("\"a"), None);
+    }
+
+    #[test]
+    fn test_map_values_record_without_injected_key() {
+        let map_values = MapValues::default();
+        assert_eq!(
+            map_values.record("\"id1\": {\"a\": 1}").unwrap(),
+            "{\"a\": 1}"
+        );
+        assert_eq!(map_values.record("\"id2\": [1]").unwrap(), "[1]");
+    }
+
+    #[test]
+    fn test_map_values_record_with_injected_key() {
+        let map_values = MapValues {
+            inject_key: Some(String::from("k\"ey")),
+        };
+        assert_eq!(
+            map_values.record("\"id1\": {\"a\": 1}").unwrap(),
+            "{\"k\\\"ey\":\"id1\",\"a\": 1}"
+        );
+        assert_eq!(
+            map_values.record("\"id2\": { }").unwrap(),
+            "{\"k\\\"ey\":\"id2\" }"
+        );
+    }
+
+    #[test]
+    fn test_map_values_record_rejects_values_that_cannot_take_the_key() {
+        let map_values = MapValues {
+            inject_key: Some(String::from("_key")),
+        };
+        assert_eq!(
+            map_values.record("\"id1\": [1]").unwrap_err(),
+            "The value of \"id1\" is not an object, so its key cannot be added to it as `_key`."
+        );
+        assert!(map_values.record("\"id2\": \"x\"").is_err());
+        assert_eq!(
+            map_values
+                .record("\"id3\": {\"a\": 1, \"_key\": 2}")
+                .unwrap_err(),
+            "The value of \"id3\" already has a `_key` key, so its key cannot be added to it."
+        );
+        // A key written with escapes is the same key.
+        assert!(map_values.record("\"id4\": {\"\\u005fkey\": 2}").is_err());
+        // Nested members of that name are not in the way.
+        assert!(map_values.record("\"id5\": {\"a\": {\"_key\": 2}}").is_ok());
+    }
+
+    #[test]
+    fn test_top_level_value_of_non_object_is_none() {
+        assert_eq!(top_level_value("[1, 2]", "id"), None);
+        assert_eq!(top_level_value("[{\"id\": 1}]", "id"), None);
+        assert_eq!(top_level_value("\"id\"", "id"), None);
+    }
+
+    fn required(keys: &[&str]) -> RequiredFields {
+        RequiredFields {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_required_fields_with_all_keys() {
+        let record = r#"{"id": 1, "name": "a,\"b\"", "tags": []}"#;
+        assert!(required(&["id", "name"]).missing(record).is_empty());
+    }
+
+    #[test]
+    fn test_required_fields_with_some_keys() {
+        let record = r#"{"id": 1, "meta": {"name": "x"}, "note": "\"name\": 2"}"#;
+        assert_eq!(
+            required(&["name", "id", "tags"]).missing(record),
+            ["name", "tags"]
+        );
+    }
+
+    #[test]
+    fn test_required_fields_with_none_of_the_keys() {
+        assert_eq!(required(&["id", "name"]).missing("{}"), ["id", "name"]);
+        assert_eq!(required(&["id"]).missing(r#"[{"id": 1}]"#), ["id"]);
+    }
+}
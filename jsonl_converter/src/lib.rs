@@ -1,5 +1,51 @@
 pub mod brackets;
+pub mod buffer;
+pub mod canonical;
+pub mod check;
 pub mod cli;
+pub mod columns;
+pub mod control;
+pub mod converter;
+pub mod decimal;
+pub mod dedupe;
+pub mod diff;
+pub mod envelope;
+pub mod errors;
+pub mod flatten;
+pub mod generator;
+pub mod glob;
+pub mod index;
+pub mod json5;
 pub mod json_object;
+pub mod jsonp;
+pub mod key_case;
+pub mod keys;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod non_finite;
+pub mod output;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod partition;
+pub mod pipeline;
+pub mod pointer;
+pub mod processors;
+pub mod progress;
+pub mod raw;
 pub mod readers;
-pub mod processors;
\ No newline at end of file
+pub mod records;
+pub mod repair;
+pub mod report;
+pub mod reverse;
+pub mod sink;
+pub mod sort;
+pub mod strict;
+#[cfg(all(unix, feature = "syslog"))]
+pub mod syslog;
+pub mod unicode;
+pub mod verify;
+pub mod writer;
+
+/// Converts a JSON file lazily, yielding each record of its root array. See
+/// `records::convert`.
+pub use records::convert;
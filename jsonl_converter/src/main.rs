@@ -1,47 +1,238 @@
 extern crate jsonl_converter;
 
-use jsonl_converter::cli::parse_args;
-use jsonl_converter::processors::byte_processor::ByteProcessor;
-use jsonl_converter::processors::line_processor::LineProcessor;
-use jsonl_converter::readers::byte_iter::ByteIterator;
-use jsonl_converter::readers::line_iter::LineIterator;
-use jsonl_converter::readers::utils::verify_first_char;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use jsonl_converter::cli::{parse_args, Args};
+use jsonl_converter::columns::ColumnsWriter;
+use jsonl_converter::errors::ConversionError;
+use jsonl_converter::index::IndexWriter;
+#[cfg(feature = "msgpack")]
+use jsonl_converter::msgpack::MsgpackSink;
+use jsonl_converter::output::{
+    ChunkWriter, NamedWriter, Output, PipeOutput, ReplaceFile, TeeWriter,
+};
+use jsonl_converter::partition::PartitionWriter;
+use jsonl_converter::pipeline::convert_inputs;
+use jsonl_converter::progress::{ProgressWriter, PROGRESS_INTERVAL};
+use jsonl_converter::report::Report;
+#[cfg(feature = "msgpack")]
+use jsonl_converter::sink::EmitSink;
+use jsonl_converter::sort::SortWriter;
+#[cfg(feature = "msgpack")]
+use jsonl_converter::writer::OutputFormat;
+
+/// The exit code for an invalid command line, as is conventional for Unix
+/// tools.
+const USAGE_EXIT_CODE: i32 = 2;
 
 fn main() {
+    let args = parse_args().unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        process::exit(USAGE_EXIT_CODE);
+    });
+    let mut report = Report::default();
+
+    let result = match (&args.pipe, &args.partition_by) {
+        (Some(command), _) => pipe(&args, command, &mut report),
+        (None, Some(key)) => partition(&args, key, &mut report),
+        (None, None) if args.in_place => in_place(&args, &mut report),
+        (None, None) => Output::open(args.output())
+            .and_then(|output| run(&args, BufWriter::new(output), &mut report)),
+    };
+    // As is conventional for Unix tools, the reader of the output closing it
+    // early (e.g. when piped to `head`) is not treated as an error.
+    let result = result.or_else(|error| {
+        if error.is_broken_pipe() {
+            Ok(())
+        } else {
+            Err(error)
+        }
+    });
+
+    if let Err(error) = &result {
+        report.errors += 1;
+        print_error(error);
+    }
+    if args.report_json {
+        eprintln!("{}", report.to_json());
+    }
+    if args.count {
+        eprintln!("{}", report.records);
+    }
+    if result.is_err() {
+        process::exit(1);
+    }
+}
 
-    let (filepath, is_messy) = parse_args();
+/// Converts the input, writing the records to `output` and, if requested, a
+/// copy of them to the `--tee` file.
+fn run<W: Write>(args: &Args, output: W, report: &mut Report) -> Result<(), ConversionError> {
+    match &args.tee {
+        Some(path) => {
+            let copy = BufWriter::new(Output::open(path)?);
+            let mut output = TeeWriter::new(output, copy);
+            convert_indexed(args, &mut output, report)?;
+            output.flush()?;
+            Ok(())
+        }
+        None => convert_indexed(args, output, report),
+    }
+}
+
+/// Converts the input, writing the records to `output` and, if requested, an
+/// index of them to the `--index` file.
+fn convert_indexed<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    match &args.index {
+        Some(path) => {
+            let mut output = IndexWriter::create(output, Path::new(path))?;
+            convert_with_progress(args, &mut output, report)?;
+            output.flush()?;
+            Ok(())
+        }
+        None => convert_chunked(args, output, report),
+    }
+}
 
-    if is_messy {
-        bytes_iter(&filepath);
-    } else {
-        line_iter(&filepath);
+/// Converts the input, writing the records to `output` in groups of
+/// `--chunk-records` if it was given.
+fn convert_chunked<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    match args.chunk_records {
+        Some(chunk_records) => {
+            let output = ChunkWriter::new(output, chunk_records, args.output_eol);
+            convert_with_progress(args, output, report)
+        }
+        None => convert_with_progress(args, output, report),
     }
 }
 
-fn bytes_iter(filepath: &str) {
-    let mut bytes_iter = ByteIterator::new(&filepath).unwrap();
-    let first_char = bytes_iter.next_char().unwrap();
-    verify_first_char(&first_char);
+/// Converts the input, writing the records to `output` and, if
+/// `--progress-records` was given, the number converted so far to stderr.
+fn convert_with_progress<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    if !args.progress_records {
+        return convert_columns(args, output, report);
+    }
+    let mut output = ProgressWriter::new(output, io::stderr(), PROGRESS_INTERVAL);
+    let result = convert_columns(args, &mut output, report);
+    let finished = output.finish();
+    result?;
+    finished?;
+    Ok(())
+}
 
-    let mut processor = ByteProcessor::new();
-    processor.bracket_stack.push(&first_char);
+/// Converts the input, writing the records to `output`, or if `--columns`
+/// was given, a summary of their top level keys instead.
+fn convert_columns<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    if !args.columns {
+        return convert_encoded(args, output, report);
+    }
+    let mut output = ColumnsWriter::new(output, args.output_eol);
+    convert_encoded(args, &mut output, report)?;
+    output.finish()?;
+    Ok(())
+}
 
-    for byte in bytes_iter {
-        let byte = byte.unwrap().to_owned().chars().next().unwrap();
-        processor.process_char(&byte);
+/// Converts the input, writing the records to `output` as MessagePack if
+/// `--format msgpack` was given.
+#[cfg(feature = "msgpack")]
+fn convert_encoded<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    if args.format != OutputFormat::Msgpack {
+        return convert_ordered(args, output, report);
     }
+    let mut output = MsgpackSink::new(output);
+    output.set_length_prefix(args.length_prefix());
+    convert_ordered(args, &mut output, report)?;
+    output.finish()
 }
 
-fn line_iter(filepath: &str) {
-    let mut line_iter = LineIterator::new(&filepath).unwrap();
-    let first_line = line_iter.next_line().unwrap();
-    let first_char = first_line.chars().next().unwrap();
-    verify_first_char(&first_char);
+/// Converts the input, writing the records to `output`. Without the
+/// `msgpack` feature, `--format msgpack` is rejected by `parse_args`.
+#[cfg(not(feature = "msgpack"))]
+fn convert_encoded<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    convert_ordered(args, output, report)
+}
+
+/// Converts the input, writing the records to `output`, sorted by the
+/// `--order-by` key if one was given.
+fn convert_ordered<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    match &args.order_by {
+        Some(key) => {
+            let mut output = SortWriter::new(output, key, args.descending);
+            convert_inputs(args, &mut output, report, print_error)?;
+            output.finish()?;
+            Ok(())
+        }
+        None => convert_inputs(args, output, report, print_error),
+    }
+}
 
-    let mut processor = LineProcessor::new();
-    processor.bracket_stack.push(&first_char);
+/// Converts the file, streaming the output to the stdin of `command`. If the
+/// command stops reading early, the conversion stops and the command's exit
+/// status decides whether the run was successful.
+fn pipe(args: &Args, command: &str, report: &mut Report) -> Result<(), ConversionError> {
+    let mut output = PipeOutput::spawn(command)?;
+    let result = run(args, &mut output, report);
+    let finished = output.finish();
 
-    for line in line_iter {
-        processor.process_line(&line);
+    match result {
+        Err(error) if error.is_broken_pipe() => finished,
+        Err(error) => Err(error),
+        Ok(()) => finished,
     }
 }
+
+/// Converts the file, replacing it with the output once the conversion has
+/// succeeded. If it fails, the file is left as it was.
+fn in_place(args: &Args, report: &mut Report) -> Result<(), ConversionError> {
+    let mut file = ReplaceFile::create(&args.filepath)?;
+    let mut output = BufWriter::new(NamedWriter::new(&mut file, &args.filepath));
+    run(args, &mut output, report)?;
+    output.flush()?;
+    drop(output);
+    file.commit()?;
+    Ok(())
+}
+
+/// Converts the file, writing each record to a file named after its value for
+/// `key`.
+fn partition(args: &Args, key: &str, report: &mut Report) -> Result<(), ConversionError> {
+    let dir = Path::new(args.partition_dir());
+    let mut output = PartitionWriter::new(dir, key, args.max_open_files())?;
+    convert_with_progress(args, &mut output, report)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Prints an error to stderr.
+fn print_error(error: &ConversionError) {
+    eprintln!("Error: {}", error);
+}
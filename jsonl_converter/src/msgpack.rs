@@ -0,0 +1,297 @@
+//! This module contains `MsgpackSink`, which writes each record as
+//! MessagePack rather than as a line of JSON, for consumers that would
+//! rather not parse JSON text. It is only built with the `msgpack` feature,
+//! which encodes records with `rmpv`.
+//!
+//! # Format
+//!
+//! Each record is read straight into an `rmpv::Value`, without going through
+//! `serde_json::Value`, and written as a single MessagePack value in the
+//! smallest encoding that holds it:
+//!
+//! * `null`, `true` and `false` are `nil`, `true` and `false`.
+//! * Integers that fit in 64 bits are `int` or `uint` of as few bytes as they
+//!   fit in, and other numbers are `float 64`, as MessagePack has nothing
+//!   larger.
+//! * Strings are `str`, arrays are `array` and objects are `map`. The members
+//!   of each object are kept in the order they appear in the record, along
+//!   with any key that appears more than once, so `--retain-key-order` holds
+//!   for MessagePack too.
+//!
+//! MessagePack values carry their own length, so by default they are simply
+//! written one after another. With a `LengthPrefix`, each is preceded by its
+//! length in bytes instead, for consumers that read length-delimited frames.
+
+use std::io::{self, Write};
+
+use crate::{errors::ConversionError, sink::EmitSink, writer::LengthPrefix};
+
+/// Writes each record sent to it as a MessagePack value.
+///
+/// Records can also be written to it as JSONL through `Write`, in which case
+/// each line is sent on as a record, so that it can take the place of the
+/// output of a `RecordWriter`.
+///
+/// # Fields
+///
+/// * `output` - Where the values are written.
+/// * `length_prefix` - If set, how the length of each value is written
+///   before it.
+/// * `records` - The number of records written.
+/// * `line` - The part of the current JSONL line written so far.
+/// * `encoded` - The value being encoded, kept to reuse its allocation.
+pub struct MsgpackSink<W: Write> {
+    output: W,
+    length_prefix: Option<LengthPrefix>,
+    records: usize,
+    line: Vec<u8>,
+    encoded: Vec<u8>,
+}
+
+impl<W: Write> MsgpackSink<W> {
+    /// Creates a new `MsgpackSink` that writes the values to `output`, one
+    /// after another.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{msgpack::MsgpackSink, sink::EmitSink};
+    ///
+    /// let mut output = Vec::new();
+    /// let mut sink = MsgpackSink::new(&mut output);
+    /// sink.emit("{\"a\": [1, true]}").unwrap();
+    /// sink.finish().unwrap();
+    /// assert_eq!(output, [0x81, 0xa1, b'a', 0x92, 0x01, 0xc3]);
+    /// ```
+    pub fn new(output: W) -> Self {
+        MsgpackSink {
+            output,
+            length_prefix: None,
+            records: 0,
+            line: Vec::new(),
+            encoded: Vec::new(),
+        }
+    }
+
+    /// Sets how the length of each value is written before it. `None` writes
+    /// the values one after another.
+    pub fn set_length_prefix(&mut self, length_prefix: Option<LengthPrefix>) {
+        self.length_prefix = length_prefix;
+    }
+
+    /// Returns the number of records written.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Sends the current JSONL line on as a record, unless it is blank, and
+    /// clears it.
+    fn emit_line(&mut self) -> Result<(), ConversionError> {
+        let line = std::mem::take(&mut self.line);
+        let result = match String::from_utf8_lossy(&line).trim() {
+            "" => Ok(()),
+            record => self.emit(record),
+        };
+        self.line = line;
+        self.line.clear();
+        result
+    }
+}
+
+impl<W: Write> EmitSink for MsgpackSink<W> {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.encoded.clear();
+        encode(record, &mut self.encoded).map_err(|error| ConversionError::InvalidRecord {
+            record: self.records + 1,
+            message: error.to_string(),
+        })?;
+        if let Some(length_prefix) = self.length_prefix {
+            let len = self.encoded.len();
+            let prefix = length_prefix.encode(len).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "A record of {} bytes is too long to be length-prefixed.",
+                        len
+                    ),
+                )
+            })?;
+            self.output.write_all(&prefix)?;
+        }
+        self.output.write_all(&self.encoded)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), ConversionError> {
+        self.emit_line()?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for MsgpackSink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for part in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(part);
+            if part.ends_with(b"\n") {
+                self.emit_line().map_err(|error| match error {
+                    ConversionError::Io(error) => error,
+                    error => io::Error::new(io::ErrorKind::InvalidData, error.to_string()),
+                })?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Appends the MessagePack encoding of the JSON `record` to `encoded`.
+///
+/// # Errors
+///
+/// * If `record` is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::msgpack::encode;
+///
+/// let mut encoded = Vec::new();
+/// encode("{\"ok\": null, \"id\": -1}", &mut encoded).unwrap();
+/// assert_eq!(encoded, [0x82, 0xa2, b'o', b'k', 0xc0, 0xa2, b'i', b'd', 0xff]);
+/// ```
+pub fn encode(record: &str, encoded: &mut Vec<u8>) -> Result<(), serde_json::Error> {
+    let value: rmpv::Value = serde_json::from_str(record)?;
+    rmpv::encode::write_value(encoded, &value).expect("Writing to a Vec cannot fail.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::Value;
+    use serde_json::json;
+
+    /// Decodes the MessagePack values in `bytes` with `rmpv`.
+    fn decode(mut bytes: &[u8]) -> Vec<Value> {
+        let mut values = Vec::new();
+        while !bytes.is_empty() {
+            values.push(rmpv::decode::read_value(&mut bytes).unwrap());
+        }
+        values
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let long_string = "x".repeat(300);
+        let long_array: Vec<usize> = (0..20).collect();
+        let records = [
+            json!({"id": 1, "name": "a", "tags": ["x", "y"], "meta": null}),
+            json!([
+                0,
+                127,
+                128,
+                255,
+                256,
+                65_535,
+                65_536,
+                4_294_967_296u64,
+                u64::MAX
+            ]),
+            json!([
+                -1,
+                -32,
+                -33,
+                -128,
+                -129,
+                -32_768,
+                -32_769,
+                -2_147_483_649i64,
+                i64::MIN
+            ]),
+            json!({"pi": 3.25, "big": 1e300, "small": -0.5, "flags": [true, false]}),
+            json!({"long": long_string, "array": long_array, "unicode": "é \u{1f600}"}),
+            json!({}),
+        ];
+        let mut output = Vec::new();
+        let mut sink = MsgpackSink::new(&mut output);
+        for record in &records {
+            sink.emit(&record.to_string()).unwrap();
+        }
+        assert_eq!(sink.records(), records.len());
+        sink.finish().unwrap();
+
+        let values: Vec<serde_json::Value> = decode(&output)
+            .iter()
+            .map(|value| serde_json::to_value(value).unwrap())
+            .collect();
+        assert_eq!(values, records);
+    }
+
+    #[test]
+    fn test_key_order_and_repeated_keys_are_kept() {
+        let mut output = Vec::new();
+        let mut sink = MsgpackSink::new(&mut output);
+        sink.emit("{\"b\": 1, \"a\": {\"z\": 2, \"y\": 3}, \"b\": 4}")
+            .unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            decode(&output),
+            [Value::Map(vec![
+                (Value::from("b"), Value::from(1)),
+                (
+                    Value::from("a"),
+                    Value::Map(vec![
+                        (Value::from("z"), Value::from(2)),
+                        (Value::from("y"), Value::from(3)),
+                    ])
+                ),
+                (Value::from("b"), Value::from(4)),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_values_are_length_prefixed() {
+        let mut output = Vec::new();
+        let mut sink = MsgpackSink::new(&mut output);
+        sink.set_length_prefix(Some(LengthPrefix::U32));
+        sink.emit("[1, 2]").unwrap();
+        sink.emit("\"ab\"").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            output,
+            [0, 0, 0, 3, 0x92, 0x01, 0x02, 0, 0, 0, 3, 0xa2, b'a', b'b']
+        );
+    }
+
+    #[test]
+    fn test_jsonl_written_to_it_is_converted() {
+        let mut output = Vec::new();
+        let mut sink = MsgpackSink::new(&mut output);
+        sink.write_all(b"{\"a\": 1}\r\n[tr").unwrap();
+        sink.write_all(b"ue]\n\n{\"b\":").unwrap();
+        sink.write_all(b" \"c\"}").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            output,
+            [0x81, 0xa1, b'a', 0x01, 0x91, 0xc3, 0x81, 0xa1, b'b', 0xa1, b'c']
+        );
+    }
+
+    #[test]
+    fn test_invalid_record_is_an_error() {
+        let mut sink = MsgpackSink::new(Vec::new());
+        sink.emit("[1]").unwrap();
+        assert!(matches!(
+            sink.emit("[1,]"),
+            Err(ConversionError::InvalidRecord { record: 2, .. })
+        ));
+        let error = sink.write_all(b"{\"a\" 1}\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,145 @@
+//! This module contains the handling of the non-finite numbers `NaN`,
+//! `Infinity` and `-Infinity`, which some producers, such as Python's `json`
+//! module, write as bare literals even though they are not valid JSON.
+
+use std::borrow::Cow;
+
+/// The bare literals that non-finite numbers are written as.
+pub const NON_FINITE_LITERALS: [&str; 3] = ["NaN", "Infinity", "-Infinity"];
+
+/// Returns the first non-finite literal outside of the strings in `record`.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::non_finite::find_non_finite;
+///
+/// assert_eq!(find_non_finite("[\"NaN\", -Infinity]"), Some("-Infinity"));
+/// assert_eq!(find_non_finite("[\"NaN\", null]"), None);
+/// ```
+pub fn find_non_finite(record: &str) -> Option<&str> {
+    let mut found = None;
+    for_each_word(record, |_, word| {
+        if found.is_none() && is_non_finite(word) {
+            found = Some(word);
+        }
+    });
+    found
+}
+
+/// Returns `record` with each non-finite literal outside of its strings
+/// replaced with `null`.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::non_finite::replace_non_finite;
+///
+/// assert_eq!(
+///     replace_non_finite("{\"a\": NaN, \"b\": \"NaN\"}"),
+///     "{\"a\": null, \"b\": \"NaN\"}"
+/// );
+/// ```
+pub fn replace_non_finite(record: &str) -> Cow<'_, str> {
+    let mut result = String::new();
+    let mut copied = 0;
+    for_each_word(record, |start, word| {
+        if is_non_finite(word) {
+            result.push_str(&record[copied..start]);
+            result.push_str("null");
+            copied = start + word.len();
+        }
+    });
+
+    if copied == 0 {
+        return Cow::Borrowed(record);
+    }
+    result.push_str(&record[copied..]);
+    Cow::Owned(result)
+}
+
+/// Checks if a word is a non-finite literal.
+fn is_non_finite(word: &str) -> bool {
+    NON_FINITE_LITERALS.contains(&word)
+}
+
+/// Calls `f` with the offset and text of each bare word outside of the
+/// strings in `record`, such as `true` or `NaN`. A word starts with an ASCII
+/// letter, or a `-` followed by one, and runs until the next character that
+/// is not an ASCII letter or digit. Exponents within numbers, such as the
+/// `e5` of `1e5`, are not words.
+fn for_each_word<'a>(record: &'a str, mut f: impl FnMut(usize, &'a str)) {
+    let bytes = record.as_bytes();
+    let mut inside_string = false;
+    let mut last_char_escape = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if inside_string {
+            inside_string = b != b'"' || last_char_escape;
+            last_char_escape = b == b'\\' && !last_char_escape;
+            i += 1;
+            continue;
+        }
+
+        let starts_word = b.is_ascii_alphabetic()
+            || (b == b'-' && bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic));
+        if starts_word {
+            let len = bytes[i + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric())
+                .count();
+            f(i, &record[i..i + 1 + len]);
+            i += 1 + len;
+        } else if b.is_ascii_digit() {
+            i += bytes[i..]
+                .iter()
+                .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'+' | b'-'))
+                .count();
+        } else {
+            inside_string = b == b'"';
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_non_finite_in_every_position() {
+        let cases = [
+            ("NaN", "null"),
+            ("Infinity", "null"),
+            ("-Infinity", "null"),
+            ("[NaN]", "[null]"),
+            ("[1, Infinity, -Infinity]", "[1, null, null]"),
+            ("{\"a\":NaN,\"b\":[-Infinity]}", "{\"a\":null,\"b\":[null]}"),
+            ("{\"a\": {\"b\": Infinity }}", "{\"a\": {\"b\": null }}"),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(replace_non_finite(record), expected, "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_strings_and_other_words_are_untouched() {
+        let record =
+            "{\"NaN\": \"NaN\", \"a\": \"\\\" Infinity\", \"b\": [true, null, NaNa, -1e5]}";
+        assert!(matches!(replace_non_finite(record), Cow::Borrowed(_)));
+        assert_eq!(find_non_finite(record), None);
+
+        let mut words = Vec::new();
+        for_each_word("[1e5, -2.5E-3, true]", |_, word| words.push(word));
+        assert_eq!(words, ["true"]);
+    }
+
+    #[test]
+    fn test_find_non_finite_returns_the_first_literal() {
+        assert_eq!(
+            find_non_finite("{\"a\": Infinity, \"b\": NaN}"),
+            Some("Infinity")
+        );
+    }
+}
@@ -0,0 +1,763 @@
+//! This module contains the destinations that completed JSONL records can be
+//! written to.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{self, Child, ChildStdin, Command, Stdio},
+};
+
+use crate::{errors::ConversionError, writer::LineEnding};
+
+/// The standard stream or file that records are written to, as chosen with
+/// `--output`.
+pub enum Output {
+    Stdout(io::StdoutLock<'static>),
+    Stderr(io::StderrLock<'static>),
+    File(File),
+}
+
+impl Output {
+    /// Opens the output named by `target`. `-` is stdout and `/dev/stderr` is
+    /// stderr, on every platform. Anything else is a path to a file, which is
+    /// created or truncated, so special files such as named pipes or
+    /// `/dev/fd/3` can be used where the platform provides them.
+    ///
+    /// Errors writing to the output, such as the disk being full, name the
+    /// output.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be opened for writing.
+    pub fn open(target: &str) -> Result<NamedWriter<Self>, ConversionError> {
+        let output = match target {
+            "-" => NamedWriter::new(Output::Stdout(io::stdout().lock()), "stdout"),
+            "/dev/stderr" => NamedWriter::new(Output::Stderr(io::stderr().lock()), "stderr"),
+            path => {
+                let file = File::create(path).map_err(|error| ConversionError::OutputOpen {
+                    path: path.to_string(),
+                    error,
+                })?;
+                NamedWriter::new(Output::File(file), path)
+            }
+        };
+        Ok(output)
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Stderr(stderr) => stderr.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Stderr(stderr) => stderr.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+/// A file that replaces another once it has been written in full, as used by
+/// `--in-place`.
+///
+/// It is written to a temporary file in the same directory as the file it
+/// replaces, which is renamed over that file by `commit`. A rename within a
+/// directory is atomic, so readers of the file see either the old contents
+/// or the new, never a mix. If the `ReplaceFile` is dropped without being
+/// committed, such as when a conversion fails, the temporary file is removed
+/// and the original is left untouched.
+///
+/// # Fields
+///
+/// * `file` - The temporary file, until it is committed.
+/// * `path` - The path of the file to replace.
+/// * `temp_path` - The path of the temporary file.
+pub struct ReplaceFile {
+    file: Option<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+}
+
+impl ReplaceFile {
+    /// Creates the temporary file that will replace the file at `path`,
+    /// giving it the same permissions.
+    ///
+    /// # Errors
+    ///
+    /// * If the file at `path` does not exist.
+    /// * If the temporary file cannot be created.
+    pub fn create(path: &str) -> Result<Self, ConversionError> {
+        let path = Path::new(path);
+        let open_error = |error| ConversionError::OutputOpen {
+            path: path.to_string_lossy().into_owned(),
+            error,
+        };
+        let permissions = fs::metadata(path).map_err(open_error)?.permissions();
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .map_err(open_error)?;
+        let replace_file = ReplaceFile {
+            file: Some(file),
+            path: path.to_path_buf(),
+            temp_path,
+        };
+        fs::set_permissions(&replace_file.temp_path, permissions).map_err(open_error)?;
+        Ok(replace_file)
+    }
+
+    /// Returns the path of the temporary file.
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    /// Syncs the temporary file to disk and renames it over the file it
+    /// replaces.
+    ///
+    /// # Errors
+    ///
+    /// * If the temporary file cannot be synced or renamed, in which case it
+    ///   is removed.
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)
+    }
+}
+
+impl Write for ReplaceFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().unwrap().flush()
+    }
+}
+
+impl Drop for ReplaceFile {
+    fn drop(&mut self) {
+        // Once renamed, the temporary path no longer exists, so this only
+        // removes a file that was not committed.
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+/// Wraps a writer, adding the name of what is being written to to the message
+/// of any error, so that an error such as the disk being full says which
+/// output it was for. The kind of the error is kept, so a broken pipe is
+/// still recognised as one.
+///
+/// A write that accepts no bytes is reported as a `WriteZero` error here,
+/// rather than by whatever is writing, so that it is named too.
+///
+/// # Fields
+///
+/// * `inner` - The wrapped writer.
+/// * `name` - The name of the output, such as its path.
+pub struct NamedWriter<W: Write> {
+    inner: W,
+    name: String,
+}
+
+impl<W: Write> NamedWriter<W> {
+    /// Creates a new `NamedWriter` around `inner`, naming it `name` in
+    /// errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::output::NamedWriter;
+    ///
+    /// let mut full: &mut [u8] = &mut [];
+    /// let mut writer = NamedWriter::new(&mut full, "out.jsonl");
+    /// let error = writer.write_all(b"{}\n").unwrap_err();
+    /// assert!(error.to_string().starts_with("Could not write to `out.jsonl`: "));
+    /// ```
+    pub fn new(inner: W, name: &str) -> Self {
+        NamedWriter {
+            inner,
+            name: name.to_string(),
+        }
+    }
+
+    /// Returns the name of the output.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Consumes the `NamedWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Adds the name of the output to the message of `error`.
+    fn with_name(&self, error: io::Error) -> io::Error {
+        io::Error::new(
+            error.kind(),
+            format!("Could not write to `{}`: {}", self.name, error),
+        )
+    }
+}
+
+impl<W: Write> Write for NamedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.write(buf) {
+            Ok(0) if !buf.is_empty() => Err(self.with_name(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "no bytes could be written",
+            ))),
+            Ok(written) => Ok(written),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => Err(error),
+            Err(error) => Err(self.with_name(error)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().map_err(|error| self.with_name(error))
+    }
+}
+
+/// Builds a `Command` that runs `command` through the system shell, so that
+/// redirects and pipelines such as `gzip > out.gz` work as expected.
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Streams output to the stdin of a child process.
+///
+/// # Fields
+///
+/// * `command` - The shell command that was spawned.
+/// * `child` - The spawned child process.
+/// * `stdin` - A buffered writer over the stdin of the child process.
+pub struct PipeOutput {
+    command: String,
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+}
+
+impl PipeOutput {
+    /// Spawns `command` through the system shell with its stdin connected to
+    /// the returned `PipeOutput`. The child inherits stdout and stderr.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The shell command to pipe the output to.
+    ///
+    /// # Errors
+    ///
+    /// * If the command cannot be spawned.
+    pub fn spawn(command: &str) -> Result<Self, ConversionError> {
+        let spawn_error = |error| ConversionError::PipeSpawn {
+            command: command.to_string(),
+            error,
+        };
+        let mut child = shell_command(command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| spawn_error(io::Error::other("the child process has no stdin")))?;
+
+        Ok(PipeOutput {
+            command: command.to_string(),
+            child,
+            stdin: BufWriter::new(stdin),
+        })
+    }
+
+    /// Closes the stdin of the child process and waits for it to exit.
+    ///
+    /// A child that exits before reading all of its input (such as `head`)
+    /// is not an error so long as it exits successfully.
+    ///
+    /// # Errors
+    ///
+    /// * If the child process exits unsuccessfully.
+    pub fn finish(self) -> Result<(), ConversionError> {
+        let PipeOutput {
+            command,
+            mut child,
+            mut stdin,
+        } = self;
+
+        match stdin.flush() {
+            Err(error) if error.kind() != io::ErrorKind::BrokenPipe => return Err(error.into()),
+            _ => drop(stdin),
+        }
+
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(ConversionError::PipeExit { command, status })
+        }
+    }
+}
+
+impl Write for PipeOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+
+/// Wraps a writer, counting the number of bytes written through it.
+///
+/// # Fields
+///
+/// * `inner` - The wrapped writer.
+/// * `bytes_written` - The number of bytes written so far.
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    /// Creates a new `CountingWriter` around `inner`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::output::CountingWriter;
+    ///
+    /// let mut writer = CountingWriter::new(Vec::new());
+    /// writeln!(writer, "{{}}").unwrap();
+    /// assert_eq!(writer.bytes_written(), 3);
+    /// ```
+    pub fn new(inner: W) -> Self {
+        CountingWriter {
+            inner,
+            bytes_written: 0,
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Consumes the `CountingWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes everything written to it to two writers, such as stdout and the
+/// file given to `--tee`.
+///
+/// Each write is written in full to `first` and then to `second`, so both
+/// receive the same bytes. An error from either writer is returned as it is;
+/// wrapping them in `NamedWriter`s says which one failed.
+///
+/// # Fields
+///
+/// * `first` - The first writer written to.
+/// * `second` - The second writer written to.
+pub struct TeeWriter<A: Write, B: Write> {
+    first: A,
+    second: B,
+}
+
+impl<A: Write, B: Write> TeeWriter<A, B> {
+    /// Creates a new `TeeWriter` that writes to both `first` and `second`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::output::TeeWriter;
+    ///
+    /// let mut writer = TeeWriter::new(Vec::new(), Vec::new());
+    /// writeln!(writer, "{{}}").unwrap();
+    /// let (first, second) = writer.into_inner();
+    /// assert_eq!(first, b"{}\n");
+    /// assert_eq!(first, second);
+    /// ```
+    pub fn new(first: A, second: B) -> Self {
+        TeeWriter { first, second }
+    }
+
+    /// Consumes the `TeeWriter`, returning both of the wrapped writers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Write, B: Write> Write for TeeWriter<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    /// Flushes both writers, even if flushing the first fails, returning the
+    /// first error.
+    fn flush(&mut self) -> io::Result<()> {
+        let first = self.first.flush();
+        let second = self.second.flush();
+        first.and(second)
+    }
+}
+
+/// Groups the JSONL written to it, writing an empty line after every
+/// `chunk_records` lines, as for `--chunk-records`.
+///
+/// The empty line is only written once the next line starts, so a final
+/// group is never followed by one, whether it is full or not.
+///
+/// # Fields
+///
+/// * `inner` - The wrapped writer.
+/// * `chunk_records` - The number of lines in each group.
+/// * `line_ending` - The line ending of the empty line.
+/// * `lines` - The number of complete lines written so far.
+/// * `in_line` - Whether part of a line has been written without its line
+///   ending.
+pub struct ChunkWriter<W: Write> {
+    inner: W,
+    chunk_records: usize,
+    line_ending: LineEnding,
+    lines: usize,
+    in_line: bool,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Creates a new `ChunkWriter` around `inner` that writes an empty line
+    /// ending with `line_ending` between each group of `chunk_records`
+    /// lines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::{output::ChunkWriter, writer::LineEnding};
+    ///
+    /// let mut writer = ChunkWriter::new(Vec::new(), 2, LineEnding::Lf);
+    /// writer.write_all(b"1\n2\n3\n").unwrap();
+    /// assert_eq!(writer.into_inner(), b"1\n2\n\n3\n");
+    /// ```
+    pub fn new(inner: W, chunk_records: usize, line_ending: LineEnding) -> Self {
+        ChunkWriter {
+            inner,
+            chunk_records: chunk_records.max(1),
+            line_ending,
+            lines: 0,
+            in_line: false,
+        }
+    }
+
+    /// Consumes the `ChunkWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if !self.in_line && self.lines > 0 && self.lines.is_multiple_of(self.chunk_records) {
+                self.inner.write_all(self.line_ending.as_str().as_bytes())?;
+            }
+            self.inner.write_all(line)?;
+            self.in_line = !line.ends_with(b"\n");
+            if !self.in_line {
+                self.lines += 1;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::RecordWriter;
+    use std::fs;
+
+    #[test]
+    fn test_output_writes_to_a_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.jsonl");
+        fs::write(&path, "old contents\n").unwrap();
+
+        let mut output = Output::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(output.name(), path.to_str().unwrap());
+        output.write_all(b"{\"a\":1}\n").unwrap();
+        output.flush().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "{\"a\":1}\n");
+    }
+
+    /// A writer that accepts `capacity` bytes and then fails with `kind`, as
+    /// when a disk fills up.
+    struct FullWriter {
+        written: Vec<u8>,
+        capacity: usize,
+        kind: Option<io::ErrorKind>,
+    }
+
+    impl Write for FullWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.capacity - self.written.len());
+            if len == 0 {
+                if let Some(kind) = self.kind {
+                    return Err(io::Error::new(kind, "No space left on device"));
+                }
+            }
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_full_output_is_a_clean_error_naming_the_output() {
+        for kind in [None, Some(io::ErrorKind::Other)] {
+            let full = FullWriter {
+                written: Vec::new(),
+                capacity: 20,
+                kind,
+            };
+            let output = BufWriter::with_capacity(8, NamedWriter::new(full, "out.jsonl"));
+            let mut writer = RecordWriter::new(output);
+            let error = (0..10)
+                .map(|i| writer.write_record(&format!("{{\"a\":{}}}", i), 0))
+                .find_map(Result::err)
+                .unwrap();
+
+            assert!(matches!(error, ConversionError::Io(_)));
+            let expected = match kind {
+                None => "Could not write to `out.jsonl`: no bytes could be written",
+                Some(_) => "Could not write to `out.jsonl`: No space left on device",
+            };
+            assert_eq!(error.to_string(), expected);
+            assert!(!error.is_broken_pipe());
+        }
+    }
+
+    #[test]
+    fn test_named_writer_keeps_broken_pipes_recognisable() {
+        let full = FullWriter {
+            written: Vec::new(),
+            capacity: 0,
+            kind: Some(io::ErrorKind::BrokenPipe),
+        };
+        let mut writer = NamedWriter::new(full, "stdout");
+        let error = ConversionError::from(writer.write_all(b"{}\n").unwrap_err());
+        assert!(error.is_broken_pipe());
+        assert!(error
+            .to_string()
+            .starts_with("Could not write to `stdout`: "));
+    }
+
+    #[test]
+    fn test_output_open_names_the_path_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("out.jsonl");
+        let error = Output::open(path.to_str().unwrap()).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with(&format!("Could not open the output `{}`: ", path.display())));
+    }
+
+    #[test]
+    fn test_tee_writer_surfaces_errors_from_either_writer() {
+        let full = || FullWriter {
+            written: Vec::new(),
+            capacity: 4,
+            kind: Some(io::ErrorKind::Other),
+        };
+
+        let mut writer = TeeWriter::new(Vec::new(), NamedWriter::new(full(), "copy.jsonl"));
+        writer.write_all(b"[]\n").unwrap();
+        let error = writer.write_all(b"{}\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Could not write to `copy.jsonl`: "));
+        let (first, second) = writer.into_inner();
+        assert_eq!(first, b"[]\n{}\n");
+        assert_eq!(second.into_inner().written, b"[]\n{");
+
+        let mut writer = TeeWriter::new(NamedWriter::new(full(), "stdout"), Vec::new());
+        writer.write_all(b"[]\n").unwrap();
+        let error = writer.write_all(b"{}\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Could not write to `stdout`: "));
+        assert_eq!(writer.into_inner().1, b"[]\n");
+    }
+
+    #[test]
+    fn test_tee_writer_flushes_both_writers() {
+        let mut writer = TeeWriter::new(BufWriter::new(Vec::new()), BufWriter::new(Vec::new()));
+        writer.write_all(b"{\"a\":1}\n").unwrap();
+        writer.flush().unwrap();
+        let (first, second) = writer.into_inner();
+        assert_eq!(first.get_ref(), b"{\"a\":1}\n");
+        assert_eq!(second.get_ref(), b"{\"a\":1}\n");
+    }
+
+    fn chunk(lines: usize, chunk_records: usize) -> String {
+        let mut writer = ChunkWriter::new(Vec::new(), chunk_records, LineEnding::Lf);
+        for i in 1..=lines {
+            writeln!(writer, "{{\"n\":{}}}", i).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_chunk_writer_separates_exact_multiples() {
+        assert_eq!(
+            chunk(4, 2),
+            "{\"n\":1}\n{\"n\":2}\n\n{\"n\":3}\n{\"n\":4}\n"
+        );
+        assert_eq!(chunk(2, 2), "{\"n\":1}\n{\"n\":2}\n");
+        assert_eq!(chunk(0, 2), "");
+    }
+
+    #[test]
+    fn test_chunk_writer_leaves_remainder_unterminated() {
+        assert_eq!(
+            chunk(5, 2),
+            "{\"n\":1}\n{\"n\":2}\n\n{\"n\":3}\n{\"n\":4}\n\n{\"n\":5}\n"
+        );
+        assert_eq!(chunk(3, 1), "{\"n\":1}\n\n{\"n\":2}\n\n{\"n\":3}\n");
+    }
+
+    #[test]
+    fn test_chunk_writer_handles_lines_split_across_writes() {
+        let mut writer = ChunkWriter::new(Vec::new(), 1, LineEnding::Crlf);
+        writer.write_all(b"{\"a\":").unwrap();
+        writer.write_all(b"1}\r\n{").unwrap();
+        writer.write_all(b"}\r\n").unwrap();
+        assert_eq!(writer.into_inner(), b"{\"a\":1}\r\n\r\n{}\r\n");
+    }
+
+    #[test]
+    fn test_replace_file_replaces_original_on_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        fs::write(&path, "[{\"a\": 1}]").unwrap();
+
+        let mut file = ReplaceFile::create(path.to_str().unwrap()).unwrap();
+        assert_eq!(file.temp_path().parent(), path.parent());
+        file.write_all(b"{\"a\": 1}\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[{\"a\": 1}]");
+        file.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"a\": 1}\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_file_dropped_leaves_original_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        fs::write(&path, "[{\"a\": 1}]").unwrap();
+
+        let mut file = ReplaceFile::create(path.to_str().unwrap()).unwrap();
+        file.write_all(b"{\"a\"").unwrap();
+        drop(file);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[{\"a\": 1}]");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        let missing = dir.path().join("missing.json");
+        assert!(ReplaceFile::create(missing.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_counting_writer_counts_bytes() {
+        let mut writer = CountingWriter::new(Vec::new());
+        writer.write_all(b"{\"a\":1}\n").unwrap();
+        writer.write_all(b"[]\n").unwrap();
+        assert_eq!(writer.bytes_written(), 11);
+        assert_eq!(writer.into_inner(), b"{\"a\":1}\n[]\n");
+    }
+}
+
+#[cfg(all(test, unix))]
+mod pipe_tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_pipe_output_streams_to_child_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.jsonl");
+
+        let mut output = PipeOutput::spawn(&format!("cat > '{}'", path.display())).unwrap();
+        writeln!(output, "{{\"a\":1}}").unwrap();
+        writeln!(output, "{{\"b\":2}}").unwrap();
+        output.finish().unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "{\"a\":1}\n{\"b\":2}\n");
+    }
+
+    #[test]
+    fn test_pipe_output_reports_failed_child() {
+        let output = PipeOutput::spawn("exit 3").unwrap();
+        let error = output.finish().unwrap_err();
+        assert!(matches!(error, ConversionError::PipeExit { .. }));
+    }
+
+    #[test]
+    fn test_pipe_output_child_exiting_early_is_a_broken_pipe() {
+        let mut output = PipeOutput::spawn("exit 0").unwrap();
+        let record = [b'a'; 1024];
+        let error = loop {
+            if let Err(error) = output.write_all(&record).and_then(|_| output.flush()) {
+                break error;
+            }
+        };
+
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert!(output.finish().is_ok());
+    }
+}
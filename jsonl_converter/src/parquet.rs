@@ -0,0 +1,554 @@
+//! This module contains `ParquetSink`, which writes records of flat objects
+//! to a Parquet file rather than as JSONL, so that they can be loaded
+//! straight into columnar tools. It is only built with the `parquet`
+//! feature, which writes the file with the `arrow` and `parquet` crates.
+//!
+//! # Schema
+//!
+//! Each record must be an object, whose top level keys are the columns. The
+//! schema is inferred from the first records, `DEFAULT_SCHEMA_SAMPLE` of
+//! them by default, which are held in memory until it has been:
+//!
+//! * Each key seen in those records is a column, sorted by name. Every column
+//!   is nullable.
+//! * A column whose values are all booleans is `Boolean`, all integers is
+//!   `Int64` and all numbers is `Float64`. Nulls are ignored.
+//! * A column whose values are all strings, or all null, is `Utf8`.
+//! * Any other column, such as one of nested objects or one with values of
+//!   more than one type, is `Utf8` holding the JSON text of each value.
+//!
+//! A later record that is missing a key is written with null for that
+//! column. A later record with a key that is not a column, or a value of the
+//! wrong type for its column, is an error rather than being dropped, as
+//! nothing of it can be written without losing data. Raising the schema
+//! sample lets more of the input decide the schema.
+//!
+//! # Format
+//!
+//! Rows are written in row groups of `DEFAULT_ROW_GROUP_ROWS`, and only a
+//! row group is held in memory at a time.
+
+use std::{collections::BTreeSet, io::Write, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+};
+use parquet::{arrow::ArrowWriter, errors::ParquetError, file::properties::WriterProperties};
+use serde_json::{Map, Value};
+
+use crate::{errors::ConversionError, sink::EmitSink};
+
+/// The number of records the schema is inferred from by default.
+pub const DEFAULT_SCHEMA_SAMPLE: usize = 100;
+
+/// The number of rows in each row group by default.
+pub const DEFAULT_ROW_GROUP_ROWS: usize = 65_536;
+
+/// The type of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Int64,
+    Double,
+    String,
+    Json,
+}
+
+impl ColumnType {
+    /// Returns the type of a column with `values`, ignoring nulls.
+    fn infer<'a>(values: impl Iterator<Item = &'a Value>) -> ColumnType {
+        let mut inferred = None;
+        for value in values {
+            let column_type = match value {
+                Value::Null => continue,
+                Value::Bool(_) => ColumnType::Boolean,
+                Value::Number(number) if number.is_i64() => ColumnType::Int64,
+                Value::Number(_) => ColumnType::Double,
+                Value::String(_) => ColumnType::String,
+                _ => return ColumnType::Json,
+            };
+            inferred = Some(match (inferred, column_type) {
+                (None, column_type) => column_type,
+                (Some(inferred), column_type) if inferred == column_type => inferred,
+                (Some(ColumnType::Int64), ColumnType::Double)
+                | (Some(ColumnType::Double), ColumnType::Int64) => ColumnType::Double,
+                _ => return ColumnType::Json,
+            });
+        }
+        inferred.unwrap_or(ColumnType::String)
+    }
+
+    /// Returns the Arrow type the column is written as.
+    fn data_type(self) -> DataType {
+        match self {
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::Int64 => DataType::Int64,
+            ColumnType::Double => DataType::Float64,
+            ColumnType::String | ColumnType::Json => DataType::Utf8,
+        }
+    }
+
+    /// Checks if `value`, which is not null, can be written to a column of
+    /// this type without losing any of it.
+    fn fits(self, value: &Value) -> bool {
+        match (self, value) {
+            (ColumnType::Boolean, Value::Bool(_)) => true,
+            (ColumnType::Int64, Value::Number(number)) => number.is_i64(),
+            (ColumnType::Double, Value::Number(_)) => true,
+            (ColumnType::String, Value::String(_)) => true,
+            (ColumnType::Json, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the name of the type, as used in errors.
+    fn name(self) -> &'static str {
+        match self {
+            ColumnType::Boolean => "boolean",
+            ColumnType::Int64 => "integer",
+            ColumnType::Double => "number",
+            ColumnType::String => "string",
+            ColumnType::Json => "JSON",
+        }
+    }
+}
+
+/// A column of the file.
+///
+/// # Fields
+///
+/// * `name` - The key the column's values are taken from.
+/// * `column_type` - The type of the column.
+struct Column {
+    name: String,
+    column_type: ColumnType,
+}
+
+impl Column {
+    /// Returns the column's values in `rows` as an Arrow array. Every value
+    /// has already been checked to fit the column.
+    fn array(&self, rows: &[Map<String, Value>]) -> ArrayRef {
+        let values = rows
+            .iter()
+            .map(|row| row.get(&self.name).filter(|value| !value.is_null()));
+        match self.column_type {
+            ColumnType::Boolean => Arc::new(BooleanArray::from(
+                values
+                    .map(|value| value.and_then(Value::as_bool))
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnType::Int64 => Arc::new(Int64Array::from(
+                values
+                    .map(|value| value.and_then(Value::as_i64))
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnType::Double => Arc::new(Float64Array::from(
+                values
+                    .map(|value| value.and_then(Value::as_f64))
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnType::String => Arc::new(StringArray::from(
+                values
+                    .map(|value| value.and_then(Value::as_str))
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnType::Json => Arc::new(StringArray::from(
+                values
+                    .map(|value| value.map(Value::to_string))
+                    .collect::<Vec<_>>(),
+            )),
+        }
+    }
+}
+
+/// Writes each record as a row of a Parquet file.
+///
+/// # Fields
+///
+/// * `output` - Where the file is written, until the schema has been
+///   inferred and it is handed to `writer`.
+/// * `writer` - Writes the file, once the schema has been inferred.
+/// * `schema_sample` - The number of records the schema is inferred from.
+/// * `row_group_rows` - The number of rows in each row group.
+/// * `columns` - The columns, once the schema has been inferred.
+/// * `schema` - The Arrow schema of the columns.
+/// * `rows` - The records held until the schema has been inferred, and then
+///   the rows of the current row group.
+/// * `records` - The number of records sent to the sink.
+pub struct ParquetSink<W: Write + Send> {
+    output: Option<W>,
+    writer: Option<ArrowWriter<W>>,
+    schema_sample: usize,
+    row_group_rows: usize,
+    columns: Vec<Column>,
+    schema: SchemaRef,
+    rows: Vec<Map<String, Value>>,
+    records: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    /// Creates a new `ParquetSink` that writes the file to `output` once
+    /// records are sent to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{
+    ///     parquet::ParquetSink,
+    ///     records::{convert_with_sink, ConverterOptions},
+    /// };
+    ///
+    /// let mut file = Vec::new();
+    /// let input = "[{\"id\": 1, \"name\": \"a\"}, {\"id\": 2}]".as_bytes();
+    /// convert_with_sink(input, ParquetSink::new(&mut file), ConverterOptions::default())
+    ///     .unwrap();
+    /// assert!(file.starts_with(b"PAR1") && file.ends_with(b"PAR1"));
+    /// ```
+    pub fn new(output: W) -> Self {
+        ParquetSink {
+            output: Some(output),
+            writer: None,
+            schema_sample: DEFAULT_SCHEMA_SAMPLE,
+            row_group_rows: DEFAULT_ROW_GROUP_ROWS,
+            columns: Vec::new(),
+            schema: Arc::new(Schema::empty()),
+            rows: Vec::new(),
+            records: 0,
+        }
+    }
+
+    /// Sets the number of records the schema is inferred from, which are
+    /// held in memory until it has been. At least one record is used.
+    pub fn set_schema_sample(&mut self, schema_sample: usize) {
+        self.schema_sample = schema_sample.max(1);
+    }
+
+    /// Sets the number of rows in each row group. At least one row is used.
+    pub fn set_row_group_rows(&mut self, row_group_rows: usize) {
+        self.row_group_rows = row_group_rows.max(1);
+    }
+
+    /// Infers the schema from the records held so far, and starts writing
+    /// the file.
+    fn infer_schema(&mut self) -> Result<(), ConversionError> {
+        let names: BTreeSet<&String> = self.rows.iter().flat_map(|record| record.keys()).collect();
+        self.columns = names
+            .into_iter()
+            .map(|name| Column {
+                name: name.clone(),
+                column_type: ColumnType::infer(
+                    self.rows.iter().filter_map(|record| record.get(name)),
+                ),
+            })
+            .collect();
+        self.schema = Arc::new(Schema::new(
+            self.columns
+                .iter()
+                .map(|column| Field::new(&column.name, column.column_type.data_type(), true))
+                .collect::<Vec<_>>(),
+        ));
+        if self.columns.is_empty() && !self.rows.is_empty() {
+            // Parquet has no way to hold rows without any columns.
+            return Err(ConversionError::InvalidRecord {
+                record: 1,
+                message: String::from(
+                    "The records have no keys, so there are no columns to write to Parquet.",
+                ),
+            });
+        }
+
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(self.row_group_rows)
+            .set_created_by(String::from("jsonl_converter"))
+            .build();
+        let output = self
+            .output
+            .take()
+            .expect("The schema is only inferred once.");
+        self.writer = Some(
+            ArrowWriter::try_new(output, self.schema.clone(), Some(properties))
+                .map_err(parquet_error)?,
+        );
+        if self.rows.len() >= self.row_group_rows {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every member of `record` fits the schema.
+    ///
+    /// # Errors
+    ///
+    /// * If a key of `record` is not a column, or its value is of the wrong
+    ///   type for its column.
+    fn check_row(&self, record: &Map<String, Value>) -> Result<(), ConversionError> {
+        for (key, value) in record {
+            let Ok(i) = self
+                .columns
+                .binary_search_by(|column| column.name.as_str().cmp(key))
+            else {
+                return Err(self.invalid_record(format!(
+                    "The key `{}` is not a column of the Parquet schema.",
+                    key
+                )));
+            };
+            let column_type = self.columns[i].column_type;
+            if !value.is_null() && !column_type.fits(value) {
+                return Err(self.invalid_record(format!(
+                    "The value of `{}` does not fit its {} column: {}",
+                    key,
+                    column_type.name(),
+                    value
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the rows of the current row group.
+    fn write_row_group(&mut self) -> Result<(), ConversionError> {
+        let rows = std::mem::take(&mut self.rows);
+        let arrays: Vec<ArrayRef> = self
+            .columns
+            .iter()
+            .map(|column| column.array(&rows))
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)
+            .map_err(|error| parquet_error(error.into()))?;
+        let writer = self.writer.as_mut().expect("The schema has been inferred.");
+        writer.write(&batch).map_err(parquet_error)?;
+        writer.flush().map_err(parquet_error)
+    }
+
+    /// Returns the error for the record about to be added not fitting the
+    /// file.
+    fn invalid_record(&self, message: String) -> ConversionError {
+        ConversionError::InvalidRecord {
+            record: self.records + 1,
+            message,
+        }
+    }
+}
+
+impl<W: Write + Send> EmitSink for ParquetSink<W> {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        let value =
+            serde_json::from_str(record).map_err(|error| self.invalid_record(error.to_string()))?;
+        let Value::Object(record) = value else {
+            return Err(self.invalid_record(String::from(
+                "A record must be an object to be written to Parquet.",
+            )));
+        };
+        if self.writer.is_some() {
+            self.check_row(&record)?;
+        }
+        self.records += 1;
+        self.rows.push(record);
+        if self.writer.is_none() {
+            if self.rows.len() >= self.schema_sample {
+                self.infer_schema()?;
+            }
+        } else if self.rows.len() >= self.row_group_rows {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), ConversionError> {
+        if self.writer.is_none() {
+            self.infer_schema()?;
+        }
+        if !self.rows.is_empty() {
+            self.write_row_group()?;
+        }
+        let writer = self.writer.take().expect("The schema has been inferred.");
+        let mut output = writer.into_inner().map_err(parquet_error)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Returns a `ParquetError` as a `ConversionError`.
+fn parquet_error(error: ParquetError) -> ConversionError {
+    ConversionError::Io(std::io::Error::other(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::{
+        fs::File,
+        io::{Seek, SeekFrom},
+    };
+
+    /// Reads the rows of a Parquet file back as JSON objects with the
+    /// `parquet` crate's own reader, leaving out nulls, along with the number
+    /// of row groups.
+    fn read_rows(file: File) -> (Vec<Map<String, Value>>, usize) {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let row_groups = reader.metadata().num_row_groups();
+        let mut rows = Vec::new();
+        for batch in reader.build().unwrap() {
+            let batch = batch.unwrap();
+            let mut batch_rows = vec![Map::new(); batch.num_rows()];
+            for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+                for (i, row) in batch_rows.iter_mut().enumerate() {
+                    if array.is_null(i) {
+                        continue;
+                    }
+                    let value = match field.data_type() {
+                        DataType::Boolean => Value::from(array.as_boolean().value(i)),
+                        DataType::Int64 => Value::from(
+                            array.as_primitive::<arrow::datatypes::Int64Type>().value(i),
+                        ),
+                        DataType::Float64 => Value::from(
+                            array
+                                .as_primitive::<arrow::datatypes::Float64Type>()
+                                .value(i),
+                        ),
+                        _ => Value::from(array.as_string::<i32>().value(i)),
+                    };
+                    row.insert(field.name().clone(), value);
+                }
+            }
+            rows.extend(batch_rows);
+        }
+        (rows, row_groups)
+    }
+
+    fn write(records: &[&str], configure: impl FnOnce(&mut ParquetSink<&mut File>)) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        let mut sink = ParquetSink::new(&mut file);
+        configure(&mut sink);
+        for record in records {
+            sink.emit(record).unwrap();
+        }
+        sink.finish().unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file
+    }
+
+    fn objects(records: &[&str]) -> Vec<Map<String, Value>> {
+        records
+            .iter()
+            .map(|record| serde_json::from_str(record).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rows_read_back_as_written() {
+        let records = [
+            "{\"id\": 1, \"name\": \"a\", \"score\": 0.5, \"ok\": true, \"tags\": [\"x\"]}",
+            "{\"id\": -2, \"name\": \"é\", \"score\": 3, \"ok\": false, \"tags\": {\"y\": 1}}",
+            "{\"id\": 3, \"name\": null, \"score\": -1.25, \"ok\": true}",
+        ];
+        for (row_group_rows, expected_groups) in [(1, 3), (2, 2), (100, 1)] {
+            let (rows, row_groups) = read_rows(write(&records, |sink| {
+                sink.set_row_group_rows(row_group_rows)
+            }));
+            let mut expected = objects(&records);
+            expected[2].remove("name");
+            // Integers in a `Float64` column come back as floats, and nested
+            // values as their JSON text.
+            expected[1]["score"] = Value::from(3.0);
+            expected[0]["tags"] = Value::from("[\"x\"]");
+            expected[1]["tags"] = Value::from("{\"y\":1}");
+            assert_eq!(rows, expected);
+            assert_eq!(row_groups, expected_groups, "{}", row_group_rows);
+        }
+    }
+
+    #[test]
+    fn test_missing_keys_are_written_as_nulls() {
+        let (rows, _) = read_rows(write(
+            &["{\"a\": 1, \"b\": \"x\"}", "{\"b\": \"y\"}"],
+            |sink| sink.set_schema_sample(1),
+        ));
+        assert_eq!(
+            rows,
+            objects(&["{\"a\": 1, \"b\": \"x\"}", "{\"b\": \"y\"}"])
+        );
+    }
+
+    #[test]
+    fn test_values_that_do_not_fit_the_schema_are_errors() {
+        for (record, message) in [
+            (
+                "{\"a\": \"three\"}",
+                "Record 2 is not valid JSON: The value of `a` does not fit its integer \
+                 column: \"three\"",
+            ),
+            (
+                "{\"a\": 18446744073709551615}",
+                "Record 2 is not valid JSON: The value of `a` does not fit its integer \
+                 column: 18446744073709551615",
+            ),
+            (
+                "{\"a\": 2, \"d\": 4}",
+                "Record 2 is not valid JSON: The key `d` is not a column of the Parquet schema.",
+            ),
+        ] {
+            let mut sink = ParquetSink::new(Vec::new());
+            sink.set_schema_sample(1);
+            sink.emit("{\"a\": 1}").unwrap();
+            let error = sink.emit(record).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_column_types_are_inferred() {
+        let cases = [
+            (vec!["true", "null", "false"], ColumnType::Boolean),
+            (vec!["1", "-2"], ColumnType::Int64),
+            (vec!["1", "2.5"], ColumnType::Double),
+            (vec!["\"a\"", "null"], ColumnType::String),
+            (vec!["null"], ColumnType::String),
+            (vec!["1", "\"a\""], ColumnType::Json),
+            (vec!["{}"], ColumnType::Json),
+        ];
+        for (values, expected) in cases {
+            let values: Vec<Value> = values
+                .iter()
+                .map(|value| serde_json::from_str(value).unwrap())
+                .collect();
+            assert_eq!(ColumnType::infer(values.iter()), expected, "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn test_records_must_be_objects() {
+        let mut sink = ParquetSink::new(Vec::new());
+        sink.emit("{\"a\": 1}").unwrap();
+        let error = sink.emit("[1]").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 2 is not valid JSON: A record must be an object to be written to Parquet."
+        );
+    }
+
+    #[test]
+    fn test_records_without_any_keys_are_an_error() {
+        let mut sink = ParquetSink::new(Vec::new());
+        sink.emit("{}").unwrap();
+        assert_eq!(
+            sink.finish().unwrap_err().to_string(),
+            "Record 1 is not valid JSON: The records have no keys, so there are no columns to \
+             write to Parquet."
+        );
+    }
+
+    #[test]
+    fn test_empty_input_is_a_file_without_rows() {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(write(&[], |_| {})).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+        assert_eq!(reader.schema().fields().len(), 0);
+        assert!(reader
+            .build()
+            .unwrap()
+            .all(|batch| batch.unwrap().num_rows() == 0));
+    }
+}
@@ -0,0 +1,372 @@
+//! This module contains the `PartitionWriter`, which splits JSONL output into
+//! one file per value of a top level key.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{keys::top_level_value, output::NamedWriter};
+
+/// The name of the partition that records without the key are written to.
+/// No value is named `@default` by `partition_file_name`, so it cannot be the
+/// partition of any value of the key, even `"@default"`.
+pub const DEFAULT_PARTITION: &str = "@default";
+
+/// The default number of partition files that are kept open at once.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// The bytes that are percent-encoded in partition file names: all but
+/// ASCII letters, digits, `-`, `_` and `.`.
+const FILE_NAME: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+/// Returns a file name for a partition value, which is different for every
+/// value, so that records with different values are never written to the
+/// same file.
+///
+/// A non-empty string is named by its contents, and any other value by `@`
+/// followed by its JSON text. Bytes other than ASCII letters, digits, `-`,
+/// `_` and `.` are percent-encoded, as is a leading `.`, so that a value
+/// cannot escape the output directory or produce a hidden or invalid file
+/// name.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::partition::partition_file_name;
+///
+/// assert_eq!(partition_file_name("\"en-GB\""), "en-GB.jsonl");
+/// assert_eq!(partition_file_name("\"../etc/passwd\""), "%2E.%2Fetc%2Fpasswd.jsonl");
+/// assert_eq!(partition_file_name("\"42\""), "42.jsonl");
+/// assert_eq!(partition_file_name("42"), "@42.jsonl");
+/// ```
+pub fn partition_file_name(value: &str) -> String {
+    let name = match serde_json::from_str::<String>(value) {
+        Ok(string) if !string.is_empty() => encode(&string),
+        _ => format!("@{}", encode(value)),
+    };
+    format!("{}.jsonl", name)
+}
+
+/// Returns `s` percent-encoded for use in a file name.
+fn encode(s: &str) -> String {
+    let encoded = utf8_percent_encode(s, FILE_NAME).to_string();
+    match encoded.strip_prefix('.') {
+        Some(rest) => format!("%2E{}", rest),
+        None => encoded,
+    }
+}
+
+/// Splits JSONL written to it into one file per value of a top level key.
+///
+/// Each line written is treated as a record. Its value for `key` decides the
+/// file within `dir` it is appended to, with records missing the key going to
+/// the `DEFAULT_PARTITION` file, `@default.jsonl`.
+///
+/// Files are truncated the first time they are written to. At most
+/// `max_open_files` files are kept open; when another is needed, the least
+/// recently used file is flushed and closed, and is reopened in append mode
+/// if needed again.
+///
+/// # Fields
+///
+/// * `dir` - The directory that partition files are written to.
+/// * `key` - The top level key that records are partitioned by.
+/// * `max_open_files` - The maximum number of files kept open at once.
+/// * `writers` - The currently open partition files, along with when each
+///   was last used.
+/// * `uses` - The number of records written, used to order the open files
+///   by when they were last used.
+/// * `created` - The partition files that have been created by this writer.
+/// * `line` - The record that is currently being written.
+pub struct PartitionWriter {
+    dir: PathBuf,
+    key: String,
+    max_open_files: usize,
+    writers: HashMap<String, (BufWriter<NamedWriter<File>>, u64)>,
+    uses: u64,
+    created: HashSet<String>,
+    line: Vec<u8>,
+}
+
+impl PartitionWriter {
+    /// Creates a new `PartitionWriter`, creating `dir` if it does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory that partition files are written to.
+    /// * `key` - The top level key that records are partitioned by.
+    /// * `max_open_files` - The maximum number of files kept open at once.
+    ///
+    /// # Errors
+    ///
+    /// * If `dir` cannot be created.
+    pub fn new(dir: &Path, key: &str, max_open_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(PartitionWriter {
+            dir: dir.to_path_buf(),
+            key: key.to_string(),
+            max_open_files: max_open_files.max(1),
+            writers: HashMap::new(),
+            uses: 0,
+            created: HashSet::new(),
+            line: Vec::new(),
+        })
+    }
+
+    /// Returns the names of the partition files that have been written to.
+    pub fn partitions(&self) -> impl Iterator<Item = &str> {
+        self.created.iter().map(String::as_str)
+    }
+
+    /// Returns the number of partition files that are currently open.
+    pub fn open_files(&self) -> usize {
+        self.writers.len()
+    }
+
+    /// Returns the writer for the partition file `name`, opening it if needed.
+    fn writer(&mut self, name: &str) -> io::Result<&mut BufWriter<NamedWriter<File>>> {
+        self.uses += 1;
+        if !self.writers.contains_key(name) {
+            if self.writers.len() >= self.max_open_files {
+                self.close_least_recently_used()?;
+            }
+
+            let mut options = OpenOptions::new();
+            if self.created.insert(name.to_string()) {
+                options.write(true).create(true).truncate(true);
+            } else {
+                options.append(true);
+            }
+            let path = self.dir.join(name);
+            let file = NamedWriter::new(options.open(&path)?, &path.to_string_lossy());
+            self.writers
+                .insert(name.to_string(), (BufWriter::new(file), self.uses));
+        }
+
+        let (writer, last_used) = self.writers.get_mut(name).unwrap();
+        *last_used = self.uses;
+        Ok(writer)
+    }
+
+    /// Flushes and closes the partition file that was used the longest ago.
+    fn close_least_recently_used(&mut self) -> io::Result<()> {
+        let oldest = self
+            .writers
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(name, _)| name.clone());
+        if let Some((mut writer, _)) = oldest.and_then(|name| self.writers.remove(&name)) {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered `line` to the partition file it belongs to.
+    fn route_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        let record = String::from_utf8_lossy(&line);
+        let name = match top_level_value(record.trim_end(), &self.key) {
+            Some(value) => partition_file_name(value),
+            None => format!("{}.jsonl", DEFAULT_PARTITION),
+        };
+
+        self.writer(&name)?.write_all(&line)?;
+
+        self.line = line;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl Write for PartitionWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                self.route_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (writer, _) in self.writers.values_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PartitionWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(dir: &Path, name: &str) -> String {
+        fs::read_to_string(dir.join(name)).unwrap()
+    }
+
+    #[test]
+    fn test_partition_file_name_sanitizes_values() {
+        assert_eq!(partition_file_name("\"a b/c\""), "a%20b%2Fc.jsonl");
+        assert_eq!(partition_file_name("\"..\""), "%2E..jsonl");
+        assert_eq!(partition_file_name("true"), "@true.jsonl");
+        assert_eq!(partition_file_name("\"café\""), "caf%C3%A9.jsonl");
+        assert_eq!(partition_file_name("\"100%\""), "100%25.jsonl");
+        assert_eq!(partition_file_name("\"\""), "@%22%22.jsonl");
+    }
+
+    #[test]
+    fn test_partition_file_name_is_different_for_every_value() {
+        let values = [
+            "\"a/b\"",
+            "\"a b\"",
+            "\"a_b\"",
+            "\"a%2Fb\"",
+            "\"\u{e9}\"",
+            "\"\u{fc}\"",
+            "\"\"",
+            "\"_\"",
+            "\"1\"",
+            "1",
+            "\"@1\"",
+            "\"%401\"",
+            "\".a\"",
+            "\"%2Ea\"",
+            "\"default\"",
+            "\"@default\"",
+            "null",
+            "\"null\"",
+        ];
+        let mut names: Vec<String> = values.iter().map(|v| partition_file_name(v)).collect();
+        names.push(format!("{}.jsonl", DEFAULT_PARTITION));
+        let unique: HashSet<&String> = names.iter().collect();
+        assert_eq!(unique.len(), names.len(), "{:?}", names);
+    }
+
+    #[test]
+    fn test_partition_file_name_decodes_escapes() {
+        assert_eq!(
+            partition_file_name("\"caf\\u00e9\""),
+            partition_file_name("\"café\"")
+        );
+    }
+
+    #[test]
+    fn test_partition_writer_routes_records_by_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "lang", 8).unwrap();
+        writeln!(writer, "{{\"lang\": \"en\", \"n\": 1}}").unwrap();
+        writeln!(writer, "{{\"lang\": \"fr\", \"n\": 2}}").unwrap();
+        writeln!(writer, "{{\"n\": 3}}").unwrap();
+        writeln!(writer, "{{\"lang\": \"en\", \"n\": 4}}").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(
+            read(dir.path(), "en.jsonl"),
+            "{\"lang\": \"en\", \"n\": 1}\n{\"lang\": \"en\", \"n\": 4}\n"
+        );
+        assert_eq!(
+            read(dir.path(), "fr.jsonl"),
+            "{\"lang\": \"fr\", \"n\": 2}\n"
+        );
+        assert_eq!(read(dir.path(), "@default.jsonl"), "{\"n\": 3}\n");
+
+        let mut partitions: Vec<&str> = writer.partitions().collect();
+        partitions.sort();
+        assert_eq!(partitions, vec!["@default.jsonl", "en.jsonl", "fr.jsonl"]);
+    }
+
+    #[test]
+    fn test_partition_writer_keeps_missing_keys_apart_from_default_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "k", 8).unwrap();
+        writeln!(writer, "{{\"k\": \"_default\"}}").unwrap();
+        writeln!(writer, "{{\"k\": \"@default\"}}").unwrap();
+        writeln!(writer, "{{\"n\": 1}}").unwrap();
+        drop(writer);
+
+        // `@` is percent-encoded in values, so neither value is the default.
+        assert_eq!(
+            read(dir.path(), "_default.jsonl"),
+            "{\"k\": \"_default\"}\n"
+        );
+        assert_eq!(
+            read(dir.path(), "%40default.jsonl"),
+            "{\"k\": \"@default\"}\n"
+        );
+        assert_eq!(read(dir.path(), "@default.jsonl"), "{\"n\": 1}\n");
+    }
+
+    #[test]
+    fn test_partition_writer_handles_records_split_across_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "k", 8).unwrap();
+        writer.write_all(b"{\"k\": ").unwrap();
+        writer.write_all(b"1}\n{\"k\": 2}\n{\"k\"").unwrap();
+        writer.write_all(b": 1}\n").unwrap();
+        drop(writer);
+
+        assert_eq!(read(dir.path(), "@1.jsonl"), "{\"k\": 1}\n{\"k\": 1}\n");
+        assert_eq!(read(dir.path(), "@2.jsonl"), "{\"k\": 2}\n");
+    }
+
+    #[test]
+    fn test_partition_writer_reopens_files_beyond_the_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "k", 2).unwrap();
+        for i in [1, 2, 3, 1, 2, 3, 1] {
+            writeln!(writer, "{{\"k\": {}}}", i).unwrap();
+        }
+        drop(writer);
+
+        assert_eq!(read(dir.path(), "@1.jsonl").lines().count(), 3);
+        assert_eq!(read(dir.path(), "@2.jsonl").lines().count(), 2);
+        assert_eq!(read(dir.path(), "@3.jsonl").lines().count(), 2);
+    }
+
+    #[test]
+    fn test_partition_writer_closes_least_recently_used_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "k", 3).unwrap();
+        let keys = [1, 2, 3, 1, 4, 1, 5, 1, 2, 6, 1, 3, 7, 1, 4];
+        for (n, k) in keys.iter().enumerate() {
+            writeln!(writer, "{{\"k\": {}, \"n\": {}}}", k, n).unwrap();
+            assert!(writer.open_files() <= 3);
+        }
+        // 1 is used often enough to never be closed.
+        assert!(writer.writers.contains_key("@1.jsonl"));
+        drop(writer);
+
+        for k in 1..=7 {
+            let expected: String = keys
+                .iter()
+                .enumerate()
+                .filter(|(_, &key)| key == k)
+                .map(|(n, _)| format!("{{\"k\": {}, \"n\": {}}}\n", k, n))
+                .collect();
+            assert_eq!(read(dir.path(), &format!("@{}.jsonl", k)), expected);
+        }
+    }
+
+    #[test]
+    fn test_partition_writer_truncates_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("@1.jsonl"), "stale\n").unwrap();
+        let mut writer = PartitionWriter::new(dir.path(), "k", 2).unwrap();
+        writeln!(writer, "{{\"k\": 1}}").unwrap();
+        drop(writer);
+
+        assert_eq!(read(dir.path(), "@1.jsonl"), "{\"k\": 1}\n");
+    }
+}
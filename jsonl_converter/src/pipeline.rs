@@ -0,0 +1,1313 @@
+//! This module ties the readers and processors together to convert a JSON
+//! file to JSONL according to the options provided on the command line.
+//!
+//! # Key order
+//!
+//! Records are copied from the input rather than parsed and serialized, so
+//! the keys of each object are written in the order they appear in the input.
+//! This holds for every option except `--flatten` and `--canonical`, which
+//! write keys in sorted order.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    buffer::{auto_buffer_size, DEFAULT_BUFFER_SIZE},
+    canonical::Canonicalizer,
+    check::check_lines,
+    cli::Args,
+    converter::{Converter, ConverterOptions},
+    diff::{canonical_records, diff_records, Difference},
+    envelope::Envelope,
+    errors::{guarded, ConversionError},
+    flatten::Flattener,
+    glob,
+    json_object::{CleanupPolicy, Format},
+    key_case::KeyCaser,
+    keys::{MapValues, RequiredFields},
+    output::CountingWriter,
+    pointer::{locate_arrays, JsonPointer},
+    readers::{byte_iter::ByteIterator, line_iter::LineIterator},
+    records::jsonl_records,
+    report::Report,
+    reverse::reverse_lines,
+    unicode::Normalizer,
+    verify::{count_elements, verify_count},
+    writer::{OutputFormat, RecordWriter},
+};
+
+#[cfg(unix)]
+use crate::readers::utils::file_from_fd;
+#[cfg(feature = "gzip")]
+use crate::readers::utils::is_gzip;
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+
+/// Converts the file described by `args` to JSONL, writing the records to
+/// `output`. If `--check-jsonl` was given, the file is instead checked to be
+/// valid JSONL and nothing is written. If `--reverse` was given, the file is
+/// instead converted from JSONL to a JSON array. If `--diff` was given, the
+/// elements that differ from those of the other file are written instead.
+///
+/// # Arguments
+///
+/// * `args` - The options provided on the command line.
+/// * `output` - Where the JSONL records are written to.
+/// * `report` - Updated with a summary of the conversion, even if the
+///   conversion fails part of the way through.
+///
+/// # Errors
+///
+/// * If the file is not valid or an output cannot be written to.
+/// * If the conversion panics, which is caught and returned as
+///   `ConversionError::Internal` so that it does not unwind into the caller.
+///   The panic hook still runs, which by default prints the panic to stderr.
+///   Whatever was written to `output` and `report` before the panic is kept.
+pub fn convert<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    guarded(|| convert_unguarded(args, output, report))
+}
+
+/// Converts the file as `convert` does, but lets panics unwind.
+fn convert_unguarded<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    if args.check_jsonl {
+        let mut line_iter = LineIterator::from_reader(input_reader(args)?);
+        line_iter.set_strict_utf8(args.strict_utf8);
+        return check_lines(&mut line_iter, report);
+    }
+    if let Some(other) = &args.diff {
+        return diff_arrays(args, other, output, report);
+    }
+    if !args.root_pointers.is_empty() {
+        return convert_pointed_arrays(args, output, report);
+    }
+    convert_array(args, output, report)
+}
+
+/// Converts the root array, or the array that starts at `--start-offset`,
+/// or converts the file from JSONL back to a JSON array with `--reverse`.
+fn convert_array<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let mut output = CountingWriter::new(output);
+    if args.reverse {
+        let mut line_iter = LineIterator::from_reader(input_reader(args)?);
+        line_iter.set_strict_utf8(args.strict_utf8);
+        let result = reverse_lines(
+            &mut line_iter,
+            &mut output,
+            args.pretty,
+            args.output_eol,
+            report,
+        );
+        report.bytes_out = output.bytes_written();
+        return result;
+    }
+    let result = convert_records(args, &mut output, report);
+    let result = result.and_then(|_| Ok(output.flush()?));
+    report.bytes_out = output.bytes_written();
+    result
+}
+
+/// With `--diff`, compares the elements of the input with those of the
+/// `other` file by index, writing each that differs as a line of `output`.
+/// See the `diff` module for the format.
+///
+/// # Errors
+///
+/// * If either file cannot be read, is not a JSON array or has an element
+///   that is not valid JSON. Errors in the other file name it.
+/// * If the output cannot be written to.
+fn diff_arrays<W: Write>(
+    args: &Args,
+    other: &str,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let in_other = |error| ConversionError::Input {
+        path: other.to_string(),
+        error: Box::new(error),
+    };
+    // Both files are read with the same options as a conversion, except
+    // that they are read from the start and may be formatted in any way, as
+    // with `--messy`.
+    let options = ConverterOptions {
+        lines: false,
+        start_offset: None,
+        stop_at_root_end: false,
+        ..converter_options(args)
+    };
+    let mut old = jsonl_records(input_reader(args)?, options);
+    let new_reader = File::open(other)
+        .and_then(|file| Input::new(file, DEFAULT_BUFFER_SIZE))
+        .map_err(|error| in_other(error.into()))?;
+    let mut new = jsonl_records(new_reader, options);
+
+    let mut output = CountingWriter::new(output);
+    let differences = diff_records(
+        canonical_records(old.by_ref()),
+        canonical_records(new.by_ref()).map(|element| element.map_err(in_other)),
+    );
+    let result = write_differences(args, differences, &mut output, report);
+    report.bytes_in = old.bytes_read() + new.bytes_read();
+    report.bytes_out = output.bytes_written();
+    result
+}
+
+/// Writes each of the `differences` to `output` on a line of its own,
+/// counting them as records in `report`.
+///
+/// # Errors
+///
+/// * If either file fails to be read, or the output cannot be written to.
+fn write_differences<W: Write>(
+    args: &Args,
+    differences: impl Iterator<Item = Result<Difference, ConversionError>>,
+    output: &mut W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    for difference in differences {
+        output.write_all(difference?.to_json().as_bytes())?;
+        output.write_all(args.output_eol.as_str().as_bytes())?;
+        report.records += 1;
+    }
+    Ok(output.flush()?)
+}
+
+/// Converts each file listed in the `--input-list` manifest or matching the
+/// `--input-glob` pattern to JSONL in turn, writing the records of all of
+/// them to `output`. If neither was given, this is the same as calling
+/// `convert`.
+///
+/// # Arguments
+///
+/// * `args` - The options provided on the command line.
+/// * `output` - Where the JSONL records are written to.
+/// * `report` - Updated with a summary of all of the conversions.
+/// * `on_error` - Called with the error of each file that is skipped because
+///   of `--continue-on-error`.
+///
+/// # Errors
+///
+/// * If the manifest cannot be read.
+/// * If no files match the pattern.
+/// * If a file cannot be converted and `--continue-on-error` was not given.
+/// * If the output cannot be written to.
+pub fn convert_inputs<W: Write>(
+    args: &Args,
+    mut output: W,
+    report: &mut Report,
+    mut on_error: impl FnMut(&ConversionError),
+) -> Result<(), ConversionError> {
+    let Some(paths) = input_paths(args)? else {
+        return convert(args, output, report);
+    };
+
+    for path in paths {
+        let file_args = Args {
+            filepath: path.clone(),
+            ..args.clone()
+        };
+        let mut file_report = Report::default();
+        let result = convert(&file_args, &mut output, &mut file_report).map_err(|error| {
+            ConversionError::Input {
+                path,
+                error: Box::new(error),
+            }
+        });
+        report.add(&file_report);
+
+        match result {
+            Err(error) if args.continue_on_error && !error.is_broken_pipe() => {
+                report.errors += 1;
+                report.skipped += 1;
+                on_error(&error);
+            }
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Converts each of the files at `paths` to JSONL independently, as without
+/// any options, writing the records of each to a file of the same name with
+/// a `.jsonl` extension in `out_dir`. Up to `threads` files are converted at
+/// once, each by its own worker with its own processor and output file.
+///
+/// Returns, for each file in the order given, the path of its output or the
+/// error that stopped its conversion, naming the file. A file that fails to
+/// convert does not stop the others, and its partial output is removed.
+/// Files with the same name in different directories would share an output,
+/// so only the first of them is converted, and each of the others fails with
+/// a `ConversionError::OutputOpen` naming the first.
+///
+/// # Arguments
+///
+/// * `paths` - The files to convert.
+/// * `out_dir` - The existing directory the outputs are written to.
+/// * `threads` - How many files to convert at once, at least one.
+pub fn convert_many(
+    paths: &[String],
+    out_dir: &Path,
+    threads: usize,
+) -> Vec<Result<PathBuf, ConversionError>> {
+    let outputs: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| output_path(path, out_dir))
+        .collect();
+    let mut results: Vec<Option<Result<PathBuf, ConversionError>>> =
+        paths.iter().map(|_| None).collect();
+    // Claim each output for the first file with its name before any worker
+    // starts, so that no two workers write to the same file.
+    let mut owners = HashMap::new();
+    let mut pending = Vec::new();
+    for (i, output) in outputs.iter().enumerate() {
+        match owners.entry(output) {
+            Entry::Vacant(entry) => {
+                entry.insert(i);
+                pending.push(i);
+            }
+            Entry::Occupied(entry) => {
+                results[i] = Some(Err(ConversionError::Input {
+                    path: paths[i].clone(),
+                    error: Box::new(ConversionError::OutputOpen {
+                        path: output.to_string_lossy().into_owned(),
+                        error: io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("it is already the output of `{}`", paths[*entry.get()]),
+                        ),
+                    }),
+                }));
+            }
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, pending.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut converted = Vec::new();
+                    loop {
+                        let Some(&i) = pending.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            return converted;
+                        };
+                        converted.push((i, convert_to_dir(&paths[i], &outputs[i])));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let converted = worker.join().expect("conversions catch their own panics");
+            for (i, result) in converted {
+                results[i] = Some(result);
+            }
+        }
+    });
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+/// Returns the path in `out_dir` of the output of the file at `path`, which
+/// is named after it with a `.jsonl` extension.
+fn output_path(path: &str, out_dir: &Path) -> PathBuf {
+    let file_name = Path::new(path).file_name().unwrap_or(path.as_ref());
+    out_dir.join(file_name).with_extension("jsonl")
+}
+
+/// Converts the file at `path` to JSONL, writing the records to the file at
+/// `output_path`, which is removed again if the conversion fails.
+///
+/// # Errors
+///
+/// * If the output file cannot be created.
+/// * If the file cannot be converted, naming it.
+fn convert_to_dir(path: &str, output_path: &Path) -> Result<PathBuf, ConversionError> {
+    let file = File::create(output_path).map_err(|error| ConversionError::OutputOpen {
+        path: output_path.to_string_lossy().into_owned(),
+        error,
+    })?;
+
+    let args = Args {
+        filepath: path.to_string(),
+        ..Args::default()
+    };
+    let mut output = BufWriter::new(file);
+    let result =
+        convert(&args, &mut output, &mut Report::default()).and_then(|_| Ok(output.flush()?));
+    drop(output);
+    if let Err(error) = result {
+        let _ = fs::remove_file(output_path);
+        return Err(ConversionError::Input {
+            path: path.to_string(),
+            error: Box::new(error),
+        });
+    }
+    Ok(output_path.to_path_buf())
+}
+
+/// Returns the paths of the files to convert given with `--input-list` or
+/// `--input-glob`, or `None` if a single file was given.
+///
+/// # Errors
+///
+/// * If the manifest cannot be read.
+/// * If no files match the pattern.
+fn input_paths(args: &Args) -> Result<Option<Vec<String>>, ConversionError> {
+    if let Some(manifest) = &args.input_list {
+        return read_input_list(manifest).map(Some);
+    }
+    let Some(pattern) = &args.input_glob else {
+        return Ok(None);
+    };
+    let paths = glob::expand(pattern)?;
+    if paths.is_empty() {
+        return Err(ConversionError::NoGlobMatches(pattern.clone()));
+    }
+    Ok(Some(paths))
+}
+
+/// Returns the paths listed in `manifest`, one per line. Blank lines are
+/// skipped.
+///
+/// # Errors
+///
+/// * If the manifest cannot be read.
+fn read_input_list(manifest: &str) -> Result<Vec<String>, ConversionError> {
+    Ok(fs::read_to_string(manifest)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Opens the input to convert: the file at the filepath, or on Unix, the
+/// file descriptor given with `--fd`.
+///
+/// # Errors
+///
+/// * If the file cannot be opened, or the file descriptor is not open.
+fn open_input(args: &Args) -> io::Result<File> {
+    #[cfg(unix)]
+    if let Some(fd) = args.fd {
+        return file_from_fd(fd);
+    }
+    File::open(&args.filepath)
+}
+
+/// Opens the input to convert with `open_input`, to be read through a buffer
+/// of `--buffer-size` bytes, or with `--auto-buffer`, of whichever size
+/// calibration picks.
+///
+/// # Errors
+///
+/// * If the file cannot be opened or read.
+fn input_reader(args: &Args) -> io::Result<Input> {
+    let size = match args.buffer_size {
+        Some(size) => size,
+        None if args.auto_buffer => auto_buffer_size(&args.filepath, args.is_messy)?,
+        None => DEFAULT_BUFFER_SIZE,
+    };
+    Input::new(open_input(args)?, size)
+}
+
+/// An input file, read through a buffer. With the `gzip` feature, a file
+/// that starts with the gzip magic bytes is decompressed as it is read,
+/// whatever its name.
+enum Input {
+    Plain(BufReader<File>),
+    #[cfg(feature = "gzip")]
+    Gzip(Box<BufReader<MultiGzDecoder<BufReader<File>>>>),
+}
+
+impl Input {
+    /// Returns `file` to be read through a buffer of `capacity` bytes.
+    ///
+    /// # Errors
+    ///
+    /// * If the start of the file cannot be read to check if it is gzip.
+    fn new(file: File, capacity: usize) -> io::Result<Self> {
+        #[allow(unused_mut)]
+        let mut reader = BufReader::with_capacity(capacity, file);
+        #[cfg(feature = "gzip")]
+        if is_gzip(&mut reader)? {
+            let decoder = MultiGzDecoder::new(reader);
+            let reader = BufReader::with_capacity(capacity, decoder);
+            return Ok(Input::Gzip(Box::new(reader)));
+        }
+        Ok(Input::Plain(reader))
+    }
+
+    /// Moves to `offset` bytes from the start of the input, so that reading
+    /// resumes from there. The offset of a gzip input is into the
+    /// decompressed data, which cannot be seeked, so it is read up to the
+    /// offset instead.
+    ///
+    /// # Errors
+    ///
+    /// * If the input cannot be seeked or read.
+    fn skip_to(&mut self, offset: u64) -> io::Result<()> {
+        match self {
+            Input::Plain(reader) => reader.seek(SeekFrom::Start(offset)).map(|_| ()),
+            #[cfg(feature = "gzip")]
+            Input::Gzip(reader) => {
+                io::copy(&mut reader.by_ref().take(offset), &mut io::sink()).map(|_| ())
+            }
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "gzip")]
+            Input::Gzip(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Input::Plain(reader) => reader.fill_buf(),
+            #[cfg(feature = "gzip")]
+            Input::Gzip(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self {
+            Input::Plain(reader) => reader.consume(amount),
+            #[cfg(feature = "gzip")]
+            Input::Gzip(reader) => reader.consume(amount),
+        }
+    }
+}
+
+/// Converts each of the arrays named by the `--root-pointer` options in
+/// turn, in the order they appear in the input, resuming from the offset of
+/// each one found by a single pre-scan.
+///
+/// # Errors
+///
+/// * If there is no array at one of the pointers.
+/// * If the file is not valid or the output cannot be written to.
+fn convert_pointed_arrays<W: Write>(
+    args: &Args,
+    mut output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let mut located = locate_roots(args)?;
+    located.dedup_by_key(|(_, offset)| *offset);
+    for (pointer, offset) in located {
+        let array_args = Args {
+            start_offset: Some(offset + 1),
+            root_pointers: vec![pointer],
+            ..args.clone()
+        };
+        let mut array_report = Report::default();
+        let result = convert_array(&array_args, &mut output, &mut array_report);
+        report.add(&array_report);
+        result?;
+    }
+    Ok(())
+}
+
+/// Returns each `--root-pointer` along with the offset of the opening
+/// bracket of its array, in the order the arrays appear in the input.
+///
+/// # Errors
+///
+/// * If there is no array at one of the pointers.
+/// * If the file cannot be read.
+fn locate_roots(args: &Args) -> Result<Vec<(JsonPointer, u64)>, ConversionError> {
+    let mut bytes_iter = ByteIterator::from_reader(input_reader(args)?);
+    bytes_iter.set_strict_utf8(args.strict_utf8);
+    bytes_iter.set_max_bytes(args.head_bytes);
+    let located = locate_arrays(&mut bytes_iter, &args.root_pointers)?;
+    Ok(located
+        .into_iter()
+        .map(|(pointer, offset)| (pointer.clone(), offset))
+        .collect())
+}
+
+/// Returns a `RecordWriter` over `output` configured from `args`.
+fn record_writer<W: Write>(args: &Args, output: W) -> RecordWriter<W> {
+    let mut writer = RecordWriter::new(output);
+    if args.emit_array_path {
+        let pointer = args.root_pointers.first().cloned().unwrap_or_default();
+        writer.set_array_path(Some(pointer.to_string()));
+    }
+    writer.set_with_offset(args.with_offset);
+    writer.set_flush_every(args.flush_every);
+    writer.set_line_ending(args.output_eol);
+    writer.set_final_line_ending(!args.no_final_eol);
+    writer.set_wrapper(
+        args.prefix.as_deref().unwrap_or_default(),
+        args.suffix.as_deref().unwrap_or_default(),
+    );
+    // With `--format msgpack`, the records are framed once they have been
+    // encoded instead.
+    if args.format == OutputFormat::Jsonl {
+        writer.set_length_prefix(args.length_prefix());
+    }
+    writer.set_normalizer(args.normalize_unicode.map(|form| Normalizer {
+        form,
+        keys: args.normalize_keys,
+    }));
+    writer.set_repairs(args.repairs());
+    writer.set_json5(args.json5);
+    writer.set_replace_non_finite(args.replace_non_finite());
+    writer.set_escape_control_chars(args.escape_control_chars);
+    writer.set_strict(
+        args.strict
+            || args.emit_errors_as_records
+            || args.json5
+            || args.format == OutputFormat::Msgpack,
+    );
+    writer.set_errors_as_records(args.emit_errors_as_records);
+    if args.flatten {
+        writer.set_flattener(Some(Flattener {
+            separator: args.flatten_separator().to_string(),
+        }));
+    }
+    writer.set_key_caser(args.key_case.map(|case| KeyCaser {
+        case,
+        recursive: args.recursive,
+    }));
+    if args.canonical {
+        writer.set_canonicalizer(Some(Canonicalizer {
+            trim_strings: args.trim_strings,
+        }));
+    }
+    if !args.fields_required.is_empty() {
+        writer.set_required_fields(Some(RequiredFields {
+            keys: args.fields_required.clone(),
+        }));
+    }
+    writer.set_dedupe_window(args.dedupe_window);
+    if args.envelope {
+        writer.set_envelope(Some(Envelope {
+            source: args.input(),
+            fields: args.envelope_fields(),
+        }));
+    }
+    if args.map_values {
+        writer.set_map_values(Some(MapValues {
+            inject_key: args.inject_key.clone(),
+        }));
+    }
+    writer
+}
+
+/// Returns how records are cleaned up, as configured by `args`.
+fn cleanup_policy(args: &Args) -> CleanupPolicy {
+    if args.preserve_whitespace {
+        CleanupPolicy::StripNewlines
+    } else if args.minify {
+        Format::Minified.into()
+    } else {
+        CleanupPolicy::default()
+    }
+}
+
+/// Converts the root array to JSONL records written to `output`, reading
+/// the input a line at a time, or a character at a time with `--messy`.
+/// With `--start-offset`, the input is read from just inside an array there
+/// instead.
+///
+/// # Errors
+///
+/// * If the input cannot be read, is not valid or fails `--verify`.
+/// * If the output cannot be written to.
+fn convert_records<W: Write>(
+    args: &Args,
+    output: W,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let mut reader = input_reader(args)?;
+    if let Some(offset) = args.start_offset {
+        reader.skip_to(offset)?;
+    }
+    let mut converter =
+        Converter::new(reader, record_writer(args, output), converter_options(args));
+    let result = converter.run();
+    let converted = converter.count();
+    let single_value = converter.is_single_value();
+    report.records = converted;
+    report.max_depth = converter.max_depth();
+    report.bytes_in = converter.bytes_read();
+    let writer = converter.into_sink();
+    report.records -= writer.duplicates();
+    report.errors += writer.error_records();
+    result.and_then(|_| verify_input(args, converted, single_value))
+}
+
+/// Returns the options that the `Converter` reads the input with, as given
+/// on the command line.
+fn converter_options(args: &Args) -> ConverterOptions {
+    ConverterOptions {
+        cleanup_policy: cleanup_policy(args),
+        newline_policy: args.newline_in_values,
+        strict_utf8: args.strict_utf8,
+        concat: args.concat,
+        lines: !args.is_messy,
+        max_depth: args.max_depth,
+        max_bytes: args.head_bytes,
+        max_line_bytes: Some(args.max_line_bytes()),
+        start_offset: args.start_offset,
+        map_values: args.map_values,
+        json5: args.json5,
+        strip_jsonp: args.strip_jsonp,
+        single_quotes: args.repairs().single_quotes,
+        allow_control_chars: args.allow_control_chars,
+        stop_at_root_end: !args.root_pointers.is_empty(),
+        recover: args.emit_errors_as_records,
+    }
+}
+
+/// With `--verify`, parses the input as a whole and checks that its root
+/// has as many elements as the `converted` records, counting those dropped
+/// as duplicates and those written as errors. A root that is a `single_value`
+/// must have been converted to exactly one record.
+///
+/// # Errors
+///
+/// * If the input cannot be read or parsed, or the counts differ.
+fn verify_input(args: &Args, converted: usize, single_value: bool) -> Result<(), ConversionError> {
+    if !args.verify {
+        return Ok(());
+    }
+    if !single_value {
+        return verify_count(input_reader(args)?, converted);
+    }
+    count_elements(input_reader(args)?)?;
+    match converted {
+        1 => Ok(()),
+        _ => Err(ConversionError::CountMismatch {
+            parsed: 1,
+            converted,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    const FIXTURE: &str = "[\n  {\"a\": 1},\n  {\"b\": 2},\n  {\"c\": 3}\n]\n";
+
+    fn write_fixture(dir: &Path, contents: &str) -> String {
+        let path = dir.join("input.json");
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn run(args: &Args) -> (String, Report) {
+        let mut output = Vec::new();
+        let mut report = Report::default();
+        convert(args, &mut output, &mut report).unwrap();
+        (String::from_utf8(output).unwrap(), report)
+    }
+
+    #[test]
+    fn test_convert_reports_each_counter() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents =
+            "[\n  {\n    \"a\": [\n      {\n        \"b\": 1\n      }\n    ]\n  },\n  [\n  ]\n]\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert_eq!(output, "{\"a\": [{\"b\": 1}]}\n[]\n");
+            assert_eq!(
+                report,
+                Report {
+                    records: 2,
+                    bytes_in: contents.len() as u64,
+                    bytes_out: output.len() as u64,
+                    errors: 0,
+                    skipped: 0,
+                    max_depth: 3,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_reports_records_written_before_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), "[\n{\"a\": 1},\n{\"b\": [2}\n]\n"),
+                is_messy,
+                ..Args::default()
+            };
+            let mut output = Vec::new();
+            let mut report = Report::default();
+            let error = convert(&args, &mut output, &mut report).unwrap_err();
+            assert!(matches!(error, ConversionError::MismatchedBracket { .. }));
+            assert_eq!(output, b"{\"a\": 1}\n");
+            assert_eq!(report.records, 1);
+            assert_eq!(report.bytes_out, output.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_convert_reports_errors_written_as_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents =
+            "[\n  {\"id\": 1, \"name\": \"a\"},\n  {\"id\": .5, \"name\": \"b\"},\n  {\"id\": 3}\n]\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                fields_required: vec![String::from("id"), String::from("name")],
+                emit_errors_as_records: true,
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert_eq!(output.lines().count(), 3);
+            assert_eq!(report.records, 3);
+            assert_eq!(report.errors, 2);
+        }
+    }
+
+    #[test]
+    fn test_convert_does_not_report_duplicates_as_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = "[\n{\"a\": 1},\n{\"a\": 1},\n{\"b\": 2},\n{\"c\": 3},\n{\"a\": 1}\n]\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                dedupe_window: Some(2),
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert_eq!(output, "{\"a\": 1}\n{\"b\": 2}\n{\"c\": 3}\n{\"a\": 1}\n");
+            assert_eq!(report.records, 4);
+        }
+    }
+
+    #[test]
+    fn test_convert_resumes_from_start_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        // The start of the second record, and the end of the first, which
+        // must not be written as an empty record.
+        let offsets = [
+            FIXTURE.find("{\"b\"").unwrap() as u64,
+            FIXTURE.find("},").unwrap() as u64 + 1,
+        ];
+        for offset in offsets {
+            for is_messy in [false, true] {
+                let args = Args {
+                    filepath: write_fixture(dir.path(), FIXTURE),
+                    is_messy,
+                    start_offset: Some(offset),
+                    ..Args::default()
+                };
+                let (output, report) = run(&args);
+                assert_eq!(output, "{\"b\": 2}\n{\"c\": 3}\n");
+                assert_eq!(report.records, 2);
+                assert_eq!(report.bytes_in, FIXTURE.len() as u64 - offset);
+            }
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_convert_resumes_from_start_offset_of_gzip_input() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let plain = fs::read_to_string(fixtures.join("pretty.json")).unwrap();
+        let offset = plain.find("{\n    \"id\": 2").unwrap() as u64;
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: fixtures
+                    .join("pretty.json.gz")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+                is_messy,
+                start_offset: Some(offset),
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert!(output.starts_with("{\"id\": 2,"), "{:?}", output);
+            assert_eq!(report.records, 1);
+            assert_eq!(report.bytes_in, plain.len() as u64 - offset);
+        }
+    }
+
+    #[test]
+    fn test_convert_resumes_from_offset_of_element_in_messy_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = "[{\"a\":1},{\"b\":[2]},{\"c\":3}]";
+        let args = Args {
+            filepath: write_fixture(dir.path(), contents),
+            is_messy: true,
+            start_offset: Some(contents.find("{\"b\"").unwrap() as u64),
+            ..Args::default()
+        };
+        assert_eq!(run(&args).0, "{\"b\":[2]}\n{\"c\":3}\n");
+    }
+
+    #[test]
+    fn test_convert_root_pointer_with_array_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = "{\n  \"meta\": [0],\n  \"data\": {\n    \"items\": [\n      {\"a\": 1},\n      {\"b\": [2]}\n    ]\n  },\n  \"after\": [3]\n}\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                root_pointers: vec!["/data/items".parse().unwrap()],
+                ..Args::default()
+            };
+            assert_eq!(run(&args).0, "{\"a\": 1}\n{\"b\": [2]}\n");
+
+            let args = Args {
+                emit_array_path: true,
+                with_offset: true,
+                ..args
+            };
+            let (output, report) = run(&args);
+            for line in output.lines() {
+                let (path, rest) = line.split_once('\t').unwrap();
+                assert_eq!(path, "/data/items");
+                let (offset, record) = rest.split_once('\t').unwrap();
+                assert!(contents[offset.parse::<usize>().unwrap()..].starts_with(record));
+            }
+            assert_eq!(report.records, 2);
+
+            let args = Args {
+                root_pointers: vec!["/data/missing".parse().unwrap()],
+                ..args
+            };
+            let mut report = Report::default();
+            let error = convert(&args, Vec::new(), &mut report).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "There is no array at `/data/missing` to convert."
+            );
+        }
+
+        let args = Args {
+            filepath: write_fixture(dir.path(), FIXTURE),
+            emit_array_path: true,
+            ..Args::default()
+        };
+        assert!(run(&args).0.starts_with("\t{\"a\": 1}\n"));
+    }
+
+    #[test]
+    fn test_convert_several_root_pointers_in_document_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents =
+            "{\n  \"a\": {\"items\": [\n    {\"id\": 1, \"tags\": [\n      {\"t\": \"x\"}\n    ]},\n    \
+                        {\"id\": 2}\n  ]},\n  \"b\": {\"items\": [\n    {\"id\": 3}\n  ]}\n}\n";
+        let pointers = ["/b/items", "/a/items/0/tags", "/a/items", "/b/items"];
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                root_pointers: pointers.iter().map(|p| p.parse().unwrap()).collect(),
+                emit_array_path: true,
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert_eq!(
+                output,
+                "/a/items\t{\"id\": 1, \"tags\": [{\"t\": \"x\"}]}\n\
+                 /a/items\t{\"id\": 2}\n\
+                 /a/items/0/tags\t{\"t\": \"x\"}\n\
+                 /b/items\t{\"id\": 3}\n",
+                "{}",
+                is_messy
+            );
+            assert_eq!(report.records, 4);
+            assert_eq!(report.bytes_out, output.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_convert_root_pointers_to_an_array_and_an_array_within_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = "{\n  \"a\": [\n    [1, 2],\n    [3]\n  ]\n}\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), contents),
+                is_messy,
+                root_pointers: vec!["/a".parse().unwrap(), "/a/0".parse().unwrap()],
+                ..Args::default()
+            };
+            assert_eq!(run(&args).0, "[1, 2]\n[3]\n1\n2\n", "{}", is_messy);
+        }
+    }
+
+    #[test]
+    fn test_convert_output_does_not_depend_on_buffer_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = "[\n  {\"é\": \"ü😀\", \"b\": [1, {\"c\": \"]\"}]},\n  {\"d\": \"\\\"x\"}\n]\n";
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), input),
+                is_messy,
+                ..Args::default()
+            };
+            let (expected, _) = run(&args);
+            assert_eq!(expected.lines().count(), 2);
+            for buffer_size in [1, 3, 64 * 1024] {
+                let sized = Args {
+                    buffer_size: Some(buffer_size),
+                    ..args.clone()
+                };
+                assert_eq!(run(&sized).0, expected, "{} {}", is_messy, buffer_size);
+            }
+            let auto = Args {
+                auto_buffer: true,
+                ..args.clone()
+            };
+            assert_eq!(run(&auto).0, expected, "{}", is_messy);
+        }
+    }
+
+    #[test]
+    fn test_convert_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), FIXTURE),
+                is_messy,
+                verify: true,
+                ..Args::default()
+            };
+            assert_eq!(
+                run(&args).0,
+                run(&Args {
+                    verify: false,
+                    ..args
+                })
+                .0
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_verify_passes_records_sharing_a_line() {
+        let dir = tempfile::tempdir().unwrap();
+        for is_messy in [false, true] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), "[\n{\"a\": 1}, {\"b\": 2}\n]\n"),
+                is_messy,
+                verify: true,
+                ..Args::default()
+            };
+            let (output, report) = run(&args);
+            assert_eq!(output, "{\"a\": 1}\n{\"b\": 2}\n");
+            assert_eq!(report.records, 2);
+        }
+    }
+
+    #[test]
+    fn test_convert_verify_single_object_in_both_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        for contents in ["{\"a\":1}", "{\n  \"a\": [1, {\"b\": \"}\"}]\n}\n"] {
+            for is_messy in [false, true] {
+                let args = Args {
+                    filepath: write_fixture(dir.path(), contents),
+                    is_messy,
+                    verify: true,
+                    ..Args::default()
+                };
+                let (output, report) = run(&args);
+                assert_eq!(output.lines().count(), 1, "{:?}", contents);
+                assert_eq!(report.records, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = dir.path().join("other.json");
+        fs::write(&other, "[{\"a\":1}, {\"b\": 3}, {\"c\": 3}, {\"d\": 4}]").unwrap();
+        let args = Args {
+            filepath: write_fixture(dir.path(), FIXTURE),
+            diff: Some(other.to_str().unwrap().to_string()),
+            ..Args::default()
+        };
+        let (output, report) = run(&args);
+        assert_eq!(
+            output,
+            "{\"index\":1,\"change\":\"modified\",\"old\":{\"b\":2},\"new\":{\"b\":3}}\n\
+             {\"index\":3,\"change\":\"added\",\"new\":{\"d\":4}}\n"
+        );
+        assert_eq!(report.records, 2);
+        assert_eq!(report.bytes_out, output.len() as u64);
+    }
+
+    #[test]
+    fn test_convert_diff_names_the_other_file_in_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = dir.path().join("other.json");
+        fs::write(&other, "[{\"a\": 1}, {b}]").unwrap();
+        let other = other.to_str().unwrap().to_string();
+        let args = Args {
+            filepath: write_fixture(dir.path(), FIXTURE),
+            diff: Some(other.clone()),
+            ..Args::default()
+        };
+        let error = convert(&args, Vec::new(), &mut Report::default()).unwrap_err();
+        assert!(matches!(
+            &error,
+            ConversionError::Input { path, error }
+                if *path == other
+                    && matches!(**error, ConversionError::InvalidRecord { record: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_convert_diff_reads_both_files_with_the_conversion_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = dir.path().join("other.json");
+        fs::write(&other, "cb([{\"a\": 1}, {\"b\": 3}, {\"c\": 3}]);").unwrap();
+        let args = Args {
+            filepath: write_fixture(dir.path(), &format!("cb({});", FIXTURE.trim_end())),
+            diff: Some(other.to_str().unwrap().to_string()),
+            strip_jsonp: true,
+            ..Args::default()
+        };
+        let (output, report) = run(&args);
+        assert_eq!(
+            output,
+            "{\"index\":1,\"change\":\"modified\",\"old\":{\"b\":2},\"new\":{\"b\":3}}\n"
+        );
+        assert_eq!(report.records, 1);
+    }
+
+    #[test]
+    fn test_convert_many_rejects_files_that_share_an_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (sub_dir, contents) in [
+            ("x", "[\n  1\n]\n"),
+            ("y", "[\n  2\n]\n"),
+            ("z", "[\n  3\n"),
+        ] {
+            let path = dir.path().join(sub_dir).join("a.json");
+            fs::create_dir(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+
+        for threads in [1, 3] {
+            let results = convert_many(&paths, out_dir.path(), threads);
+            let a = results[0].as_ref().unwrap();
+            assert_eq!(fs::read_to_string(a).unwrap(), "1\n");
+            for (i, result) in results.iter().enumerate().skip(1) {
+                assert!(matches!(
+                    result,
+                    Err(ConversionError::Input { path, error })
+                        if path == &paths[i]
+                            && matches!(**error, ConversionError::OutputOpen { .. })
+                            && error.to_string().contains(&paths[0])
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_many_converts_each_file_to_its_own_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (name, contents) in [
+            ("a.json", "[\n  {\"a\": 1},\n  {\"a\": 2}\n]\n"),
+            ("b.json", "[\n  {\"b\": 1\n]\n"),
+            ("c.json", "{\"c\": 1}\n"),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+
+        for threads in [1, 2, 8] {
+            let results = convert_many(&paths, out_dir.path(), threads);
+            assert_eq!(results.len(), 3);
+            let a = results[0].as_ref().unwrap();
+            assert_eq!(a, &out_dir.path().join("a.jsonl"));
+            assert_eq!(fs::read_to_string(a).unwrap(), "{\"a\": 1}\n{\"a\": 2}\n");
+            assert!(matches!(
+                &results[1],
+                Err(ConversionError::Input { path, .. }) if path == &paths[1]
+            ));
+            assert!(!out_dir.path().join("b.jsonl").exists());
+            let c = results[2].as_ref().unwrap();
+            assert_eq!(fs::read_to_string(c).unwrap(), "{\"c\": 1}\n");
+        }
+    }
+
+    #[test]
+    fn test_convert_rejects_invalid_start_without_panicking() {
+        let dir = tempfile::tempdir().unwrap();
+        for (map_values, is_messy, input) in [
+            (false, false, "\"a\""),
+            (false, true, "\"a\""),
+            (true, false, "[1]"),
+            (true, true, "[1]"),
+        ] {
+            let args = Args {
+                filepath: write_fixture(dir.path(), input),
+                is_messy,
+                map_values,
+                ..Args::default()
+            };
+            let error = convert_unguarded(&args, Vec::new(), &mut Report::default()).unwrap_err();
+            assert!(matches!(
+                error,
+                ConversionError::InvalidStart { map_values: m, .. } if m == map_values
+            ));
+        }
+    }
+
+    #[test]
+    fn test_convert_inputs_skips_files_that_do_not_start_with_a_bracket() {
+        let dir = tempfile::tempdir().unwrap();
+        let valid = write_fixture(dir.path(), FIXTURE);
+        let invalid = dir.path().join("scalar.json");
+        fs::write(&invalid, "1").unwrap();
+        let manifest = dir.path().join("inputs.txt");
+        fs::write(&manifest, format!("{}\n{}\n", invalid.display(), valid)).unwrap();
+        let args = Args {
+            input_list: Some(manifest.to_str().unwrap().to_string()),
+            continue_on_error: true,
+            ..Args::default()
+        };
+        let mut errors = Vec::new();
+        let mut report = Report::default();
+        convert_inputs(&args, Vec::new(), &mut report, |error| {
+            errors.push(error.to_string())
+        })
+        .unwrap();
+        assert_eq!(report.skipped, 1);
+        assert!(errors[0].ends_with("The input must start with a '[' or a '{', not '1'."));
+    }
+
+    #[test]
+    fn test_convert_inputs_continues_past_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.json");
+        let second = dir.path().join("second.json");
+        let missing = dir.path().join("missing.json");
+        fs::write(&first, "[\n  {\"a\": 1}\n]\n").unwrap();
+        fs::write(&second, "[\n  {\"b\": 2},\n  {\"c\": 3}\n]\n").unwrap();
+        let manifest = dir.path().join("files.txt");
+        fs::write(
+            &manifest,
+            format!(
+                "{}\n{}\n\n{}\n",
+                first.display(),
+                missing.display(),
+                second.display()
+            ),
+        )
+        .unwrap();
+
+        let args = Args {
+            input_list: Some(manifest.to_str().unwrap().to_string()),
+            continue_on_error: true,
+            ..Args::default()
+        };
+        let mut output = Vec::new();
+        let mut report = Report::default();
+        let mut errors = Vec::new();
+        convert_inputs(&args, &mut output, &mut report, |error| {
+            errors.push(error.to_string())
+        })
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"a\": 1}\n{\"b\": 2}\n{\"c\": 3}\n"
+        );
+        assert_eq!(report.records, 3);
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("Could not convert `{}`", missing.display())));
+    }
+
+    #[test]
+    fn test_convert_inputs_from_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.json"), "[\n  {\"b\": 2}\n]\n").unwrap();
+        fs::write(dir.path().join("a.json"), "[\n  {\"a\": 1}\n]\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "not json").unwrap();
+        fs::write(dir.path().join("d.json.bak"), "not json").unwrap();
+
+        let args = Args {
+            input_glob: Some(format!("{}/*.json", dir.path().display())),
+            ..Args::default()
+        };
+        let mut output = Vec::new();
+        let mut report = Report::default();
+        convert_inputs(&args, &mut output, &mut report, |_| {}).unwrap();
+        assert_eq!(output, b"{\"a\": 1}\n{\"b\": 2}\n");
+        assert_eq!(report.records, 2);
+
+        let args = Args {
+            input_glob: Some(format!("{}/*.jsonl", dir.path().display())),
+            ..Args::default()
+        };
+        let error = convert_inputs(&args, Vec::new(), &mut report, |_| {}).unwrap_err();
+        assert!(matches!(error, ConversionError::NoGlobMatches(_)));
+    }
+
+    #[test]
+    fn test_convert_inputs_stops_at_missing_file_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("files.txt");
+        fs::write(
+            &manifest,
+            format!(
+                "{}\n{}\n",
+                dir.path().join("missing.json").display(),
+                write_fixture(dir.path(), FIXTURE)
+            ),
+        )
+        .unwrap();
+
+        let args = Args {
+            input_list: Some(manifest.to_str().unwrap().to_string()),
+            ..Args::default()
+        };
+        let mut output = Vec::new();
+        let mut report = Report::default();
+        let result = convert_inputs(&args, &mut output, &mut report, |_| {
+            panic!("errors are returned when not continuing")
+        });
+        assert!(matches!(result, Err(ConversionError::Input { .. })));
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+//! This module contains the JSON pointers given to `--root-pointer`, which
+//! name arrays nested within the input to convert instead of the root, and
+//! the pre-scan that finds where those arrays start.
+//!
+//! Pointers follow RFC 6901: `/data/items` names the `items` member of the
+//! `data` member of the root, array elements are named by their index, and
+//! `~1` and `~0` stand for `/` and `~` within a name. The empty pointer names
+//! the root itself.
+
+use std::{fmt, io::BufRead, slice, str::FromStr};
+
+use crate::{errors::ConversionError, readers::byte_iter::ByteIterator};
+
+/// A JSON pointer, such as `/data/items`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JsonPointer {
+    tokens: Vec<String>,
+}
+
+impl JsonPointer {
+    /// Returns the names that make up the pointer, unescaped.
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+}
+
+impl FromStr for JsonPointer {
+    type Err = String;
+
+    /// Parses a pointer, which is either empty or starts with `/`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::pointer::JsonPointer;
+    ///
+    /// let pointer: JsonPointer = "/a~1b/0".parse().unwrap();
+    /// assert_eq!(pointer.tokens(), ["a/b", "0"]);
+    /// assert!("items".parse::<JsonPointer>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(JsonPointer::default());
+        }
+        let Some(rest) = s.strip_prefix('/') else {
+            return Err(format!(
+                "Invalid JSON pointer `{}`. It must be empty or start with `/`.",
+                s
+            ));
+        };
+        let tokens = rest
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        Ok(JsonPointer { tokens })
+    }
+}
+
+impl fmt::Display for JsonPointer {
+    /// Writes the pointer with its names escaped again.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "/{}", token.replace('~', "~0").replace('/', "~1"))?;
+        }
+        Ok(())
+    }
+}
+
+/// An object or array that the pre-scan is inside of, which gives the name
+/// of the value being scanned within it.
+enum Frame {
+    /// An object, with the key of the member being scanned.
+    Object(String),
+    /// An array, with the index of the element being scanned.
+    Array(usize),
+}
+
+impl Frame {
+    fn name(&self) -> String {
+        match self {
+            Frame::Object(key) => key.clone(),
+            Frame::Array(index) => index.to_string(),
+        }
+    }
+}
+
+/// Scans the characters read by `bytes_iter` for the array named by
+/// `pointer`, returning the offset of its opening bracket. Reading stops
+/// there, so the input is only read as far as the array.
+///
+/// # Errors
+///
+/// * If there is no array at `pointer`.
+/// * If the input cannot be read.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::{pointer::locate_array, readers::byte_iter::ByteIterator};
+///
+/// let input = r#"{"meta": [0], "data": {"items": [{"a": 1}]}}"#;
+/// let mut bytes_iter = ByteIterator::from_reader(input.as_bytes());
+/// let offset = locate_array(&mut bytes_iter, &"/data/items".parse().unwrap()).unwrap();
+/// assert_eq!(&input[offset as usize..], r#"[{"a": 1}]}}"#);
+/// ```
+pub fn locate_array<R: BufRead>(
+    bytes_iter: &mut ByteIterator<R>,
+    pointer: &JsonPointer,
+) -> Result<u64, ConversionError> {
+    match locate_arrays(bytes_iter, slice::from_ref(pointer))?.first() {
+        Some(&(_, offset)) => Ok(offset),
+        None => Err(ConversionError::PointerNotFound(pointer.to_string())),
+    }
+}
+
+/// Scans the characters read by `bytes_iter` for the arrays named by each of
+/// `pointers` at once, returning each pointer along with the offset of the
+/// opening bracket of its array, in the order the arrays appear in the
+/// input. Reading stops once every array has been found.
+///
+/// The scan carries on into each array found, so a pointer may name an
+/// array nested within another. Pointers that are the same are both
+/// returned, with the same offset. With no pointers, nothing is read and
+/// nothing is returned.
+///
+/// # Errors
+///
+/// * If there is no array at one of `pointers`.
+/// * If the input cannot be read.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::{pointer::{locate_arrays, JsonPointer}, readers::byte_iter::ByteIterator};
+///
+/// let input = r#"{"b": {"items": [{"c": [2]}]}, "a": [1]}"#;
+/// let pointers: Vec<JsonPointer> = ["/a", "/b/items", "/b/items/0/c"]
+///     .iter()
+///     .map(|pointer| pointer.parse().unwrap())
+///     .collect();
+/// let mut bytes_iter = ByteIterator::from_reader(input.as_bytes());
+/// let located = locate_arrays(&mut bytes_iter, &pointers).unwrap();
+/// let starts: Vec<(String, &str)> = located
+///     .iter()
+///     .map(|(pointer, offset)| (pointer.to_string(), &input[*offset as usize..]))
+///     .collect();
+/// assert_eq!(starts[0], (String::from("/b/items"), r#"[{"c": [2]}]}, "a": [1]}"#));
+/// assert_eq!(starts[1], (String::from("/b/items/0/c"), r#"[2]}]}, "a": [1]}"#));
+/// assert_eq!(starts[2], (String::from("/a"), "[1]}"));
+/// ```
+pub fn locate_arrays<'a, R: BufRead>(
+    bytes_iter: &mut ByteIterator<R>,
+    pointers: &'a [JsonPointer],
+) -> Result<Vec<(&'a JsonPointer, u64)>, ConversionError> {
+    if pointers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut found = vec![false; pointers.len()];
+    let mut located = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut key = None;
+    let mut inside_string = false;
+    let mut last_char_escape = false;
+    loop {
+        let offset = bytes_iter.offset();
+        let Some(c) = bytes_iter.read_char() else {
+            return match found.iter().position(|&found| !found) {
+                Some(missing) => Err(ConversionError::PointerNotFound(
+                    pointers[missing].to_string(),
+                )),
+                None => Ok(located),
+            };
+        };
+        let c = c?;
+
+        if inside_string {
+            if c == '"' && !last_char_escape {
+                inside_string = false;
+            }
+            if let Some(key) = key.as_mut() {
+                String::push(key, c);
+            }
+            last_char_escape = c == '\\' && !last_char_escape;
+            continue;
+        }
+        match c {
+            '"' => {
+                inside_string = true;
+                if matches!(frames.last(), Some(Frame::Object(name)) if name.is_empty()) {
+                    key = Some(String::from('"'));
+                }
+            }
+            ':' => {
+                if let (Some(raw), Some(Frame::Object(name))) = (key.take(), frames.last_mut()) {
+                    *name = serde_json::from_str(&raw).unwrap_or(raw);
+                }
+            }
+            '[' => {
+                for (pointer, found) in pointers.iter().zip(&mut found) {
+                    if !*found && is_at(&frames, pointer) {
+                        *found = true;
+                        located.push((pointer, offset));
+                    }
+                }
+                if located.len() == pointers.len() {
+                    return Ok(located);
+                }
+                frames.push(Frame::Array(0));
+            }
+            '{' => frames.push(Frame::Object(String::new())),
+            ']' | '}' => {
+                frames.pop();
+            }
+            ',' => match frames.last_mut() {
+                Some(Frame::Array(index)) => *index += 1,
+                Some(Frame::Object(name)) => name.clear(),
+                None => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Checks if the value being scanned is the one named by `pointer`.
+fn is_at(frames: &[Frame], pointer: &JsonPointer) -> bool {
+    frames.len() == pointer.tokens.len()
+        && frames
+            .iter()
+            .zip(&pointer.tokens)
+            .all(|(frame, token)| frame.name() == *token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locate(input: &str, pointer: &str) -> Result<u64, ConversionError> {
+        let mut bytes_iter = ByteIterator::from_reader(input.as_bytes());
+        locate_array(&mut bytes_iter, &pointer.parse().unwrap())
+    }
+
+    #[test]
+    fn test_pointer_round_trips() {
+        for pointer in ["", "/a", "/a/0/b", "/a~1b/c~0d", "/"] {
+            assert_eq!(pointer.parse::<JsonPointer>().unwrap().to_string(), pointer);
+        }
+        assert_eq!("/".parse::<JsonPointer>().unwrap().tokens(), [""]);
+    }
+
+    #[test]
+    fn test_locate_array_finds_nested_arrays() {
+        let input = r#"{"a": {"x": [1], "items": [[2], {"b": [3]}]}, "c": "[{]"}"#;
+        let at = |pointer| &input[locate(input, pointer).unwrap() as usize..];
+        assert!(at("/a/items").starts_with("[[2]"));
+        assert!(at("/a/x").starts_with("[1]"));
+        assert!(at("/a/items/0").starts_with("[2]"));
+        assert!(at("/a/items/1/b").starts_with("[3]"));
+        assert_eq!(locate("[1]", "").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_locate_array_matches_unescaped_keys() {
+        let input = r#"{"a\"b": 1, "a/b": [1], "c": [2]}"#;
+        assert_eq!(
+            locate(input, "/a~1b").unwrap(),
+            input.find("[1]").unwrap() as u64
+        );
+        assert_eq!(
+            locate(input, "/c").unwrap(),
+            input.find("[2]").unwrap() as u64
+        );
+    }
+
+    #[test]
+    fn test_locate_array_skips_strings_that_look_like_keys() {
+        let input = r#"{"a": ["items", {"items": 1}], "items": [{"items": [1]}]}"#;
+        let offset = input.find("[{\"items\": [1]").unwrap();
+        assert_eq!(locate(input, "/items").unwrap(), offset as u64);
+    }
+
+    #[test]
+    fn test_locate_arrays_finds_siblings_and_nested_arrays_in_order() {
+        let input = r#"{"a": {"items": [{"b": [1]}]}, "c": {"items": [2]}}"#;
+        let pointers: Vec<JsonPointer> = ["/c/items", "/a/items/0/b", "/a/items", "/c/items"]
+            .iter()
+            .map(|pointer| pointer.parse().unwrap())
+            .collect();
+        let mut bytes_iter = ByteIterator::from_reader(input.as_bytes());
+        let located: Vec<(String, usize)> = locate_arrays(&mut bytes_iter, &pointers)
+            .unwrap()
+            .into_iter()
+            .map(|(pointer, offset)| (pointer.to_string(), offset as usize))
+            .collect();
+        let a_items = input.find("[{").unwrap();
+        let b = input.find("[1]").unwrap();
+        let c_items = input.find("[2]").unwrap();
+        assert_eq!(
+            located,
+            [
+                (String::from("/a/items"), a_items),
+                (String::from("/a/items/0/b"), b),
+                (String::from("/c/items"), c_items),
+                (String::from("/c/items"), c_items),
+            ]
+        );
+        assert_eq!(bytes_iter.offset() as usize, c_items + 1);
+
+        let mut bytes_iter = ByteIterator::from_reader(input.as_bytes());
+        let pointers = [pointers[0].clone(), "/d".parse().unwrap()];
+        let error = locate_arrays(&mut bytes_iter, &pointers).unwrap_err();
+        assert_eq!(error.to_string(), "There is no array at `/d` to convert.");
+    }
+
+    #[test]
+    fn test_locate_arrays_of_no_pointers_reads_nothing() {
+        let mut bytes_iter = ByteIterator::from_reader(&b"{\"a\": [1]}"[..]);
+        assert!(locate_arrays(&mut bytes_iter, &[]).unwrap().is_empty());
+        assert_eq!(bytes_iter.offset(), 0);
+    }
+
+    #[test]
+    fn test_locate_array_errors_when_not_found() {
+        for pointer in ["/missing", "/a", "/a/b/0", "/"] {
+            assert!(matches!(
+                locate(r#"{"a": {"b": [{}]}}"#, pointer),
+                Err(ConversionError::PointerNotFound(_))
+            ));
+        }
+    }
+}
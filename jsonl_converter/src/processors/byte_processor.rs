@@ -1,9 +1,22 @@
 //! This module contains the functionality to process a stream of bytes to
 //! convert JSON to JSONL.
+//!
+//! An element of the root array ends as soon as its closing bracket is seen,
+//! so elements that are missing the comma between them, such as
+//! `[{"a":1} {"b":2}]` or `[{"a":1}{"b":2}]`, are still written as separate
+//! records.
+
+use std::{fmt, io};
 
 use crate::{
     brackets::{is_closing_bracket, is_opening_bracket, BracketStack},
-    json_object::JSONLString,
+    control::is_control_char,
+    errors::ConversionError,
+    json5::{CommentStep, Comments},
+    json_object::{CleanupPolicy, JSONLString, NewlinePolicy},
+    jsonp::JsonpTail,
+    sink::EmitSink,
+    writer::RecordWriter,
 };
 
 /// This struct contains the functionality to process a stream of bytes to
@@ -14,29 +27,337 @@ use crate::{
 ///
 /// * `bracket_stack` - A stack of brackets that have been opened but not closed.
 /// * `jsonl_string` - The JSONL string that is being built.
-pub struct ByteProcessor {
+/// * `root_closed` - Whether the root array has been closed. Once it has, only
+///   whitespace may follow, or another root if `concat` is set.
+/// * `root_object` - Whether the root is an object rather than an array, in
+///   which case only whitespace may follow it.
+/// * `single_value` - Whether the root is a single value that is written as
+///   the only record, rather than an array of them.
+/// * `jsonp_tail` - If set, the root is wrapped in JSONP padding, and this
+///   tracks the `);` that must follow it.
+/// * `single_quotes` - Whether strings may also be quoted with `'`.
+/// * `quote` - The quote that opened the string being read, if inside one.
+/// * `allow_control_chars` - Whether raw control characters inside strings
+///   are passed through rather than an error.
+/// * `comments` - If set, comments are dropped, and this tracks whether the
+///   input is inside one.
+/// * `concat` - Whether a root following the root array or single value is
+///   converted too, as if its elements were part of the first root.
+/// * `stop_at_root_end` - Whether everything after the root array is ignored.
+/// * `sink` - Where completed JSONL records are sent.
+/// * `count` - The number of records sent to the `sink`.
+/// * `max_depth` - The deepest nesting of objects and arrays seen within a
+///   record.
+/// * `depth_limit` - If set, the deepest nesting of objects and arrays allowed
+///   within a record.
+/// * `position` - The offset in the input of the next character.
+/// * `record_start` - The offset in the input of the first character of the
+///   record being built.
+/// * `record_end` - The offset in the input just after the last character
+///   of the record being built so far.
+/// * `recover` - Whether an element that is malformed is sent to the sink
+///   as an error rather than stopping the conversion.
+/// * `broken` - If set, why the element being built is malformed.
+///
+/// A processor can be cloned to snapshot its state, for example to try
+/// processing some input and roll back if it fails. This copies the record
+/// being built and the bracket stack, so costs time in proportion to their
+/// sizes, and also clones the sink. As such, a processor is only `Clone` if
+/// its sink is, such as a writer over an in-memory buffer.
+#[derive(Clone)]
+pub struct ByteProcessor<S: EmitSink = RecordWriter> {
     pub bracket_stack: BracketStack,
     jsonl_string: JSONLString,
     inside_string: bool,
     last_char_escape: bool,
+    root_closed: bool,
+    root_object: bool,
+    single_value: bool,
+    jsonp_tail: Option<JsonpTail>,
+    single_quotes: bool,
+    quote: char,
+    allow_control_chars: bool,
+    comments: Option<Comments>,
+    concat: bool,
+    stop_at_root_end: bool,
+    sink: S,
+    count: usize,
+    max_depth: usize,
+    depth_limit: Option<usize>,
+    position: u64,
+    record_start: Option<u64>,
+    record_end: u64,
+    recover: bool,
+    broken: Option<String>,
 }
 
 impl ByteProcessor {
-    /// Creates a new instance of `ByteProcessor`.
+    /// Creates a new instance of `ByteProcessor` that writes to stdout.
     pub fn new() -> Self {
+        ByteProcessor::with_sink(RecordWriter::new(io::stdout()))
+    }
+}
+
+impl<S: EmitSink> ByteProcessor<S> {
+    /// Creates a new instance of `ByteProcessor` that sends completed JSONL
+    /// records to `sink`, such as a `RecordWriter` over stdout, a file or any
+    /// other `io::Write`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::byte_processor::ByteProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.push_bracket(&'[').unwrap();
+    /// for c in "{\"a\":1}, [2]]".chars() {
+    ///     processor.process_char(&c).unwrap();
+    /// }
+    /// assert_eq!(processor.into_sink().into_inner(), b"{\"a\":1}\n[2]\n");
+    /// ```
+    pub fn with_sink(sink: S) -> Self {
         ByteProcessor {
             bracket_stack: BracketStack::new(),
             jsonl_string: JSONLString::new(),
             inside_string: false,
             last_char_escape: false,
+            root_closed: false,
+            root_object: false,
+            single_value: false,
+            jsonp_tail: None,
+            single_quotes: false,
+            quote: '"',
+            allow_control_chars: false,
+            comments: None,
+            concat: false,
+            stop_at_root_end: false,
+            sink,
+            count: 0,
+            max_depth: 0,
+            depth_limit: None,
+            position: 0,
+            record_start: None,
+            record_end: 0,
+            recover: false,
+            broken: None,
         }
     }
 
+    /// Consumes the processor, returning the sink it was sending records to.
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Returns the sink that records are sent to.
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Returns the number of records that have been sent to the sink.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the deepest nesting of objects and arrays seen within a
+    /// record, where a flat object has a depth of 1.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Sets the offset in the input of the next character to be processed.
+    /// `process_char` advances the offset by the UTF-8 length of each
+    /// character, so this only needs to be called when that would not match
+    /// the reader, such as after seeking or when invalid bytes have been
+    /// replaced.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Sets the deepest nesting of objects and arrays allowed within a
+    /// record, where a flat object has a depth of 1. `None` allows any depth,
+    /// as does `Some(usize::MAX)`.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.depth_limit = max_depth;
+        // The root bracket takes one more place in the stack.
+        self.bracket_stack
+            .set_capacity(max_depth.and_then(|max_depth| max_depth.checked_add(1)));
+    }
+
+    /// Sets whether roots that follow the root array or single value, as in
+    /// `[...][...]` or `{...}{...}`, are converted too. Another array has
+    /// its elements converted, and another object is converted as a single
+    /// value. Otherwise, they are an error.
+    pub fn set_concat(&mut self, concat: bool) {
+        self.concat = concat;
+    }
+
+    /// Sets whether everything after the root array is ignored, as when the
+    /// root array is nested within a larger document.
+    pub fn set_stop_at_root_end(&mut self, stop_at_root_end: bool) {
+        self.stop_at_root_end = stop_at_root_end;
+    }
+
+    /// Sets whether the root is wrapped in JSONP padding, in which case it
+    /// must be followed by `)` and optionally `;`.
+    pub fn set_jsonp(&mut self, padded: bool) {
+        self.jsonp_tail = padded.then(JsonpTail::default);
+    }
+
+    /// Sets whether `//` and `/* */` comments outside of strings are
+    /// dropped, as in JSON5, so that brackets and quotes inside them are not
+    /// mistaken for the structure of the record.
+    pub fn set_comments(&mut self, comments: bool) {
+        self.comments = comments.then(Comments::default);
+    }
+
+    /// Sets whether strings may also be quoted with `'`, so that brackets
+    /// inside them are not mistaken for the structure of the record.
+    pub fn set_single_quotes(&mut self, single_quotes: bool) {
+        self.single_quotes = single_quotes;
+    }
+
+    /// Sets whether raw control characters inside strings, other than line
+    /// breaks, are passed through. Otherwise, they are an error.
+    pub fn set_allow_control_chars(&mut self, allow_control_chars: bool) {
+        self.allow_control_chars = allow_control_chars;
+    }
+
+    /// Sets whether the processor recovers from an element that is
+    /// malformed, rather than stopping the conversion. The element is sent
+    /// to the sink with `EmitSink::emit_error`, and processing resumes at the
+    /// next element of the root.
+    ///
+    /// An element with a raw control character in a string is malformed, as
+    /// is one with a closing bracket that does not match the last opening
+    /// bracket. The element ends there if the bracket closes no bracket
+    /// within it, and otherwise ends as usual, once the bracket that it
+    /// does close has been closed. A stray closing bracket between elements
+    /// is an element of its own. Input that ends before the root is closed
+    /// ends the conversion, with the element that was cut short, if any,
+    /// sent as an error by `close_truncated_root`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::byte_processor::ByteProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.set_recover(true);
+    /// processor.push_bracket(&'[').unwrap();
+    /// for c in "{\"a\": [1}, {\"b\": 2}]".chars() {
+    ///     processor.process_char(&c).unwrap();
+    /// }
+    /// let output = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+    /// let lines: Vec<&str> = output.lines().collect();
+    /// assert!(lines[0].starts_with("{\"_error\":\"Expected `]`"));
+    /// assert!(lines[0].ends_with(",\"_raw\":\"{\\\"a\\\": [1}\"}"));
+    /// assert_eq!(lines[1], "{\"b\": 2}");
+    /// ```
+    pub fn set_recover(&mut self, recover: bool) {
+        self.recover = recover;
+    }
+
+    /// Checks that the JSONP padding around the root, if there is any, has
+    /// been closed. Called once the input has ended.
+    ///
+    /// # Errors
+    ///
+    /// * If the root has been closed but the padding has not.
+    pub fn check_jsonp_closed(&self) -> Result<(), ConversionError> {
+        match self.jsonp_tail {
+            Some(tail) if self.root_closed && !tail.is_closed() => {
+                Err(ConversionError::InvalidJsonp(String::from(
+                    "The JSONP padding around the root is not closed with `)`.",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the conversion where the input has ended, when recovering from
+    /// malformed elements and the root is still open. The element that was
+    /// cut short, if any, is sent to the sink as an error, and the root is
+    /// closed. Called once the input has ended, before `check_root_closed`.
+    ///
+    /// # Errors
+    ///
+    /// * If the error cannot be sent to the sink.
+    pub fn close_truncated_root(&mut self) -> Result<(), ConversionError> {
+        if !self.recover || self.root_closed || self.bracket_stack.is_empty() {
+            return Ok(());
+        }
+        self.jsonl_string.trim_end();
+        if !self.jsonl_string.is_empty() {
+            self.broken = Some(String::from(
+                "The input ended before the record was closed, so it may have been cut short.",
+            ));
+            self.write_record()?;
+        }
+        self.bracket_stack.clear();
+        self.root_closed = true;
+        Ok(())
+    }
+
+    /// Checks that the root has been closed, if it was ever opened. Called
+    /// once the input has ended.
+    ///
+    /// # Errors
+    ///
+    /// * If the root is still open, as when the input has been cut short.
+    pub fn check_root_closed(&self) -> Result<(), ConversionError> {
+        if self.root_closed || self.bracket_stack.is_empty() {
+            Ok(())
+        } else {
+            Err(ConversionError::UnexpectedEof)
+        }
+    }
+
+    /// Returns whether the root array has been closed and everything after
+    /// it is ignored, so there is no need to process any more input.
+    pub fn is_done(&self) -> bool {
+        self.root_closed && self.stop_at_root_end
+    }
+
+    /// Sets how completed records are cleaned up before they are written.
+    pub fn set_cleanup_policy(&mut self, policy: CleanupPolicy) {
+        self.jsonl_string.set_policy(policy);
+    }
+
+    /// Sets how line breaks within strings are handled before records are
+    /// written. `None` leaves them to the cleanup policy.
+    pub fn set_newline_policy(&mut self, newlines: Option<NewlinePolicy>) {
+        self.jsonl_string.set_newlines(newlines);
+    }
+
+    /// Resets the processor so that it can process another input from the
+    /// start. Records that have already been written, and the count of them,
+    /// are kept.
+    pub fn reset(&mut self) {
+        self.bracket_stack.clear();
+        self.jsonl_string.clear();
+        self.inside_string = false;
+        self.last_char_escape = false;
+        self.root_closed = false;
+        self.single_value = false;
+        self.jsonp_tail = self.jsonp_tail.map(|_| JsonpTail::default());
+        self.comments = self.comments.map(|_| Comments::default());
+        self.position = 0;
+        self.record_start = None;
+        self.record_end = 0;
+        self.broken = None;
+    }
+
     /// Adds a bracket to the `bracket_stack`.
     ///
     /// # Arguments
     ///
-    /// * `byte` - A character.
+    /// * `byte` - An opening bracket.
+    ///
+    /// # Errors
+    ///
+    /// * If `byte` is not an opening bracket, as a
+    ///   `ConversionError::InvalidStart`, as pushing anything else would
+    ///   corrupt the `bracket_stack`.
     ///
     /// # Examples
     ///
@@ -44,10 +365,51 @@ impl ByteProcessor {
     /// use jsonl_converter::processors::byte_processor::ByteProcessor;
     ///
     /// let mut processor = ByteProcessor::new();
-    /// processor.push_bracket(&'[');
+    /// processor.push_bracket(&'[').unwrap();
     /// ```
-    pub fn push_bracket(&mut self, byte: &char) {
-        self.bracket_stack.push(&byte);
+    pub fn push_bracket(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if !is_opening_bracket(byte) {
+            return Err(ConversionError::InvalidStart {
+                found: *byte,
+                map_values: false,
+            });
+        }
+        self.push_opening_bracket(byte);
+        Ok(())
+    }
+
+    /// Adds `byte`, which is known to be an opening bracket, to the
+    /// `bracket_stack`.
+    fn push_opening_bracket(&mut self, byte: &char) {
+        if self.bracket_stack.is_empty() {
+            self.root_object = *byte == '{';
+        }
+        self.bracket_stack.push(byte);
+    }
+
+    /// Starts a root that is a single value, such as a lone object, rather
+    /// than an array, as if it were the only element of one. The value,
+    /// starting with its opening bracket, is then processed as usual and
+    /// written as the only record, after which the root is closed and only
+    /// whitespace may follow, as after a root object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::byte_processor::ByteProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.start_single_value();
+    /// for c in "{\"a\":1}".chars() {
+    ///     processor.process_char(&c).unwrap();
+    /// }
+    /// assert!(processor.check_root_closed().is_ok());
+    /// assert_eq!(processor.into_sink().into_inner(), b"{\"a\":1}\n");
+    /// ```
+    pub fn start_single_value(&mut self) {
+        self.push_opening_bracket(&'[');
+        self.single_value = true;
+        self.root_object = true;
     }
 
     /// Processes a character. This function will either add the character to the
@@ -59,28 +421,145 @@ impl ByteProcessor {
     ///
     /// * `byte` - A character.
     ///
+    /// # Errors
+    ///
+    /// * If a non-whitespace character follows the closing bracket of the
+    ///   root array or single value, other than the opening bracket of
+    ///   another root when `concat` is set, or follows the closing brace of
+    ///   a root object whose values are the records.
+    /// * If a string contains a raw control character, other than a line
+    ///   break, unless they are allowed with `set_allow_control_chars`.
+    /// * If a closing bracket does not match the last opening bracket.
+    /// * If a completed record cannot be written to the output.
+    ///
     /// # Examples
     ///
     /// ```
     /// use jsonl_converter::processors::byte_processor::ByteProcessor;
     ///
     /// let mut processor = ByteProcessor::new();
-    /// processor.push_bracket(&'[');
-    /// processor.process_char(&'{');
-    /// processor.process_char(&'a');
-    /// processor.process_char(&':');
-    /// processor.process_char(&'1');
-    /// processor.process_char(&'}');
+    /// processor.push_bracket(&'[').unwrap();
+    /// processor.process_char(&'{').unwrap();
+    /// processor.process_char(&'a').unwrap();
+    /// processor.process_char(&':').unwrap();
+    /// processor.process_char(&'1').unwrap();
+    /// processor.process_char(&'}').unwrap();
+    /// processor.process_char(&']').unwrap();
+    /// assert!(processor.process_char(&'[').is_err());
     /// ```
-    pub fn process_char(&mut self, byte: &char) {
+    pub fn process_char(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if let Some(comments) = self.comments.as_mut().filter(|_| !self.inside_string) {
+            match comments.accept(*byte) {
+                CommentStep::Keep => {}
+                CommentStep::Drop => {
+                    self.position += byte.len_utf8() as u64;
+                    return Ok(());
+                }
+                CommentStep::KeepSlash => {
+                    // The `/` was dropped when it was read, one byte back.
+                    self.position -= 1;
+                    self.process_code_char(&'/')?;
+                }
+            }
+        }
+        self.process_code_char(byte)
+    }
+
+    /// Processes a character that is not part of a comment.
+    fn process_code_char(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if self.root_closed {
+            self.position += byte.len_utf8() as u64;
+            return self.process_trailing_char(byte);
+        }
+
+        if self.inside_string && !self.allow_control_chars && is_control_char(*byte) {
+            let error = ConversionError::ControlCharacter {
+                character: *byte,
+                offset: self.position,
+            };
+            if !self.recover {
+                return Err(error);
+            }
+            self.broken.get_or_insert(error.to_string());
+        }
+        self.update_record_start(byte);
+        self.position += byte.len_utf8() as u64;
+        self.update_record_end(byte);
+
         match byte {
             &'"' => self.process_quote(byte),
-            b if !self.inside_string && is_opening_bracket(&b) => self.process_opening_bracket(b),
-            b if !self.inside_string && is_closing_bracket(&b) => self.process_closing_bracket(b),
+            &'\'' if self.single_quotes => self.process_quote(byte),
+            b if !self.inside_string && is_opening_bracket(b) => self.process_opening_bracket(b)?,
+            b if !self.inside_string && is_closing_bracket(b) => self.process_closing_bracket(b)?,
+            &',' if !self.inside_string && self.should_print() => self.write_scalar_record()?,
             _ => self.process_other_char(byte),
         }
 
         self.update_last_char_escape(byte);
+        Ok(())
+    }
+
+    /// Processes a character that appears after the root array has been
+    /// closed. Whitespace is allowed, as is the opening bracket of another
+    /// root when `concat` is set. Anything else means that the input
+    /// contains more than a single JSON array. Only whitespace may follow a
+    /// root object whose values are the records, and only the `);` of its
+    /// padding may follow a root wrapped in JSONP padding.
+    fn process_trailing_char(&mut self, byte: &char) -> Result<(), ConversionError> {
+        match byte {
+            _ if self.stop_at_root_end => Ok(()),
+            b if b.is_whitespace() => Ok(()),
+            b if self.jsonp_tail.as_mut().is_some_and(|tail| tail.accept(*b)) => Ok(()),
+            _ if self.jsonp_tail.is_some() => Err(ConversionError::TrailingContent(*byte)),
+            _ if self.root_object && !self.single_value => {
+                Err(ConversionError::TrailingObjectContent(*byte))
+            }
+            '[' | '{' if self.concat => self.start_next_root(byte),
+            _ if self.root_object => Err(ConversionError::TrailingObjectContent(*byte)),
+            '[' => Err(ConversionError::ConcatenatedArrays),
+            _ => Err(ConversionError::TrailingContent(*byte)),
+        }
+    }
+
+    /// Starts another root once the last one has been closed, which is an
+    /// array when `byte` is a `[` or otherwise a single value.
+    fn start_next_root(&mut self, byte: &char) -> Result<(), ConversionError> {
+        self.root_closed = false;
+        self.single_value = false;
+        if *byte == '[' {
+            self.push_opening_bracket(byte);
+            return Ok(());
+        }
+        self.start_single_value();
+        // The opening bracket of a single value is part of its record, so is
+        // processed again as such, from its own offset.
+        self.position -= byte.len_utf8() as u64;
+        self.process_code_char(byte)
+    }
+
+    /// Records the offset of the character if it is the first character of an
+    /// element of the root array. Whitespace and the commas between elements
+    /// are not part of an element.
+    fn update_record_start(&mut self, byte: &char) {
+        if self.record_start.is_none() && self.should_print() && !is_separator(byte) {
+            self.record_start = Some(self.position);
+        }
+    }
+
+    /// Records the offset just after the character if it is part of the
+    /// record being built. A record never ends with whitespace or a comma,
+    /// nor with the closing bracket of the root array.
+    fn update_record_end(&mut self, byte: &char) {
+        let closes_root = !self.inside_string && self.should_print() && is_closing_bracket(byte);
+        if self.record_start.is_some() && !is_separator(byte) && !closes_root {
+            self.record_end = self.position;
+        }
+    }
+
+    /// Checks if the character is whitespace or a comma between two elements
+    /// of the root array, rather than part of an element.
+    fn is_between_elements(&self, byte: &char) -> bool {
+        self.record_start.is_none() && self.should_print() && is_separator(byte)
     }
 
     /// If the current character is an escape character, this function will
@@ -91,68 +570,306 @@ impl ByteProcessor {
     }
 
     /// Processes a character that is a quote. This function will add the
-    /// character to the `jsonl_string` and toggle the `inside_string` flag.
+    /// character to the `jsonl_string` and toggle the `inside_string` flag,
+    /// unless it is inside a string opened by the other quote.
     fn process_quote(&mut self, byte: &char) {
-        self.jsonl_string.push_char(&byte);
-        if !self.last_char_escape {
-            self.inside_string = !self.inside_string;
+        self.jsonl_string.push_char(byte);
+        if self.last_char_escape {
+            return;
+        }
+        if !self.inside_string {
+            self.inside_string = true;
+            self.quote = *byte;
+        } else if *byte == self.quote {
+            self.inside_string = false;
         }
     }
 
     /// Processes an opening bracket by adding it to the `bracket_stack` and
     /// `jsonl_string`.
-    fn process_opening_bracket(&mut self, byte: &char) {
-        self.bracket_stack.push(&byte);
-        self.jsonl_string.push_char(&byte);
+    ///
+    /// # Errors
+    ///
+    /// * If the bracket nests deeper than the limit set with `set_max_depth`.
+    fn process_opening_bracket(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if self.bracket_stack.is_full() {
+            return Err(ConversionError::TooDeep {
+                max_depth: self.depth_limit.unwrap_or_default(),
+                offset: self.position - byte.len_utf8() as u64,
+            });
+        }
+        self.bracket_stack.push(byte);
+        // The root bracket is not part of any record.
+        self.max_depth = self.max_depth.max(self.bracket_stack.len() - 1);
+        self.jsonl_string.push_char(byte);
+        Ok(())
     }
 
     /// Processes a closing bracket by popping the corresponding opening bracket
     /// from the `bracket_stack` and adding it to the `jsonl_string`. If the
     /// `bracket_stack` is empty (except for the initial opening bracket), the
-    /// `jsonl_string` is printed and cleared.
-    fn process_closing_bracket(&mut self, byte: &char) {
-        self.bracket_stack.pop_pair(&byte).unwrap();
-
-        if self.should_print() {
-            self.jsonl_string.push_char(&byte);
+    /// `jsonl_string` is written to the output and cleared. A scalar left
+    /// before the root bracket closes is written as the last record.
+    ///
+    /// When recovering from malformed elements, a bracket that does not
+    /// match is kept in the `jsonl_string`, which is then sent as an error
+    /// once the element ends. See `set_recover`.
+    ///
+    /// # Errors
+    ///
+    /// * If the bracket does not match the last opening bracket.
+    fn process_closing_bracket(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if let Err(error) = self.bracket_stack.pop_pair(byte) {
+            let error = error.at(*byte, self.position - byte.len_utf8() as u64);
+            if !self.recover {
+                return Err(error);
+            }
+            self.broken.get_or_insert(error.to_string());
+            self.bracket_stack.pop_through(byte);
+            self.jsonl_string.push_char(byte);
+            if self.should_print() {
+                self.write_record()?;
+            }
+            return Ok(());
+        }
 
-            println!("{}", self.jsonl_string);
+        if self.bracket_stack.is_empty() {
+            self.write_scalar_record()?;
+            self.root_closed = true;
             self.jsonl_string.clear();
+            self.record_start = None;
+        } else if self.should_print() {
+            self.jsonl_string.push_char(byte);
+            self.write_record()?;
         } else {
-            self.jsonl_string.push_char(&byte);
+            self.jsonl_string.push_char(byte);
+        }
+        Ok(())
+    }
+
+    /// Sends the `jsonl_string` to the sink as a record, or as an error if
+    /// it is malformed, and clears it.
+    fn write_record(&mut self) -> Result<(), ConversionError> {
+        let offset = self.record_start.take().unwrap_or_default();
+        match self.broken.take() {
+            Some(message) => self.sink.emit_error(&message, &self.jsonl_string, offset)?,
+            None => self
+                .sink
+                .emit_record_span(&self.jsonl_string, offset, self.record_end)?,
         }
+        self.count += 1;
+        self.jsonl_string.clear();
+        self.close_single_value();
+        Ok(())
+    }
+
+    /// Closes the root once the record of a single value has been written,
+    /// as there is no closing bracket of an array to do so.
+    fn close_single_value(&mut self) {
+        if self.single_value {
+            self.bracket_stack.clear();
+            self.root_closed = true;
+        }
+    }
+
+    /// Writes the element of the root array that has been read so far as a
+    /// record, as when a comma or the root bracket ends a scalar such as a
+    /// number or a string. Objects and arrays have already been written when
+    /// they closed, so nothing is left to write after them.
+    fn write_scalar_record(&mut self) -> Result<(), ConversionError> {
+        self.jsonl_string.trim_end();
+        if self.jsonl_string.is_empty() {
+            self.record_start = None;
+            return Ok(());
+        }
+        self.write_record()
     }
 
     /// Processes a character that is not a bracket by adding it to the
-    /// `jsonl_string`.
+    /// `jsonl_string`. Whitespace and commas between elements are dropped so
+    /// that they never lead the next record.
     fn process_other_char(&mut self, byte: &char) {
-        self.jsonl_string.push_char(&byte);
+        if !self.is_between_elements(byte) {
+            self.jsonl_string.push_char(byte);
+        }
     }
 
     /// Checks if the `jsonl_string` should be printed. This is the case if the
     /// `bracket_stack` is empty (except for the initial opening bracket).
-    fn should_print(&mut self) -> bool {
+    fn should_print(&self) -> bool {
         self.bracket_stack.len() == 1
     }
 }
 
+/// Checks if the character can separate the elements of an array.
+fn is_separator(byte: &char) -> bool {
+    byte.is_whitespace() || byte == &','
+}
+
+/// Shows the state of the processor without dumping the contents of the
+/// in-progress record, which could be very large.
+impl<S: EmitSink> fmt::Debug for ByteProcessor<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ByteProcessor")
+            .field("depth", &self.bracket_stack.len())
+            .field("inside_string", &self.inside_string)
+            .field("last_char_escape", &self.last_char_escape)
+            .field("root_closed", &self.root_closed)
+            .field("record_len", &self.jsonl_string.len())
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+impl Default for ByteProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sink::CountingSink;
 
     #[test]
     fn test_processor_new_returns_processor_with_empty_attrs() {
         let processor = ByteProcessor::new();
-        assert_eq!(processor.bracket_stack.is_empty(), true);
+        assert!(processor.bracket_stack.is_empty());
     }
 
     #[test]
     fn test_processor_push_bracket_adds_bracket_to_bracket_stack() {
         let mut processor = ByteProcessor::new();
-        processor.push_bracket(&'[');
+        processor.push_bracket(&'[').unwrap();
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
     }
 
+    #[test]
+    fn test_processor_push_bracket_rejects_non_bracket() {
+        let mut processor = ByteProcessor::new();
+        assert!(matches!(
+            processor.push_bracket(&'a'),
+            Err(ConversionError::InvalidStart { found: 'a', .. })
+        ));
+        assert!(processor.bracket_stack.is_empty());
+    }
+
+    #[test]
+    fn test_processor_push_bracket_rejects_closing_bracket() {
+        let mut processor = ByteProcessor::new();
+        assert!(matches!(
+            processor.push_bracket(&']'),
+            Err(ConversionError::InvalidStart { found: ']', .. })
+        ));
+        assert!(processor.bracket_stack.is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_bracket_is_an_error() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(1);
+        let error = "{\"a\": [1}]"
+            .chars()
+            .try_for_each(|c| processor.process_char(&c))
+            .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ConversionError::MismatchedBracket {
+                    expected: Some(']'),
+                    got: '}',
+                    offset: 9
+                }
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    /// Processes `input` inside a root array, recovering from malformed
+    /// elements, and returns the records written with the `_raw` of each
+    /// error record in place of the record.
+    fn recovered(input: &str) -> Vec<String> {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.set_recover(true);
+        processor.push_bracket(&'[').unwrap();
+        input
+            .chars()
+            .try_for_each(|c| processor.process_char(&c))
+            .unwrap();
+        processor.close_truncated_root().unwrap();
+        processor.check_root_closed().unwrap();
+        String::from_utf8(processor.into_sink().into_inner())
+            .unwrap()
+            .lines()
+            .map(
+                |line| match serde_json::from_str::<serde_json::Value>(line) {
+                    Ok(error) if error.get("_error").is_some() => {
+                        format!("error: {}", error["_raw"].as_str().unwrap())
+                    }
+                    _ => line.to_string(),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_resumes_at_the_next_element() {
+        assert_eq!(
+            recovered("{\"a\": [1}, {\"b\": 2}]"),
+            ["error: {\"a\": [1}", "{\"b\": 2}"]
+        );
+        // The `]` closes no bracket within the element, so ends it.
+        assert_eq!(
+            recovered("{\"a\": 1], {\"b\": 2}]"),
+            ["error: {\"a\": 1]", "{\"b\": 2}"]
+        );
+        // The `}` closes the inner object, so the element carries on.
+        assert_eq!(
+            recovered("{\"a\": {\"b\": [}, \"c\": 1}, 2]"),
+            ["error: {\"a\": {\"b\": [}, \"c\": 1}", "2"]
+        );
+    }
+
+    #[test]
+    fn test_recover_writes_stray_closing_brackets_on_their_own() {
+        assert_eq!(
+            recovered("{\"a\": 1}}{\"b\": 2}, 3}, 4]"),
+            ["{\"a\": 1}", "error: }", "{\"b\": 2}", "error: 3}", "4"]
+        );
+    }
+
+    #[test]
+    fn test_recover_from_control_characters() {
+        assert_eq!(
+            recovered("{\"a\": \"\u{1}\"}, 1]"),
+            ["error: {\"a\": \"\u{1}\"}", "1"]
+        );
+    }
+
+    #[test]
+    fn test_recover_writes_the_element_cut_short() {
+        assert_eq!(recovered("1, {\"a\": [2, "), ["1", "error: {\"a\": [2"]);
+        assert_eq!(recovered("1, "), ["1"]);
+    }
+
+    #[test]
+    fn test_root_left_open_is_an_error() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.check_root_closed().unwrap();
+        processor.push_bracket(&'[').unwrap();
+        for c in "[1], {\"a\"".chars() {
+            processor.process_char(&c).unwrap();
+        }
+        assert!(matches!(
+            processor.check_root_closed(),
+            Err(ConversionError::UnexpectedEof)
+        ));
+        processor.process_char(&']').unwrap_err();
+    }
+
     #[test]
     fn test_processor_process_quote_pushes_quote_to_jsonl_string() {
         let mut processor = ByteProcessor::new();
@@ -164,34 +881,34 @@ mod tests {
     fn test_processor_process_quote_flips_inside_string_flag() {
         let mut processor = ByteProcessor::new();
         processor.process_quote(&'"');
-        assert_eq!(processor.inside_string, true);
+        assert!(processor.inside_string);
         processor.process_quote(&'"');
-        assert_eq!(processor.inside_string, false);
+        assert!(!processor.inside_string);
     }
 
     #[test]
     fn test_last_char_escape_flag_flipped_on_escape_char() {
         let mut processor = ByteProcessor::new();
-        processor.process_char(&'\\');
-        assert_eq!(processor.last_char_escape, true);
-        processor.process_char(&'a');
-        assert_eq!(processor.last_char_escape, false);
+        processor.process_char(&'\\').unwrap();
+        assert!(processor.last_char_escape);
+        processor.process_char(&'a').unwrap();
+        assert!(!processor.last_char_escape);
     }
 
     #[test]
     fn test_bracket_inside_str_is_treated_as_string() {
         let mut processor = ByteProcessor::new();
-        processor.process_char(&'"');
-        processor.process_char(&'[');
+        processor.process_char(&'"').unwrap();
+        processor.process_char(&'[').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("\"["));
-        assert_eq!(processor.inside_string, true);
+        assert!(processor.inside_string);
         assert_eq!(processor.bracket_stack.stack.len(), 0);
     }
 
     #[test]
     fn test_process_opening_bracket() {
         let mut processor = ByteProcessor::new();
-        processor.process_opening_bracket(&'[');
+        processor.process_opening_bracket(&'[').unwrap();
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
         assert_eq!(processor.jsonl_string.to_string(), String::from("["));
     }
@@ -202,8 +919,8 @@ mod tests {
         processor.bracket_stack.push(&'[');
         processor.bracket_stack.push(&'{');
         processor.bracket_stack.push(&'{');
-        processor.jsonl_string.push_str(&"{'a': {'a': 1");
-        processor.process_closing_bracket(&'}');
+        processor.jsonl_string.push_str("{'a': {'a': 1");
+        processor.process_closing_bracket(&'}').unwrap();
 
         assert_eq!(
             processor.jsonl_string.to_string(),
@@ -217,8 +934,8 @@ mod tests {
         let mut processor = ByteProcessor::new();
         processor.bracket_stack.push(&'[');
         processor.bracket_stack.push(&'{');
-        processor.jsonl_string.push_str(&"{'a': {'a': 1}");
-        processor.process_closing_bracket(&'}');
+        processor.jsonl_string.push_str("{'a': {'a': 1}");
+        processor.process_closing_bracket(&'}').unwrap();
 
         // After it notices that the line is complete, it prints the line
         // and clears the `jsonl_string`.
@@ -238,7 +955,7 @@ mod tests {
     fn test_should_print_true_if_bracket_stack_len_1() {
         let mut processor = ByteProcessor::new();
         processor.bracket_stack.push(&'[');
-        assert_eq!(processor.should_print(), true);
+        assert!(processor.should_print());
     }
 
     #[test]
@@ -246,7 +963,7 @@ mod tests {
         let mut processor = ByteProcessor::new();
         processor.bracket_stack.push(&'[');
         processor.bracket_stack.push(&'{');
-        assert_eq!(processor.should_print(), false);
+        assert!(!processor.should_print());
     }
 
     #[test]
@@ -255,44 +972,44 @@ mod tests {
         processor.bracket_stack.push(&'[');
 
         // {
-        processor.process_char(&'{');
+        processor.process_char(&'{').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
         // {a
-        processor.process_char(&'a');
+        processor.process_char(&'a').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
         // {a:
-        processor.process_char(&':');
+        processor.process_char(&':').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
         // {a:{
-        processor.process_char(&'{');
+        processor.process_char(&'{').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:{"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '{']);
 
         // {a:{b
-        processor.process_char(&'b');
+        processor.process_char(&'b').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:{b"));
 
         // {a:{b:
-        processor.process_char(&':');
+        processor.process_char(&':').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:{b:"));
 
         // {a:{b:1
-        processor.process_char(&'1');
+        processor.process_char(&'1').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:{b:1"));
 
         // {a:{b:1}
-        processor.process_char(&'}');
+        processor.process_char(&'}').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("{a:{b:1}"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
         // {a:{b:1}}
-        processor.process_char(&'}');
+        processor.process_char(&'}').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from(""));
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
     }
@@ -303,45 +1020,394 @@ mod tests {
         processor.bracket_stack.push(&'[');
 
         // [
-        processor.process_char(&'[');
+        processor.process_char(&'[').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("["));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '[']);
 
         // [a
-        processor.process_char(&'a');
+        processor.process_char(&'a').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '[']);
 
         // [a:
-        processor.process_char(&':');
+        processor.process_char(&':').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '[']);
 
         // [a:[
-        processor.process_char(&'[');
+        processor.process_char(&'[').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:["));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '[', '[']);
 
         // [a:[b
-        processor.process_char(&'b');
+        processor.process_char(&'b').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:[b"));
 
         // [a:[b:
-        processor.process_char(&':');
+        processor.process_char(&':').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:[b:"));
 
         // [a:[b:1
-        processor.process_char(&'1');
+        processor.process_char(&'1').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:[b:1"));
 
         // [a:[b:1]
-        processor.process_char(&']');
+        processor.process_char(&']').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from("[a:[b:1]"));
         assert_eq!(processor.bracket_stack.stack, vec!['[', '[']);
 
         // [a:[b:1]]
-        processor.process_char(&']');
+        processor.process_char(&']').unwrap();
         assert_eq!(processor.jsonl_string.to_string(), String::from(""));
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
     }
+
+    fn process_str<S: EmitSink>(
+        processor: &mut ByteProcessor<S>,
+        s: &str,
+    ) -> Result<(), ConversionError> {
+        for c in s.chars() {
+            processor.process_char(&c)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_whitespace_after_root_array_is_allowed() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        assert!(process_str(&mut processor, "{\"a\": 1}]  \n").is_ok());
+        assert!(processor.root_closed);
+    }
+
+    #[test]
+    fn test_reset_allows_another_root_array() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\": 1}]").unwrap();
+
+        processor.reset();
+        assert!(processor.bracket_stack.is_empty());
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"b\": 2}]").unwrap();
+        assert_eq!(processor.count(), 2);
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": 1}\n{\"b\": 2}\n"
+        );
+    }
+
+    #[test]
+    fn test_clone_mid_record_advances_independently() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\": [1, \"]").unwrap();
+
+        let mut clone = processor.clone();
+        process_str(&mut clone, "x\"]}]").unwrap();
+        assert_eq!(clone.count(), 1);
+        assert_eq!(clone.into_sink().into_inner(), b"{\"a\": [1, \"]x\"]}\n");
+
+        assert_eq!(processor.count(), 0);
+        assert_eq!(processor.jsonl_string.to_string(), "{\"a\": [1, \"]");
+        process_str(&mut processor, "y\"]}]").unwrap();
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": [1, \"]y\"]}\n"
+        );
+    }
+
+    #[test]
+    fn test_content_after_root_array_is_an_error() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        assert!(matches!(
+            process_str(&mut processor, "{\"a\": 1}]garbage"),
+            Err(ConversionError::TrailingContent('g'))
+        ));
+    }
+
+    #[test]
+    fn test_content_after_root_object_is_an_error() {
+        for concat in [false, true] {
+            let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.set_concat(concat);
+            processor.push_bracket(&'{').unwrap();
+            assert!(matches!(
+                process_str(&mut processor, "\"a\": [1]}garbage"),
+                Err(ConversionError::TrailingObjectContent('g'))
+            ));
+            let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.set_concat(concat);
+            processor.push_bracket(&'{').unwrap();
+            assert!(matches!(
+                process_str(&mut processor, "\"a\": [1]} [2]"),
+                Err(ConversionError::TrailingObjectContent('['))
+            ));
+        }
+
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'{').unwrap();
+        process_str(&mut processor, "\"a\": [1]}\n").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"\"a\": [1]\n");
+    }
+
+    #[test]
+    fn test_single_value_is_one_record() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.start_single_value();
+        process_str(&mut processor, "{\"a\": {\"b\": \"}\"}}\n").unwrap();
+        assert_eq!(processor.count(), 1);
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": {\"b\": \"}\"}}\n"
+        );
+
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.start_single_value();
+        assert!(matches!(
+            process_str(&mut processor, "{\"a\": 1} {\"b\": 2}"),
+            Err(ConversionError::TrailingObjectContent('{'))
+        ));
+    }
+
+    #[test]
+    fn test_second_root_array_is_an_error() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        assert!(matches!(
+            process_str(&mut processor, "{\"a\": 1}]\n[{\"b\": 2}]"),
+            Err(ConversionError::ConcatenatedArrays)
+        ));
+    }
+
+    #[test]
+    fn test_concat_converts_concatenated_arrays() {
+        for input in [
+            "{\"a\": 1}]\n[{\"b\": 2}, [3]]\n",
+            "{\"a\": 1}][{\"b\": 2}, [3]]",
+        ] {
+            let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.sink_mut().set_with_offset(true);
+            processor.set_concat(true);
+            processor.push_bracket(&'[').unwrap();
+            process_str(&mut processor, input).unwrap();
+            let offset = input.find("{\"b").unwrap();
+            assert_eq!(
+                String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+                format!(
+                    "0\t{{\"a\": 1}}\n{}\t{{\"b\": 2}}\n{}\t[3]\n",
+                    offset,
+                    offset + 10
+                )
+            );
+        }
+
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.set_concat(true);
+        processor.push_bracket(&'[').unwrap();
+        assert!(matches!(
+            process_str(&mut processor, "[1]] 2"),
+            Err(ConversionError::TrailingContent('2'))
+        ));
+    }
+
+    #[test]
+    fn test_concat_converts_adjacent_single_values() {
+        for (input, expected) in [
+            ("{\"a\":1}{\"b\":2}", "0\t{\"a\":1}\n7\t{\"b\":2}\n"),
+            (
+                "{\"a\":1}\n\n  {\"b\":2}\n[{\"c\":3}]",
+                "0\t{\"a\":1}\n11\t{\"b\":2}\n20\t{\"c\":3}\n",
+            ),
+        ] {
+            let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.sink_mut().set_with_offset(true);
+            processor.set_concat(true);
+            processor.start_single_value();
+            process_str(&mut processor, input).unwrap();
+            processor.check_root_closed().unwrap();
+            assert_eq!(
+                String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+                expected
+            );
+        }
+
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.set_concat(true);
+        processor.start_single_value();
+        process_str(&mut processor, "{\"a\":1} {\"b\":").unwrap();
+        assert!(matches!(
+            processor.check_root_closed(),
+            Err(ConversionError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn test_separators_are_dropped_before_second_and_third_elements() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\":1}, {\"b\":2} ,\t{\"c\":[3]}]").unwrap();
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"a\":1}\n{\"b\":2}\n{\"c\":[3]}\n"
+        );
+    }
+
+    #[test]
+    fn test_elements_without_commas_are_separate_records() {
+        for (input, offsets) in [
+            ("{\"a\":1} {\"b\":2}\n\t[3] ]", ["0\t", "8\t", "17\t"]),
+            ("{\"a\":1}{\"b\":2}[3]]", ["0\t", "7\t", "14\t"]),
+        ] {
+            let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.sink_mut().set_with_offset(true);
+            processor.push_bracket(&'[').unwrap();
+            process_str(&mut processor, input).unwrap();
+            assert_eq!(
+                String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+                format!(
+                    "{}{{\"a\":1}}\n{}{{\"b\":2}}\n{}[3]\n",
+                    offsets[0], offsets[1], offsets[2]
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_scalar_elements_are_separate_records() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.push_bracket(&'[').unwrap();
+        process_str(
+            &mut processor,
+            "1, \"a, [b]\" ,{\"c\": [2]},\n  null , true\n]",
+        )
+        .unwrap();
+        assert_eq!(processor.count(), 5);
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "0\t1\n3\t\"a, [b]\"\n13\t{\"c\": [2]}\n27\tnull\n34\ttrue\n"
+        );
+    }
+
+    #[test]
+    fn test_separator_is_not_kept_in_record_being_built() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\":1},\n  [\",\"").unwrap();
+        assert_eq!(processor.jsonl_string.to_string(), "[\",\"");
+        process_str(&mut processor, "] , {").unwrap();
+        assert_eq!(processor.jsonl_string.to_string(), "{");
+    }
+
+    #[test]
+    fn test_completed_records_are_written_to_output() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\": 1},\n[2]]").unwrap();
+        assert_eq!(processor.count(), 2);
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"a\": 1}\n[2]\n"
+        );
+    }
+
+    #[test]
+    fn test_nesting_deeper_than_max_depth_is_an_error() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.set_max_depth(Some(2));
+        process_str(&mut processor, "{\"a\": [1, \"[{\"]}, ").unwrap();
+        let error = process_str(&mut processor, "{\"b\": [{}]}").unwrap_err();
+        assert!(matches!(
+            error,
+            ConversionError::TooDeep {
+                max_depth: 2,
+                offset: 25
+            }
+        ));
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": [1, \"[{\"]}\n"
+        );
+    }
+
+    #[test]
+    fn test_newline_policy_applies_to_strings_spanning_lines() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.set_newline_policy(Some(NewlinePolicy::Escape));
+        process_str(&mut processor, "{\n  \"a\": \"b\nc\"\n}]").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"{\"a\": \"b\\nc\"}\n");
+    }
+
+    #[test]
+    fn test_comments_are_dropped() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(1);
+        processor.set_comments(true);
+        process_str(
+            &mut processor,
+            " // first ]\n{\"a\": \"//\" /* } */},\n/* [ */ [1 / 2]\n] // end [",
+        )
+        .unwrap();
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"13\t{\"a\": \"//\" }\n42\t[1 / 2]\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_reflects_mid_record_state() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\": [\"b").unwrap();
+        assert_eq!(
+            format!("{:?}", processor),
+            "ByteProcessor { depth: 3, inside_string: true, last_char_escape: false, \
+             root_closed: false, record_len: 9, count: 0 }"
+        );
+    }
+
+    #[test]
+    fn test_records_are_sent_to_any_sink() {
+        let mut processor = ByteProcessor::with_sink(CountingSink::new());
+        processor.push_bracket(&'[').unwrap();
+        process_str(&mut processor, "{\"a\": 1}, [2]]").unwrap();
+        assert_eq!(processor.count(), 2);
+        let sink = processor.into_sink();
+        assert_eq!(sink.records(), 2);
+        assert_eq!(sink.bytes(), 11);
+    }
+
+    #[test]
+    fn test_with_offset_prefixes_records_with_their_first_byte() {
+        let input = "[\n  {\"a\": [1]},\n  [\"é\"],\n{\"c\": {}}\n]";
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(1);
+        process_str(&mut processor, &input[1..]).unwrap();
+
+        let output = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+        for line in output.lines() {
+            let (offset, record) = line.split_once('\t').unwrap();
+            let offset: usize = offset.parse().unwrap();
+            assert!(input[offset..].starts_with(record), "{}", line);
+        }
+        assert_eq!(output.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_set_position_offsets_subsequent_records() {
+        let mut processor = ByteProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(100);
+        process_str(&mut processor, "\n{}]").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"101\t{}\n");
+    }
 }
@@ -3,39 +3,307 @@
 //!
 //! Specifically, this is used to convert JSON to JSONL format.
 //!
-//! It assumes that the JSON file is well formatted.
+//! It assumes that the JSON file is well formatted: each element of the root
+//! array may span many lines, and ends either at the end of a line or where
+//! the next element on the same line starts.
 
 // This struct contains the functionality to process lines of a file one at a
 // time to convert JSON to JSONL format. It keeps track of the brackets that
-// have been opened and closed by observing the start and end of each line.
+// have been opened and closed by scanning each line, skipping those inside
+// strings.
 //
 // # Fields
 //
 
+use std::{fmt, io};
+
 use crate::{
-    brackets::{brackets_map, is_closing_bracket, is_opening_bracket, BracketStack},
-    json_object::JSONLString,
+    brackets::{is_closing_bracket, is_opening_bracket, BracketStack},
+    control::is_control_char,
+    errors::ConversionError,
+    json_object::{CleanupPolicy, JSONLString},
+    jsonp::JsonpTail,
+    sink::EmitSink,
+    writer::RecordWriter,
 };
 
-pub struct LineProcessor {
+/// Processes the lines of a well formatted JSON file, writing each element of
+/// the root array to the output as a JSONL record.
+///
+/// A processor can be cloned to snapshot its state, for example to try
+/// processing some input and roll back if it fails. This copies the record
+/// being built and the bracket stack, so costs time in proportion to their
+/// sizes, and also clones the sink. As such, a processor is only `Clone` if
+/// its sink is, such as a writer over an in-memory buffer.
+#[derive(Clone)]
+pub struct LineProcessor<S: EmitSink = RecordWriter> {
     pub bracket_stack: BracketStack,
     pub jsonl_string: JSONLString,
+    root_closed: bool,
+    root_object: bool,
+    single_value: bool,
+    jsonp_tail: Option<JsonpTail>,
+    single_quotes: bool,
+    allow_control_chars: bool,
+    concat: bool,
+    stop_at_root_end: bool,
+    sink: S,
+    count: usize,
+    max_depth: usize,
+    depth_limit: Option<usize>,
+    position: u64,
+    record_start: Option<u64>,
+    record_end: u64,
+    recover: bool,
+    broken: Option<String>,
 }
 
 impl LineProcessor {
-    /// Creates a mew instance pf `LineProcessor`.
+    /// Creates a mew instance pf `LineProcessor` that writes to stdout.
     pub fn new() -> Self {
+        LineProcessor::with_sink(RecordWriter::new(io::stdout()))
+    }
+}
+
+impl<S: EmitSink> LineProcessor<S> {
+    /// Creates a new instance of `LineProcessor` that sends completed JSONL
+    /// records to `sink`, such as a `RecordWriter` over stdout, a file or any
+    /// other `io::Write`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::line_processor::LineProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.push_bracket(&'[').unwrap();
+    /// processor.process_line("  {\"a\":1},\n").unwrap();
+    /// processor.process_line("  [2]\n").unwrap();
+    /// assert_eq!(processor.into_sink().into_inner(), b"{\"a\":1}\n[2]\n");
+    /// ```
+    pub fn with_sink(sink: S) -> Self {
         Self {
             bracket_stack: BracketStack::new(),
             jsonl_string: JSONLString::new(),
+            root_closed: false,
+            root_object: false,
+            single_value: false,
+            jsonp_tail: None,
+            single_quotes: false,
+            allow_control_chars: false,
+            concat: false,
+            stop_at_root_end: false,
+            sink,
+            count: 0,
+            max_depth: 0,
+            depth_limit: None,
+            position: 0,
+            record_start: None,
+            record_end: 0,
+            recover: false,
+            broken: None,
         }
     }
 
+    /// Consumes the processor, returning the sink it was sending records to.
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Returns the sink that records are sent to.
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Returns the number of records that have been sent to the sink.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the deepest nesting of objects and arrays seen within a
+    /// record, where a flat object has a depth of 1.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Sets the offset in the input of the start of the next line to be
+    /// processed. `process_line` advances the offset by the length of each
+    /// line, so this only needs to be called when that would not match the
+    /// reader, such as after seeking.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Sets the deepest nesting of objects and arrays allowed within a
+    /// record, where a flat object has a depth of 1. `None` allows any depth,
+    /// as does `Some(usize::MAX)`.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.depth_limit = max_depth;
+        // The root bracket takes one more place in the stack.
+        self.bracket_stack
+            .set_capacity(max_depth.and_then(|max_depth| max_depth.checked_add(1)));
+    }
+
+    /// Sets whether roots that follow the root array or single value, as when
+    /// two files have been concatenated, are converted too. Another array has
+    /// its elements converted, and another object is converted as a single
+    /// value. Otherwise, they are an error. Each root must start on a new
+    /// line.
+    pub fn set_concat(&mut self, concat: bool) {
+        self.concat = concat;
+    }
+
+    /// Sets whether everything after the root array is ignored, as when the
+    /// root array is nested within a larger document.
+    pub fn set_stop_at_root_end(&mut self, stop_at_root_end: bool) {
+        self.stop_at_root_end = stop_at_root_end;
+    }
+
+    /// Sets whether the root is wrapped in JSONP padding, in which case it
+    /// must be followed by `)` and optionally `;`.
+    pub fn set_jsonp(&mut self, padded: bool) {
+        self.jsonp_tail = padded.then(JsonpTail::default);
+    }
+
+    /// Sets whether strings may also be quoted with `'`, so that brackets
+    /// inside them are not mistaken for the structure of the record.
+    pub fn set_single_quotes(&mut self, single_quotes: bool) {
+        self.single_quotes = single_quotes;
+    }
+
+    /// Sets whether raw control characters inside strings are passed
+    /// through. Otherwise, they are an error.
+    pub fn set_allow_control_chars(&mut self, allow_control_chars: bool) {
+        self.allow_control_chars = allow_control_chars;
+    }
+
+    /// Sets whether the processor recovers from an element that is
+    /// malformed, rather than stopping the conversion. The element is sent
+    /// to the sink with `EmitSink::emit_error`, and processing resumes at the
+    /// next element of the root.
+    ///
+    /// An element with a raw control character in a string is malformed, as
+    /// is one with a closing bracket that does not match the last opening
+    /// bracket. The element ends there if the bracket closes no bracket
+    /// within it, and otherwise ends as usual, once the bracket that it
+    /// does close has been closed. Input that ends before the root is closed
+    /// ends the conversion, with the element that was cut short, if any,
+    /// sent as an error by `close_truncated_root`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::line_processor::LineProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.set_recover(true);
+    /// processor.push_bracket(&'[').unwrap();
+    /// processor.process_line("  {\"a\": [1},\n").unwrap();
+    /// processor.process_line("  {\"b\": 2}\n").unwrap();
+    /// processor.process_line("]\n").unwrap();
+    /// let output = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+    /// let lines: Vec<&str> = output.lines().collect();
+    /// assert!(lines[0].starts_with("{\"_error\":\"Expected `]`"));
+    /// assert!(lines[0].ends_with(",\"_raw\":\"{\\\"a\\\": [1}\"}"));
+    /// assert_eq!(lines[1], "{\"b\": 2}");
+    /// ```
+    pub fn set_recover(&mut self, recover: bool) {
+        self.recover = recover;
+    }
+
+    /// Checks that the JSONP padding around the root, if there is any, has
+    /// been closed. Called once the input has ended.
+    ///
+    /// # Errors
+    ///
+    /// * If the root has been closed but the padding has not.
+    pub fn check_jsonp_closed(&self) -> Result<(), ConversionError> {
+        match self.jsonp_tail {
+            Some(tail) if self.root_closed && !tail.is_closed() => {
+                Err(ConversionError::InvalidJsonp(String::from(
+                    "The JSONP padding around the root is not closed with `)`.",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the conversion where the input has ended, when recovering from
+    /// malformed elements and the root is still open. The element that was
+    /// cut short, if any, is sent to the sink as an error, and the root is
+    /// closed. Called once the input has ended, before `check_root_closed`.
+    ///
+    /// # Errors
+    ///
+    /// * If the error cannot be sent to the sink.
+    pub fn close_truncated_root(&mut self) -> Result<(), ConversionError> {
+        if !self.recover || self.root_closed || self.bracket_stack.is_empty() {
+            return Ok(());
+        }
+        if !self.jsonl_string.trim_matches(is_separator).is_empty() {
+            self.broken = Some(String::from(
+                "The input ended before the record was closed, so it may have been cut short.",
+            ));
+            self.write_record()?;
+        }
+        self.jsonl_string.clear();
+        self.record_start = None;
+        self.bracket_stack.clear();
+        self.root_closed = true;
+        Ok(())
+    }
+
+    /// Checks that the root has been closed, if it was ever opened. Called
+    /// once the input has ended.
+    ///
+    /// # Errors
+    ///
+    /// * If the root is still open, as when the input has been cut short.
+    pub fn check_root_closed(&self) -> Result<(), ConversionError> {
+        if self.root_closed || self.bracket_stack.is_empty() {
+            Ok(())
+        } else {
+            Err(ConversionError::UnexpectedEof)
+        }
+    }
+
+    /// Returns whether the root array has been closed and everything after
+    /// it is ignored, so there is no need to process any more input.
+    pub fn is_done(&self) -> bool {
+        self.root_closed && self.stop_at_root_end
+    }
+
+    /// Sets how completed records are cleaned up before they are written.
+    pub fn set_cleanup_policy(&mut self, policy: CleanupPolicy) {
+        self.jsonl_string.set_policy(policy);
+    }
+
+    /// Resets the processor so that it can process another input from the
+    /// start. Records that have already been written, and the count of them,
+    /// are kept.
+    pub fn reset(&mut self) {
+        self.bracket_stack.clear();
+        self.jsonl_string.clear();
+        self.root_closed = false;
+        self.single_value = false;
+        self.jsonp_tail = self.jsonp_tail.map(|_| JsonpTail::default());
+        self.position = 0;
+        self.record_start = None;
+        self.record_end = 0;
+        self.broken = None;
+    }
+
     /// Adds a bracket to the `bracket_stack`.
     ///
     /// # Arguments
     ///
-    /// * `byte` - A character.
+    /// * `byte` - An opening bracket.
+    ///
+    /// # Errors
+    ///
+    /// * If `byte` is not an opening bracket, as a
+    ///   `ConversionError::InvalidStart`, as pushing anything else would
+    ///   corrupt the `bracket_stack`.
     ///
     /// # Examples
     ///
@@ -43,69 +311,347 @@ impl LineProcessor {
     /// use jsonl_converter::processors::line_processor::LineProcessor;
     ///
     /// let mut processor = LineProcessor::new();
-    /// processor.push_bracket(&'[');
+    /// processor.push_bracket(&'[').unwrap();
     /// assert_eq!(processor.bracket_stack.len(), 1);
     /// ```
-    pub fn push_bracket(&mut self, byte: &char) {
-        self.bracket_stack.push(&byte);
+    pub fn push_bracket(&mut self, byte: &char) -> Result<(), ConversionError> {
+        if !is_opening_bracket(byte) {
+            return Err(ConversionError::InvalidStart {
+                found: *byte,
+                map_values: false,
+            });
+        }
+        self.push_opening_bracket(byte);
+        Ok(())
+    }
+
+    /// Adds `byte`, which is known to be an opening bracket, to the
+    /// `bracket_stack`.
+    fn push_opening_bracket(&mut self, byte: &char) {
+        if self.bracket_stack.is_empty() {
+            self.root_object = *byte == '{';
+        }
+        self.bracket_stack.push(byte);
+    }
+
+    /// Starts a root that is a single value, such as a lone object, rather
+    /// than an array, as if it were the only element of one. The value,
+    /// starting with its opening bracket, is then processed as usual and
+    /// written as the only record, after which the root is closed and only
+    /// whitespace may follow, as after a root object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{processors::line_processor::LineProcessor, writer::RecordWriter};
+    ///
+    /// let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+    /// processor.start_single_value();
+    /// processor.process_line("{\"a\":1}\n").unwrap();
+    /// assert!(processor.check_root_closed().is_ok());
+    /// assert_eq!(processor.into_sink().into_inner(), b"{\"a\":1}\n");
+    /// ```
+    pub fn start_single_value(&mut self) {
+        self.push_opening_bracket(&'[');
+        self.single_value = true;
+        self.root_object = true;
     }
 
     /// Processes a line of a file. Whilst processing the line, it checks if
-    /// their are any brackets. Keeping a track of the brackets allows it to
-    /// determine when a JSON object has been fully read.
-    /// If the JSON object has been fully read, then the JSON object is printed
-    /// to stdout.
+    /// their are any brackets outside of strings. Keeping a track of the
+    /// brackets allows it to determine when a JSON object has been fully read,
+    /// however many lines it spans. If the JSON object has been fully read at
+    /// the end of the line, then the JSON object is written to the output.
+    /// Blank lines are skipped.
+    ///
+    /// Once the root array has been closed, only whitespace may follow, or
+    /// another root if `concat` is set.
     ///
     /// # Arguments
     ///
     /// * `line` - A line of a file.
-    pub fn process_line(&mut self, line: &str) {
-        let line = line.trim();
+    ///
+    /// # Errors
+    ///
+    /// * If a completed record cannot be written to the output.
+    /// * If anything other than whitespace follows the root array or single
+    ///   value, other than another root when `concat` is set, or follows a
+    ///   root object whose values are the records.
+    /// * If a string contains a raw control character, unless they are
+    ///   allowed with `set_allow_control_chars`.
+    /// * If a closing bracket does not match the last opening bracket.
+    pub fn process_line(&mut self, line: &str) -> Result<(), ConversionError> {
+        let line_start = self.position;
+        self.position += line.len() as u64;
+        self.process_text(line, line_start)
+    }
 
-        let start_char = line.chars().next().unwrap();
-        let end_char = self.get_end_char(&line);
+    /// Processes the part of a line that starts at offset `start` in the
+    /// input.
+    fn process_text(&mut self, text: &str, start: u64) -> Result<(), ConversionError> {
+        let indent = text.len() - text.trim_start().len();
+        let content = self.record_content(text);
+        let text = text.trim();
+        let start = start + indent as u64;
+        if text.is_empty() {
+            return Ok(());
+        }
+        if self.root_closed {
+            return self.process_trailing_text(text, start);
+        }
+        if self.record_start.is_none() && self.should_print() {
+            self.record_start = Some(start);
+        }
 
-        if is_opening_bracket(&start_char) {
-            self.push_bracket(&start_char);
+        match self.scan_brackets(text, start)? {
+            Some(ScanStop::RootEnd(root_end)) => {
+                // Anything before the closing bracket of the root array ends
+                // the last record, without the whitespace before the
+                // bracket. A single value ends with, and includes, its own
+                // closing bracket.
+                let value_end = if self.single_value {
+                    root_end
+                } else {
+                    root_end - 1
+                };
+                self.end_record(text, content, value_end, start)?;
+                self.root_closed = true;
+                return self.process_text(&text[root_end..], start + root_end as u64);
+            }
+            Some(ScanStop::ElementEnd { end, next }) => {
+                // Another element follows on the same line, so this one is
+                // written before the rest of the line is processed.
+                self.end_record(text, content, end, start)?;
+                return self.process_text(&text[next..], start + next as u64);
+            }
+            None => {}
         }
 
-        if is_closing_bracket(&end_char) {
-            self.bracket_stack.pop_pair(&end_char);
+        self.update_record_end(text, start);
+        self.jsonl_string.push_str(content);
+
+        if !self.should_print() {
+            return Ok(());
         }
+        if self.jsonl_string.trim_matches(is_separator).is_empty() {
+            // Only the comma after an element, as when resuming from the
+            // end of it, which is not a record of its own.
+            self.jsonl_string.clear();
+            self.record_start = None;
+            return Ok(());
+        }
+        self.write_record()
+    }
 
-        if is_opening_bracket(&end_char) {
-            self.push_bracket(&end_char);
+    /// Ends the record being built with the first `end` bytes of the trimmed
+    /// `text`, writing it unless it is only separators, as when the record
+    /// was already written at the end of the previous line. `content` is the
+    /// untrimmed text as returned by `record_content`.
+    fn end_record(
+        &mut self,
+        text: &str,
+        content: &str,
+        end: usize,
+        start: u64,
+    ) -> Result<(), ConversionError> {
+        let last = text[..end].trim_end();
+        self.update_record_end(last, start);
+        // The content keeps the indentation when whitespace is preserved.
+        let kept_indent = content.len() - content.trim_start().len();
+        self.jsonl_string
+            .push_str(&content[..kept_indent + last.len()]);
+        if !self.jsonl_string.trim_matches(is_separator).is_empty() {
+            self.write_record()?;
         }
+        self.jsonl_string.clear();
+        self.record_start = None;
+        Ok(())
+    }
 
-        if is_closing_bracket(&start_char) {
-            self.bracket_stack.pop_pair(&start_char);
+    /// Processes text that follows the root array, which is either another
+    /// root to convert when `concat` is set, or an error. Any text after a
+    /// root object whose values are the records is an error, as is anything
+    /// but the `);` of the JSONP padding around a padded root.
+    fn process_trailing_text(&mut self, text: &str, start: u64) -> Result<(), ConversionError> {
+        if let Some(tail) = &mut self.jsonp_tail {
+            if self.stop_at_root_end {
+                return Ok(());
+            }
+            return match text
+                .chars()
+                .find(|&c| !c.is_whitespace() && !tail.accept(c))
+            {
+                Some(c) => Err(ConversionError::TrailingContent(c)),
+                None => Ok(()),
+            };
         }
+        let first = text.chars().next().unwrap();
+        match text.strip_prefix('[') {
+            _ if self.stop_at_root_end => Ok(()),
+            _ if self.root_object && !self.single_value => {
+                Err(ConversionError::TrailingObjectContent(first))
+            }
+            Some(rest) if self.concat => {
+                self.root_closed = false;
+                self.single_value = false;
+                self.push_opening_bracket(&'[');
+                self.process_text(rest, start + 1)
+            }
+            None if self.concat && first == '{' => {
+                self.root_closed = false;
+                self.start_single_value();
+                self.process_text(text, start)
+            }
+            _ if self.root_object => Err(ConversionError::TrailingObjectContent(first)),
+            Some(_) => Err(ConversionError::ConcatenatedArrays),
+            None => Err(ConversionError::TrailingContent(first)),
+        }
+    }
 
-        self.jsonl_string.push_str(&line);
+    /// Updates the `bracket_stack` with the brackets opened and closed by a
+    /// line, skipping those inside strings. Strings cannot contain line
+    /// breaks, so every string that starts on the line also ends on it.
+    ///
+    /// If the root array is closed, or a root that is a single value is
+    /// complete, scanning stops and the offset in the line just after its
+    /// closing bracket is returned. Scanning also stops where an element of
+    /// the root array ends and another starts on the same line, such as at
+    /// the comma in `{"a":1},{"b":2}`, so that each is a record of its own.
+    ///
+    /// # Errors
+    ///
+    /// * If a bracket nests deeper than the limit set with `set_max_depth`,
+    ///   a closing bracket does not match the last opening bracket, or a
+    ///   string contains a raw control character that is not allowed.
+    ///   `start` is the offset of the line in the input, to report where.
+    ///   When recovering from malformed elements, the last two are instead
+    ///   kept for `write_record` to send as an error, and scanning carries
+    ///   on. See `set_recover`.
+    fn scan_brackets(
+        &mut self,
+        line: &str,
+        start: u64,
+    ) -> Result<Option<ScanStop>, ConversionError> {
+        // The quote that opened the string being scanned, if inside one.
+        let mut quote = None;
+        let mut last_char_escape = false;
+        for (i, c) in line.char_indices() {
+            let is_quote = c == '"' || (c == '\'' && self.single_quotes);
+            if is_quote && !last_char_escape {
+                match quote {
+                    None => quote = Some(c),
+                    Some(open) if open == c => quote = None,
+                    Some(_) => {}
+                }
+            } else if quote.is_some() && !self.allow_control_chars && is_control_char(c) {
+                let error = ConversionError::ControlCharacter {
+                    character: c,
+                    offset: start + i as u64,
+                };
+                if !self.recover {
+                    return Err(error);
+                }
+                self.broken.get_or_insert(error.to_string());
+            } else if quote.is_none() && is_opening_bracket(&c) {
+                if self.bracket_stack.is_full() {
+                    return Err(ConversionError::TooDeep {
+                        max_depth: self.depth_limit.unwrap_or_default(),
+                        offset: start + i as u64,
+                    });
+                }
+                self.push_opening_bracket(&c);
+                // The root bracket is not part of any record.
+                self.max_depth = self.max_depth.max(self.bracket_stack.len() - 1);
+            } else if quote.is_none() && is_closing_bracket(&c) {
+                if let Err(error) = self.bracket_stack.pop_pair(&c) {
+                    let error = error.at(c, start + i as u64);
+                    if !self.recover {
+                        return Err(error);
+                    }
+                    self.broken.get_or_insert(error.to_string());
+                    self.bracket_stack.pop_through(&c);
+                }
+                if self.bracket_stack.is_empty()
+                    || (self.single_value && self.bracket_stack.len() == 1)
+                {
+                    return Ok(Some(ScanStop::RootEnd(i + 1)));
+                }
+                // An element that is followed by another without a comma.
+                let rest = line[i + 1..].trim_start();
+                if self.at_element_level()
+                    && !rest.is_empty()
+                    && !starts_with_closing_or_comma(rest)
+                {
+                    let next = line.len() - rest.len();
+                    return Ok(Some(ScanStop::ElementEnd { end: i + 1, next }));
+                }
+            } else if quote.is_none() && c == ',' && self.at_element_level() {
+                // The comma between two elements on the same line.
+                if !line[i + 1..].trim_matches(is_separator).is_empty() {
+                    return Ok(Some(ScanStop::ElementEnd {
+                        end: i,
+                        next: i + 1,
+                    }));
+                }
+            }
+            last_char_escape = c == '\\' && !last_char_escape;
+        }
+        Ok(None)
+    }
 
-        if self.should_print() {
-            println!("{}", self.jsonl_string);
-            self.jsonl_string.clear();
+    /// Records the offset just after the last character of `text`, which
+    /// starts at offset `start` in the input, that is part of the record
+    /// being built. Whitespace and commas between elements are not.
+    fn update_record_end(&mut self, text: &str, start: u64) {
+        let content = text.trim_end_matches(is_separator);
+        if !content.is_empty() {
+            self.record_end = start + content.len() as u64;
         }
     }
 
-    /// Returns the character that ends the `line`. If the `line` ends with a
-    /// comma, then the second to last character is returned.
-    /// If the length of the `line` is 1, then an empty character is returned.
-    fn get_end_char(&self, line: &str) -> char {
-        let cleaned_line = line.trim_end_matches(',');
-        if cleaned_line.len() == 1 {
-            return ' ';
+    /// Returns the part of the `line` that is added to the `jsonl_string`.
+    /// Usually, this is the trimmed line, but when whitespace is preserved
+    /// the indentation of every line but the first line of a record is kept.
+    fn record_content<'a>(&self, line: &'a str) -> &'a str {
+        if self.jsonl_string.policy() == CleanupPolicy::StripNewlines
+            && !self.jsonl_string.is_empty()
+        {
+            line.trim_end_matches(['\r', '\n'])
+        } else {
+            line.trim()
         }
-        let last_char = cleaned_line.chars().last().unwrap();
-        if is_closing_bracket(&last_char) {
-            // check if the bracket before is the corresponding opening bracket
-            let second_to_last_char = cleaned_line.chars().rev().nth(1).unwrap();
-            if brackets_map().get(&last_char) == Some(&second_to_last_char) {
-                return ' '; // Cancels each other out
-            }
+    }
+
+    /// Sends the `jsonl_string` to the sink as a record, or as an error if
+    /// it is malformed, and clears it.
+    fn write_record(&mut self) -> Result<(), ConversionError> {
+        let offset = self.record_start.take().unwrap_or_default();
+        match self.broken.take() {
+            Some(message) => self.sink.emit_error(&message, &self.jsonl_string, offset)?,
+            None => self
+                .sink
+                .emit_record_span(&self.jsonl_string, offset, self.record_end)?,
         }
-        last_char
+        self.count += 1;
+        self.jsonl_string.clear();
+        self.close_single_value();
+        Ok(())
+    }
+
+    /// Closes the root once the record of a single value has been written,
+    /// as there is no closing bracket of an array to do so.
+    fn close_single_value(&mut self) {
+        if self.single_value {
+            self.bracket_stack.clear();
+            self.root_closed = true;
+        }
+    }
+
+    /// Checks if scanning is between the elements of the root array, or the
+    /// values of a root object whose values are the records, rather than
+    /// within one of them.
+    fn at_element_level(&self) -> bool {
+        self.bracket_stack.len() == 1 && !self.single_value
     }
 
     /// Checks if the `jsonl_string` should be printed. This is the case if the
@@ -115,104 +661,586 @@ impl LineProcessor {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Where `scan_brackets` stopped scanning a line.
+enum ScanStop {
+    /// The root was closed, and the rest of the line starts at this offset.
+    RootEnd(usize),
+    /// An element of the root ended at offset `end`, and the rest of the
+    /// line, which holds another, starts at offset `next`.
+    ElementEnd { end: usize, next: usize },
+}
 
-    #[test]
-    fn test_new_returns_processor_with_empty_attrs() {
-        let processor = LineProcessor::new();
-        assert_eq!(processor.bracket_stack.len(), 0);
-        assert_eq!(processor.jsonl_string.len(), 0);
-    }
+/// Checks if `text` starts with a closing bracket or a comma, which may
+/// follow an element on the same line without another element starting.
+fn starts_with_closing_or_comma(text: &str) -> bool {
+    text.starts_with(|c| is_closing_bracket(&c) || c == ',')
+}
 
-    #[test]
-    fn test_get_end_char_returns_empty_char_when_line_is_one_char() {
-        let processor = LineProcessor::new();
-        let line = "{";
-        assert_eq!(processor.get_end_char(&line), ' ');
-    }
+/// Checks if the character can separate the elements of an array.
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ','
+}
 
-    #[test]
-    fn test_get_end_char_returns_second_to_last_char_if_ends_with_comma() {
-        let processor = LineProcessor::new();
-        let line = "  \"name\": \"John\",";
-        assert_eq!(processor.get_end_char(&line), '"');
+/// Shows the state of the processor without dumping the contents of the
+/// in-progress record, which could be very large.
+impl<S: EmitSink> fmt::Debug for LineProcessor<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LineProcessor")
+            .field("depth", &self.bracket_stack.len())
+            .field("record_len", &self.jsonl_string.len())
+            .field("count", &self.count)
+            .finish()
     }
+}
 
-    #[test]
-    fn test_get_end_char_returns_last_char_if_does_not_end_with_comma() {
-        let processor = LineProcessor::new();
-        let line = "  \"name\": \"John\"";
-        assert_eq!(processor.get_end_char(&line), '"');
+impl Default for LineProcessor {
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::CallbackSink;
 
     #[test]
-    fn test_get_end_char_returns_empty_str_if_len_2_and_last_char_is_comma() {
+    fn test_new_returns_processor_with_empty_attrs() {
         let processor = LineProcessor::new();
-        let line = "{,";
-        assert_eq!(processor.get_end_char(&line), ' ');
+        assert_eq!(processor.bracket_stack.len(), 0);
+        assert_eq!(processor.jsonl_string.len(), 0);
     }
 
     #[test]
-    fn test_get_end_char_returns_str_if_last_two_open_and_close() {
-        let processor = LineProcessor::new();
-        let line = "cars: [],";
-        assert_eq!(processor.get_end_char(&line), ' ');
+    fn test_push_bracket_rejects_non_bracket() {
+        let mut processor = LineProcessor::new();
+        assert!(matches!(
+            processor.push_bracket(&'a'),
+            Err(ConversionError::InvalidStart { found: 'a', .. })
+        ));
+        assert!(processor.bracket_stack.is_empty());
     }
 
     #[test]
     fn test_process_line_returns_object_when_filled() {
         let mut processor = LineProcessor::new();
 
-        processor.process_line("[");
+        processor.process_line("[").unwrap();
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
 
-        processor.process_line("  {");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("  {").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
-        processor.process_line("    \"name\": \"John\",");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("    \"name\": \"John\",").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
-        processor.process_line("    \"age\": 30,");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("    \"age\": 30,").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
-        processor.process_line("    \"cars\": [");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("    \"cars\": [").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[']);
 
-        processor.process_line("    \"cars\": [");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("    \"cars\": [").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[', '[']);
 
-        processor.process_line(
-            "      { \"name\": \"Ford\", \"models\": [ \"Fiesta\", \"Focus\", \"Mustang\" ] },",
-        );
-        assert_eq!(processor.should_print(), false);
+        processor
+            .process_line(
+                "      { \"name\": \"Ford\", \"models\": [ \"Fiesta\", \"Focus\", \"Mustang\" ] },",
+            )
+            .unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[', '[']);
 
         processor
-            .process_line("      { \"name\": \"BMW\", \"models\": [ \"320\", \"X3\", \"X5\" ] },");
-        assert_eq!(processor.should_print(), false);
+            .process_line("      { \"name\": \"BMW\", \"models\": [ \"320\", \"X3\", \"X5\" ] },")
+            .unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[', '[']);
 
-        processor.process_line("      { \"name\": \"Fiat\", \"models\": [ \"500\", \"Panda\" ] }");
-        assert_eq!(processor.should_print(), false);
+        processor
+            .process_line("      { \"name\": \"Fiat\", \"models\": [ \"500\", \"Panda\" ] }")
+            .unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[', '[']);
 
-        processor.process_line("    ]");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("    ]").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{', '[']);
 
-        processor.process_line("  ]");
-        assert_eq!(processor.should_print(), false);
+        processor.process_line("  ]").unwrap();
+        assert!(!processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
 
-        processor.process_line("}");
-        assert_eq!(processor.should_print(), true);
+        processor.process_line("}").unwrap();
+        assert!(processor.should_print());
         assert_eq!(processor.bracket_stack.stack, vec!['[']);
     }
+
+    #[test]
+    fn test_record_spanning_many_lines_is_written_once() {
+        let input = "[
+  {
+    \"name\": \"John\",
+    \"tags\": [\"a\", \"]\", \"{\"],
+    \"cars\": [
+      {
+        \"name\": \"Ford\",
+        \"models\": [
+          \"Fiesta\",
+          \"Focus\"
+        ]
+      },
+      {\"name\": \"BMW\", \"models\": []}
+    ],
+
+    \"note\": \"\\\"}\"
+  },
+  [1, [2]]
+]
+";
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        let mut lines = input.lines();
+        processor
+            .push_bracket(&lines.next().unwrap().chars().next().unwrap())
+            .unwrap();
+        for line in lines {
+            let written = processor.count();
+            processor.process_line(line).unwrap();
+            if processor.count() > written {
+                assert!(line == "  }," || line == "  [1, [2]]", "{}", line);
+            }
+        }
+        assert_eq!(processor.count(), 2);
+        assert_eq!(processor.max_depth(), 4);
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"name\": \"John\",\"tags\": [\"a\", \"]\", \"{\"],\"cars\": [{\"name\": \"Ford\",\"models\": [\"Fiesta\",\"Focus\"]},{\"name\": \"BMW\", \"models\": []}],\"note\": \"\\\"}\"}\n[1, [2]]\n"
+        );
+    }
+
+    #[test]
+    fn test_brackets_inside_strings_are_not_counted() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        let input = "  {\n    \"note\": \"array [1,2,3]\",\n    \"quote\": \"say \\\"}\\\" {\",\n    \"path\": \"C:\\\\\",\n    \"end\": \"]\"\n  },\n  [\"}\"]\n]";
+        process_lines(&mut processor, input).unwrap();
+        processor.check_root_closed().unwrap();
+        assert_eq!(processor.count(), 2);
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"note\": \"array [1,2,3]\",\"quote\": \"say \\\"}\\\" {\",\"path\": \"C:\\\\\",\"end\": \"]\"}\n[\"}\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_process_line_writes_completed_records_to_output() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        for line in ["  {", "    \"a\": 1", "  },", "  {\"b\": 2}", "]"] {
+            processor.process_line(line).unwrap();
+        }
+        assert_eq!(processor.count(), 2);
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"a\": 1}\n{\"b\": 2}\n"
+        );
+    }
+
+    #[test]
+    fn test_elements_sharing_a_line_are_separate_records() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.process_line("{\"a\":1},{\"b\":2}]\n").unwrap();
+        assert_eq!(processor.count(), 2);
+        assert!(processor.check_root_closed().is_ok());
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "{\"a\":1}\n{\"b\":2}\n"
+        );
+    }
+
+    #[test]
+    fn test_scalars_and_elements_without_commas_sharing_a_line_are_separate_records() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_lines(
+            &mut processor,
+            "  \"x,]\", 4, [5]\n  {\"a\": 1} {\"b\": 2}\n]",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "\"x,]\"\n4\n[5]\n{\"a\": 1}\n{\"b\": 2}\n"
+        );
+    }
+
+    fn process_lines(
+        processor: &mut LineProcessor<RecordWriter<Vec<u8>>>,
+        input: &str,
+    ) -> Result<(), ConversionError> {
+        input
+            .lines()
+            .try_for_each(|line| processor.process_line(line))
+    }
+
+    #[test]
+    fn test_blank_lines_are_skipped() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_lines(&mut processor, "\n   \n\t\n  [1]\n\n]").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"[1]\n");
+    }
+
+    #[test]
+    fn test_mismatched_bracket_is_an_error() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        let error = process_lines(&mut processor, "  [1],\n  {\"a\": [2}\n]").unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ConversionError::MismatchedBracket {
+                    expected: Some(']'),
+                    got: '}',
+                    offset: 16
+                }
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_closing_bracket_with_none_open_is_an_error() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        let error = processor.process_line("  ]").unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ConversionError::MismatchedBracket {
+                    expected: None,
+                    got: ']',
+                    offset: 2
+                }
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    /// Processes the lines of `input` inside a root array, recovering from
+    /// malformed elements, and returns the records written with the `_raw`
+    /// of each error record in place of the record.
+    fn recovered(input: &str) -> Vec<String> {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.set_recover(true);
+        processor.push_bracket(&'[').unwrap();
+        process_lines(&mut processor, input).unwrap();
+        processor.close_truncated_root().unwrap();
+        processor.check_root_closed().unwrap();
+        String::from_utf8(processor.into_sink().into_inner())
+            .unwrap()
+            .lines()
+            .map(
+                |line| match serde_json::from_str::<serde_json::Value>(line) {
+                    Ok(error) if error.get("_error").is_some() => {
+                        format!("error: {}", error["_raw"].as_str().unwrap())
+                    }
+                    _ => line.to_string(),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_resumes_at_the_next_element() {
+        assert_eq!(
+            recovered("  {\"a\": [1},\n  {\"b\": 2}\n]"),
+            ["error: {\"a\": [1}", "{\"b\": 2}"]
+        );
+        assert_eq!(
+            recovered("  {\"a\": 1], {\"b\": 2}\n]"),
+            ["error: {\"a\": 1]", "{\"b\": 2}"]
+        );
+        assert_eq!(
+            recovered("  {\"a\": {\"b\": [},\n    \"c\": 1},\n  2\n]"),
+            ["error: {\"a\": {\"b\": [},\"c\": 1}", "2"]
+        );
+    }
+
+    #[test]
+    fn test_recover_from_control_characters() {
+        assert_eq!(
+            recovered("  {\"a\": \"\u{1}\"},\n  1\n]"),
+            ["error: {\"a\": \"\u{1}\"}", "1"]
+        );
+    }
+
+    #[test]
+    fn test_recover_writes_the_element_cut_short() {
+        assert_eq!(
+            recovered("  1,\n  {\"a\": [\n    2,"),
+            ["1", "error: {\"a\": [2"]
+        );
+        assert_eq!(recovered("  1,\n"), ["1"]);
+    }
+
+    #[test]
+    fn test_root_left_open_is_an_error() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        process_lines(&mut processor, "  [1],\n  {\"a\":").unwrap();
+        assert!(matches!(
+            processor.check_root_closed(),
+            Err(ConversionError::UnexpectedEof)
+        ));
+        process_lines(&mut processor, " 2}\n]").unwrap();
+        processor.check_root_closed().unwrap();
+    }
+
+    #[test]
+    fn test_nesting_deeper_than_max_depth_is_an_error() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.set_max_depth(Some(2));
+        let input = "  {\"a\": [1, \"[{\"]},\n  {\"b\": [{}]}\n]";
+        let error = process_lines(&mut processor, input).unwrap_err();
+        assert!(matches!(
+            error,
+            ConversionError::TooDeep {
+                max_depth: 2,
+                offset: 28
+            }
+        ));
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": [1, \"[{\"]}\n"
+        );
+    }
+
+    #[test]
+    fn test_max_depth_of_usize_max_allows_any_depth() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.set_max_depth(Some(usize::MAX));
+        process_lines(&mut processor, "  {\"a\": [[[{}]]]}\n]").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"{\"a\": [[[{}]]]}\n");
+    }
+
+    #[test]
+    fn test_single_value_is_one_record() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.start_single_value();
+        process_lines(&mut processor, "{\n  \"a\": {\"b\": \"}\"}\n}\n").unwrap();
+        assert_eq!(processor.count(), 1);
+        assert_eq!(
+            processor.into_sink().into_inner(),
+            b"{\"a\": {\"b\": \"}\"}}\n"
+        );
+
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.start_single_value();
+        let result = process_lines(&mut processor, "{\"a\": 1}\n{\"b\": 2}\n");
+        assert!(matches!(
+            result,
+            Err(ConversionError::TrailingObjectContent('{'))
+        ));
+    }
+
+    #[test]
+    fn test_second_root_array_is_an_error() {
+        for (input, error) in [
+            ("  {\"a\": 1}\n]\n[\n  {\"b\": 2}\n]", "Concatenated"),
+            ("  {\"a\": 1}\n][{\"b\": 2}]", "Concatenated"),
+            ("  {\"a\": 1}\n]\n\n  x", "TrailingContent('x')"),
+        ] {
+            let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.push_bracket(&'[').unwrap();
+            let result = process_lines(&mut processor, input);
+            assert!(format!("{:?}", result).contains(error), "{}", input);
+            assert_eq!(processor.into_sink().into_inner(), b"{\"a\": 1}\n");
+        }
+    }
+
+    #[test]
+    fn test_content_after_root_object_is_an_error() {
+        for input in ["  \"a\": [1]\n}garbage", "  \"a\": [1]\n}\n\n[2]"] {
+            let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.set_concat(true);
+            processor.push_bracket(&'{').unwrap();
+            let result = process_lines(&mut processor, input);
+            assert!(
+                matches!(result, Err(ConversionError::TrailingObjectContent(_))),
+                "{}",
+                input
+            );
+            assert_eq!(processor.into_sink().into_inner(), b"\"a\": [1]\n");
+        }
+
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'{').unwrap();
+        process_lines(&mut processor, "  \"a\": [1]\n}\n  \n").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"\"a\": [1]\n");
+    }
+
+    #[test]
+    fn test_concat_converts_concatenated_arrays() {
+        for input in [
+            "  {\"a\": 1}\n]\n[\n  {\"b\": 2},\n  [3]\n]\n",
+            "  {\"a\": 1}\n][\n  {\"b\": 2},\n  [3]]\n",
+            "  {\"a\": 1}]  [{\"b\": 2},\n  [3]\n]",
+        ] {
+            let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+            processor.sink_mut().set_with_offset(true);
+            processor.set_concat(true);
+            processor.push_bracket(&'[').unwrap();
+            process_lines(&mut processor, input).unwrap();
+            let output = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+            let records: Vec<&str> = output
+                .lines()
+                .map(|l| l.split_once('\t').unwrap().1)
+                .collect();
+            assert_eq!(records, ["{\"a\": 1}", "{\"b\": 2}", "[3]"], "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_concat_converts_single_values_on_separate_lines() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.set_concat(true);
+        processor.start_single_value();
+        process_lines(
+            &mut processor,
+            "{\"a\": 1}\n\n  {\n    \"b\": 2\n  }\n[{\"c\": 3}]\n",
+        )
+        .unwrap();
+        processor.check_root_closed().unwrap();
+        assert_eq!(
+            String::from_utf8(processor.into_sink().into_inner()).unwrap(),
+            "0\t{\"a\": 1}\n10\t{\"b\": 2}\n25\t{\"c\": 3}\n"
+        );
+    }
+
+    #[test]
+    fn test_reset_discards_partial_record() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.process_line("  {").unwrap();
+
+        processor.reset();
+        assert!(processor.bracket_stack.is_empty());
+        assert!(processor.jsonl_string.is_empty());
+        processor.push_bracket(&'[').unwrap();
+        processor.process_line("  {\"b\": 2}").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"{\"b\": 2}\n");
+    }
+
+    #[test]
+    fn test_clone_mid_record_advances_independently() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.process_line("  {").unwrap();
+
+        let mut clone = processor.clone();
+        clone.process_line("    \"a\": 1").unwrap();
+        clone.process_line("  },").unwrap();
+        assert_eq!(clone.into_sink().into_inner(), b"{\"a\": 1}\n");
+
+        assert_eq!(processor.bracket_stack.stack, vec!['[', '{']);
+        processor.process_line("  }").unwrap();
+        assert_eq!(processor.into_sink().into_inner(), b"{}\n");
+    }
+
+    #[test]
+    fn test_debug_reflects_mid_record_state() {
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.push_bracket(&'[').unwrap();
+        processor.process_line("  {").unwrap();
+        processor.process_line("    \"a\": 1,").unwrap();
+        assert_eq!(
+            format!("{:?}", processor),
+            "LineProcessor { depth: 2, record_len: 8, count: 0 }"
+        );
+    }
+
+    #[test]
+    fn test_records_are_sent_to_any_sink() {
+        let mut records = Vec::new();
+        let mut processor = LineProcessor::with_sink(CallbackSink::new(|record: &str| {
+            records.push(record.to_string());
+            Ok(())
+        }));
+        processor.push_bracket(&'[').unwrap();
+        for line in ["  {", "    \"a\": 1", "  },", "  {\"b\": 2}", "]"] {
+            processor.process_line(line).unwrap();
+        }
+        assert_eq!(processor.count(), 2);
+        drop(processor);
+        assert_eq!(records, ["{\"a\": 1}", "{\"b\": 2}"]);
+    }
+
+    /// Collects the span of each record in the input.
+    #[derive(Default)]
+    struct SpanSink {
+        spans: Vec<(u64, u64)>,
+    }
+
+    impl EmitSink for SpanSink {
+        fn emit(&mut self, _: &str) -> Result<(), ConversionError> {
+            unreachable!("the processor sends the span of every record")
+        }
+
+        fn emit_record_span(
+            &mut self,
+            _: &dyn fmt::Display,
+            start: u64,
+            end: u64,
+        ) -> Result<(), ConversionError> {
+            self.spans.push((start, end));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records_are_sent_with_their_span() {
+        let input = "[\n  {\n    \"a\": 1\n  },\n  [2] ,\n  3]\n";
+        let mut processor = LineProcessor::with_sink(SpanSink::default());
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(1);
+        for line in input[1..].split_inclusive('\n') {
+            processor.process_line(line).unwrap();
+        }
+        let spans: Vec<&str> = processor
+            .into_sink()
+            .spans
+            .iter()
+            .map(|&(start, end)| &input[start as usize..end as usize])
+            .collect();
+        assert_eq!(spans, ["{\n    \"a\": 1\n  }", "[2]", "3"]);
+    }
+
+    #[test]
+    fn test_with_offset_prefixes_records_with_their_first_byte() {
+        let input = "[\n  {\n    \"a\": 1\n  },\n  {\"b\": 2}\n]\n";
+        let mut lines = input.split_inclusive('\n');
+
+        let mut processor = LineProcessor::with_sink(RecordWriter::new(Vec::new()));
+        processor.sink_mut().set_with_offset(true);
+        processor.push_bracket(&'[').unwrap();
+        processor.set_position(lines.next().unwrap().len() as u64);
+        for line in lines {
+            processor.process_line(line).unwrap();
+        }
+
+        let output = String::from_utf8(processor.into_sink().into_inner()).unwrap();
+        assert_eq!(output, "4\t{\"a\": 1}\n24\t{\"b\": 2}\n");
+        assert!(input[4..].starts_with('{'));
+        assert!(input[24..].starts_with("{\"b\": 2}"));
+    }
 }
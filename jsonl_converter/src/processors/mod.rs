@@ -1,4 +1,4 @@
 //! Contains modules for processing the JSON file contents.
 
 pub mod byte_processor;
-pub mod line_processor;
\ No newline at end of file
+pub mod line_processor;
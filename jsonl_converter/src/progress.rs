@@ -0,0 +1,147 @@
+//! This module contains the periodic progress messages of
+//! `--progress-records`, which count the records converted so far. Unlike a
+//! progress bar, they do not need to know how long the input is, so they
+//! work when reading from a pipe.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// How often the number of records converted so far is reported.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Counts the lines of JSONL written through it, reporting the count to
+/// `progress` whenever `interval` has passed since it was last reported.
+///
+/// The clock is only read when a line is completed, so no timer thread is
+/// needed, and nothing is reported while the conversion is stalled.
+///
+/// # Fields
+///
+/// * `inner` - The wrapped writer.
+/// * `progress` - Where the counts are reported, usually stderr.
+/// * `interval` - How long to wait between reports.
+/// * `started` - When the `ProgressWriter` was created.
+/// * `last_report` - When the count was last reported.
+/// * `records` - The number of complete lines written so far.
+pub struct ProgressWriter<W: Write, P: Write = io::Stderr> {
+    inner: W,
+    progress: P,
+    interval: Duration,
+    started: Instant,
+    last_report: Instant,
+    records: usize,
+}
+
+impl<W: Write, P: Write> ProgressWriter<W, P> {
+    /// Creates a new `ProgressWriter` around `inner` that reports to
+    /// `progress` every `interval`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{io::Write, time::Duration};
+    /// use jsonl_converter::progress::ProgressWriter;
+    ///
+    /// let mut progress = Vec::new();
+    /// let mut writer = ProgressWriter::new(Vec::new(), &mut progress, Duration::ZERO);
+    /// writer.write_all(b"{}\n[]\n").unwrap();
+    /// assert_eq!(writer.records(), 2);
+    /// ```
+    pub fn new(inner: W, progress: P, interval: Duration) -> Self {
+        let now = Instant::now();
+        ProgressWriter {
+            inner,
+            progress,
+            interval,
+            started: now,
+            last_report: now,
+            records: 0,
+        }
+    }
+
+    /// Returns the number of complete lines written so far.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Reports the final count along with how long the conversion took, and
+    /// returns the wrapped writer.
+    ///
+    /// # Errors
+    ///
+    /// * If the count cannot be reported.
+    pub fn finish(mut self) -> io::Result<W> {
+        writeln!(
+            self.progress,
+            "Processed {} records in {:.1}s.",
+            self.records,
+            self.started.elapsed().as_secs_f64()
+        )?;
+        self.progress.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, P: Write> Write for ProgressWriter<W, P> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let lines = buf[..written].iter().filter(|&&b| b == b'\n').count();
+        if lines > 0 {
+            self.records += lines;
+            let now = Instant::now();
+            if now.duration_since(self.last_report) >= self.interval {
+                self.last_report = now;
+                writeln!(self.progress, "Processed {} records.", self.records)?;
+                self.progress.flush()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_count_of_complete_lines() {
+        let mut progress = Vec::new();
+        let mut writer = ProgressWriter::new(Vec::new(), &mut progress, Duration::ZERO);
+        writer.write_all(b"{\"a\":1}\n{\"b\"").unwrap();
+        writer.write_all(b":2}\n").unwrap();
+        writer.write_all(b"[3]\n[4]\n").unwrap();
+        assert_eq!(
+            writer.finish().unwrap(),
+            b"{\"a\":1}\n{\"b\":2}\n[3]\n[4]\n"
+        );
+
+        let progress = String::from_utf8(progress).unwrap();
+        let lines: Vec<&str> = progress.lines().collect();
+        assert_eq!(
+            lines[..3],
+            [
+                "Processed 1 records.",
+                "Processed 2 records.",
+                "Processed 4 records."
+            ]
+        );
+        assert!(lines[3].starts_with("Processed 4 records in "));
+    }
+
+    #[test]
+    fn test_waits_for_the_interval_between_reports() {
+        let mut progress = Vec::new();
+        let mut writer = ProgressWriter::new(Vec::new(), &mut progress, Duration::from_secs(3600));
+        writer.write_all(b"{}\n{}\n").unwrap();
+        writer.finish().unwrap();
+        let progress = String::from_utf8(progress).unwrap();
+        assert_eq!(progress.lines().count(), 1);
+        assert!(progress.starts_with("Processed 2 records in "));
+    }
+}
@@ -0,0 +1,226 @@
+//! This module contains `RawJson`, a parsed record that keeps what parsing
+//! it into a `serde_json::Value` would lose: numbers are kept as they are
+//! written, rather than rounded to a 64-bit float, and the members of each
+//! object are kept in order, with a key that appears twice being an error
+//! rather than one of its values being dropped.
+
+use std::{collections::HashSet, fmt};
+
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::value::RawValue;
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawJson<'a> {
+    /// An object, with its members in the order they are written.
+    Object(Vec<(String, RawJson<'a>)>),
+    /// An array.
+    Array(Vec<RawJson<'a>>),
+    /// A string, with its escapes decoded.
+    String(String),
+    /// A number, `true`, `false` or `null`, exactly as it is written.
+    Literal(&'a str),
+}
+
+impl<'a> RawJson<'a> {
+    /// Parses `record` as a single JSON value.
+    ///
+    /// # Errors
+    ///
+    /// * If `record` is not valid JSON.
+    /// * If a key appears more than once in the same object, naming it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::raw::RawJson;
+    ///
+    /// assert_eq!(
+    ///     RawJson::parse(r#"{"b": 18446744073709551617, "a": 0.10}"#).unwrap(),
+    ///     RawJson::Object(vec![
+    ///         (String::from("b"), RawJson::Literal("18446744073709551617")),
+    ///         (String::from("a"), RawJson::Literal("0.10")),
+    ///     ])
+    /// );
+    /// assert!(RawJson::parse(r#"{"a": 1, "a": 2}"#).is_err());
+    /// ```
+    pub fn parse(record: &'a str) -> Result<Self, String> {
+        let raw: &RawValue = serde_json::from_str(record).map_err(|error| error.to_string())?;
+        RawJson::from_raw(raw)
+    }
+
+    /// Returns whether the value is an object or an array with at least one
+    /// member.
+    pub fn has_members(&self) -> bool {
+        match self {
+            RawJson::Object(members) => !members.is_empty(),
+            RawJson::Array(elements) => !elements.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Parses the value whose text is `raw`, which serde_json has already
+    /// checked to be valid JSON.
+    fn from_raw(raw: &'a RawValue) -> Result<Self, String> {
+        let text = raw.get();
+        if text.starts_with('{') {
+            let Members(members) = serde_json::from_str(text).map_err(|error| error.to_string())?;
+            let mut keys = HashSet::new();
+            let mut object = Vec::with_capacity(members.len());
+            for (key, value) in members {
+                if !keys.insert(key.clone()) {
+                    return Err(format!(
+                        "The key `{}` appears more than once in the same object.",
+                        key
+                    ));
+                }
+                object.push((key, RawJson::from_raw(value)?));
+            }
+            Ok(RawJson::Object(object))
+        } else if text.starts_with('[') {
+            let elements: Vec<&RawValue> =
+                serde_json::from_str(text).map_err(|error| error.to_string())?;
+            elements
+                .into_iter()
+                .map(RawJson::from_raw)
+                .collect::<Result<_, _>>()
+                .map(RawJson::Array)
+        } else if text.starts_with('"') {
+            serde_json::from_str(text)
+                .map(RawJson::String)
+                .map_err(|error| error.to_string())
+        } else {
+            Ok(RawJson::Literal(text))
+        }
+    }
+}
+
+impl fmt::Display for RawJson<'_> {
+    /// Writes the value as compact JSON, with the members of each object in
+    /// the order they are held and strings escaped as by serde_json.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawJson::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+            RawJson::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("]")
+            }
+            RawJson::String(string) => write_string(f, string),
+            RawJson::Literal(literal) => f.write_str(literal),
+        }
+    }
+}
+
+/// Writes `string` to `f` as a JSON string.
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    f.write_str(&serde_json::to_string(string).map_err(|_| fmt::Error)?)
+}
+
+/// The members of an object, in order and with any repeated keys, each with
+/// the text of its value.
+struct Members<'a>(Vec<(String, &'a RawValue)>);
+
+impl<'de> Deserialize<'de> for Members<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MembersVisitor)
+    }
+}
+
+/// Collects the members of an object into `Members`.
+struct MembersVisitor;
+
+impl<'de> Visitor<'de> for MembersVisitor {
+    type Value = Members<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut members = Vec::new();
+        while let Some(member) = map.next_entry()? {
+            members.push(member);
+        }
+        Ok(Members(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers_are_kept_as_written() {
+        assert_eq!(
+            RawJson::parse("[1.0, 1e400, -0, 123456789012345678901234567890.5]").unwrap(),
+            RawJson::Array(vec![
+                RawJson::Literal("1.0"),
+                RawJson::Literal("1e400"),
+                RawJson::Literal("-0"),
+                RawJson::Literal("123456789012345678901234567890.5"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_strings_and_keys_are_decoded() {
+        assert_eq!(
+            RawJson::parse(r#"{"é": "a\"b", "n": [true, null]}"#).unwrap(),
+            RawJson::Object(vec![
+                (String::from("é"), RawJson::String(String::from("a\"b"))),
+                (
+                    String::from("n"),
+                    RawJson::Array(vec![RawJson::Literal("true"), RawJson::Literal("null")])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_repeated_keys_are_rejected_at_any_depth() {
+        assert_eq!(
+            RawJson::parse(r#"[{"a": {"b": 1, "b": 2}}]"#).unwrap_err(),
+            "The key `b` appears more than once in the same object."
+        );
+        // Keys that are written differently but decode to the same key are
+        // repeated too.
+        assert!(RawJson::parse(r#"{"é": 1, "\u00e9": 2}"#).is_err());
+        // The same key in different objects is not.
+        assert!(RawJson::parse(r#"[{"a": 1}, {"a": {"a": 2}}]"#).is_ok());
+    }
+
+    #[test]
+    fn test_display_is_compact_and_keeps_order() {
+        let record = "{ \"b\" : [1.50, \"\\u00e9\\n\"], \"a\": {} }";
+        assert_eq!(
+            RawJson::parse(record).unwrap().to_string(),
+            "{\"b\":[1.50,\"é\\n\"],\"a\":{}}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_json_is_rejected() {
+        for record in ["{", "[1,]", "01", "{\"a\" 1}", "1 2"] {
+            assert!(RawJson::parse(record).is_err(), "{}", record);
+        }
+    }
+}
@@ -4,18 +4,35 @@
 
 use std::{
     fs::File,
-    io::{self, BufReader, Read},
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
 };
 
+use super::utils::{input_too_long, invalid_utf8, utf8_sequence_len};
 
-/// This struct is used to iterate over the bytes of a file.
+/// This struct is used to iterate over the characters of a file, reading as
+/// many bytes as each UTF-8 encoded character takes. Any other buffered
+/// reader can be read from with `from_reader`.
 ///
 ///
 /// # Fields
 ///
 /// * `reader` - A `BufReader` that reads the file.
-pub struct ByteIterator {
-    reader: BufReader<File>,
+/// * `start_offset` - The offset in the file that reading started from.
+/// * `bytes_read` - The number of bytes read so far.
+/// * `lines_read` - The number of lines started so far.
+/// * `at_line_start` - Whether the next byte read starts a line.
+/// * `strict_utf8` - Whether invalid UTF-8 is an error rather than being
+///   replaced with `U+FFFD`.
+/// * `max_bytes` - If set, the most bytes that are read before it is an
+///   error for the input to go on.
+pub struct ByteIterator<R: BufRead = BufReader<File>> {
+    reader: R,
+    start_offset: u64,
+    bytes_read: u64,
+    lines_read: u64,
+    at_line_start: bool,
+    strict_utf8: bool,
+    max_bytes: Option<u64>,
 }
 
 impl ByteIterator {
@@ -31,26 +48,258 @@ impl ByteIterator {
     /// * If the file cannot be opened.
     pub fn new(filename: &str) -> io::Result<Self> {
         let file = File::open(filename)?;
-        let reader = BufReader::new(file);
-        Ok(Self { reader })
+        Ok(ByteIterator::from_reader(BufReader::new(file)))
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl ByteIterator<Box<dyn BufRead>> {
+    /// Creates a new `ByteIterator` from a file that is decompressed as it
+    /// is read if it is compressed with gzip, whatever its extension, and
+    /// read as it is otherwise. Offsets count decompressed bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be opened or read.
+    pub fn new_gzip(filename: &str) -> io::Result<Self> {
+        let file = File::open(filename)?;
+        Ok(ByteIterator::from_reader(super::utils::decompressed(
+            BufReader::new(file),
+        )?))
+    }
+}
+
+impl<R: BufRead + Seek> ByteIterator<R> {
+    /// Moves the reader to `offset` bytes from the start of the file, so that
+    /// reading resumes from there.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset from the start of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be seeked.
+    pub fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.set_start_offset(offset);
+        Ok(())
+    }
+}
+
+impl<R: BufRead> ByteIterator<R> {
+    /// Creates a new `ByteIterator` that reads from `reader`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::readers::byte_iter::ByteIterator;
+    ///
+    /// let mut bytes_iter = ByteIterator::from_reader("[é]".as_bytes());
+    /// assert_eq!(bytes_iter.nth(1).unwrap().unwrap(), "é");
+    /// ```
+    pub fn from_reader(reader: R) -> Self {
+        ByteIterator {
+            reader,
+            start_offset: 0,
+            bytes_read: 0,
+            lines_read: 0,
+            at_line_start: true,
+            strict_utf8: false,
+            max_bytes: None,
+        }
+    }
+
+    /// Sets whether invalid UTF-8 is an error. Otherwise, each invalid
+    /// sequence is replaced with `U+FFFD`.
+    pub fn set_strict_utf8(&mut self, strict_utf8: bool) {
+        self.strict_utf8 = strict_utf8;
+    }
+
+    /// Sets the offset in the file that the reader is at, as when it has
+    /// already been moved there, so that offsets count from it. The counts
+    /// of bytes and lines read start again from zero.
+    pub fn set_start_offset(&mut self, offset: u64) {
+        self.start_offset = offset;
+        self.bytes_read = 0;
+        self.lines_read = 0;
+        self.at_line_start = true;
+    }
+
+    /// Sets the most bytes that are read, counted from where reading
+    /// started. Reading a character past them is an error, rather than the
+    /// end of the input. `None` reads the whole input.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::readers::byte_iter::ByteIterator;
+    ///
+    /// let mut bytes_iter = ByteIterator::from_reader("[1]\n".as_bytes());
+    /// bytes_iter.set_max_bytes(Some(3));
+    /// for _ in 0..3 {
+    ///     bytes_iter.read_char().unwrap().unwrap();
+    /// }
+    /// assert!(bytes_iter.read_char().unwrap().is_err());
+    /// ```
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Returns the number of bytes read from the file so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the number of lines read from the file so far, counting a
+    /// line from its first byte, so a final line without a line break is
+    /// counted too. As with `bytes_read`, lines are counted from where
+    /// reading started.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::readers::byte_iter::ByteIterator;
+    ///
+    /// let mut bytes_iter = ByteIterator::from_reader("[\n\n1]".as_bytes());
+    /// bytes_iter.read_char().unwrap().unwrap();
+    /// assert_eq!(bytes_iter.lines_read(), 1);
+    /// while bytes_iter.read_char().is_some() {}
+    /// assert_eq!(bytes_iter.lines_read(), 3);
+    /// ```
+    pub fn lines_read(&self) -> u64 {
+        self.lines_read
+    }
+
+    /// Returns the offset in the file of the next byte to be read.
+    pub fn offset(&self) -> u64 {
+        self.start_offset + self.bytes_read
+    }
+
+    /// Counts the first byte of a character, which is `byte`, as read.
+    fn count_byte(&mut self, byte: u8) {
+        self.bytes_read += 1;
+        if self.at_line_start {
+            self.lines_read += 1;
+        }
+        self.at_line_start = byte == b'\n';
     }
 
     /// Returns the next character of the file.
     pub fn next_char(&mut self) -> Option<char> {
-        self.next().unwrap().unwrap().chars().next()
+        self.read_char()?.ok()
+    }
+
+    /// Returns the next character of the file, the same as `next` but
+    /// without allocating a `String` for it.
+    ///
+    /// ASCII bytes, which make up most or all of most JSON files, are taken
+    /// straight from the buffer, as they are always a character of their own.
+    /// The UTF-8 aware decoding of `next` is only used from the first byte
+    /// that is not ASCII, and only for that character.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::readers::byte_iter::ByteIterator;
+    ///
+    /// let mut bytes_iter = ByteIterator::from_reader("aé".as_bytes());
+    /// assert_eq!(bytes_iter.read_char().unwrap().unwrap(), 'a');
+    /// assert_eq!(bytes_iter.read_char().unwrap().unwrap(), 'é');
+    /// assert!(bytes_iter.read_char().is_none());
+    /// ```
+    pub fn read_char(&mut self) -> Option<io::Result<char>> {
+        if let Some(stop) = self.stop_at_max_bytes() {
+            return stop;
+        }
+        match self.reader.fill_buf() {
+            Ok([byte, ..]) if byte.is_ascii() => {
+                let byte = *byte;
+                self.reader.consume(1);
+                self.count_byte(byte);
+                return Some(Ok(char::from(byte)));
+            }
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(error)),
+        }
+        self.next().map(|c| c.map(|c| c.chars().next().unwrap()))
+    }
+
+    /// Returns `None` if reading may go on. Otherwise, as many bytes as
+    /// allowed by `max_bytes` have been read, and this returns what reading
+    /// does instead: the end of the input, or an error if it goes on.
+    fn stop_at_max_bytes<T>(&mut self) -> Option<Option<io::Result<T>>> {
+        let max = self.max_bytes.filter(|&max| self.bytes_read >= max)?;
+        Some(match self.reader.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(Err(input_too_long(max))),
+            Err(error) => Some(Err(error)),
+        })
+    }
+
+    /// Returns the next character of the file that is not whitespace. This is
+    /// used to find the opening bracket of the root array when the file starts
+    /// with leading whitespace. Returns `None` if the file ends first.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be read, or is not valid UTF-8 when UTF-8 is
+    ///   validated strictly.
+    pub fn next_non_whitespace_char(&mut self) -> io::Result<Option<char>> {
+        while let Some(c) = self.read_char() {
+            let c = c?;
+            if !c.is_whitespace() {
+                return Ok(Some(c));
+            }
+        }
+        Ok(None)
     }
 }
 
-impl Iterator for ByteIterator {
+impl<R: BufRead> Iterator for ByteIterator<R> {
     type Item = io::Result<String>;
 
-    /// Returns the next byte of the file.
+    /// Returns the next character of the file.
     fn next(&mut self) -> Option<Self::Item> {
-        let mut buffer = [0; 1];
-        match self.reader.read_exact(&mut buffer) {
-            Ok(_) => Some(Ok(String::from_utf8_lossy(&buffer).into_owned())),
-            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
-            Err(error) => Some(Err(error)),
+        let offset = self.offset();
+        if let Some(stop) = self.stop_at_max_bytes() {
+            return stop;
+        }
+        let mut buffer = [0; 4];
+        match self.reader.read_exact(&mut buffer[..1]) {
+            Ok(_) => self.count_byte(buffer[0]),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(error) => return Some(Err(error)),
+        }
+
+        // Only continuation bytes are consumed, so that a truncated sequence
+        // does not swallow the start of the next character.
+        let mut len = 1;
+        while len < utf8_sequence_len(buffer[0]) {
+            let next = match self.reader.fill_buf() {
+                Ok(available) => available.first().copied(),
+                Err(error) => return Some(Err(error)),
+            };
+            match next {
+                Some(byte) if byte & 0xC0 == 0x80 => {
+                    buffer[len] = byte;
+                    self.reader.consume(1);
+                    self.bytes_read += 1;
+                    len += 1;
+                }
+                _ => break,
+            }
+        }
+
+        match std::str::from_utf8(&buffer[..len]) {
+            Ok(c) => Some(Ok(c.to_string())),
+            Err(_) if self.strict_utf8 => Some(Err(invalid_utf8(offset))),
+            Err(_) => Some(Ok(String::from_utf8_lossy(&buffer[..len]).into_owned())),
         }
     }
 }
@@ -65,6 +314,20 @@ mod tests {
         assert!(bytes_iter.is_ok());
     }
 
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_byte_iter_new_gzip_reads_compressed_and_plain_files() {
+        let plain = std::fs::read_to_string("tests/fixtures/pretty.json").unwrap();
+        for filename in [
+            "tests/fixtures/pretty.json.gz",
+            "tests/fixtures/pretty.json",
+        ] {
+            let bytes_iter = ByteIterator::new_gzip(filename).unwrap();
+            let chars: String = bytes_iter.map(Result::unwrap).collect();
+            assert_eq!(chars, plain);
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_byte_iter_new_instance_panics_on_invalid_filename() {
@@ -83,4 +346,118 @@ mod tests {
 
         assert_eq!(bytes, include_str!("mod.rs"));
     }
+
+    #[test]
+    fn test_byte_iter_bytes_read_counts_consumed_bytes() {
+        let mut bytes_iter = ByteIterator::new("tests/line_iter_testcase.txt").unwrap();
+        bytes_iter.next_char();
+        bytes_iter.next_char();
+        assert_eq!(bytes_iter.bytes_read(), 2);
+
+        bytes_iter.by_ref().for_each(drop);
+        assert_eq!(bytes_iter.bytes_read(), 49);
+    }
+
+    #[test]
+    fn test_byte_iter_lines_read_counts_started_lines() {
+        let mut bytes_iter = ByteIterator::new("tests/line_iter_testcase.txt").unwrap();
+        assert_eq!(bytes_iter.lines_read(), 0);
+        bytes_iter.next_char();
+        assert_eq!(bytes_iter.lines_read(), 1);
+
+        bytes_iter.by_ref().for_each(drop);
+        assert_eq!(bytes_iter.lines_read(), 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes_iter = byte_iter_over(&dir, "é\n\nü".as_bytes());
+        while bytes_iter.read_char().is_some() {}
+        assert_eq!((bytes_iter.bytes_read(), bytes_iter.lines_read()), (6, 3));
+    }
+
+    #[test]
+    fn test_byte_iter_next_non_whitespace_char_skips_leading_whitespace() {
+        let mut bytes_iter = ByteIterator::new("tests/byte_iter_leading_whitespace.txt").unwrap();
+        assert_eq!(bytes_iter.next_non_whitespace_char().unwrap(), Some('['));
+        assert_eq!(bytes_iter.next_char(), Some('{'));
+    }
+
+    #[test]
+    fn test_byte_iter_next_non_whitespace_char_returns_read_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes_iter = byte_iter_over(&dir, b" \xff[1]");
+        bytes_iter.set_strict_utf8(true);
+        assert_eq!(
+            bytes_iter.next_non_whitespace_char().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    fn byte_iter_over(dir: &tempfile::TempDir, contents: &[u8]) -> ByteIterator {
+        let path = dir.path().join("input.json");
+        std::fs::write(&path, contents).unwrap();
+        ByteIterator::new(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_byte_iter_reads_multibyte_characters_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes_iter = byte_iter_over(&dir, "aé€😀".as_bytes());
+        let chars: Vec<String> = bytes_iter.map(Result::unwrap).collect();
+        assert_eq!(chars, vec!["a", "é", "€", "😀"]);
+    }
+
+    #[test]
+    fn test_byte_iter_read_char_matches_next() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = b"[\"a\xC3\xA9\xE2\x82\xAC\xE2\x82b\xFF\", \xF0\x9F\x98\x80]";
+        let expected: Vec<String> = byte_iter_over(&dir, contents).map(Result::unwrap).collect();
+
+        let mut bytes_iter = byte_iter_over(&dir, contents);
+        let mut chars = Vec::new();
+        let mut offsets = Vec::new();
+        while let Some(c) = bytes_iter.read_char() {
+            chars.push(c.unwrap().to_string());
+            offsets.push(bytes_iter.offset());
+        }
+        assert_eq!(chars, expected);
+        assert_eq!(offsets[..4], [1, 2, 3, 5]);
+        assert_eq!(bytes_iter.bytes_read(), contents.len() as u64);
+
+        let mut bytes_iter = byte_iter_over(&dir, b"[\xE9]");
+        bytes_iter.set_strict_utf8(true);
+        assert_eq!(bytes_iter.read_char().unwrap().unwrap(), '[');
+        let error = bytes_iter.read_char().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte offset 1.");
+    }
+
+    #[test]
+    fn test_byte_iter_replaces_invalid_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes_iter = byte_iter_over(&dir, b"a\xE2\x82b\xFF");
+        let chars: Vec<String> = bytes_iter.by_ref().map(Result::unwrap).collect();
+        assert_eq!(chars, vec!["a", "\u{FFFD}", "b", "\u{FFFD}"]);
+        assert_eq!(bytes_iter.bytes_read(), 5);
+    }
+
+    #[test]
+    fn test_byte_iter_strict_utf8_errors_with_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes_iter = byte_iter_over(&dir, b"[\"\xC3\xA9\xE9\"]");
+        bytes_iter.set_strict_utf8(true);
+        assert_eq!(bytes_iter.next_char(), Some('['));
+        assert_eq!(bytes_iter.next_char(), Some('"'));
+        assert_eq!(bytes_iter.next_char(), Some('é'));
+        let error = bytes_iter.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte offset 4.");
+    }
+
+    #[test]
+    fn test_byte_iter_seek_to_resumes_from_offset() {
+        let mut bytes_iter = ByteIterator::new("tests/line_iter_testcase.txt").unwrap();
+        bytes_iter.seek_to(8).unwrap();
+        assert_eq!(bytes_iter.next_char(), Some('l'));
+        assert_eq!(bytes_iter.bytes_read(), 1);
+        assert_eq!(bytes_iter.offset(), 9);
+    }
 }
@@ -4,18 +4,198 @@
 
 use std::{
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
 };
 
-pub struct LineIterator {
-    reader: BufReader<File>,
+use super::utils::{input_too_long, invalid_utf8, line_too_long};
+
+/// The default maximum length of a line, including its line ending, when
+/// converting in line mode.
+pub const DEFAULT_MAX_LINE_BYTES: u64 = 64 * 1024 * 1024;
+
+pub struct LineIterator<R: BufRead = BufReader<File>> {
+    reader: R,
+    buffer: String,
+    start_offset: u64,
+    bytes_read: u64,
+    lines_read: u64,
+    strict_utf8: bool,
+    max_line_bytes: Option<u64>,
+    max_bytes: Option<u64>,
 }
 
 impl LineIterator {
     pub fn new(filename: &str) -> io::Result<Self> {
+        Ok(LineIterator::from_file(File::open(filename)?))
+    }
+
+    /// Creates a new `LineIterator` that reads from a file that is already
+    /// open, such as one from `file_from_fd`.
+    pub fn from_file(file: File) -> Self {
+        LineIterator::from_reader(BufReader::new(file))
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl LineIterator<Box<dyn BufRead>> {
+    /// Creates a new `LineIterator` from a file that is decompressed as it
+    /// is read if it is compressed with gzip, whatever its extension, and
+    /// read as it is otherwise. Offsets count decompressed bytes.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be opened or read.
+    pub fn new_gzip(filename: &str) -> io::Result<Self> {
         let file = File::open(filename)?;
-        let reader = BufReader::new(file);
-        Ok(Self { reader })
+        Ok(LineIterator::from_reader(super::utils::decompressed(
+            BufReader::new(file),
+        )?))
+    }
+}
+
+impl<R: BufRead> LineIterator<R> {
+    /// Creates a new `LineIterator` that reads through `reader`, such as a
+    /// file with a buffer of a particular size, or stdin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use jsonl_converter::readers::line_iter::LineIterator;
+    ///
+    /// let mut line_iter = LineIterator::from_reader(Cursor::new("[\n  1\n]"));
+    /// assert_eq!(line_iter.next_line_ref(), Some("[\n"));
+    /// ```
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: String::new(),
+            start_offset: 0,
+            bytes_read: 0,
+            lines_read: 0,
+            strict_utf8: false,
+            max_line_bytes: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Sets whether invalid UTF-8 is an error when reading lines with
+    /// `try_next_line_ref`. Otherwise, each invalid sequence is replaced with
+    /// `U+FFFD`.
+    pub fn set_strict_utf8(&mut self, strict_utf8: bool) {
+        self.strict_utf8 = strict_utf8;
+    }
+
+    /// Sets the offset in the file that the reader is at, as when it has
+    /// already been moved there, so that offsets count from it. The counts
+    /// of bytes and lines read start again from zero.
+    pub fn set_start_offset(&mut self, offset: u64) {
+        self.start_offset = offset;
+        self.bytes_read = 0;
+        self.lines_read = 0;
+    }
+
+    /// Sets the maximum length in bytes of a line read with
+    /// `try_next_line_ref`, including its line ending. A longer line is an
+    /// error rather than being read into memory whole. `None` allows lines of
+    /// any length.
+    pub fn set_max_line_bytes(&mut self, max_line_bytes: Option<u64>) {
+        self.max_line_bytes = max_line_bytes;
+    }
+
+    /// Sets the most bytes read with `try_next_line_ref`, counted from where
+    /// reading started. A line that goes past them is an error, rather than
+    /// the end of the input. `None` reads the whole input.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Returns the number of bytes read from the file so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the number of lines read from the file so far, including a
+    /// final line without a line break. As with `bytes_read`, lines are
+    /// counted from where reading started.
+    pub fn lines_read(&self) -> u64 {
+        self.lines_read
+    }
+
+    /// Returns the offset in the file of the next byte to be read.
+    pub fn offset(&self) -> u64 {
+        self.start_offset + self.bytes_read
+    }
+
+    /// Returns the next line of the file as a slice of a buffer owned by the
+    /// iterator. Unlike `next_line`, the buffer is reused between calls so no
+    /// allocation is made per line once the buffer has grown to fit the
+    /// longest line seen so far.
+    ///
+    /// The returned slice is only valid until the next call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::readers::line_iter::LineIterator;
+    ///
+    /// let mut line_iter = LineIterator::new("tests/line_iter_testcase.txt").unwrap();
+    /// while let Some(line) = line_iter.next_line_ref() {
+    ///     assert!(line.contains("This is line"));
+    /// }
+    /// ```
+    pub fn next_line_ref(&mut self) -> Option<&str> {
+        self.try_next_line_ref().ok().flatten()
+    }
+
+    /// Returns the next line of the file in the same way as `next_line_ref`,
+    /// but returns an error rather than `None` if the file cannot be read.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be read.
+    /// * If the line is not valid UTF-8 and UTF-8 is validated strictly.
+    /// * If the line is longer than the maximum set with
+    ///   `set_max_line_bytes`.
+    /// * If the line goes past the maximum set with `set_max_bytes`.
+    pub fn try_next_line_ref(&mut self) -> io::Result<Option<&str>> {
+        // The buffer's allocation is lent out to read the raw bytes into and
+        // taken back once they have been checked.
+        let mut bytes = std::mem::take(&mut self.buffer).into_bytes();
+        bytes.clear();
+        let line_start = self.offset();
+        // Reading one byte past the maximum is enough to tell that a line is
+        // too long, without reading the rest of it.
+        let limit = self.max_line_bytes.map_or(u64::MAX, |max| max + 1);
+        let limit = match self.max_bytes {
+            Some(max) => limit.min(max.saturating_sub(self.bytes_read) + 1),
+            None => limit,
+        };
+        let n = (&mut self.reader)
+            .take(limit)
+            .read_until(b'\n', &mut bytes)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.bytes_read += n as u64;
+        self.lines_read += 1;
+        if let Some(max) = self.max_line_bytes.filter(|&max| n as u64 > max) {
+            return Err(line_too_long(line_start, max));
+        }
+        if let Some(max) = self.max_bytes.filter(|&max| self.bytes_read > max) {
+            return Err(input_too_long(max));
+        }
+
+        self.buffer = match String::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(error) if self.strict_utf8 => {
+                let valid_up_to = error.utf8_error().valid_up_to() as u64;
+                return Err(invalid_utf8(line_start + valid_up_to));
+            }
+            Err(error) => String::from_utf8_lossy(error.as_bytes()).into_owned(),
+        };
+        Ok(Some(&self.buffer))
     }
 
     /// Returns the next line of the file.
@@ -26,6 +206,8 @@ impl LineIterator {
                 if s == 0 {
                     return None;
                 }
+                self.bytes_read += s as u64;
+                self.lines_read += 1;
                 Some(buffer)
             }
             Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
@@ -34,7 +216,25 @@ impl LineIterator {
     }
 }
 
-impl Iterator for LineIterator {
+impl<R: BufRead + Seek> LineIterator<R> {
+    /// Moves the reader to `offset` bytes from the start of the file, so that
+    /// reading resumes from there.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset from the start of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the file cannot be seeked.
+    pub fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.set_start_offset(offset);
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for LineIterator<R> {
     type Item = String;
 
     /// Returns the next line of the file.
@@ -46,6 +246,7 @@ impl Iterator for LineIterator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
 
     #[test]
     fn test_line_iter_new_instance_accepts_valid_filename() {
@@ -60,6 +261,32 @@ mod tests {
         assert!(line_iter.is_ok());
     }
 
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_line_iter_new_gzip_reads_compressed_files() {
+        let plain = std::fs::read_to_string("tests/fixtures/pretty.json").unwrap();
+        let mut line_iter = LineIterator::new_gzip("tests/fixtures/pretty.json.gz").unwrap();
+        let mut lines = String::new();
+        while let Some(line) = line_iter.try_next_line_ref().unwrap() {
+            lines.push_str(line);
+        }
+        assert_eq!(lines, plain);
+        assert_eq!(line_iter.bytes_read(), plain.len() as u64);
+    }
+
+    #[test]
+    fn test_line_iter_reads_lines_from_any_reader() {
+        let input = "[\n  {\"a\": 1},\r\n  [2]\n]";
+        let mut line_iter = LineIterator::from_reader(Cursor::new(input));
+        let mut lines = Vec::new();
+        while let Some(line) = line_iter.try_next_line_ref().unwrap() {
+            lines.push(line.to_string());
+        }
+        assert_eq!(lines, ["[\n", "  {\"a\": 1},\r\n", "  [2]\n", "]"]);
+        assert_eq!(line_iter.bytes_read(), input.len() as u64);
+        assert_eq!(line_iter.lines_read(), 4);
+    }
+
     #[test]
     fn test_line_iter_can_iterate_over_lines() {
         let fp = "tests/line_iter_testcase.txt";
@@ -76,4 +303,139 @@ mod tests {
             "This is line 1\n  This is line 2\nThis is line 3  \n"
         );
     }
+
+    #[test]
+    fn test_line_iter_next_line_ref_yields_each_line() {
+        let fp = "tests/line_iter_testcase.txt";
+        let mut line_iter = LineIterator::new(fp).unwrap();
+        let mut lines = String::new();
+
+        while let Some(line) = line_iter.next_line_ref() {
+            lines.push_str(line);
+        }
+
+        assert_eq!(
+            lines,
+            "This is line 1\n  This is line 2\nThis is line 3  \n"
+        );
+    }
+
+    #[test]
+    fn test_line_iter_next_line_ref_reuses_buffer() {
+        let fp = "tests/line_iter_testcase.txt";
+        let mut line_iter = LineIterator::new(fp).unwrap();
+
+        // The second line is the longest, so the buffer stops growing there.
+        line_iter.next_line_ref();
+        line_iter.next_line_ref();
+        let capacity = line_iter.buffer.capacity();
+        let ptr = line_iter.buffer.as_ptr();
+        line_iter.next_line_ref();
+
+        assert_eq!(line_iter.buffer.capacity(), capacity);
+        assert_eq!(line_iter.buffer.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_line_iter_bytes_read_counts_consumed_bytes() {
+        let fp = "tests/line_iter_testcase.txt";
+        let mut line_iter = LineIterator::new(fp).unwrap();
+
+        line_iter.next_line_ref();
+        assert_eq!(line_iter.bytes_read(), 15);
+        line_iter.next_line();
+        assert_eq!(line_iter.bytes_read(), 32);
+    }
+
+    #[test]
+    fn test_line_iter_lines_read_counts_each_line() {
+        let mut line_iter = LineIterator::new("tests/line_iter_testcase.txt").unwrap();
+        line_iter.next_line_ref();
+        line_iter.next_line();
+        assert_eq!(line_iter.lines_read(), 2);
+        while line_iter.next_line_ref().is_some() {}
+        assert_eq!((line_iter.bytes_read(), line_iter.lines_read()), (49, 3));
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut line_iter = line_iter_over(&dir, b"a\n\nb");
+        while line_iter.next_line_ref().is_some() {}
+        assert_eq!(line_iter.lines_read(), 3);
+    }
+
+    fn line_iter_over(dir: &tempfile::TempDir, contents: &[u8]) -> LineIterator {
+        let path = dir.path().join("input.json");
+        std::fs::write(&path, contents).unwrap();
+        LineIterator::new(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_line_iter_replaces_invalid_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut line_iter = line_iter_over(&dir, b"caf\xC3\xA9\ncaf\xE9\nend");
+        assert_eq!(line_iter.next_line_ref(), Some("café\n"));
+        assert_eq!(line_iter.next_line_ref(), Some("caf\u{FFFD}\n"));
+        assert_eq!(line_iter.next_line_ref(), Some("end"));
+        assert_eq!(line_iter.bytes_read(), 14);
+    }
+
+    #[test]
+    fn test_line_iter_strict_utf8_errors_with_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut line_iter = line_iter_over(&dir, b"caf\xC3\xA9\ncaf\xE9\n");
+        line_iter.set_strict_utf8(true);
+        assert_eq!(line_iter.try_next_line_ref().unwrap(), Some("café\n"));
+        let error = line_iter.try_next_line_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte offset 9.");
+    }
+
+    #[test]
+    fn test_line_iter_max_line_bytes_stops_at_long_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut contents = b"[\n".to_vec();
+        contents.extend(std::iter::repeat_n(b'1', 100));
+        let mut line_iter = line_iter_over(&dir, &contents);
+        line_iter.set_max_line_bytes(Some(100));
+        assert_eq!(line_iter.next_line_ref(), Some("[\n"));
+        assert_eq!(line_iter.try_next_line_ref().unwrap().unwrap().len(), 100);
+
+        contents.push(b'\n');
+        let mut line_iter = line_iter_over(&dir, &contents);
+        line_iter.set_max_line_bytes(Some(100));
+        line_iter.next_line_ref();
+        let error = line_iter.try_next_line_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error
+            .to_string()
+            .starts_with("The line at byte offset 2 is longer than 100 bytes. Use --messy"));
+        assert_eq!(line_iter.bytes_read(), 103);
+    }
+
+    #[test]
+    fn test_line_iter_max_bytes_stops_within_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut line_iter = line_iter_over(&dir, b"[\n[1],\n[2]\n]\n");
+        line_iter.set_max_bytes(Some(12));
+        assert_eq!(line_iter.next_line_ref(), Some("[\n"));
+        assert_eq!(line_iter.next_line_ref(), Some("[1],\n"));
+        assert_eq!(line_iter.next_line_ref(), Some("[2]\n"));
+        let error = line_iter.try_next_line_ref().unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Stopped reading after 12 bytes"));
+
+        let mut line_iter = line_iter_over(&dir, b"[\n[1]\n]\n");
+        line_iter.set_max_bytes(Some(8));
+        while line_iter.try_next_line_ref().unwrap().is_some() {}
+        assert_eq!(line_iter.bytes_read(), 8);
+    }
+
+    #[test]
+    fn test_line_iter_seek_to_resumes_from_offset() {
+        let fp = "tests/line_iter_testcase.txt";
+        let mut line_iter = LineIterator::new(fp).unwrap();
+        line_iter.seek_to(15).unwrap();
+        assert_eq!(line_iter.next_line_ref(), Some("  This is line 2\n"));
+        assert_eq!(line_iter.offset(), 32);
+    }
 }
@@ -1,5 +1,5 @@
 //! Contains modules for reading the JSON file contents.
 
-pub mod utils;
 pub mod byte_iter;
-pub mod line_iter;
\ No newline at end of file
+pub mod line_iter;
+pub mod utils;
@@ -1,29 +1,189 @@
-///! This module contains utilities for the `readers` module.
+//! This module contains utilities for the `readers` module.
 
-/// Verifies that the first character of the file is a '['.
+use std::io;
+
+use crate::errors::ConversionError;
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "gzip")]
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::{
+    fs::File,
+    mem::ManuallyDrop,
+    os::unix::io::{FromRawFd, RawFd},
+};
+
+/// Returns the error for an invalid UTF-8 sequence when UTF-8 is validated
+/// strictly.
+///
+/// # Arguments
+///
+/// * `offset` - The offset in the file of the first byte of the sequence.
+pub fn invalid_utf8(offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid UTF-8 at byte offset {}.", offset),
+    )
+}
+
+/// Returns the error for a line that is longer than the limit set on a
+/// `LineIterator`, suggesting `--messy` as the file is likely not split into
+/// lines at all.
+///
+/// # Arguments
+///
+/// * `offset` - The offset in the file of the start of the line.
+/// * `limit` - The maximum length of a line in bytes.
+pub fn line_too_long(offset: u64, limit: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "The line at byte offset {} is longer than {} bytes. Use --messy to convert files \
+             that are not split into lines, or --max-line-bytes to allow longer lines.",
+            offset, limit
+        ),
+    )
+}
+
+/// Returns the error for input that goes on past the cap set on a reader
+/// with `--head-bytes`.
+///
+/// # Arguments
+///
+/// * `limit` - The maximum number of bytes read from the input.
+pub fn input_too_long(limit: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Stopped reading after {} bytes, the limit set with --head-bytes, before the input \
+             ended.",
+            limit
+        ),
+    )
+}
+
+/// Returns a file that reads from the already open file descriptor `fd`, as
+/// given with `--fd`, such as a pipe passed down by a shell or supervisor.
+///
+/// The descriptor is duplicated rather than taken over, so that it is not
+/// closed when the file is dropped and can be opened again.
+///
+/// # Errors
+///
+/// * If `fd` is not an open file descriptor.
+#[cfg(unix)]
+pub fn file_from_fd(fd: RawFd) -> io::Result<File> {
+    // SAFETY: The file is never dropped, so `fd` is only borrowed to be
+    // duplicated, and an invalid `fd` makes the duplication fail.
+    let borrowed = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    borrowed.try_clone()
+}
+
+/// The first two bytes of a gzip member.
+#[cfg(feature = "gzip")]
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Checks if `reader` starts with `GZIP_MAGIC`, without consuming anything.
+/// Input is recognised as gzip by its first bytes rather than by the
+/// extension of the file, so renamed files are still read.
+///
+/// # Errors
+///
+/// * If the reader cannot be read.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::readers::utils::is_gzip;
+///
+/// assert!(is_gzip(&mut &[0x1f, 0x8b, 8][..]).unwrap());
+/// assert!(!is_gzip(&mut "[1]".as_bytes()).unwrap());
+/// ```
+#[cfg(feature = "gzip")]
+pub fn is_gzip<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(&GZIP_MAGIC))
+}
+
+/// Returns `reader` to be read decompressed if it starts with `GZIP_MAGIC`,
+/// or as it is otherwise. Each member of the input is decompressed in turn,
+/// as `gzip -d` does for concatenated files.
+///
+/// # Errors
+///
+/// * If the reader cannot be read.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Read;
+///
+/// use jsonl_converter::readers::utils::decompressed;
+///
+/// let mut plain = String::new();
+/// decompressed("[1]".as_bytes()).unwrap().read_to_string(&mut plain).unwrap();
+/// assert_eq!(plain, "[1]");
+/// ```
+#[cfg(feature = "gzip")]
+pub fn decompressed<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    if is_gzip(&mut reader)? {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Returns the number of bytes in the UTF-8 sequence that starts with
+/// `byte`. Bytes that cannot start a sequence are treated as a sequence of
+/// their own.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::readers::utils::utf8_sequence_len;
+///
+/// assert_eq!(utf8_sequence_len(b'a'), 1);
+/// assert_eq!(utf8_sequence_len("é".as_bytes()[0]), 2);
+/// assert_eq!(utf8_sequence_len("€".as_bytes()[0]), 3);
+/// assert_eq!(utf8_sequence_len(0xFF), 1);
+/// ```
+pub fn utf8_sequence_len(byte: u8) -> usize {
+    match byte {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 1,
+    }
+}
+
+/// Verifies that the first character of the file is a '[', or a '{' for a
+/// file that is a single object.
 ///
 /// # Arguments
 ///
 /// * `first_char` - The first character of the file.
 ///
-/// # Panics
+/// # Errors
 ///
-/// * If the first character of the file is not a '['.
+/// * If the first character of the file is neither a '[' nor a '{', as for
+///   a file that is a bare scalar.
 ///
 /// # Examples
 ///
 /// ```
 /// use jsonl_converter::readers::utils::verify_first_char;
 ///
-/// let first_char = '[';
-/// verify_first_char(&first_char);
+/// assert!(verify_first_char(&'[').is_ok());
+/// assert!(verify_first_char(&'{').is_ok());
+/// assert!(verify_first_char(&'4').is_err());
 /// ```
-pub fn verify_first_char(first_char: &char) {
-    if first_char != &'[' {
-        panic!(
-            "The first character of the file must be a '[', not a '{}'.",
-            first_char
-        );
+pub fn verify_first_char(first_char: &char) -> Result<(), ConversionError> {
+    match first_char {
+        '[' | '{' => Ok(()),
+        _ => Err(ConversionError::InvalidStart {
+            found: *first_char,
+            map_values: false,
+        }),
     }
 }
 
@@ -33,12 +193,59 @@ mod tests {
 
     #[test]
     fn test_verify_first_char_passes() {
-        verify_first_char(&'[');
+        assert!(verify_first_char(&'[').is_ok());
+        assert!(verify_first_char(&'{').is_ok());
+    }
+
+    #[test]
+    fn test_verify_first_char_rejects_invalid_first_char() {
+        assert!(matches!(
+            verify_first_char(&'a'),
+            Err(ConversionError::InvalidStart {
+                found: 'a',
+                map_values: false
+            })
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decompressed_reads_concatenated_members() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::{Read, Write};
+
+        let mut compressed = Vec::new();
+        for part in ["[1,", "2]"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        let mut plain = String::new();
+        decompressed(io::Cursor::new(compressed))
+            .unwrap()
+            .read_to_string(&mut plain)
+            .unwrap();
+        assert_eq!(plain, "[1,2]");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decompressed_corrupt_input_is_an_error() {
+        use std::io::Read;
+
+        let compressed = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff];
+        let mut plain = Vec::new();
+        assert!(decompressed(io::Cursor::new(compressed))
+            .unwrap()
+            .read_to_end(&mut plain)
+            .is_err());
     }
 
     #[test]
-    #[should_panic]
-    fn test_verify_first_char_panics_on_invalid_first_char() {
-        verify_first_char(&'a');
+    fn test_verify_first_char_rejects_scalar() {
+        assert!(matches!(
+            verify_first_char(&'4'),
+            Err(ConversionError::InvalidStart { found: '4', .. })
+        ));
     }
 }
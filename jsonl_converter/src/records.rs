@@ -0,0 +1,840 @@
+//! This module contains `jsonl_records`, the streaming library entry point,
+//! which converts a JSON array read from any `BufRead` into an iterator of
+//! JSONL records.
+//!
+//! As an ordinary iterator, it composes with the standard adapters:
+//!
+//! ```
+//! use jsonl_converter::records::{jsonl_records, ConverterOptions};
+//!
+//! let input = "[{\"id\": 1}, {\"id\": 2}, [3], {\"id\": 4}]".as_bytes();
+//! let ids: Vec<String> = jsonl_records(input, ConverterOptions::default())
+//!     .filter_map(Result::ok)
+//!     .filter(|record| record.text.starts_with('{'))
+//!     .map(|record| record.text)
+//!     .take(2)
+//!     .collect();
+//! assert_eq!(ids, ["{\"id\": 1}", "{\"id\": 2}"]);
+//! ```
+//!
+//! Records are read lazily: only as much of the input as is needed to
+//! complete the next record is read. The input may be formatted in any way,
+//! as with `--messy`.
+//!
+//! `convert_with_sink` instead pushes every record to an `EmitSink`, such as
+//! a `ChannelSink` or a sink of the caller's own, and returns the
+//! `ConversionStats` of the conversion once the input has been read.
+//!
+//! `convert` instead reads a file a line at a time, as without `--messy`,
+//! yielding the text of each record:
+//!
+//! ```no_run
+//! for record in jsonl_converter::records::convert("data.json")? {
+//!     println!("{}", record?);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `convert_to_map` collects the records into a `HashMap` keyed by the value
+//! of a top level field, for building lookups.
+//!
+//! A panic whilst converting, including one in an `EmitSink`, is caught by
+//! each of these entry points and returned as `ConversionError::Internal`,
+//! as it is by `pipeline::convert`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use crate::{
+    converter::Converter,
+    errors::{guarded, ConversionError},
+    keys::{top_level_value, unquote},
+    sink::EmitSink,
+};
+
+/// Options for how `jsonl_records` and the other entry points read their
+/// input and clean up records. See `converter::ConverterOptions`.
+pub use crate::converter::ConverterOptions;
+
+/// What `convert_to_map` does when two records have the same key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// Keeps the last record with the key, replacing those before it.
+    LastWins,
+    /// Stops with an error at the second record with the key, so that no
+    /// record is dropped without it being asked for.
+    #[default]
+    Error,
+}
+
+/// The key of a record in the map built by `convert_to_map`: the value of
+/// its key field. The JSON type of the value is kept, so that the string
+/// `"1"` and the number `1` are different keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RecordKey {
+    /// A string, without its quotes, as written in the record, escape
+    /// sequences and all.
+    String(String),
+    /// Any other value, as its JSON text as written in the record.
+    Other(String),
+}
+
+impl RecordKey {
+    /// Returns the key for the JSON text of a value, as written in a record.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::records::RecordKey;
+    ///
+    /// assert_eq!(RecordKey::from_json("\"1\""), RecordKey::String(String::from("1")));
+    /// assert_eq!(RecordKey::from_json("1"), RecordKey::Other(String::from("1")));
+    /// ```
+    pub fn from_json(value: &str) -> Self {
+        if value.starts_with('"') {
+            RecordKey::String(unquote(value).to_string())
+        } else {
+            RecordKey::Other(value.to_string())
+        }
+    }
+}
+
+impl fmt::Display for RecordKey {
+    /// Writes the key as the JSON text it was read from.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordKey::String(string) => write!(f, "\"{}\"", string),
+            RecordKey::Other(value) => f.write_str(value),
+        }
+    }
+}
+
+/// Options for how `convert_to_map` reads its input and collects records.
+///
+/// # Fields
+///
+/// * `converter` - How the input is read and records are cleaned up.
+/// * `duplicates` - What happens when two records have the same key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MapOptions {
+    pub converter: ConverterOptions,
+    pub duplicates: DuplicateKeyPolicy,
+}
+
+/// A JSONL record: an element of the root array.
+///
+/// # Fields
+///
+/// * `text` - The element, cleaned up and on a single line unless pretty
+///   printed.
+/// * `offset` - The offset in the input of the first byte of the element.
+/// * `end` - The offset in the input just after the last byte of the
+///   element, so that the element occupied the bytes from `offset` up to,
+///   but not including, `end`, as when building an index into the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonlRecord {
+    pub text: String,
+    pub offset: u64,
+    pub end: u64,
+}
+
+/// A summary of a conversion by `convert_with_sink`.
+///
+/// # Fields
+///
+/// * `records` - The number of records sent to the sink, not counting
+///   errors.
+/// * `bytes_in` - The number of bytes read from the input.
+/// * `bytes_out` - The total length in bytes of the records sent to the sink,
+///   not counting line endings or errors.
+/// * `errors` - The number of errors sent to the sink, one for each element
+///   that was skipped.
+/// * `skipped` - The number of elements that were malformed, and so were
+///   skipped rather than converted to records, with `recover`. Without it,
+///   a malformed element stops the conversion instead.
+/// * `max_depth` - The deepest nesting of objects and arrays within a record,
+///   where a record that is a flat object has a depth of 1.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionStats {
+    pub records: usize,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub errors: usize,
+    pub skipped: usize,
+    pub max_depth: usize,
+}
+
+impl fmt::Display for JsonlRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Returns an iterator over the elements of the JSON array read from
+/// `reader`, as JSONL records.
+///
+/// The iterator yields an error if the input is empty, does not start with a
+/// `[` or a `{`, cannot be read or has content after the root array, or if
+/// the conversion panics, and ends after yielding an error.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::records::{jsonl_records, ConverterOptions};
+///
+/// let mut records = jsonl_records("[\n  {\"a\": 1},\n  [2]\n]".as_bytes(), Default::default());
+/// let record = records.next().unwrap().unwrap();
+/// assert_eq!((record.text.as_str(), record.offset, record.end), ("{\"a\": 1}", 4, 12));
+/// assert_eq!(records.next().unwrap().unwrap().text, "[2]");
+/// assert!(records.next().is_none());
+/// ```
+pub fn jsonl_records<R: BufRead>(reader: R, options: ConverterOptions) -> JsonlRecords<R> {
+    JsonlRecords {
+        converter: Converter::new(reader, QueueSink::default(), options),
+    }
+}
+
+/// Opens the JSON file at `path` and returns an iterator over the elements
+/// of its root array, as JSONL records.
+///
+/// The file is read a line at a time, as without `--messy`, so it must be
+/// well formatted: each element may span many lines, but must end at the end
+/// of a line or where the next element on it starts. Lines are buffered
+/// until the element they belong to closes, and only as many are read as
+/// are needed to complete the next record.
+///
+/// The iterator yields an error if the file is empty, does not start with a
+/// `[` or a `{`, cannot be read, is malformed or has content after the root
+/// array, or if the conversion panics, and ends after yielding an error.
+///
+/// # Errors
+///
+/// * If the file cannot be opened.
+pub fn convert(path: &str) -> io::Result<LineRecords> {
+    let options = ConverterOptions {
+        lines: true,
+        ..ConverterOptions::default()
+    };
+    let reader = BufReader::new(File::open(path)?);
+    Ok(LineRecords {
+        converter: Converter::new(reader, QueueSink::default(), options),
+    })
+}
+
+/// Converts the JSON array read from `reader` to JSONL, sending each record
+/// to `sink` along with its offset in the input. The sink is finished once
+/// the input has been read.
+///
+/// # Returns
+///
+/// * A summary of the conversion.
+///
+/// # Errors
+///
+/// * If the input is empty, cannot be read or has content after the root
+///   array.
+/// * If the first character of the input that is not whitespace is not a
+///   `[` or a `{`, as a `ConversionError::InvalidStart`.
+/// * If the sink does not accept a record or cannot be finished. Records
+///   sent before the error are not taken back.
+/// * If the conversion panics, as a `ConversionError::Internal`.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::{
+///     records::{convert_with_sink, ConverterOptions},
+///     sink::CountingSink,
+/// };
+///
+/// let stats = convert_with_sink(
+///     "[{\"a\": 1}, [2]]".as_bytes(),
+///     CountingSink::new(),
+///     ConverterOptions::default(),
+/// )
+/// .unwrap();
+/// assert_eq!((stats.records, stats.bytes_in), (2, 15));
+/// ```
+pub fn convert_with_sink<R: BufRead, S: EmitSink>(
+    reader: R,
+    sink: S,
+    options: ConverterOptions,
+) -> Result<ConversionStats, ConversionError> {
+    let sink = StatsSink {
+        sink,
+        records: 0,
+        bytes_out: 0,
+        errors: 0,
+    };
+    let mut converter = Converter::new(reader, sink, options);
+    converter.run()?;
+    let bytes_in = converter.bytes_read();
+    let max_depth = converter.max_depth();
+    let sink = converter.into_sink();
+    let stats = ConversionStats {
+        records: sink.records,
+        bytes_in,
+        bytes_out: sink.bytes_out,
+        errors: sink.errors,
+        skipped: sink.errors,
+        max_depth,
+    };
+    guarded(|| sink.finish())?;
+    Ok(stats)
+}
+
+/// Converts the JSON array read from `reader` to JSONL, collecting the
+/// records into a map keyed by the value of their top level `key_field`.
+///
+/// Each key keeps the JSON type of its value, so the string `"1"` and the
+/// number `1` are different keys. See `RecordKey`. Keys and values are
+/// compared exactly as they are written in the record, escape sequences and
+/// all. By default, two records with the same key are an error; see
+/// `DuplicateKeyPolicy`.
+///
+/// # Errors
+///
+/// * If the input is empty, cannot be read or has content after the root
+///   array.
+/// * If a record is not an object with `key_field`, as a
+///   `ConversionError::MissingFields`.
+/// * If two records have the same key and `options.duplicates` is
+///   `DuplicateKeyPolicy::Error`.
+/// * If the first character of the input that is not whitespace is not a
+///   `[` or a `{`, as a `ConversionError::InvalidStart`.
+/// * If the conversion panics, as a `ConversionError::Internal`.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::records::{convert_to_map, MapOptions, RecordKey};
+///
+/// let input = "[{\"id\": \"a\", \"n\": 1}, {\"id\": 2}, {\"id\": \"2\", \"n\": 3}]";
+/// let map = convert_to_map(input.as_bytes(), "id", MapOptions::default()).unwrap();
+/// assert_eq!(map.len(), 3);
+/// assert_eq!(map[&RecordKey::String(String::from("a"))].text, "{\"id\": \"a\", \"n\": 1}");
+/// assert_eq!(map[&RecordKey::Other(String::from("2"))].offset, 22);
+/// ```
+pub fn convert_to_map<R: BufRead>(
+    reader: R,
+    key_field: &str,
+    options: MapOptions,
+) -> Result<HashMap<RecordKey, JsonlRecord>, ConversionError> {
+    guarded(|| collect_map(reader, key_field, options))
+}
+
+/// Collects the records as `convert_to_map` does, but lets panics unwind.
+fn collect_map<R: BufRead>(
+    reader: R,
+    key_field: &str,
+    options: MapOptions,
+) -> Result<HashMap<RecordKey, JsonlRecord>, ConversionError> {
+    let mut map = HashMap::new();
+    for (index, record) in jsonl_records(reader, options.converter).enumerate() {
+        let record = record?;
+        let Some(value) = top_level_value(&record.text, key_field) else {
+            return Err(ConversionError::MissingFields {
+                record: index + 1,
+                fields: vec![key_field.to_string()],
+            });
+        };
+        let value = RecordKey::from_json(value);
+        if options.duplicates == DuplicateKeyPolicy::Error && map.contains_key(&value) {
+            return Err(ConversionError::DuplicateKey {
+                record: index + 1,
+                key: key_field.to_string(),
+                value: value.to_string(),
+            });
+        }
+        map.insert(value, record);
+    }
+    Ok(map)
+}
+
+/// The iterator returned by `jsonl_records`.
+pub struct JsonlRecords<R: BufRead> {
+    converter: Converter<R, QueueSink>,
+}
+
+impl<R: BufRead> JsonlRecords<R> {
+    /// Returns the number of bytes of the input read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.converter.bytes_read()
+    }
+}
+
+impl<R: BufRead> Iterator for JsonlRecords<R> {
+    type Item = Result<JsonlRecord, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_record(&mut self.converter)
+    }
+}
+
+/// The iterator returned by `convert`.
+pub struct LineRecords {
+    converter: Converter<BufReader<File>, QueueSink>,
+}
+
+impl LineRecords {
+    /// Returns the number of bytes of the file read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.converter.bytes_read()
+    }
+}
+
+impl Iterator for LineRecords {
+    type Item = Result<String, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_record(&mut self.converter).map(|record| record.map(|record| record.text))
+    }
+}
+
+/// Steps the `converter` until it has queued a record, returning it, or has
+/// read all of the input. Once it has returned an error, the records queued
+/// before the error are still returned, and then `None`.
+fn next_record<R: BufRead>(
+    converter: &mut Converter<R, QueueSink>,
+) -> Option<Result<JsonlRecord, ConversionError>> {
+    loop {
+        if let Some(record) = converter.sink_mut().records.pop_front() {
+            return Some(Ok(record));
+        }
+        match converter.step() {
+            Ok(true) => {}
+            Ok(false) if converter.sink_mut().records.is_empty() => return None,
+            Ok(false) => {}
+            Err(error) => return Some(Err(error)),
+        }
+    }
+}
+
+/// Queues the records completed by the processor until the iterator yields
+/// them.
+#[derive(Default)]
+struct QueueSink {
+    records: VecDeque<JsonlRecord>,
+}
+
+impl EmitSink for QueueSink {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.emit_record_span(&record, 0, 0)
+    }
+
+    fn emit_record_span(
+        &mut self,
+        record: &dyn fmt::Display,
+        start: u64,
+        end: u64,
+    ) -> Result<(), ConversionError> {
+        self.records.push_back(JsonlRecord {
+            text: record.to_string(),
+            offset: start,
+            end,
+        });
+        Ok(())
+    }
+}
+
+/// Passes each record on to the `sink`, counting the records, their bytes
+/// and the errors for the `ConversionStats` of `convert_with_sink`.
+struct StatsSink<S: EmitSink> {
+    sink: S,
+    records: usize,
+    bytes_out: u64,
+    errors: usize,
+}
+
+impl<S: EmitSink> EmitSink for StatsSink<S> {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.sink.emit(record)?;
+        self.records += 1;
+        self.bytes_out += record.len() as u64;
+        Ok(())
+    }
+
+    fn emit_record_span(
+        &mut self,
+        record: &dyn fmt::Display,
+        start: u64,
+        end: u64,
+    ) -> Result<(), ConversionError> {
+        // Rendered once here, so that its length can be counted without the
+        // sink rendering it again.
+        let record = record.to_string();
+        self.sink.emit_record_span(&record, start, end)?;
+        self.records += 1;
+        self.bytes_out += record.len() as u64;
+        Ok(())
+    }
+
+    fn emit_error(
+        &mut self,
+        message: &str,
+        record: &dyn fmt::Display,
+        start: u64,
+    ) -> Result<(), ConversionError> {
+        self.sink.emit_error(message, record, start)?;
+        self.errors += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), ConversionError> {
+        self.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{json_object::CleanupPolicy, sink::CountingSink};
+
+    const INPUT: &str = "[\n  {\"a\": 1},\n  {\"b\": [2, 3]},\n  [4]\n]\n";
+
+    fn records(input: &str) -> JsonlRecords<&[u8]> {
+        jsonl_records(input.as_bytes(), ConverterOptions::default())
+    }
+
+    #[test]
+    fn test_records_have_text_and_offsets() {
+        let records: Vec<JsonlRecord> = records(INPUT).map(Result::unwrap).collect();
+        let texts: Vec<&str> = records.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts, ["{\"a\": 1}", "{\"b\": [2, 3]}", "[4]"]);
+        for record in records {
+            assert_eq!(
+                &INPUT[record.offset as usize..record.end as usize],
+                record.text
+            );
+        }
+    }
+
+    #[test]
+    fn test_records_span_the_raw_bytes_of_each_element() {
+        let input = "[\n  {\n    \"a\": \"]\"\n  } ,\n  {\"b\": [2, 3]}\n]\n";
+        let options = ConverterOptions {
+            cleanup_policy: CleanupPolicy::Compact,
+            ..ConverterOptions::default()
+        };
+        let spans: Vec<(String, &str)> = jsonl_records(input.as_bytes(), options)
+            .map(Result::unwrap)
+            .map(|r| (r.text, &input[r.offset as usize..r.end as usize]))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (String::from("{\"a\":\"]\"}"), "{\n    \"a\": \"]\"\n  }"),
+                (String::from("{\"b\":[2,3]}"), "{\"b\": [2, 3]}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_records_span_scalars_without_their_separators() {
+        let input = "[1 , \"a\"\n, {}, null]";
+        let spans: Vec<&str> = records(input)
+            .map(Result::unwrap)
+            .map(|r| &input[r.offset as usize..r.end as usize])
+            .collect();
+        assert_eq!(spans, ["1", "\"a\"", "{}", "null"]);
+    }
+
+    #[test]
+    fn test_records_chain_with_iterator_adapters() {
+        let keys: Vec<String> = records(INPUT)
+            .map(Result::unwrap)
+            .filter(|record| record.text.starts_with('{'))
+            .map(|record| record.text[2..3].to_string())
+            .collect();
+        assert_eq!(keys, ["a", "b"]);
+
+        let mut records = records(INPUT).skip(1).step_by(2);
+        assert_eq!(records.next().unwrap().unwrap().text, "{\"b\": [2, 3]}");
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_records_are_read_lazily() {
+        let mut first = records(INPUT).take(1);
+        assert!(first.next().is_some());
+        assert!(first.next().is_none());
+
+        let mut all = records(INPUT);
+        all.next().unwrap().unwrap();
+        assert_eq!(all.bytes_read(), 12);
+    }
+
+    #[test]
+    fn test_records_use_the_cleanup_policy() {
+        let options = ConverterOptions {
+            cleanup_policy: CleanupPolicy::Compact,
+            ..ConverterOptions::default()
+        };
+        let texts: Result<Vec<String>, _> = jsonl_records(INPUT.as_bytes(), options)
+            .map(|record| record.map(|record| record.text))
+            .collect();
+        assert_eq!(texts.unwrap(), ["{\"a\":1}", "{\"b\":[2,3]}", "[4]"]);
+    }
+
+    /// Records each call made to it, in order.
+    #[derive(Default)]
+    struct RecordingSink {
+        calls: Vec<String>,
+    }
+
+    impl EmitSink for &mut RecordingSink {
+        fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+            self.calls.push(format!("emit {}", record));
+            Ok(())
+        }
+
+        fn emit_record(
+            &mut self,
+            record: &dyn fmt::Display,
+            offset: u64,
+        ) -> Result<(), ConversionError> {
+            self.calls
+                .push(format!("emit_record {} {}", record, offset));
+            Ok(())
+        }
+
+        fn finish(self) -> Result<(), ConversionError> {
+            self.calls.push(String::from("finish"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_convert_with_sink_calls_the_sink_in_order() {
+        let mut sink = RecordingSink::default();
+        let stats = convert_with_sink(INPUT.as_bytes(), &mut sink, Default::default()).unwrap();
+        assert_eq!(
+            sink.calls,
+            [
+                "emit_record {\"a\": 1} 4",
+                "emit_record {\"b\": [2, 3]} 16",
+                "emit_record [4] 33",
+                "finish",
+            ]
+        );
+        assert_eq!(
+            stats,
+            ConversionStats {
+                records: 3,
+                bytes_in: INPUT.len() as u64,
+                bytes_out: 24,
+                errors: 0,
+                skipped: 0,
+                max_depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_convert_with_sink_counts_skipped_elements() {
+        let mut texts = Vec::new();
+        let sink = crate::sink::CallbackSink::new(|record: &str| {
+            texts.push(record.to_string());
+            Ok(())
+        });
+        let options = ConverterOptions {
+            recover: true,
+            ..ConverterOptions::default()
+        };
+        let input = "[{\"a\": [1}, {\"b\": 2}, [3, {], 4]";
+        let stats = convert_with_sink(input.as_bytes(), sink, options).unwrap();
+        assert_eq!(
+            stats,
+            ConversionStats {
+                records: 2,
+                bytes_in: input.len() as u64,
+                bytes_out: 9,
+                errors: 2,
+                skipped: 2,
+                max_depth: 2,
+            }
+        );
+        assert_eq!(texts.len(), 4);
+        assert_eq!(texts[1], "{\"b\": 2}");
+        assert!(texts[2].starts_with("{\"_error\":"));
+    }
+
+    #[test]
+    fn test_convert_with_sink_matches_the_iterator() {
+        let mut texts = Vec::new();
+        let sink = crate::sink::CallbackSink::new(|record: &str| {
+            texts.push(record.to_string());
+            Ok(())
+        });
+        convert_with_sink(INPUT.as_bytes(), sink, Default::default()).unwrap();
+        let expected: Vec<String> = records(INPUT).map(|r| r.unwrap().text).collect();
+        assert_eq!(texts, expected);
+    }
+
+    #[test]
+    fn test_convert_with_sink_does_not_finish_after_an_error() {
+        let mut sink = RecordingSink::default();
+        let error = convert_with_sink("[[1]] x".as_bytes(), &mut sink, Default::default());
+        assert!(matches!(error, Err(ConversionError::TrailingContent('x'))));
+        assert_eq!(sink.calls, ["emit_record [1] 1"]);
+
+        let mut sink = RecordingSink::default();
+        let error = convert_with_sink(" ".as_bytes(), &mut sink, Default::default());
+        assert!(matches!(error, Err(ConversionError::EmptyInput)));
+        assert!(sink.calls.is_empty());
+    }
+
+    #[test]
+    fn test_convert_with_sink_returns_panics_as_errors() {
+        let sink = crate::sink::CallbackSink::new(|_: &str| panic!("Bad sink."));
+        let error = convert_with_sink(INPUT.as_bytes(), sink, Default::default()).unwrap_err();
+        assert!(matches!(
+            &error,
+            ConversionError::Internal(message) if message == "Bad sink."
+        ));
+
+        let sink = crate::sink::CallbackSink::new(|_: &str| panic!("Bad sink."));
+        let mut converter = Converter::new(INPUT.as_bytes(), sink, Default::default());
+        assert!(converter.run().is_err());
+        assert!(!converter.step().unwrap());
+    }
+
+    fn write_file(dir: &std::path::Path, contents: &str) -> String {
+        let path = dir.join("data.json");
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_convert_yields_records_of_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), &format!("\n{}", INPUT));
+        let records: Vec<String> = convert(&path).unwrap().map(Result::unwrap).collect();
+        assert_eq!(records, ["{\"a\": 1}", "{\"b\": [2, 3]}", "[4]"]);
+
+        let path = write_file(dir.path(), "[{\"a\": 1},\n  {\"b\":\n 2}]");
+        let records: Vec<String> = convert(&path).unwrap().map(Result::unwrap).collect();
+        assert_eq!(records, ["{\"a\": 1}", "{\"b\":2}"]);
+    }
+
+    #[test]
+    fn test_convert_is_lazy_and_ends_after_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "[\n  [1],\n  {\"b\": 2]\n]\n");
+        let mut records = convert(&path).unwrap();
+        assert_eq!(records.next().unwrap().unwrap(), "[1]");
+        assert!(records.bytes_read() < 15);
+        assert!(matches!(
+            records.next(),
+            Some(Err(ConversionError::MismatchedBracket { got: ']', .. }))
+        ));
+        assert!(records.next().is_none());
+
+        let path = write_file(dir.path(), "[\n  [1],\n  {\"b\":\n");
+        let results: Vec<_> = convert(&path).unwrap().collect();
+        assert!(matches!(
+            results[..],
+            [Ok(_), Err(ConversionError::UnexpectedEof)]
+        ));
+
+        let path = write_file(dir.path(), " \n\n");
+        assert!(matches!(
+            convert(&path).unwrap().next(),
+            Some(Err(ConversionError::EmptyInput))
+        ));
+        assert!(convert(&dir.path().join("missing.json").to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn test_convert_to_map_keys_records_by_a_field() {
+        let input = "[{\"id\": \"a\"}, {\"n\": 1, \"id\": 2}, {\"id\": {\"x\": 1}}]";
+        let map = convert_to_map(input.as_bytes(), "id", MapOptions::default()).unwrap();
+        let mut keys: Vec<String> = map.keys().map(RecordKey::to_string).collect();
+        keys.sort();
+        assert_eq!(keys, ["\"a\"", "2", "{\"x\": 1}"]);
+        let two = RecordKey::Other(String::from("2"));
+        assert_eq!(map[&two].text, "{\"n\": 1, \"id\": 2}");
+
+        let error = convert_to_map(input.as_bytes(), "n", MapOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            ConversionError::MissingFields { record: 1, fields } if fields == ["n"]
+        ));
+    }
+
+    #[test]
+    fn test_convert_to_map_duplicate_key_policy() {
+        let input = "[{\"id\": \"1\", \"v\": \"x\"}, {\"id\": 2}, {\"id\": \"1\", \"v\": \"y\"}]";
+        let error = convert_to_map(input.as_bytes(), "id", MapOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 3 has the same `id` as an earlier record: \"1\"."
+        );
+
+        let options = MapOptions {
+            duplicates: DuplicateKeyPolicy::LastWins,
+            ..MapOptions::default()
+        };
+        let map = convert_to_map(input.as_bytes(), "id", options).unwrap();
+        assert_eq!(map.len(), 2);
+        let one = RecordKey::String(String::from("1"));
+        assert_eq!(map[&one].text, "{\"id\": \"1\", \"v\": \"y\"}");
+    }
+
+    #[test]
+    fn test_convert_to_map_keeps_the_type_of_keys() {
+        let input = "[{\"id\": 1}, {\"id\": \"1\"}, {\"id\": true}, {\"id\": \"true\"}]";
+        let map = convert_to_map(input.as_bytes(), "id", MapOptions::default()).unwrap();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map[&RecordKey::Other(String::from("1"))].offset, 1);
+        assert_eq!(map[&RecordKey::String(String::from("1"))].offset, 12);
+    }
+
+    #[test]
+    fn test_errors_end_the_iterator() {
+        let mut empty = records(" \n");
+        assert!(matches!(
+            empty.next(),
+            Some(Err(ConversionError::EmptyInput))
+        ));
+        assert!(empty.next().is_none());
+
+        let results: Vec<_> = records("[{\"a\": 1}] x [2]").collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(ConversionError::TrailingContent('x'))
+        ));
+
+        let options = ConverterOptions {
+            strict_utf8: true,
+            ..ConverterOptions::default()
+        };
+        let mut invalid = jsonl_records(&b"[[1], [\xff]]"[..], options);
+        assert_eq!(invalid.next().unwrap().unwrap().text, "[1]");
+        assert!(invalid.next().unwrap().is_err());
+        assert!(invalid.next().is_none());
+    }
+
+    #[test]
+    fn test_records_use_the_same_options_as_the_cli() {
+        let input = "cb([\n  {\"a\": 1},\n  [2]\n]);\n";
+        for lines in [false, true] {
+            let options = ConverterOptions {
+                lines,
+                strip_jsonp: true,
+                ..ConverterOptions::default()
+            };
+            let texts: Vec<String> = jsonl_records(input.as_bytes(), options)
+                .map(|record| record.unwrap().text)
+                .collect();
+            assert_eq!(texts, ["{\"a\": 1}", "[2]"]);
+            let stats = convert_with_sink(input.as_bytes(), CountingSink::new(), options);
+            assert_eq!(stats.unwrap().records, 2);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! This module contains the best-effort repairs of `--repair`, which salvage
+//! records from JSON that was written by hand or by a lax producer.
+//!
+//! # Repairs
+//!
+//! * Trailing commas before a closing bracket are removed, so `[1, 2,]`
+//!   becomes `[1, 2]`.
+//! * Missing commas between the elements of an array or the members of an
+//!   object are added, so `{"a": 1 "b": 2}` becomes `{"a": 1, "b": 2}`.
+//! * Single-quoted keys and strings are double-quoted, so `{'a': 'say "hi"'}`
+//!   becomes `{"a": "say \"hi\""}`. Apostrophes inside double-quoted strings
+//!   are left alone.
+//! * Object keys that are unquoted identifiers, as JSON5 allows, are quoted,
+//!   so `{id: 1, $ref: 2}` becomes `{"id": 1, "$ref": 2}`.
+//!
+//! `--repair` also replaces `NaN`, `Infinity` and `-Infinity` with `null`, as
+//! with `--replace-nan-inf`.
+//!
+//! Repairs are made by scanning the record rather than parsing it, so they
+//! cannot fix every defect, and may guess wrongly where the intent is not
+//! clear. Combining `--repair` with `--strict` checks that each repaired
+//! record is valid JSON.
+
+use std::borrow::Cow;
+
+/// The repairs made to each record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Repairs {
+    pub trailing_commas: bool,
+    pub missing_commas: bool,
+    pub single_quotes: bool,
+    pub unquoted_keys: bool,
+}
+
+/// What an open container expects next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// The key of an object's member.
+    Key,
+    /// The colon after a key.
+    Colon,
+    /// A value, after a colon or as an element of an array.
+    Value,
+    /// A comma or the closing bracket, after a value.
+    CommaOrEnd,
+}
+
+/// An open object or array.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    object: bool,
+    expect: Expect,
+}
+
+impl Frame {
+    /// Returns what the container expects after a comma.
+    fn after_comma(&self) -> Expect {
+        if self.object {
+            Expect::Key
+        } else {
+            Expect::Value
+        }
+    }
+}
+
+impl Repairs {
+    /// Every repair.
+    pub const ALL: Repairs = Repairs {
+        trailing_commas: true,
+        missing_commas: true,
+        single_quotes: true,
+        unquoted_keys: true,
+    };
+
+    /// Returns whether no repairs are made.
+    pub fn is_empty(&self) -> bool {
+        *self == Repairs::default()
+    }
+
+    /// Returns `record` with the repairs made, or borrowed as it is if none
+    /// were needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::repair::Repairs;
+    ///
+    /// assert_eq!(
+    ///     Repairs::ALL.repair("{\"a\": [1 2,], \"b\": \"c,]\" \"d\": 3,}"),
+    ///     "{\"a\": [1, 2], \"b\": \"c,]\", \"d\": 3}"
+    /// );
+    /// ```
+    pub fn repair<'a>(&self, record: &'a str) -> Cow<'a, str> {
+        if self.is_empty() {
+            return Cow::Borrowed(record);
+        }
+        let mut repaired = String::with_capacity(record.len() + 8);
+        let mut changed = false;
+        let mut stack: Vec<Frame> = Vec::new();
+        // Where in `repaired` the last comma was written, while nothing but
+        // whitespace has followed it.
+        let mut last_comma: Option<usize> = None;
+        // Where in `repaired` the last token ended, before any whitespace.
+        let mut token_end = 0;
+
+        let mut rest = record;
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                repaired.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            let single_quoted = c == '\'' && self.single_quotes;
+            let token_len = match c {
+                '"' => string_len(rest, '"'),
+                _ if single_quoted => string_len(rest, '\''),
+                '{' | '}' | '[' | ']' | ':' | ',' => 1,
+                _ => rest
+                    .find(|c: char| {
+                        c.is_whitespace()
+                            || "{}[]:,\"".contains(c)
+                            || (c == '\'' && self.single_quotes)
+                    })
+                    .unwrap_or(rest.len()),
+            };
+            let (token, after) = rest.split_at(token_len);
+            rest = after;
+
+            let is_closing = c == '}' || c == ']';
+            if is_closing && self.trailing_commas {
+                if let Some(comma) = last_comma {
+                    repaired.remove(comma);
+                    changed = true;
+                }
+            }
+            last_comma = None;
+
+            let starts_value = !is_closing && c != ':' && c != ',';
+            let mut is_key = false;
+            if let Some(frame) = stack.last_mut() {
+                if starts_value && frame.expect == Expect::CommaOrEnd && self.missing_commas {
+                    repaired.insert(token_end, ',');
+                    if token_end + 1 == repaired.len() {
+                        repaired.push(' ');
+                    }
+                    changed = true;
+                    frame.expect = frame.after_comma();
+                }
+                is_key = starts_value && frame.expect == Expect::Key;
+                frame.expect = match c {
+                    ':' => Expect::Value,
+                    ',' => frame.after_comma(),
+                    _ if is_closing => frame.expect,
+                    _ if frame.expect == Expect::Key => Expect::Colon,
+                    _ => Expect::CommaOrEnd,
+                };
+            }
+            match c {
+                '{' | '[' => stack.push(Frame {
+                    object: c == '{',
+                    expect: if c == '{' { Expect::Key } else { Expect::Value },
+                }),
+                '}' | ']' => {
+                    stack.pop();
+                }
+                ',' => last_comma = Some(repaired.len()),
+                _ => {}
+            }
+            if single_quoted {
+                double_quote(token, &mut repaired);
+                changed = true;
+            } else if is_key && self.unquoted_keys && is_identifier(token) {
+                repaired.push('"');
+                repaired.push_str(token);
+                repaired.push('"');
+                changed = true;
+            } else {
+                repaired.push_str(token);
+            }
+            token_end = repaired.len();
+        }
+
+        if changed {
+            Cow::Owned(repaired)
+        } else {
+            Cow::Borrowed(record)
+        }
+    }
+}
+
+/// Returns the length of the string quoted with `quote` at the start of `s`,
+/// including its quotes, or the rest of `s` if the string is not closed.
+fn string_len(s: &str, quote: char) -> usize {
+    let mut last_char_escape = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if c == quote && !last_char_escape {
+            return i + 1;
+        }
+        last_char_escape = c == '\\' && !last_char_escape;
+    }
+    s.len()
+}
+
+/// Checks if `token` is an identifier that JSON5 allows as an unquoted key:
+/// a letter, `$` or `_`, followed by any number of those or digits.
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '$' || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '$' || c == '_')
+}
+
+/// Pushes the single-quoted `string` onto `repaired` in double quotes,
+/// escaping the double quotes inside it and unescaping its single quotes.
+fn double_quote(string: &str, repaired: &mut String) {
+    let inner = &string[1..];
+    let inner = inner.strip_suffix('\'').unwrap_or(inner);
+    repaired.push('"');
+    let mut last_char_escape = false;
+    for c in inner.chars() {
+        match c {
+            '\'' if last_char_escape => {
+                repaired.pop();
+                repaired.push(c);
+            }
+            '"' if !last_char_escape => repaired.push_str("\\\""),
+            _ => repaired.push(c),
+        }
+        last_char_escape = c == '\\' && !last_char_escape;
+    }
+    repaired.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailing_commas_are_removed() {
+        let repairs = Repairs {
+            trailing_commas: true,
+            ..Repairs::default()
+        };
+        let cases = [
+            ("[1, 2,]", "[1, 2]"),
+            (
+                "{\"a\": [1,\n], \"b\": {},\n}",
+                "{\"a\": [1\n], \"b\": {}\n}",
+            ),
+            ("[[],[{},],]", "[[],[{}]]"),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(repairs.repair(record), expected, "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_missing_commas_are_added() {
+        let repairs = Repairs {
+            missing_commas: true,
+            ..Repairs::default()
+        };
+        let cases = [
+            ("[1 2 \"a\"]", "[1, 2, \"a\"]"),
+            (
+                "{\"a\": 1 \"b\": [true false]}",
+                "{\"a\": 1, \"b\": [true, false]}",
+            ),
+            ("[{}{} [] null]", "[{}, {}, [], null]"),
+            (
+                "{\"a\": {\"b\": 1}\"c\": 2}",
+                "{\"a\": {\"b\": 1}, \"c\": 2}",
+            ),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(repairs.repair(record), expected, "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_valid_records_and_strings_are_untouched() {
+        for record in [
+            "{\"a\": [1, 2], \"b\": \"x,] y\\\" [1 2,]\"}",
+            "{\"a b\" : -1.5e3, \"c\":{}}",
+            "[]",
+            "\"1 2,]\"",
+            "12",
+        ] {
+            assert!(
+                matches!(Repairs::ALL.repair(record), Cow::Borrowed(_)),
+                "{}",
+                record
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_quotes_are_double_quoted() {
+        let repairs = Repairs {
+            single_quotes: true,
+            ..Repairs::default()
+        };
+        let cases = [
+            ("{'a': 1}", "{\"a\": 1}"),
+            (
+                "{\"a\": 'b', \"c\": ['d', 'e']}",
+                "{\"a\": \"b\", \"c\": [\"d\", \"e\"]}",
+            ),
+            (
+                "{'say': 'he said \"hi\"'}",
+                "{\"say\": \"he said \\\"hi\\\"\"}",
+            ),
+            (
+                "['it\\'s', 'a\\\\', 'x\\ny']",
+                "[\"it's\", \"a\\\\\", \"x\\ny\"]",
+            ),
+            (
+                "{'a': \"b's\", \"c\": 'd'}",
+                "{\"a\": \"b's\", \"c\": \"d\"}",
+            ),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(repairs.repair(record), expected, "{}", record);
+        }
+        assert!(matches!(
+            repairs.repair("{\"it's\": \"don't\", \"a\": \"'b'\"}"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_unquoted_keys_are_quoted() {
+        let repairs = Repairs {
+            unquoted_keys: true,
+            ..Repairs::default()
+        };
+        let cases = [
+            ("{id: 1}", "{\"id\": 1}"),
+            (
+                "{$ref: \"a\", _b2: {c:true}, \"d\": e}",
+                "{\"$ref\": \"a\", \"_b2\": {\"c\":true}, \"d\": e}",
+            ),
+            ("[a, {null: null}]", "[a, {\"null\": null}]"),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(repairs.repair(record), expected, "{}", record);
+        }
+        // Numbers and other tokens that are not identifiers are left for
+        // `--strict` to reject.
+        assert!(matches!(repairs.repair("{1a: 1, -b: 2}"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_keys_are_not_separated_from_their_values() {
+        // A missing colon is not repaired, and must not be mistaken for a
+        // missing comma.
+        assert_eq!(Repairs::ALL.repair("{\"a\" 1}"), "{\"a\" 1}");
+    }
+}
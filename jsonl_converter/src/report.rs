@@ -0,0 +1,134 @@
+//! This module contains the summary of a conversion, which can be reported
+//! once the conversion has finished with `--report-json`, or inspected by
+//! code that calls `convert` directly.
+
+/// A summary of a conversion.
+///
+/// # Fields
+///
+/// * `records` - The number of JSONL records written.
+/// * `bytes_in` - The number of bytes read from the input.
+/// * `bytes_out` - The number of bytes written to the output.
+/// * `errors` - The number of errors that occurred.
+/// * `skipped` - The number of input files skipped because of
+///   `--continue-on-error`.
+/// * `max_depth` - The deepest nesting of objects and arrays within a record,
+///   where a record that is a flat object has a depth of 1.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub records: usize,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub errors: usize,
+    pub skipped: usize,
+    pub max_depth: usize,
+}
+
+impl Report {
+    /// Adds the totals of `other` to this report, keeping the greater of the
+    /// two maximum depths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::report::Report;
+    ///
+    /// let mut report = Report {
+    ///     records: 2,
+    ///     ..Report::default()
+    /// };
+    /// report.add(&Report {
+    ///     records: 3,
+    ///     errors: 1,
+    ///     ..Report::default()
+    /// });
+    /// assert_eq!(report.records, 5);
+    /// assert_eq!(report.errors, 1);
+    /// ```
+    pub fn add(&mut self, other: &Report) {
+        self.records += other.records;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.errors += other.errors;
+        self.skipped += other.skipped;
+        self.max_depth = self.max_depth.max(other.max_depth);
+    }
+
+    /// Returns the report as a single line JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::report::Report;
+    ///
+    /// let report = Report {
+    ///     records: 2,
+    ///     bytes_in: 30,
+    ///     bytes_out: 20,
+    ///     max_depth: 1,
+    ///     ..Report::default()
+    /// };
+    /// assert_eq!(
+    ///     report.to_json(),
+    ///     r#"{"records":2,"bytes_in":30,"bytes_out":20,"errors":0,"skipped":0,"max_depth":1}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"records\":{},\"bytes_in\":{},\"bytes_out\":{},\"errors\":{},\"skipped\":{},\"max_depth\":{}}}",
+            self.records, self.bytes_in, self.bytes_out, self.errors, self.skipped, self.max_depth
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_is_parseable_and_has_all_fields() {
+        let report = Report {
+            records: 3,
+            bytes_in: 120,
+            bytes_out: 90,
+            errors: 1,
+            skipped: 1,
+            max_depth: 4,
+        };
+        let value: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(value["records"], 3);
+        assert_eq!(value["bytes_in"], 120);
+        assert_eq!(value["bytes_out"], 90);
+        assert_eq!(value["errors"], 1);
+        assert_eq!(value["skipped"], 1);
+        assert_eq!(value["max_depth"], 4);
+        assert_eq!(value.as_object().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_default_report_is_empty() {
+        assert_eq!(
+            Report::default().to_json(),
+            "{\"records\":0,\"bytes_in\":0,\"bytes_out\":0,\"errors\":0,\"skipped\":0,\"max_depth\":0}"
+        );
+    }
+
+    #[test]
+    fn test_add_sums_counts_and_keeps_max_depth() {
+        let mut report = Report {
+            records: 1,
+            skipped: 1,
+            max_depth: 3,
+            ..Report::default()
+        };
+        report.add(&Report {
+            records: 2,
+            skipped: 1,
+            max_depth: 2,
+            ..Report::default()
+        });
+        assert_eq!(report.records, 3);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.max_depth, 3);
+    }
+}
@@ -0,0 +1,317 @@
+//! This module contains the conversion of JSONL back to JSON for
+//! `--reverse`, which writes the records of a JSONL file as the elements of
+//! a single array.
+//!
+//! Elements are written as soon as they are read, so the array is never held
+//! in memory. Each line is copied into the array as it is, so lines should
+//! be checked with `--check-jsonl` first if they may not be valid JSON.
+
+use std::io::{BufRead, Write};
+
+use crate::{
+    errors::ConversionError,
+    json_object::{CleanupPolicy, JSONLString},
+    readers::line_iter::LineIterator,
+    report::Report,
+    writer::LineEnding,
+};
+
+/// Writes the elements of a JSON array to an output one at a time.
+///
+/// When pretty printed, the array is laid out as `serde_json` lays it out:
+/// each element starts on a line of its own, indented by two spaces, with
+/// its nested objects and arrays indented by two more spaces per level. An
+/// empty array is written as `[]`.
+///
+/// # Fields
+///
+/// * `output` - Where the array is written to.
+/// * `count` - The number of elements written.
+/// * `pretty` - Whether the array is pretty printed rather than written on
+///   one line.
+/// * `line_ending` - The line break written after the array, and between
+///   the lines of a pretty printed array.
+/// * `element` - The element being written, reused between elements.
+pub struct ArrayWriter<W: Write> {
+    output: W,
+    count: usize,
+    pretty: bool,
+    line_ending: LineEnding,
+    element: JSONLString,
+}
+
+impl<W: Write> ArrayWriter<W> {
+    /// Creates a new `ArrayWriter` that writes to `output`. Nothing is
+    /// written until the first element or `finish`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::reverse::ArrayWriter;
+    ///
+    /// let mut writer = ArrayWriter::new(Vec::new(), true);
+    /// writer.write_element("{\"a\": [1]}").unwrap();
+    /// assert_eq!(
+    ///     writer.finish().unwrap(),
+    ///     b"[\n  {\n    \"a\": [\n      1\n    ]\n  }\n]\n"
+    /// );
+    /// ```
+    pub fn new(output: W, pretty: bool) -> Self {
+        let policy = if pretty {
+            CleanupPolicy::Pretty
+        } else {
+            CleanupPolicy::Compact
+        };
+        ArrayWriter {
+            output,
+            count: 0,
+            pretty,
+            line_ending: LineEnding::default(),
+            element: JSONLString::with_policy(policy),
+        }
+    }
+
+    /// Sets the line break written after the array, and between the lines
+    /// of a pretty printed array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::{reverse::ArrayWriter, writer::LineEnding};
+    ///
+    /// let mut writer = ArrayWriter::new(Vec::new(), true);
+    /// writer.set_line_ending(LineEnding::Crlf);
+    /// writer.write_element("[1]").unwrap();
+    /// assert_eq!(writer.finish().unwrap(), b"[\r\n  [\r\n    1\r\n  ]\r\n]\r\n");
+    /// ```
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Returns the number of elements that have been written.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Writes an element of the array, opening the array first if this is
+    /// the first element.
+    ///
+    /// # Errors
+    ///
+    /// * If the output cannot be written to.
+    pub fn write_element(&mut self, element: &str) -> Result<(), ConversionError> {
+        let separator = if self.count == 0 { "[" } else { "," };
+        self.output.write_all(separator.as_bytes())?;
+
+        self.element.clear();
+        self.element.push_str(element);
+        if self.pretty {
+            for line in self.element.to_string().lines() {
+                write!(self.output, "{}  {}", self.line_ending.as_str(), line)?;
+            }
+        } else {
+            write!(self.output, "{}", self.element)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Closes the array and flushes the output, returning it.
+    ///
+    /// # Errors
+    ///
+    /// * If the output cannot be written to.
+    pub fn finish(mut self) -> Result<W, ConversionError> {
+        let eol = self.line_ending.as_str();
+        match (self.count, self.pretty) {
+            (0, _) => write!(self.output, "[]{}", eol)?,
+            (_, true) => write!(self.output, "{}]{}", eol, eol)?,
+            (_, false) => write!(self.output, "]{}", eol)?,
+        }
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Converts the JSONL read from `reader` to a JSON array written to
+/// `output`, as `--reverse` does. Blank lines are skipped, and empty input is
+/// written as `[]`.
+///
+/// # Returns
+///
+/// * A summary of the conversion. Only `records` and `bytes_in` are filled
+///   in.
+///
+/// # Errors
+///
+/// * If the input cannot be read or the output cannot be written to.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::reverse::jsonl_to_json;
+///
+/// let mut output = Vec::new();
+/// let report = jsonl_to_json("{\"a\": 1}\n[2]\n\n".as_bytes(), &mut output, false).unwrap();
+/// assert_eq!(output, b"[{\"a\":1},[2]]\n");
+/// assert_eq!(report.records, 2);
+///
+/// let mut output = Vec::new();
+/// jsonl_to_json("".as_bytes(), &mut output, true).unwrap();
+/// assert_eq!(output, b"[]\n");
+/// ```
+pub fn jsonl_to_json<R: BufRead, W: Write>(
+    reader: R,
+    output: W,
+    pretty: bool,
+) -> Result<Report, ConversionError> {
+    let mut report = Report::default();
+    let mut line_iter = LineIterator::from_reader(reader);
+    reverse_lines(
+        &mut line_iter,
+        output,
+        pretty,
+        LineEnding::default(),
+        &mut report,
+    )?;
+    Ok(report)
+}
+
+/// Writes each line read by `line_iter` to `output` as an element of a JSON
+/// array. Blank lines are skipped.
+///
+/// # Arguments
+///
+/// * `line_iter` - The lines of a JSONL file.
+/// * `output` - Where the array is written to.
+/// * `pretty` - Whether the array is pretty printed.
+/// * `line_ending` - The line break written after the array, and between
+///   the lines of it when pretty printed.
+/// * `report` - Updated with the number of elements and bytes read, even if
+///   the conversion fails part of the way through.
+///
+/// # Errors
+///
+/// * If the file cannot be read or the output cannot be written to.
+pub fn reverse_lines<R: BufRead, W: Write>(
+    line_iter: &mut LineIterator<R>,
+    output: W,
+    pretty: bool,
+    line_ending: LineEnding,
+    report: &mut Report,
+) -> Result<(), ConversionError> {
+    let mut writer = ArrayWriter::new(output, pretty);
+    writer.set_line_ending(line_ending);
+    let result = write_each_line(line_iter, &mut writer);
+    report.records = writer.count();
+    report.bytes_in = line_iter.bytes_read();
+    result.and_then(|_| writer.finish().map(|_| ()))
+}
+
+fn write_each_line<R: BufRead, W: Write>(
+    line_iter: &mut LineIterator<R>,
+    writer: &mut ArrayWriter<W>,
+) -> Result<(), ConversionError> {
+    while let Some(line) = line_iter.try_next_line_ref()? {
+        let line = line.trim();
+        if !line.is_empty() {
+            writer.write_element(line)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::fs;
+
+    fn reverse(contents: &str, pretty: bool) -> (String, Report) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.jsonl");
+        fs::write(&path, contents).unwrap();
+        let mut line_iter = LineIterator::new(path.to_str().unwrap()).unwrap();
+        let mut output = Vec::new();
+        let mut report = Report::default();
+        reverse_lines(
+            &mut line_iter,
+            &mut output,
+            pretty,
+            LineEnding::default(),
+            &mut report,
+        )
+        .unwrap();
+        (String::from_utf8(output).unwrap(), report)
+    }
+
+    /// Returns the array of the records in `contents` as `serde_json` would
+    /// write it.
+    fn expected(contents: &str, pretty: bool) -> String {
+        let array: Vec<Value> = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let array = if pretty {
+            serde_json::to_string_pretty(&array)
+        } else {
+            serde_json::to_string(&array)
+        };
+        array.unwrap() + "\n"
+    }
+
+    #[test]
+    fn test_reverse_matches_serde_json() {
+        let cases = [
+            "",
+            "\n\n",
+            "{\"a\": 1}\n",
+            "{\"a\": 1, \"b\": [true, null, {}], \"c\": {\"d\": []}}\r\n[1, [2, 3]]\n\n\"s\"\n3.5\n",
+            "{\"a: [1]\": \"{\\\"b\\\", [c]}\", \"e\": {\"f\": {\"g\": [[]]}}}\n",
+        ];
+        for contents in cases {
+            for pretty in [false, true] {
+                assert_eq!(
+                    reverse(contents, pretty).0,
+                    expected(contents, pretty),
+                    "{:?} pretty: {}",
+                    contents,
+                    pretty
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reverse_reports_elements_and_bytes() {
+        let contents = "{\"a\": 1}\n\n[2]\n";
+        let (_, report) = reverse(contents, true);
+        assert_eq!(report.records, 2);
+        assert_eq!(report.bytes_in, contents.len() as u64);
+    }
+
+    #[test]
+    fn test_jsonl_to_json_reads_from_any_reader() {
+        for contents in ["", "\n \n", "{\"a\": 1}\n[2]\n\n  \n", "[2]"] {
+            for pretty in [false, true] {
+                let mut output = Vec::new();
+                let report = jsonl_to_json(contents.as_bytes(), &mut output, pretty).unwrap();
+                assert_eq!(
+                    String::from_utf8(output).unwrap(),
+                    expected(contents, pretty)
+                );
+                assert_eq!(report.bytes_in, contents.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_array_writer_streams_elements() {
+        let mut writer = ArrayWriter::new(Vec::new(), false);
+        writer.write_element("{\"a\": 1}").unwrap();
+        assert_eq!(writer.output, b"[{\"a\":1}");
+        writer.write_element("[ ]").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"[{\"a\":1},[]]\n");
+    }
+}
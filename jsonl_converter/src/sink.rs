@@ -0,0 +1,294 @@
+//! This module contains the `EmitSink` trait, which describes where the
+//! processors send completed records, along with the built-in sinks.
+//!
+//! To write each record to stdout, a file or any other `io::Write` on a line
+//! of its own, use a `RecordWriter`.
+//!
+//! New destinations only need to implement `emit`. The processors pass each
+//! record to `emit_record_span` along with the offsets in the input of its
+//! first byte and of the byte just after it. By default, this passes the
+//! first offset on to `emit_record`, which sinks that write the offset, such
+//! as the `RecordWriter`, override.
+
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    sync::mpsc::Sender,
+};
+
+use crate::{
+    errors::ConversionError,
+    writer::{error_record, RecordWriter},
+};
+
+/// A destination for completed records.
+pub trait EmitSink {
+    /// Sends a completed record to the sink.
+    ///
+    /// # Errors
+    ///
+    /// * If the sink cannot accept the record.
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError>;
+
+    /// Sends a completed record that started at `offset` in the input to the
+    /// sink. By default, the record is rendered and passed to `emit`.
+    ///
+    /// # Errors
+    ///
+    /// * If the sink cannot accept the record.
+    fn emit_record(&mut self, record: &dyn Display, offset: u64) -> Result<(), ConversionError> {
+        let _ = offset;
+        self.emit(&record.to_string())
+    }
+
+    /// Sends a completed record that occupied the bytes from `start` up to,
+    /// but not including, `end` in the input to the sink. By default, the
+    /// record is passed to `emit_record` with its `start`.
+    ///
+    /// # Errors
+    ///
+    /// * If the sink cannot accept the record.
+    fn emit_record_span(
+        &mut self,
+        record: &dyn Display,
+        start: u64,
+        end: u64,
+    ) -> Result<(), ConversionError> {
+        let _ = end;
+        self.emit_record(record, start)
+    }
+
+    /// Sends an element of the input that could not be converted, because
+    /// of `message`, to the sink in place of its record, when the processors
+    /// are set to recover from such errors. By default, this is sent to
+    /// `emit_record` as an error record holding the `message` and the
+    /// element as it was read. See `error_record`.
+    ///
+    /// # Errors
+    ///
+    /// * If the sink cannot accept the record.
+    fn emit_error(
+        &mut self,
+        message: &str,
+        record: &dyn Display,
+        start: u64,
+    ) -> Result<(), ConversionError> {
+        self.emit_record(&error_record(message, &record.to_string()), start)
+    }
+
+    /// Consumes the sink once every record has been sent, flushing anything
+    /// that it has buffered.
+    ///
+    /// # Errors
+    ///
+    /// * If buffered records cannot be flushed.
+    fn finish(self) -> Result<(), ConversionError>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+}
+
+impl<W: Write> EmitSink for RecordWriter<W> {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.write_record(&record, 0)
+    }
+
+    fn emit_record(&mut self, record: &dyn Display, offset: u64) -> Result<(), ConversionError> {
+        self.write_record(record, offset)
+    }
+
+    fn emit_error(
+        &mut self,
+        message: &str,
+        record: &dyn Display,
+        start: u64,
+    ) -> Result<(), ConversionError> {
+        self.write_error_record(message, &record.to_string(), start)
+    }
+
+    fn finish(mut self) -> Result<(), ConversionError> {
+        self.flush()
+    }
+}
+
+/// Calls a function with each record.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::sink::{CallbackSink, EmitSink};
+///
+/// let mut records = Vec::new();
+/// let mut sink = CallbackSink::new(|record: &str| {
+///     records.push(record.to_string());
+///     Ok(())
+/// });
+/// sink.emit("{}").unwrap();
+/// sink.finish().unwrap();
+/// assert_eq!(records, ["{}"]);
+/// ```
+pub struct CallbackSink<F: FnMut(&str) -> Result<(), ConversionError>> {
+    callback: F,
+}
+
+impl<F: FnMut(&str) -> Result<(), ConversionError>> CallbackSink<F> {
+    /// Creates a new `CallbackSink` that calls `callback` with each record.
+    /// An error returned by `callback` stops the conversion.
+    pub fn new(callback: F) -> Self {
+        CallbackSink { callback }
+    }
+}
+
+impl<F: FnMut(&str) -> Result<(), ConversionError>> EmitSink for CallbackSink<F> {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        (self.callback)(record)
+    }
+}
+
+/// Sends each record down a channel, such as to a thread that consumes them.
+/// Finishing the sink closes the channel, unless it has been cloned.
+pub struct ChannelSink {
+    sender: Sender<String>,
+}
+
+impl ChannelSink {
+    /// Creates a new `ChannelSink` that sends records with `sender`.
+    pub fn new(sender: Sender<String>) -> Self {
+        ChannelSink { sender }
+    }
+}
+
+impl EmitSink for ChannelSink {
+    /// Sends the record down the channel. If the receiver has been dropped,
+    /// this is a broken pipe, as with an output whose reader stops early.
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.sender.send(record.to_string()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the receiver of the channel was dropped",
+            )
+            .into()
+        })
+    }
+}
+
+/// Counts the records and their bytes, discarding the records themselves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CountingSink {
+    records: usize,
+    bytes: u64,
+}
+
+impl CountingSink {
+    /// Creates a new `CountingSink`.
+    pub fn new() -> Self {
+        CountingSink::default()
+    }
+
+    /// Returns the number of records emitted.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Returns the total length in bytes of the records emitted, not counting
+    /// line endings.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl EmitSink for CountingSink {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.records += 1;
+        self.bytes += record.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::BufWriter, sync::mpsc, thread};
+
+    #[test]
+    fn test_record_writer_writes_records_with_offsets() {
+        let mut sink = RecordWriter::new(BufWriter::new(Vec::new()));
+        sink.set_with_offset(true);
+        sink.emit_record(&"{\"a\":1}", 3).unwrap();
+        sink.emit("[]").unwrap();
+        assert!(sink.get_ref().get_ref().is_empty());
+        assert_eq!(sink.count(), 2);
+
+        let mut output = Vec::new();
+        let mut sink = RecordWriter::new(&mut output);
+        sink.emit("{}").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(output, b"{}\n");
+    }
+
+    #[test]
+    fn test_errors_are_sent_as_error_records() {
+        let mut records = Vec::new();
+        let mut sink = CallbackSink::new(|record: &str| {
+            records.push(record.to_string());
+            Ok(())
+        });
+        sink.emit_error("Broken.", &"{\"a\": [}", 0).unwrap();
+        assert_eq!(
+            records,
+            ["{\"_error\":\"Broken.\",\"_raw\":\"{\\\"a\\\": [}\"}"]
+        );
+
+        let mut sink = RecordWriter::new(Vec::new());
+        sink.emit_error("Broken.", &"[}", 0).unwrap();
+        assert_eq!(sink.error_records(), 1);
+        assert_eq!(sink.count(), 1);
+    }
+
+    #[test]
+    fn test_callback_sink_stops_on_error() {
+        let mut calls = 0;
+        let mut sink = CallbackSink::new(|record: &str| {
+            calls += 1;
+            match record {
+                "{}" => Ok(()),
+                _ => Err(ConversionError::EmptyInput),
+            }
+        });
+        sink.emit("{}").unwrap();
+        assert!(sink.emit("[]").is_err());
+        sink.finish().unwrap();
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_channel_sink_sends_records_to_receiver() {
+        let (sender, receiver) = mpsc::channel();
+        let consumer = thread::spawn(move || receiver.iter().collect::<Vec<_>>());
+
+        let mut sink = ChannelSink::new(sender);
+        sink.emit_record(&"{\"a\":1}", 5).unwrap();
+        sink.emit("[]").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(consumer.join().unwrap(), ["{\"a\":1}", "[]"]);
+    }
+
+    #[test]
+    fn test_channel_sink_dropped_receiver_is_a_broken_pipe() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let error = ChannelSink::new(sender).emit("{}").unwrap_err();
+        assert!(error.is_broken_pipe());
+    }
+
+    #[test]
+    fn test_counting_sink_counts_records_and_bytes() {
+        let mut sink = CountingSink::new();
+        sink.emit("{\"a\":1}").unwrap();
+        sink.emit_record(&"[]", 9).unwrap();
+        assert_eq!(sink.records(), 2);
+        assert_eq!(sink.bytes(), 9);
+    }
+}
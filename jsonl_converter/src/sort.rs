@@ -0,0 +1,558 @@
+//! This module contains the `SortWriter`, which sorts JSONL output by the
+//! value of a top level key for `--order-by`.
+//!
+//! # Order
+//!
+//! * Numbers are compared by their exact value, however many digits they
+//!   have, and come before strings.
+//! * Strings are compared by their unescaped text, so `"\u0061"` equals
+//!   `"a"`, and come before any other values.
+//! * Other values, such as `true`, `null` or objects, are compared by their
+//!   text.
+//! * Records without the key, or that are not objects, come last, whether
+//!   sorting in ascending or descending order.
+//! * Records with equal keys keep the order they were written in.
+//!
+//! # Memory
+//!
+//! Records are held in memory until they take up more than the buffer size,
+//! at which point they are sorted and spilled to a temporary file as a run.
+//! Once every record has been written, the runs are merged into the output,
+//! so only the buffer and one record per run are ever held in memory.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+use crate::{decimal::Decimal, keys::top_level_value};
+
+/// The default number of bytes of records held in memory before they are
+/// spilled to a temporary file.
+pub const DEFAULT_SORT_BUFFER_BYTES: usize = 64 * 1024 * 1024;
+
+/// The number of runs spilled by this process, used to give each run a
+/// unique file name.
+static SPILLED_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// The value of the key that records are sorted by.
+#[derive(Debug, Clone)]
+pub enum SortKey {
+    Number(Decimal),
+    String(String),
+    Other(String),
+}
+
+impl SortKey {
+    /// Parses the raw JSON text of a value into a `SortKey`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::sort::SortKey;
+    ///
+    /// assert!(SortKey::parse("10") > SortKey::parse("9.5"));
+    /// assert!(SortKey::parse("\"b\"") > SortKey::parse("\"\\u0061\""));
+    /// assert!(SortKey::parse("\"1\"") > SortKey::parse("2"));
+    /// ```
+    pub fn parse(raw: &str) -> SortKey {
+        let raw = raw.trim();
+        if raw.starts_with('"') {
+            if let Ok(string) = serde_json::from_str(raw) {
+                return SortKey::String(string);
+            }
+        } else if let Some(number) = Decimal::parse(raw) {
+            return SortKey::Number(number);
+        }
+        SortKey::Other(raw.to_string())
+    }
+
+    /// Returns the position of the kind of the key in the order.
+    fn rank(&self) -> u8 {
+        match self {
+            SortKey::Number(_) => 0,
+            SortKey::String(_) => 1,
+            SortKey::Other(_) => 2,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.cmp(b),
+            (SortKey::String(a), SortKey::String(b)) | (SortKey::Other(a), SortKey::Other(b)) => {
+                a.cmp(b)
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+/// Compares the keys of two records, placing records without the key last.
+fn compare_keys(a: &Option<SortKey>, b: &Option<SortKey>, descending: bool) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+    }
+}
+
+/// A temporary file holding a sorted run of records, which is removed when
+/// dropped.
+struct Run {
+    path: PathBuf,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The next record of a run being merged.
+///
+/// # Fields
+///
+/// * `key` - The record's value for the key being sorted by.
+/// * `line` - The record, including its line ending.
+/// * `run` - The index of the run the record came from, so that records with
+///   equal keys are written in the order their runs were spilled.
+/// * `descending` - Whether the records are sorted in descending order.
+struct Head {
+    key: Option<SortKey>,
+    line: Vec<u8>,
+    run: usize,
+    descending: bool,
+}
+
+impl Ord for Head {
+    /// Orders heads so that the next record to write is the greatest, as
+    /// `BinaryHeap` pops the greatest element first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.key, &other.key, self.descending)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Sorts the JSONL written to it by the value of a top level key, writing
+/// the sorted records to `inner` once `finish` is called.
+///
+/// Each line written is treated as a record. Anything before a record's
+/// opening `{`, such as the prefix added by `--with-offset`, is ignored when
+/// looking up the key. See the module documentation for the order. A line
+/// ending is added to the last record if it does not have one, so that it
+/// cannot run into the record after it.
+///
+/// Records that are written but never finished are discarded, along with
+/// any runs that were spilled.
+///
+/// # Fields
+///
+/// * `inner` - Where the sorted records are written to.
+/// * `key` - The top level key that records are sorted by.
+/// * `descending` - Whether records are sorted in descending order.
+/// * `buffer_bytes` - The number of bytes of records held in memory before
+///   they are spilled.
+/// * `temp_dir` - The directory that runs are spilled to.
+/// * `records` - The records held in memory, along with their keys.
+/// * `buffered` - The number of bytes of records held in memory.
+/// * `runs` - The runs spilled so far, in the order they were spilled.
+/// * `line` - The record that is currently being written.
+pub struct SortWriter<W: Write> {
+    inner: W,
+    key: String,
+    descending: bool,
+    buffer_bytes: usize,
+    temp_dir: PathBuf,
+    records: Vec<(Option<SortKey>, Vec<u8>)>,
+    buffered: usize,
+    runs: Vec<Run>,
+    line: Vec<u8>,
+}
+
+impl<W: Write> SortWriter<W> {
+    /// Creates a new `SortWriter` that sorts records by `key` before writing
+    /// them to `inner`, spilling to the system's temporary directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use jsonl_converter::sort::SortWriter;
+    ///
+    /// let mut writer = SortWriter::new(Vec::new(), "n", false);
+    /// writeln!(writer, "{{\"n\": 2}}").unwrap();
+    /// writeln!(writer, "{{\"n\": 1}}").unwrap();
+    /// let output = writer.finish().unwrap();
+    /// assert_eq!(output, b"{\"n\": 1}\n{\"n\": 2}\n");
+    /// ```
+    pub fn new(inner: W, key: &str, descending: bool) -> Self {
+        SortWriter {
+            inner,
+            key: key.to_string(),
+            descending,
+            buffer_bytes: DEFAULT_SORT_BUFFER_BYTES,
+            temp_dir: env::temp_dir(),
+            records: Vec::new(),
+            buffered: 0,
+            runs: Vec::new(),
+            line: Vec::new(),
+        }
+    }
+
+    /// Sets the number of bytes of records held in memory before they are
+    /// spilled.
+    pub fn set_buffer_bytes(&mut self, buffer_bytes: usize) {
+        self.buffer_bytes = buffer_bytes;
+    }
+
+    /// Sets the directory that runs are spilled to.
+    pub fn set_temp_dir(&mut self, temp_dir: PathBuf) {
+        self.temp_dir = temp_dir;
+    }
+
+    /// Returns the number of runs that have been spilled.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Writes the records in sorted order to the inner writer, merging any
+    /// spilled runs, and returns it.
+    ///
+    /// # Errors
+    ///
+    /// * If a run cannot be spilled or read back.
+    /// * If the inner writer cannot be written to.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            self.buffer_line()?;
+        }
+        if self.runs.is_empty() {
+            self.sort_records();
+            for (_, line) in std::mem::take(&mut self.records) {
+                self.inner.write_all(&line)?;
+            }
+        } else {
+            self.spill()?;
+            self.merge_runs()?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Returns the value of the key being sorted by in `line`, if it has one.
+    fn record_key(&self, line: &[u8]) -> Option<SortKey> {
+        let record = String::from_utf8_lossy(line);
+        top_level_value(record.trim_end(), &self.key).map(SortKey::parse)
+    }
+
+    /// Stably sorts the records held in memory.
+    fn sort_records(&mut self) {
+        let descending = self.descending;
+        self.records
+            .sort_by(|(a, _), (b, _)| compare_keys(a, b, descending));
+    }
+
+    /// Moves the record that is currently being written into memory,
+    /// spilling the records held in memory if they have outgrown the buffer.
+    fn buffer_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        let key = self.record_key(&line);
+        self.buffered += line.len();
+        self.records.push((key, line));
+        if self.buffered > self.buffer_bytes {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the records held in memory and writes them to a new run.
+    fn spill(&mut self) -> io::Result<()> {
+        self.sort_records();
+        let run = Run {
+            path: self.temp_dir.join(format!(
+                "jsonl_converter-{}-{}.run",
+                process::id(),
+                SPILLED_RUNS.fetch_add(1, AtomicOrdering::Relaxed)
+            )),
+        };
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&run.path)?;
+        self.runs.push(run);
+
+        let mut file = BufWriter::new(file);
+        for (_, line) in self.records.drain(..) {
+            file.write_all(&line)?;
+        }
+        file.flush()?;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Merges the spilled runs into the inner writer.
+    fn merge_runs(&mut self) -> io::Result<()> {
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heads = BinaryHeap::with_capacity(self.runs.len());
+        for (i, run) in self.runs.iter().enumerate() {
+            let mut reader = BufReader::new(File::open(&run.path)?);
+            if let Some(head) = self.read_head(&mut reader, i)? {
+                heads.push(head);
+            }
+            readers.push(reader);
+        }
+
+        while let Some(head) = heads.pop() {
+            self.inner.write_all(&head.line)?;
+            if let Some(next) = self.read_head(&mut readers[head.run], head.run)? {
+                heads.push(next);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next record of the run `run` from `reader`, or `None` if
+    /// the run has been fully read.
+    fn read_head(&self, reader: &mut impl BufRead, run: usize) -> io::Result<Option<Head>> {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Head {
+            key: self.record_key(&line),
+            line,
+            run,
+            descending: self.descending,
+        }))
+    }
+}
+
+impl<W: Write> Write for SortWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                self.buffer_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Does nothing, as no record can be written until every record has
+    /// been, so flushing is left to `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(records: &[&str], key: &str, descending: bool, buffer_bytes: usize) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SortWriter::new(Vec::new(), key, descending);
+        writer.set_buffer_bytes(buffer_bytes);
+        writer.set_temp_dir(dir.path().to_path_buf());
+        for record in records {
+            writeln!(writer, "{}", record).unwrap();
+        }
+        let output = writer.finish().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_key_order() {
+        let mut keys: Vec<SortKey> = ["\"b\"", "true", "-1.5", "\"\\u0061\"", "10", "2", "null"]
+            .into_iter()
+            .map(SortKey::parse)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                SortKey::parse("-1.5"),
+                SortKey::parse("2"),
+                SortKey::parse("10"),
+                SortKey::String(String::from("a")),
+                SortKey::String(String::from("b")),
+                SortKey::Other(String::from("null")),
+                SortKey::Other(String::from("true")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_numeric_key() {
+        let records = [
+            "{\"n\": 10}",
+            "{\"n\": -2}",
+            "{\"n\": 2.5}",
+            "{\"n\": 1e1, \"x\": 1}",
+        ];
+        for buffer_bytes in [DEFAULT_SORT_BUFFER_BYTES, 0] {
+            assert_eq!(
+                sort(&records, "n", false, buffer_bytes),
+                [
+                    "{\"n\": -2}",
+                    "{\"n\": 2.5}",
+                    "{\"n\": 10}",
+                    "{\"n\": 1e1, \"x\": 1}"
+                ]
+            );
+            assert_eq!(
+                sort(&records, "n", true, buffer_bytes),
+                [
+                    "{\"n\": 10}",
+                    "{\"n\": 1e1, \"x\": 1}",
+                    "{\"n\": 2.5}",
+                    "{\"n\": -2}"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_key_compares_numbers_exactly() {
+        let ordered = [
+            "-1e400",
+            "9007199254740992",
+            "9007199254740993",
+            "18446744073709551617",
+            "1e400",
+            "\"a\"",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(
+                SortKey::parse(pair[0]) < SortKey::parse(pair[1]),
+                "{:?}",
+                pair
+            );
+        }
+        assert!(matches!(SortKey::parse("1e400"), SortKey::Number(_)));
+        assert_eq!(SortKey::parse("1.0"), SortKey::parse("1"));
+    }
+
+    #[test]
+    fn test_sort_by_string_key() {
+        let records = [
+            "{\"s\": \"b\", \"t\": \"}\"}",
+            "{\"t\": {\"s\": \"z\"}, \"s\": \"a,b\"}",
+            "{\"s\": \"\\u0063\"}",
+        ];
+        for buffer_bytes in [DEFAULT_SORT_BUFFER_BYTES, 20] {
+            assert_eq!(
+                sort(&records, "s", false, buffer_bytes),
+                [records[1], records[0], records[2]]
+            );
+            assert_eq!(
+                sort(&records, "s", true, buffer_bytes),
+                [records[2], records[0], records[1]]
+            );
+        }
+    }
+
+    #[test]
+    fn test_records_missing_the_key_come_last() {
+        let records = [
+            "{\"a\": 1}",
+            "{\"n\": 2}",
+            "[1]",
+            "{\"n\": 1}",
+            "{\"b\": 2}",
+        ];
+        for descending in [false, true] {
+            let sorted = sort(&records, "n", descending, 10);
+            assert_eq!(&sorted[2..], ["{\"a\": 1}", "[1]", "{\"b\": 2}"]);
+        }
+    }
+
+    #[test]
+    fn test_spilled_runs_are_merged_stably() {
+        let records: Vec<String> = (0..50)
+            .map(|i| format!("{{\"k\": {}, \"i\": {}}}", i % 3, i))
+            .collect();
+        let records: Vec<&str> = records.iter().map(String::as_str).collect();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SortWriter::new(Vec::new(), "k", false);
+        writer.set_buffer_bytes(100);
+        writer.set_temp_dir(dir.path().to_path_buf());
+        for record in &records {
+            writeln!(writer, "{}", record).unwrap();
+        }
+        assert!(writer.runs() > 1);
+        drop(writer);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let mut expected = records.clone();
+        expected.sort_by_key(|record| top_level_value(record, "k").unwrap().to_string());
+        assert_eq!(sort(&records, "k", false, 100), expected);
+        assert_eq!(
+            sort(&records, "k", false, DEFAULT_SORT_BUFFER_BYTES),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_sort_writer_handles_records_split_across_writes() {
+        let mut writer = SortWriter::new(Vec::new(), "k", false);
+        writer.write_all(b"{\"k\": ").unwrap();
+        writer.write_all(b"2}\r\n{\"k\": 1}\r\n{\"k\"").unwrap();
+        writer.write_all(b": 3}").unwrap();
+        assert_eq!(
+            writer.finish().unwrap(),
+            b"{\"k\": 1}\r\n{\"k\": 2}\r\n{\"k\": 3}\n"
+        );
+
+        let mut writer = SortWriter::new(Vec::new(), "k", true);
+        writer.set_buffer_bytes(0);
+        writer.write_all(b"{\"k\": 1}\n{\"k\": 2}").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"{\"k\": 2}\n{\"k\": 1}\n");
+    }
+}
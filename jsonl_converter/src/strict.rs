@@ -0,0 +1,211 @@
+//! This module contains the validation of records for `--strict`, which
+//! checks that each record written is valid JSON.
+//!
+//! Numbers are checked against the JSON grammar before the record is parsed,
+//! so that numbers which are common in other languages but not legal in JSON,
+//! such as `+1` or `.5`, are reported clearly.
+
+use crate::non_finite::find_non_finite;
+
+/// Checks that `record` is a single valid JSON value.
+///
+/// # Errors
+///
+/// * If `record` contains a number that is not legal in JSON, naming the
+///   number.
+/// * If `record` contains `NaN`, `Infinity` or `-Infinity`.
+/// * If `record` is not valid JSON for any other reason.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::strict::check_record;
+///
+/// assert!(check_record("{\"a\": [-0, 1e10]}").is_ok());
+/// assert_eq!(
+///     check_record("{\"a\": +1}").unwrap_err(),
+///     "`+1` is not a valid JSON number."
+/// );
+/// ```
+pub fn check_record(record: &str) -> Result<(), String> {
+    if let Some(literal) = find_non_finite(record) {
+        return Err(format!(
+            "`{}` is not valid JSON. Use --replace-nan-inf to write it as null.",
+            literal
+        ));
+    }
+    check_numbers(record)?;
+    serde_json::from_str::<serde_json::Value>(record)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// Checks each number outside of the strings in `record` against the JSON
+/// grammar.
+fn check_numbers(record: &str) -> Result<(), String> {
+    let mut in_string = false;
+    let mut last_char_escape = false;
+    let mut number_start = None;
+    for (i, c) in record.char_indices() {
+        if in_string {
+            in_string = c != '"' || last_char_escape;
+            last_char_escape = c == '\\' && !last_char_escape;
+            continue;
+        }
+        match number_start {
+            Some(_) if is_number_char(c) => continue,
+            Some(start) => {
+                check_number(&record[start..i])?;
+                number_start = None;
+            }
+            None => {}
+        }
+        if c == '"' {
+            in_string = true;
+        } else if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') {
+            number_start = Some(i);
+        }
+    }
+    match number_start {
+        Some(start) => check_number(&record[start..]),
+        None => Ok(()),
+    }
+}
+
+fn check_number(number: &str) -> Result<(), String> {
+    if is_json_number(number) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a valid JSON number.", number))
+    }
+}
+
+/// Checks if a character can be part of a number.
+fn is_number_char(c: char) -> bool {
+    c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')
+}
+
+/// Checks if `s` is a number as defined by the JSON grammar: an optional
+/// minus sign, an integer part without leading zeros, an optional fraction
+/// and an optional exponent.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::strict::is_json_number;
+///
+/// assert!(is_json_number("2.5E-3"));
+/// assert!(!is_json_number("01"));
+/// ```
+pub fn is_json_number(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let s = match s.strip_prefix('0') {
+        Some(rest) => rest,
+        None => match strip_digits(s) {
+            Some(rest) if !s.starts_with('0') => rest,
+            _ => return false,
+        },
+    };
+    let s = match s.strip_prefix('.') {
+        Some(fraction) => match strip_digits(fraction) {
+            Some(rest) => rest,
+            None => return false,
+        },
+        None => s,
+    };
+    match s.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            strip_digits(exponent) == Some("")
+        }
+        None => s.is_empty(),
+    }
+}
+
+/// Strips one or more leading ASCII digits from `s`, returning `None` if it
+/// does not start with a digit.
+fn strip_digits(s: &str) -> Option<&str> {
+    let rest = s.trim_start_matches(|c: char| c.is_ascii_digit());
+    (rest.len() < s.len()).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_json_number() {
+        let cases = [
+            ("0", true),
+            ("-0", true),
+            ("7", true),
+            ("-12", true),
+            ("0.5", true),
+            ("1e10", true),
+            ("1E+10", true),
+            ("2.5E-3", true),
+            ("-0.0e0", true),
+            ("+1", false),
+            ("01", false),
+            ("-01", false),
+            (".5", false),
+            ("1.", false),
+            ("1.e5", false),
+            ("1e", false),
+            ("1e+", false),
+            ("-", false),
+            ("--1", false),
+            ("1-", false),
+            ("", false),
+        ];
+        for (number, legal) in cases {
+            assert_eq!(is_json_number(number), legal, "{}", number);
+        }
+    }
+
+    #[test]
+    fn test_check_record_names_illegal_numbers() {
+        assert_eq!(
+            check_record("[1, .5]").unwrap_err(),
+            "`.5` is not a valid JSON number."
+        );
+        assert_eq!(
+            check_record("{\"a\":{\"b\":01}}").unwrap_err(),
+            "`01` is not a valid JSON number."
+        );
+        assert_eq!(
+            check_record("[1.]").unwrap_err(),
+            "`1.` is not a valid JSON number."
+        );
+    }
+
+    #[test]
+    fn test_check_record_names_non_finite_literals() {
+        assert_eq!(
+            check_record("{\"a\": -Infinity}").unwrap_err(),
+            "`-Infinity` is not valid JSON. Use --replace-nan-inf to write it as null."
+        );
+        assert!(check_record("{\"a\": \"NaN\"}").is_ok());
+    }
+
+    #[test]
+    fn test_check_record_ignores_numbers_in_strings() {
+        assert!(check_record("{\"+1\": \"\\\".5 01\"}").is_ok());
+    }
+
+    #[test]
+    fn test_check_record_rejects_other_invalid_json() {
+        assert!(check_record("{\"a\": 1,}").is_err());
+        assert!(check_record("[tru]").is_err());
+        assert!(check_record("3").is_ok());
+    }
+
+    #[test]
+    fn test_check_record_rejects_missing_value_with_its_position() {
+        assert_eq!(
+            check_record("{\"a\": }").unwrap_err(),
+            "expected value at line 1 column 7"
+        );
+        assert!(check_record("{\"a\": {\"b\": [1, null]}}").is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+//! This module contains `SyslogSink`, which sends each record to the local
+//! syslog daemon, or to journald, which listens on the same socket, so that
+//! conversions can feed an existing log pipeline. It is only built with the
+//! `syslog` feature, on Unix, and sends messages with the `syslog` crate.
+//!
+//! # Format
+//!
+//! Each record is sent as an RFC 5424 message from the `user` facility with
+//! the `info` severity, such as:
+//!
+//! ```text
+//! <14>1 2024-05-01T12:30:00.5Z host jsonl_converter 4242 1 [record@32473 offset="17"] {"a":1}
+//! ```
+//!
+//! The record is the message, with the message ID `RECORD_MSG_ID`, and its
+//! offset in the input is structured data. A summary of the conversion can
+//! be sent afterwards with `send_report`, with the message ID
+//! `REPORT_MSG_ID`.
+
+use std::{collections::HashMap, fmt::Display, io, path::Path, process};
+
+use syslog::{Facility, Formatter5424, Logger, LoggerBackend};
+
+use crate::{errors::ConversionError, report::Report, sink::EmitSink};
+
+/// The socket that the syslog daemon and journald listen on.
+pub const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+
+/// The message ID of each record.
+pub const RECORD_MSG_ID: u32 = 1;
+
+/// The message ID of the summary of a conversion.
+pub const REPORT_MSG_ID: u32 = 2;
+
+/// The structured data of a message, by SD-ID and then by parameter name, as
+/// the `syslog` crate takes it.
+type StructuredData = HashMap<String, HashMap<String, String>>;
+
+/// Sends each record to syslog as a message of its own.
+pub struct SyslogSink {
+    logger: Logger<LoggerBackend, Formatter5424>,
+}
+
+impl SyslogSink {
+    /// Creates a new `SyslogSink` that sends messages to the socket at `path`,
+    /// usually `DEFAULT_SYSLOG_SOCKET`, under the name `jsonl_converter`.
+    ///
+    /// # Errors
+    ///
+    /// * If the socket cannot be connected to.
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let formatter = Formatter5424 {
+            facility: Facility::LOG_USER,
+            process: String::from("jsonl_converter"),
+            pid: process::id(),
+            ..Formatter5424::default()
+        };
+        let logger = syslog::unix_custom(formatter, path).map_err(syslog_error)?;
+        Ok(SyslogSink { logger })
+    }
+
+    /// Sets the name the messages are sent under. Spaces are replaced with
+    /// underscores, as the name cannot contain them.
+    pub fn set_app_name(&mut self, app_name: &str) {
+        self.logger.formatter.process = app_name.replace(' ', "_");
+    }
+
+    /// Sends a summary of a conversion as a JSON object, as with
+    /// `--report-json`.
+    ///
+    /// # Errors
+    ///
+    /// * If the message cannot be sent.
+    pub fn send_report(&mut self, report: &Report) -> Result<(), ConversionError> {
+        self.send(REPORT_MSG_ID, StructuredData::new(), &report.to_json())
+    }
+
+    /// Sends a message with the message ID `msg_id` and the structured data
+    /// `data`.
+    fn send(
+        &mut self,
+        msg_id: u32,
+        data: StructuredData,
+        message: &dyn Display,
+    ) -> Result<(), ConversionError> {
+        self.logger
+            .info((msg_id, data, message))
+            .map_err(syslog_error)?;
+        Ok(())
+    }
+}
+
+impl EmitSink for SyslogSink {
+    fn emit(&mut self, record: &str) -> Result<(), ConversionError> {
+        self.send(RECORD_MSG_ID, StructuredData::new(), &record)
+    }
+
+    fn emit_record(&mut self, record: &dyn Display, offset: u64) -> Result<(), ConversionError> {
+        let data = HashMap::from([(
+            String::from("record@32473"),
+            HashMap::from([(String::from("offset"), offset.to_string())]),
+        )]);
+        self.send(RECORD_MSG_ID, data, record)
+    }
+}
+
+/// Returns an error of the `syslog` crate as an `io::Error`.
+fn syslog_error(error: syslog::Error) -> io::Error {
+    io::Error::other(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    fn receive(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_sends_records_and_reports_to_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.sock");
+        let daemon = UnixDatagram::bind(&path).unwrap();
+
+        let mut sink = SyslogSink::connect(&path).unwrap();
+        sink.set_app_name("my converter");
+        sink.emit_record(&"{\"a\":1}", 17).unwrap();
+        sink.emit("[2]").unwrap();
+        sink.send_report(&Report {
+            records: 2,
+            ..Report::default()
+        })
+        .unwrap();
+
+        let pid = process::id();
+        let message = receive(&daemon);
+        assert!(message.starts_with("<14>1 "), "{}", message);
+        assert!(
+            message.ends_with(&format!(
+                " my_converter {} 1 [record@32473 offset=\"17\"] {{\"a\":1}}",
+                pid
+            )),
+            "{}",
+            message
+        );
+        assert!(receive(&daemon).ends_with(&format!(" {} 1 - [2]", pid)));
+        let report = receive(&daemon);
+        assert!(report.contains(&format!(" {} 2 - {{\"records\":2,", pid)));
+    }
+
+    #[test]
+    fn test_connecting_to_a_missing_socket_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(SyslogSink::connect(dir.path().join("missing.sock")).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! This module contains the Unicode normalization of the strings in a
+//! record, so that text that looks the same is also byte for byte the same.
+
+use std::{borrow::Cow, collections::HashMap, str::FromStr};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A Unicode normalization form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical composition, e.g. `e` followed by a combining acute accent
+    /// becomes `é`.
+    Nfc,
+    /// Canonical decomposition, e.g. `é` becomes `e` followed by a combining
+    /// acute accent.
+    Nfd,
+}
+
+impl FromStr for NormalizationForm {
+    type Err = String;
+
+    /// Parses `nfc` or `nfd`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nfc" => Ok(NormalizationForm::Nfc),
+            "nfd" => Ok(NormalizationForm::Nfd),
+            _ => Err(format!(
+                "Unknown normalization form `{}`. Expected `nfc` or `nfd`.",
+                s
+            )),
+        }
+    }
+}
+
+/// Normalizes the strings in a record.
+///
+/// # Fields
+///
+/// * `form` - The normalization form to convert strings to.
+/// * `keys` - Whether object keys are normalized as well as values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalizer {
+    pub form: NormalizationForm,
+    pub keys: bool,
+}
+
+impl Normalizer {
+    /// Returns `record` with the contents of its strings normalized. Only the
+    /// characters written literally are normalized, not those written as
+    /// `\u` escapes.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A JSON value.
+    ///
+    /// # Errors
+    ///
+    /// * If `keys` is set and two keys of the same object would become the
+    ///   same key, with a message naming them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::unicode::{NormalizationForm, Normalizer};
+    ///
+    /// let normalizer = Normalizer {
+    ///     form: NormalizationForm::Nfc,
+    ///     keys: false,
+    /// };
+    /// assert_eq!(
+    ///     normalizer.normalize("{\"e\u{301}\": \"e\u{301}\"}").unwrap(),
+    ///     "{\"e\u{301}\": \"\u{e9}\"}"
+    /// );
+    ///
+    /// let normalizer = Normalizer {
+    ///     form: NormalizationForm::Nfc,
+    ///     keys: true,
+    /// };
+    /// assert!(normalizer
+    ///     .normalize("{\"\u{e9}\": 1, \"e\u{301}\": 2}")
+    ///     .is_err());
+    /// ```
+    pub fn normalize<'a>(&self, record: &'a str) -> Result<Cow<'a, str>, String> {
+        // ASCII is the same in every normalization form.
+        if record.is_ascii() {
+            return Ok(Cow::Borrowed(record));
+        }
+
+        let mut result = String::with_capacity(record.len());
+        let mut copied = 0;
+        // For each object or array that is open, the normalized keys seen in
+        // it so far, each with the key it was normalized from. Arrays have
+        // none.
+        let mut open: Vec<Option<HashMap<String, &str>>> = Vec::new();
+        let mut string_start = None;
+        let mut last_char_escape = false;
+        for (i, c) in record.char_indices() {
+            let Some(start) = string_start else {
+                match c {
+                    '"' => string_start = Some(i + 1),
+                    '{' => open.push(Some(HashMap::new())),
+                    '[' => open.push(None),
+                    '}' | ']' => {
+                        open.pop();
+                    }
+                    _ => {}
+                }
+                continue;
+            };
+            if c == '"' && !last_char_escape {
+                let string = &record[start..i];
+                let key = is_key(&record[i + 1..]);
+                if self.keys || !key {
+                    result.push_str(&record[copied..start]);
+                    let normalized_start = result.len();
+                    self.push_normalized(&mut result, string);
+                    copied = i;
+                    if let (true, Some(Some(keys))) = (key, open.last_mut()) {
+                        check_collision(keys, &result[normalized_start..], string)?;
+                    }
+                }
+                string_start = None;
+            }
+            last_char_escape = c == '\\' && !last_char_escape;
+        }
+        result.push_str(&record[copied..]);
+        Ok(Cow::Owned(result))
+    }
+
+    /// Pushes `s` onto `result` in the normalization form.
+    fn push_normalized(&self, result: &mut String, s: &str) {
+        match self.form {
+            NormalizationForm::Nfc => result.extend(s.nfc()),
+            NormalizationForm::Nfd => result.extend(s.nfd()),
+        }
+    }
+}
+
+/// Records that `key` of an object was normalized to `normalized`, where
+/// `keys` holds the keys of the object normalized so far.
+///
+/// # Errors
+///
+/// * If a different key of the object was already normalized to the same
+///   key. A key repeated as it is in the input is left to the reader.
+fn check_collision<'a>(
+    keys: &mut HashMap<String, &'a str>,
+    normalized: &str,
+    key: &'a str,
+) -> Result<(), String> {
+    match keys.get(normalized) {
+        Some(&previous) if previous != key => Err(format!(
+            "The keys `{}` and `{}` would both become `{}`.",
+            previous, key, normalized
+        )),
+        Some(_) => Ok(()),
+        None => {
+            keys.insert(normalized.to_string(), key);
+            Ok(())
+        }
+    }
+}
+
+/// Checks if a string is an object key, given the part of the record that
+/// follows its closing quote.
+fn is_key(rest: &str) -> bool {
+    rest.trim_start().starts_with(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPOSED: &str = "{\"caf\u{e9}\": [\"caf\u{e9}\", 1]}";
+    const DECOMPOSED: &str = "{\"cafe\u{301}\": [\"cafe\u{301}\", 1]}";
+
+    fn normalizer(form: NormalizationForm, keys: bool) -> Normalizer {
+        Normalizer { form, keys }
+    }
+
+    #[test]
+    fn test_nfc_makes_composed_and_decomposed_values_identical() {
+        let normalizer = normalizer(NormalizationForm::Nfc, true);
+        assert_eq!(normalizer.normalize(DECOMPOSED).unwrap(), COMPOSED);
+        assert_eq!(normalizer.normalize(COMPOSED).unwrap(), COMPOSED);
+    }
+
+    #[test]
+    fn test_nfd_decomposes_values() {
+        let normalizer = normalizer(NormalizationForm::Nfd, true);
+        assert_eq!(normalizer.normalize(COMPOSED).unwrap(), DECOMPOSED);
+    }
+
+    #[test]
+    fn test_keys_are_left_alone_unless_requested() {
+        let normalizer = normalizer(NormalizationForm::Nfc, false);
+        assert_eq!(
+            normalizer.normalize(DECOMPOSED).unwrap(),
+            "{\"cafe\u{301}\": [\"caf\u{e9}\", 1]}"
+        );
+    }
+
+    #[test]
+    fn test_escaped_quotes_do_not_end_the_string() {
+        let normalizer = normalizer(NormalizationForm::Nfc, false);
+        assert_eq!(
+            normalizer
+                .normalize("[\"\\\"e\u{301}\\\\\", \"e\u{301}\" ]")
+                .unwrap(),
+            "[\"\\\"\u{e9}\\\\\", \"\u{e9}\" ]"
+        );
+    }
+
+    #[test]
+    fn test_keys_that_collide_are_rejected() {
+        let keys = normalizer(NormalizationForm::Nfc, true);
+        assert_eq!(
+            keys.normalize("{\"caf\u{e9}\": 1, \"cafe\u{301}\": 2}"),
+            Err(String::from(
+                "The keys `caf\u{e9}` and `cafe\u{301}` would both become `caf\u{e9}`."
+            ))
+        );
+        assert!(keys
+            .normalize("[{\"a\": {\"e\u{301}\": [], \"\u{e9}\": {}}}]")
+            .is_err());
+        // The same keys in different objects, a key repeated as it is in the
+        // input, and keys that are left alone do not collide.
+        assert!(keys
+            .normalize("[{\"caf\u{e9}\": 1}, {\"cafe\u{301}\": {\"caf\u{e9}\": 2}}]")
+            .is_ok());
+        assert!(keys.normalize("{\"e\u{301}\": 1, \"e\u{301}\": 2}").is_ok());
+        assert!(normalizer(NormalizationForm::Nfc, false)
+            .normalize("{\"\u{e9}\": 1, \"e\u{301}\": 2}")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("NFC".parse(), Ok(NormalizationForm::Nfc));
+        assert_eq!("nfd".parse(), Ok(NormalizationForm::Nfd));
+        assert!("nfkc".parse::<NormalizationForm>().is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! This module contains the self-check behind `--verify`, which parses the
+//! whole input with `serde_json` once it has been converted and checks that
+//! its root has as many elements as the converter found. Finding a different
+//! number means that the converter split or joined records in the wrong
+//! place.
+//!
+//! Parsing the whole input holds all of it in memory, as a `Value`, which is
+//! what the converter otherwise avoids, so the check is only made when asked
+//! for.
+
+use std::io::Read;
+
+use serde_json::Value;
+
+use crate::errors::ConversionError;
+
+/// Returns the number of elements in the root array, or of members in the
+/// root object, of the JSON document read from `reader`. Members with the
+/// same key are counted once.
+///
+/// # Errors
+///
+/// * If the input is not a single valid JSON document, or cannot be read.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::verify::count_elements;
+///
+/// assert_eq!(count_elements(&b"[{\"a\": 1}, [2], 3]"[..]).unwrap(), 3);
+/// assert_eq!(count_elements(&b"{\"a\": 1, \"b\": [2]}"[..]).unwrap(), 2);
+/// ```
+pub fn count_elements<R: Read>(reader: R) -> Result<usize, ConversionError> {
+    let root: Value = serde_json::from_reader(reader).map_err(ConversionError::Unverifiable)?;
+    Ok(match root {
+        Value::Array(elements) => elements.len(),
+        Value::Object(members) => members.len(),
+        _ => 0,
+    })
+}
+
+/// Checks that the JSON document read from `reader` has as many elements in
+/// its root as the `converted` records.
+///
+/// # Errors
+///
+/// * If the input cannot be parsed, with `count_elements`.
+/// * If the counts differ.
+pub fn verify_count<R: Read>(reader: R, converted: usize) -> Result<(), ConversionError> {
+    let parsed = count_elements(reader)?;
+    if parsed != converted {
+        return Err(ConversionError::CountMismatch { parsed, converted });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_elements_counts_only_the_root() {
+        let input = "[\n  {\"a\": [1, 2]},\n  [[3], \"],[\"],\n  null\n]\n";
+        assert_eq!(count_elements(input.as_bytes()).unwrap(), 3);
+        assert_eq!(count_elements(&b"[]"[..]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_verify_count_catches_a_misplaced_boundary() {
+        let input = "[{\"a\": \"}, {\"}, {\"b\": 2}]";
+        assert!(verify_count(input.as_bytes(), 2).is_ok());
+        // A converter that ended a record at the `}` inside the string would
+        // find three records.
+        let error = verify_count(input.as_bytes(), 3).unwrap_err();
+        assert!(matches!(
+            error,
+            ConversionError::CountMismatch {
+                parsed: 2,
+                converted: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn test_verify_count_fails_if_the_input_cannot_be_parsed() {
+        let error = verify_count(&b"[{\"a\": 1},]"[..], 1).unwrap_err();
+        assert!(matches!(error, ConversionError::Unverifiable(_)));
+    }
+}
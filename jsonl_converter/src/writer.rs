@@ -0,0 +1,1209 @@
+//! This module contains the `RecordWriter`, which is shared by the processors
+//! to write completed JSONL records to an output. Everything about how a
+//! record is laid out in the output is decided here.
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    io::{self, Write},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use crate::{
+    canonical::Canonicalizer,
+    control::escape_control_chars,
+    dedupe::DedupeWindow,
+    envelope::Envelope,
+    errors::{quote_fields, ConversionError},
+    flatten::Flattener,
+    json5,
+    key_case::KeyCaser,
+    keys::{MapValues, RequiredFields},
+    non_finite::replace_non_finite,
+    repair::Repairs,
+    strict,
+    unicode::Normalizer,
+};
+
+/// The separator written after each record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`, as used on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as used on Windows.
+    Crlf,
+}
+
+impl LineEnding {
+    /// Returns the separator as a string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    /// Parses `lf` or `crlf`, ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::LineEnding;
+    ///
+    /// assert_eq!("crlf".parse(), Ok(LineEnding::Crlf));
+    /// assert!("cr".parse::<LineEnding>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(format!(
+                "Unknown line ending `{}`. Expected `lf` or `crlf`.",
+                s
+            )),
+        }
+    }
+}
+
+/// The length written before each record instead of a line ending, for
+/// consumers that read length-delimited frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// A 4 byte unsigned integer, most significant byte first.
+    U32,
+    /// An unsigned LEB128 varint, as used by Protocol Buffers: 7 bits at a
+    /// time, least significant first, with the top bit of each byte set if
+    /// another byte follows.
+    Varint,
+}
+
+impl LengthPrefix {
+    /// Returns the encoding of `len`.
+    ///
+    /// # Errors
+    ///
+    /// * If `len` does not fit in 4 bytes when using `U32`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::LengthPrefix;
+    ///
+    /// assert_eq!(LengthPrefix::U32.encode(300), Some(vec![0, 0, 1, 44]));
+    /// assert_eq!(LengthPrefix::Varint.encode(300), Some(vec![0xac, 0x02]));
+    /// ```
+    pub fn encode(&self, len: usize) -> Option<Vec<u8>> {
+        match self {
+            LengthPrefix::U32 => u32::try_from(len)
+                .ok()
+                .map(|len| len.to_be_bytes().to_vec()),
+            LengthPrefix::Varint => {
+                let mut bytes = Vec::new();
+                let mut len = len;
+                while len >= 0x80 {
+                    bytes.push((len & 0x7f) as u8 | 0x80);
+                    len >>= 7;
+                }
+                bytes.push(len as u8);
+                Some(bytes)
+            }
+        }
+    }
+}
+
+/// The format that records are written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A line of JSON per record.
+    #[default]
+    Jsonl,
+    /// A MessagePack value per record, written by `msgpack::MsgpackSink`,
+    /// which is only built with the `msgpack` feature.
+    Msgpack,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parses `jsonl` or `msgpack`, ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::OutputFormat;
+    ///
+    /// assert_eq!("msgpack".parse(), Ok(OutputFormat::Msgpack));
+    /// assert!("csv".parse::<OutputFormat>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "msgpack" => Ok(OutputFormat::Msgpack),
+            _ => Err(format!(
+                "Unknown format `{}`. Expected `jsonl` or `msgpack`.",
+                s
+            )),
+        }
+    }
+}
+
+/// Writes completed JSONL records to an output.
+///
+/// # Fields
+///
+/// * `output` - Where records are written to.
+/// * `count` - The number of records written.
+/// * `array_path` - If set, each record is prefixed with this JSON pointer to
+///   the array it came from.
+/// * `with_offset` - Whether to prefix each record with the offset in the
+///   input that it started at.
+/// * `flush_every` - If set, the output is flushed after this many records.
+///   Otherwise, flushing is left to the output and the caller.
+/// * `line_ending` - The separator written after each record.
+/// * `final_line_ending` - Whether the `line_ending` follows the last record.
+///   Otherwise, it is written before each record but the first instead.
+/// * `prefix` - Text written verbatim before each record.
+/// * `suffix` - Text written verbatim after each record, before the
+///   `line_ending`.
+/// * `length_prefix` - If set, each record is written as a frame: its length
+///   in bytes followed by the record, without a line ending.
+/// * `normalizer` - If set, normalizes the Unicode of the strings in each
+///   record.
+/// * `map_values` - If set, each record is a member of a root object that is
+///   turned into a record of its own.
+/// * `repairs` - The best-effort repairs made to each record.
+/// * `json5` - Whether the JSON5 numbers and string escapes in each record
+///   are rewritten as JSON.
+/// * `replace_non_finite` - Whether `NaN`, `Infinity` and `-Infinity` in
+///   each record are replaced with `null`.
+/// * `escape_control_chars` - Whether raw control characters inside the
+///   strings of each record are escaped.
+/// * `strict` - Whether each record is checked to be valid JSON before it is
+///   written.
+/// * `flattener` - If set, nested objects and arrays in each record are
+///   flattened into dotted keys.
+/// * `key_caser` - If set, the keys of each record are converted to a case.
+/// * `canonicalizer` - If set, each record is written in canonical form.
+/// * `required_fields` - If set, the top level keys each record must contain.
+/// * `dedupe` - If set, records identical to one recently written are
+///   dropped.
+/// * `duplicates` - The number of records dropped as duplicates.
+/// * `envelope` - If set, each record is wrapped in an envelope of metadata.
+/// * `errors_as_records` - Whether a record that fails a check is written as
+///   an error record rather than stopping the conversion.
+/// * `error_records` - The number of error records written.
+#[derive(Clone)]
+pub struct RecordWriter<W: Write = io::Stdout> {
+    output: W,
+    count: usize,
+    array_path: Option<String>,
+    with_offset: bool,
+    flush_every: Option<usize>,
+    line_ending: LineEnding,
+    final_line_ending: bool,
+    prefix: String,
+    suffix: String,
+    length_prefix: Option<LengthPrefix>,
+    normalizer: Option<Normalizer>,
+    map_values: Option<MapValues>,
+    repairs: Repairs,
+    json5: bool,
+    replace_non_finite: bool,
+    escape_control_chars: bool,
+    strict: bool,
+    flattener: Option<Flattener>,
+    key_caser: Option<KeyCaser>,
+    canonicalizer: Option<Canonicalizer>,
+    required_fields: Option<RequiredFields>,
+    dedupe: Option<DedupeWindow>,
+    duplicates: usize,
+    envelope: Option<Envelope>,
+    errors_as_records: bool,
+    error_records: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Creates a new `RecordWriter` that writes to `output`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::RecordWriter;
+    ///
+    /// let mut writer = RecordWriter::new(Vec::new());
+    /// writer.write_record(&"{\"a\":1}", 1).unwrap();
+    /// assert_eq!(writer.into_inner(), b"{\"a\":1}\n");
+    /// ```
+    pub fn new(output: W) -> Self {
+        RecordWriter {
+            output,
+            count: 0,
+            array_path: None,
+            with_offset: false,
+            flush_every: None,
+            line_ending: LineEnding::default(),
+            final_line_ending: true,
+            prefix: String::new(),
+            suffix: String::new(),
+            length_prefix: None,
+            normalizer: None,
+            map_values: None,
+            repairs: Repairs::default(),
+            json5: false,
+            replace_non_finite: false,
+            escape_control_chars: false,
+            strict: false,
+            flattener: None,
+            key_caser: None,
+            canonicalizer: None,
+            required_fields: None,
+            dedupe: None,
+            duplicates: 0,
+            envelope: None,
+            errors_as_records: false,
+            error_records: 0,
+        }
+    }
+
+    /// Sets the JSON pointer to the array that records come from, which each
+    /// record is prefixed with, followed by a tab. `None` writes no prefix.
+    pub fn set_array_path(&mut self, array_path: Option<String>) {
+        self.array_path = array_path;
+    }
+
+    /// Sets whether each record is prefixed with the offset in the input of
+    /// its first character, followed by a tab.
+    pub fn set_with_offset(&mut self, with_offset: bool) {
+        self.with_offset = with_offset;
+    }
+
+    /// Sets how many records are written between each flush of the output.
+    /// `None` or `Some(0)` leaves flushing to the output and the caller, which
+    /// gives the best throughput.
+    pub fn set_flush_every(&mut self, flush_every: Option<usize>) {
+        self.flush_every = flush_every.filter(|&n| n > 0);
+    }
+
+    /// Sets the separator written after each record.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Sets whether the line ending follows the last record, as it does by
+    /// default. Otherwise, it is written before each record but the first,
+    /// so that records are separated rather than terminated by it, for
+    /// readers that reject a trailing empty line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::RecordWriter;
+    ///
+    /// let mut writer = RecordWriter::new(Vec::new());
+    /// writer.set_final_line_ending(false);
+    /// writer.write_record(&"{\"a\":1}", 0).unwrap();
+    /// writer.write_record(&"{\"a\":2}", 8).unwrap();
+    /// assert_eq!(writer.into_inner(), b"{\"a\":1}\n{\"a\":2}");
+    /// ```
+    pub fn set_final_line_ending(&mut self, final_line_ending: bool) {
+        self.final_line_ending = final_line_ending;
+    }
+
+    /// Sets the text written verbatim around each record, such as to turn
+    /// records into statements for a script. The prefix follows the array
+    /// path and offset, if they are written, and the suffix comes before the
+    /// line ending.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonl_converter::writer::RecordWriter;
+    ///
+    /// let mut writer = RecordWriter::new(Vec::new());
+    /// writer.set_wrapper("INSERT INTO t VALUES('", "');");
+    /// writer.write_record(&"{\"a\":1}", 1).unwrap();
+    /// assert_eq!(writer.into_inner(), b"INSERT INTO t VALUES('{\"a\":1}');\n");
+    /// ```
+    pub fn set_wrapper(&mut self, prefix: &str, suffix: &str) {
+        self.prefix = prefix.to_string();
+        self.suffix = suffix.to_string();
+    }
+
+    /// Sets whether each record is prefixed with its length instead of being
+    /// followed by a line ending, and how the length is written. The length
+    /// counts the bytes of the UTF-8 record, after any other processing, and
+    /// not the prefix itself.
+    pub fn set_length_prefix(&mut self, length_prefix: Option<LengthPrefix>) {
+        self.length_prefix = length_prefix;
+    }
+
+    /// Sets how the Unicode of the strings in each record is normalized.
+    /// `None` writes records as they are.
+    pub fn set_normalizer(&mut self, normalizer: Option<Normalizer>) {
+        self.normalizer = normalizer;
+    }
+
+    /// Sets whether each record is a member of a root object, and if so how
+    /// it is turned into a record.
+    pub fn set_map_values(&mut self, map_values: Option<MapValues>) {
+        self.map_values = map_values;
+    }
+
+    /// Sets the best-effort repairs made to each record, before it is
+    /// checked strictly.
+    pub fn set_repairs(&mut self, repairs: Repairs) {
+        self.repairs = repairs;
+    }
+
+    /// Sets whether the JSON5 numbers and string escapes in each record are
+    /// rewritten as JSON, after the repairs are made.
+    pub fn set_json5(&mut self, json5: bool) {
+        self.json5 = json5;
+    }
+
+    /// Sets whether `NaN`, `Infinity` and `-Infinity` outside of the strings
+    /// in each record are replaced with `null`.
+    pub fn set_replace_non_finite(&mut self, replace_non_finite: bool) {
+        self.replace_non_finite = replace_non_finite;
+    }
+
+    /// Sets whether raw control characters inside the strings of each record
+    /// are escaped, before it is checked strictly.
+    pub fn set_escape_control_chars(&mut self, escape_control_chars: bool) {
+        self.escape_control_chars = escape_control_chars;
+    }
+
+    /// Sets whether each record is checked to be valid JSON before it is
+    /// written.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Sets how each record is flattened. `None` writes records as they are.
+    pub fn set_flattener(&mut self, flattener: Option<Flattener>) {
+        self.flattener = flattener;
+    }
+
+    /// Sets how the keys of each record are cased. `None` writes keys as they
+    /// are. Keys are converted after flattening, so flattened keys are cased
+    /// too.
+    pub fn set_key_caser(&mut self, key_caser: Option<KeyCaser>) {
+        self.key_caser = key_caser;
+    }
+
+    /// Sets how each record is written in canonical form. `None` writes
+    /// records as they are. Records are canonicalized once every other change
+    /// but the envelope has been made to them, so they are compared in
+    /// canonical form when dropping duplicates.
+    pub fn set_canonicalizer(&mut self, canonicalizer: Option<Canonicalizer>) {
+        self.canonicalizer = canonicalizer;
+    }
+
+    /// Sets the top level keys that each record must contain. They are
+    /// checked once every other change but the envelope has been made to the
+    /// record, so are the keys as written. `None` checks nothing.
+    pub fn set_required_fields(&mut self, required_fields: Option<RequiredFields>) {
+        self.required_fields = required_fields;
+    }
+
+    /// Sets how many of the most recently written records each record is
+    /// compared with, dropping it if it is identical to one of them. Records
+    /// are compared once every other change but the envelope has been made to
+    /// them. `None` or `Some(0)` writes every record.
+    pub fn set_dedupe_window(&mut self, window: Option<usize>) {
+        self.dedupe = window.filter(|&n| n > 0).map(DedupeWindow::new);
+    }
+
+    /// Sets the envelope each record is wrapped in. `None` writes records
+    /// as they are. Records are wrapped last, once every other change has
+    /// been made to them.
+    pub fn set_envelope(&mut self, envelope: Option<Envelope>) {
+        self.envelope = envelope;
+    }
+
+    /// Sets whether a record that is not valid JSON, when records are
+    /// checked strictly, flattened or canonicalized, or that is missing a
+    /// required field, is written as an error record rather than stopping the
+    /// conversion. See `error_record` for the format.
+    pub fn set_errors_as_records(&mut self, errors_as_records: bool) {
+        self.errors_as_records = errors_as_records;
+    }
+
+    /// Returns the number of records that have been written.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the number of records that have been dropped as duplicates.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Returns the number of records that have been written as error
+    /// records.
+    pub fn error_records(&self) -> usize {
+        self.error_records
+    }
+
+    /// Returns a reference to the output.
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Consumes the `RecordWriter`, returning the output.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Writes a completed record.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record.
+    /// * `offset` - The offset in the input of the first character of the
+    ///   record.
+    ///
+    /// # Errors
+    ///
+    /// * If the output cannot be written to.
+    /// * If the record is not valid JSON and records are checked strictly,
+    ///   flattened or canonicalized, unless errors are written as records.
+    /// * If the record is missing a required field, unless errors are
+    ///   written as records.
+    /// * If the record is too long for its length to be written in 4 bytes.
+    pub fn write_record(
+        &mut self,
+        record: &dyn Display,
+        offset: u64,
+    ) -> Result<(), ConversionError> {
+        if self.normalizer.is_none()
+            && self.map_values.is_none()
+            && self.repairs.is_empty()
+            && !self.json5
+            && !self.replace_non_finite
+            && !self.escape_control_chars
+            && !self.strict
+            && self.flattener.is_none()
+            && self.key_caser.is_none()
+            && self.canonicalizer.is_none()
+            && self.required_fields.is_none()
+            && self.dedupe.is_none()
+            && self.envelope.is_none()
+        {
+            self.write_line(record, offset)?;
+        } else {
+            let raw = record.to_string();
+            match self.transform(&raw) {
+                Ok(Some(record)) => self.write_line(&record, offset)?,
+                Ok(None) => return Ok(()),
+                Err(error) if self.errors_as_records => {
+                    return self.write_error_record(&error.message(), &raw, offset);
+                }
+                Err(RecordError::Invalid(message)) => return Err(self.invalid_record(message)),
+                Err(RecordError::MissingFields(fields)) => {
+                    return Err(ConversionError::MissingFields {
+                        record: self.count + 1,
+                        fields,
+                    })
+                }
+            }
+        }
+        self.count_written()
+    }
+
+    /// Counts a record as written, flushing the output if it is due to be.
+    fn count_written(&mut self) -> Result<(), ConversionError> {
+        self.count += 1;
+        if let Some(flush_every) = self.flush_every {
+            if self.count.is_multiple_of(flush_every) {
+                self.output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an error record in place of the record `raw`, which could not
+    /// be converted because of `message`, whether or not errors are written
+    /// as records otherwise. See `error_record`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Why the record could not be converted.
+    /// * `raw` - The record as it was read.
+    /// * `offset` - The offset in the input of the first character of the
+    ///   record.
+    ///
+    /// # Errors
+    ///
+    /// * If the output cannot be written to.
+    pub fn write_error_record(
+        &mut self,
+        message: &str,
+        raw: &str,
+        offset: u64,
+    ) -> Result<(), ConversionError> {
+        self.write_line(&error_record(message, raw), offset)?;
+        self.error_records += 1;
+        self.count_written()
+    }
+
+    /// Applies each of the transforms that are set to a record, in order,
+    /// returning the record to write, or `None` if it is a duplicate.
+    ///
+    /// # Errors
+    ///
+    /// * If the record is not valid JSON and records are checked strictly,
+    ///   flattened or canonicalized, with a message saying why.
+    /// * If the record is missing a required field, with the fields missing.
+    fn transform<'a>(&mut self, record: &'a str) -> Result<Option<Cow<'a, str>>, RecordError> {
+        let record = match &self.map_values {
+            Some(map_values) => map_values.record(record)?,
+            None => Cow::Borrowed(record),
+        };
+        let record = match self.repairs.repair(&record) {
+            Cow::Owned(repaired) => Cow::Owned(repaired),
+            Cow::Borrowed(_) => record,
+        };
+        let record = if self.json5 {
+            match json5::to_json(&record) {
+                Cow::Owned(rewritten) => Cow::Owned(rewritten),
+                Cow::Borrowed(_) => record,
+            }
+        } else {
+            record
+        };
+        let record = if self.replace_non_finite {
+            match replace_non_finite(&record) {
+                Cow::Owned(replaced) => Cow::Owned(replaced),
+                Cow::Borrowed(_) => record,
+            }
+        } else {
+            record
+        };
+        let record = if self.escape_control_chars {
+            match escape_control_chars(&record) {
+                Cow::Owned(escaped) => Cow::Owned(escaped),
+                Cow::Borrowed(_) => record,
+            }
+        } else {
+            record
+        };
+        if self.strict {
+            strict::check_record(&record)?;
+        }
+        let record = match &self.flattener {
+            Some(flattener) => Cow::Owned(flattener.flatten(&record)?),
+            None => record,
+        };
+        let record = match &self.key_caser {
+            Some(key_caser) => match key_caser.rewrite(&record)? {
+                Cow::Owned(rewritten) => Cow::Owned(rewritten),
+                Cow::Borrowed(_) => record,
+            },
+            None => record,
+        };
+        let record = match &self.normalizer {
+            Some(normalizer) => match normalizer.normalize(&record)? {
+                Cow::Owned(normalized) => Cow::Owned(normalized),
+                Cow::Borrowed(_) => record,
+            },
+            None => record,
+        };
+        let record = match &self.canonicalizer {
+            Some(canonicalizer) => Cow::Owned(canonicalizer.canonicalize(&record)?),
+            None => record,
+        };
+        if let Some(required_fields) = &self.required_fields {
+            let missing = required_fields.missing(&record);
+            if !missing.is_empty() {
+                return Err(RecordError::MissingFields(
+                    missing.into_iter().map(String::from).collect(),
+                ));
+            }
+        }
+        if let Some(dedupe) = &mut self.dedupe {
+            if !dedupe.insert(&record) {
+                self.duplicates += 1;
+                return Ok(None);
+            }
+        }
+        let record = match &self.envelope {
+            Some(envelope) => Cow::Owned(envelope.wrap(&record, self.count, SystemTime::now())),
+            None => record,
+        };
+        Ok(Some(record))
+    }
+
+    /// Returns the error for the record about to be written not being valid
+    /// JSON.
+    fn invalid_record(&self, message: String) -> ConversionError {
+        ConversionError::InvalidRecord {
+            record: self.count + 1,
+            message,
+        }
+    }
+
+    /// Writes a record's line, prefixed with its array's path and its offset
+    /// if requested and wrapped in the prefix and suffix, or its frame when
+    /// using a length prefix.
+    fn write_line(&mut self, record: &dyn Display, offset: u64) -> Result<(), ConversionError> {
+        if let Some(length_prefix) = self.length_prefix {
+            let record = record.to_string();
+            let prefix = length_prefix.encode(record.len()).ok_or_else(|| {
+                self.invalid_record(format!(
+                    "{} bytes is too long for a 4 byte length prefix.",
+                    record.len()
+                ))
+            })?;
+            self.output.write_all(&prefix)?;
+            self.output.write_all(record.as_bytes())?;
+            return Ok(());
+        }
+
+        // Without a final line ending, it is deferred until the next record
+        // is known to be coming.
+        let (before, after) = if self.final_line_ending {
+            ("", self.line_ending.as_str())
+        } else if self.count == 0 {
+            ("", "")
+        } else {
+            (self.line_ending.as_str(), "")
+        };
+        self.output.write_all(before.as_bytes())?;
+        if let Some(array_path) = &self.array_path {
+            write!(self.output, "{}\t", array_path)?;
+        }
+        if self.with_offset {
+            write!(self.output, "{}\t", offset)?;
+        }
+        write!(
+            self.output,
+            "{}{}{}{}",
+            self.prefix, record, self.suffix, after
+        )?;
+        Ok(())
+    }
+
+    /// Flushes the output.
+    ///
+    /// # Errors
+    ///
+    /// * If the output cannot be flushed.
+    pub fn flush(&mut self) -> Result<(), ConversionError> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Why a record could not be written.
+enum RecordError {
+    /// The record is not valid JSON, with a message saying why.
+    Invalid(String),
+    /// The record does not contain some of the required fields.
+    MissingFields(Vec<String>),
+}
+
+impl From<String> for RecordError {
+    fn from(message: String) -> Self {
+        RecordError::Invalid(message)
+    }
+}
+
+impl RecordError {
+    /// Returns the message written in the record's error record.
+    fn message(&self) -> String {
+        match self {
+            RecordError::Invalid(message) => message.clone(),
+            RecordError::MissingFields(fields) => {
+                format!("Missing the required fields {}.", quote_fields(fields))
+            }
+        }
+    }
+}
+
+/// Returns the record written in place of a record that failed a check,
+/// which holds why it failed and the record as it was read, such as
+/// `{"_error":"...","_raw":"{\"a\": .5}"}`. Consumers can tell these apart
+/// by their `_error` key.
+///
+/// # Examples
+///
+/// ```
+/// use jsonl_converter::writer::error_record;
+///
+/// assert_eq!(
+///     error_record("Unexpected `}`.", "{\"a\": }"),
+///     r#"{"_error":"Unexpected `}`.","_raw":"{\"a\": }"}"#
+/// );
+/// ```
+pub fn error_record(message: &str, raw: &str) -> String {
+    format!(
+        "{{\"_error\":{},\"_raw\":{}}}",
+        serde_json::to_string(message).unwrap(),
+        serde_json::to_string(raw).unwrap()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_case::KeyCase, unicode::NormalizationForm};
+    use std::io::BufWriter;
+
+    #[test]
+    fn test_write_record_counts_records() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.write_record(&"{}", 0).unwrap();
+        writer.write_record(&"[]", 3).unwrap();
+        assert_eq!(writer.count(), 2);
+        assert_eq!(writer.into_inner(), b"{}\n[]\n");
+    }
+
+    #[test]
+    fn test_write_record_with_offset() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_with_offset(true);
+        writer.write_record(&"{}", 7).unwrap();
+        assert_eq!(writer.into_inner(), b"7\t{}\n");
+    }
+
+    #[test]
+    fn test_write_record_with_array_path() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_array_path(Some(String::from("/data/items")));
+        writer.set_with_offset(true);
+        writer.write_record(&"{}", 7).unwrap();
+        assert_eq!(writer.into_inner(), b"/data/items\t7\t{}\n");
+    }
+
+    #[test]
+    fn test_wrapper_is_written_around_each_record() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_wrapper("INSERT INTO t VALUES('", "');");
+        writer.set_with_offset(true);
+        writer.set_line_ending(LineEnding::Crlf);
+        writer.write_record(&"{\"a\":1}", 1).unwrap();
+        writer.write_record(&"[\"\\n\"]", 12).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "1\tINSERT INTO t VALUES('{\"a\":1}');\r\n12\tINSERT INTO t VALUES('[\"\\n\"]');\r\n"
+        );
+    }
+
+    #[test]
+    fn test_line_ending_defaults_to_lf() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.write_record(&"{}", 0).unwrap();
+        writer.write_record(&"[]", 3).unwrap();
+        assert_eq!(writer.into_inner(), b"{}\n[]\n");
+    }
+
+    #[test]
+    fn test_line_ending_crlf() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_with_offset(true);
+        writer.set_line_ending(LineEnding::Crlf);
+        writer.write_record(&"{}", 0).unwrap();
+        writer.write_record(&"[]", 3).unwrap();
+        assert_eq!(writer.into_inner(), b"0\t{}\r\n3\t[]\r\n");
+    }
+
+    #[test]
+    fn test_without_final_line_ending_records_are_only_separated() {
+        for n in [0, 1, 5] {
+            let mut writer = RecordWriter::new(Vec::new());
+            writer.set_final_line_ending(false);
+            writer.set_line_ending(LineEnding::Crlf);
+            for i in 0..n {
+                writer.write_record(&format!("{{\"a\":{}}}", i), 0).unwrap();
+            }
+            let output = String::from_utf8(writer.into_inner()).unwrap();
+            assert_eq!(output.matches("\r\n").count(), n.max(1) - 1);
+            assert!(!output.ends_with('\n'));
+            assert_eq!(output.split("\r\n").filter(|l| !l.is_empty()).count(), n);
+        }
+    }
+
+    #[test]
+    fn test_line_ending_from_str() {
+        assert_eq!("lf".parse(), Ok(LineEnding::Lf));
+        assert_eq!("CRLF".parse(), Ok(LineEnding::Crlf));
+        assert!("\\r\\n".parse::<LineEnding>().is_err());
+    }
+
+    /// Reads the records back from length-prefixed frames.
+    fn read_frames(mut frames: &[u8], length_prefix: LengthPrefix) -> Vec<String> {
+        let mut records = Vec::new();
+        while !frames.is_empty() {
+            let len = match length_prefix {
+                LengthPrefix::U32 => {
+                    let (len, rest) = frames.split_at(4);
+                    frames = rest;
+                    u32::from_be_bytes(len.try_into().unwrap()) as usize
+                }
+                LengthPrefix::Varint => {
+                    let mut len = 0;
+                    let mut shift = 0;
+                    loop {
+                        let (&b, rest) = frames.split_first().unwrap();
+                        frames = rest;
+                        len |= ((b & 0x7f) as usize) << shift;
+                        shift += 7;
+                        if b & 0x80 == 0 {
+                            break len;
+                        }
+                    }
+                }
+            };
+            let (record, rest) = frames.split_at(len);
+            records.push(String::from_utf8(record.to_vec()).unwrap());
+            frames = rest;
+        }
+        records
+    }
+
+    #[test]
+    fn test_length_prefixed_frames_can_be_read_back() {
+        let long = format!("[\"{}\"]", "é".repeat(100));
+        let records = ["{}", "{\"a\": \"\n\"}", long.as_str()];
+        for length_prefix in [LengthPrefix::U32, LengthPrefix::Varint] {
+            let mut writer = RecordWriter::new(Vec::new());
+            writer.set_length_prefix(Some(length_prefix));
+            writer.set_line_ending(LineEnding::Crlf);
+            for record in records {
+                writer.write_record(&record, 0).unwrap();
+            }
+            assert_eq!(read_frames(&writer.into_inner(), length_prefix), records);
+        }
+    }
+
+    #[test]
+    fn test_length_prefix_encoding() {
+        assert_eq!(LengthPrefix::U32.encode(2), Some(vec![0, 0, 0, 2]));
+        assert_eq!(LengthPrefix::Varint.encode(0), Some(vec![0]));
+        assert_eq!(LengthPrefix::Varint.encode(127), Some(vec![0x7f]));
+        assert_eq!(LengthPrefix::Varint.encode(128), Some(vec![0x80, 0x01]));
+        assert_eq!(LengthPrefix::U32.encode(1 << 32), None);
+    }
+
+    #[test]
+    fn test_normalizer_is_applied_to_records() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_normalizer(Some(Normalizer {
+            form: NormalizationForm::Nfc,
+            keys: false,
+        }));
+        writer
+            .write_record(&"{\"e\u{301}\": \"e\u{301}\"}", 0)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "{\"e\u{301}\": \"\u{e9}\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_normalizer_rejects_keys_that_collide() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_normalizer(Some(Normalizer {
+            form: NormalizationForm::Nfd,
+            keys: true,
+        }));
+        let error = writer
+            .write_record(&"{\"\u{e9}\": 1, \"e\u{301}\": 2}", 0)
+            .unwrap_err();
+        assert!(error.to_string().contains("would both become"), "{}", error);
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_strict_rejects_invalid_records() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_strict(true);
+        writer.set_with_offset(true);
+        writer.write_record(&"[-0, 2.5E-3]", 0).unwrap();
+        let error = writer.write_record(&"[+1]", 14).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 2 is not valid JSON: `+1` is not a valid JSON number."
+        );
+        assert_eq!(writer.into_inner(), b"0\t[-0, 2.5E-3]\n");
+    }
+
+    #[test]
+    fn test_flattener_is_applied_to_records() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_flattener(Some(Flattener::default()));
+        writer.write_record(&"{\"a\": {\"b\": [1]}}", 0).unwrap();
+        let error = writer.write_record(&"{\"a\" 1}", 0).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Record 2 is not valid JSON: "));
+        assert_eq!(writer.into_inner(), b"{\"a.b.0\":1}\n");
+    }
+
+    #[test]
+    fn test_flattener_collision_is_an_invalid_record() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_flattener(Some(Flattener::default()));
+        let error = writer
+            .write_record(&"{\"a.b\": 1, \"a\": {\"b\": 2}}", 0)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 1 is not valid JSON: More than one value would be flattened to the key `a.b`."
+        );
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_key_caser_is_applied_after_flattening() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_flattener(Some(Flattener::default()));
+        writer.set_key_caser(Some(KeyCaser {
+            case: KeyCase::Snake,
+            recursive: false,
+        }));
+        writer
+            .write_record(&"{\"userInfo\": {\"firstName\": \"aB\"}}", 0)
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"{\"user_info.first_name\":\"aB\"}\n");
+    }
+
+    #[test]
+    fn test_key_caser_collision_is_an_invalid_record() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_key_caser(Some(KeyCaser {
+            case: KeyCase::Camel,
+            recursive: false,
+        }));
+        let error = writer
+            .write_record(&"{\"first_name\": 1, \"firstName\": 2}", 0)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 1 is not valid JSON: The keys `first_name` and `firstName` would both \
+             become `firstName`."
+        );
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_canonicalizer_is_applied_before_dedupe() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_key_caser(Some(KeyCaser {
+            case: KeyCase::Snake,
+            recursive: false,
+        }));
+        writer.set_canonicalizer(Some(Canonicalizer::default()));
+        writer.set_dedupe_window(Some(2));
+        writer
+            .write_record(&"{\"zeta\": 1.0, \"Alpha\": [2.50]}", 0)
+            .unwrap();
+        writer
+            .write_record(&"{\"alpha\":[25e-1],\"zeta\":1}", 0)
+            .unwrap();
+        assert_eq!(writer.duplicates(), 1);
+        assert_eq!(writer.into_inner(), b"{\"alpha\":[2.5],\"zeta\":1}\n");
+    }
+
+    #[test]
+    fn test_envelope_wraps_records_last_and_counts_them() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_with_offset(true);
+        writer.set_key_caser(Some(KeyCaser {
+            case: KeyCase::Snake,
+            recursive: false,
+        }));
+        writer.set_envelope(Some(Envelope {
+            source: String::from("data.json"),
+            fields: "source,index".parse().unwrap(),
+        }));
+        writer.write_record(&"{\"aB\": 1}", 4).unwrap();
+        writer.write_record(&"[2]", 16).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "4\t{\"source\":\"data.json\",\"index\":0,\"data\":{\"a_b\": 1}}\n\
+             16\t{\"source\":\"data.json\",\"index\":1,\"data\":[2]}\n"
+        );
+    }
+
+    #[test]
+    fn test_dedupe_window_drops_recent_duplicates_before_wrapping() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_dedupe_window(Some(2));
+        writer.set_envelope(Some(Envelope {
+            source: String::from("data.json"),
+            fields: "index".parse().unwrap(),
+        }));
+        for record in ["{\"a\": 1}", "{\"a\": 1}", "[2]", "[3]", "{\"a\": 1}"] {
+            writer.write_record(&record, 0).unwrap();
+        }
+        assert_eq!(writer.count(), 4);
+        assert_eq!(writer.duplicates(), 1);
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "{\"index\":0,\"data\":{\"a\": 1}}\n\
+             {\"index\":1,\"data\":[2]}\n\
+             {\"index\":2,\"data\":[3]}\n\
+             {\"index\":3,\"data\":{\"a\": 1}}\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_non_finite_is_applied_before_strict() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_strict(true);
+        let error = writer.write_record(&"[NaN]", 0).unwrap_err();
+        assert!(error.to_string().contains("`NaN` is not valid JSON."));
+
+        writer.set_replace_non_finite(true);
+        writer
+            .write_record(&"{\"NaN\": NaN, \"b\": [-Infinity]}", 0)
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"{\"NaN\": null, \"b\": [null]}\n");
+    }
+
+    #[test]
+    fn test_escape_control_chars_is_applied_before_strict() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_strict(true);
+        assert!(writer.write_record(&"{\"a\": \"b\tc\"}", 0).is_err());
+
+        writer.set_escape_control_chars(true);
+        writer.write_record(&"{\"a\": \"b\tc\"}", 0).unwrap();
+        assert_eq!(writer.into_inner(), b"{\"a\": \"b\\tc\"}\n");
+    }
+
+    #[test]
+    fn test_json5_is_rewritten_before_strict() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_strict(true);
+        writer.set_repairs(Repairs::ALL);
+        writer.set_json5(true);
+        writer.set_replace_non_finite(true);
+        writer
+            .write_record(
+                &"{hex: 0xFF, half: +.5, inf: +Infinity, s: 'a\\\nb\\x21'}",
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"{\"hex\": 255, \"half\": 0.5, \"inf\": null, \"s\": \"ab\\u0021\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_repairs_are_made_before_strict() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_strict(true);
+        writer.set_repairs(Repairs::ALL);
+        writer.set_replace_non_finite(true);
+        writer
+            .write_record(&"{\"a\": [1 NaN,] \"b\": {\"c\": 2,},}", 0)
+            .unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"{\"a\": [1, null], \"b\": {\"c\": 2}}\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_as_records_keep_the_raw_record() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_flattener(Some(Flattener {
+            separator: String::from("."),
+        }));
+        writer.set_errors_as_records(true);
+        writer.set_with_offset(true);
+        writer.write_record(&"{\"a\": {\"b\": 1}}", 1).unwrap();
+        writer.write_record(&"{\"a\": {\"b\": }}", 20).unwrap();
+        writer.write_record(&"{\"c\": 2}", 40).unwrap();
+        assert_eq!(writer.count(), 3);
+        assert_eq!(writer.error_records(), 1);
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "1\t{\"a.b\":1}");
+        assert_eq!(lines[2], "40\t{\"c\":2}");
+        let (offset, record) = lines[1].split_once('\t').unwrap();
+        assert_eq!(offset, "20");
+        let value: serde_json::Value = serde_json::from_str(record).unwrap();
+        assert_eq!(value["_raw"], "{\"a\": {\"b\": }}");
+        assert!(!value["_error"].as_str().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_records_missing_required_fields_are_errors() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_required_fields(Some(RequiredFields {
+            keys: vec![String::from("id"), String::from("name")],
+        }));
+        writer
+            .write_record(&"{\"id\": 1, \"name\": \"a\"}", 0)
+            .unwrap();
+        let error = writer.write_record(&"{\"id\": 2}", 20).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 2 is missing the required fields `name`."
+        );
+        assert_eq!(writer.into_inner(), b"{\"id\": 1, \"name\": \"a\"}\n");
+    }
+
+    #[test]
+    fn test_records_missing_required_fields_can_be_written_as_errors() {
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.set_required_fields(Some(RequiredFields {
+            keys: vec![String::from("id"), String::from("name")],
+        }));
+        writer.set_errors_as_records(true);
+        writer.write_record(&"{\"tags\": []}", 0).unwrap();
+        writer
+            .write_record(&"{\"name\": \"b\", \"id\": 3}", 20)
+            .unwrap();
+        assert_eq!(writer.error_records(), 1);
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "{\"_error\":\"Missing the required fields `id`, `name`.\",\"_raw\":\"{\\\"tags\\\": []}\"}\n\
+             {\"name\": \"b\", \"id\": 3}\n"
+        );
+    }
+
+    #[test]
+    fn test_output_is_not_flushed_by_default() {
+        let mut writer = RecordWriter::new(BufWriter::new(Vec::new()));
+        writer.write_record(&"{}", 0).unwrap();
+        assert!(writer.get_ref().get_ref().is_empty());
+
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().get_ref(), b"{}\n");
+    }
+
+    #[test]
+    fn test_flush_every_makes_output_visible_after_n_records() {
+        let mut writer = RecordWriter::new(BufWriter::new(Vec::new()));
+        writer.set_flush_every(Some(2));
+
+        writer.write_record(&"{\"a\":1}", 0).unwrap();
+        assert!(writer.get_ref().get_ref().is_empty());
+        writer.write_record(&"{\"a\":2}", 0).unwrap();
+        assert_eq!(writer.get_ref().get_ref(), b"{\"a\":1}\n{\"a\":2}\n");
+        writer.write_record(&"{\"a\":3}", 0).unwrap();
+        assert_eq!(writer.get_ref().get_ref().len(), 16);
+    }
+
+    #[test]
+    fn test_flush_every_zero_is_disabled() {
+        let mut writer = RecordWriter::new(BufWriter::new(Vec::new()));
+        writer.set_flush_every(Some(0));
+        writer.write_record(&"{}", 0).unwrap();
+        assert!(writer.get_ref().get_ref().is_empty());
+    }
+}
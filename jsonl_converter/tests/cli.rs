@@ -0,0 +1,949 @@
+//! End-to-end tests that run the `jsonl_converter` binary.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    process::Stdio,
+};
+
+use assert_cmd::{cargo::CommandCargoExt, Command};
+use jsonl_converter::{
+    generator::{generate, GeneratorOptions},
+    index::read_entry,
+};
+use predicates::prelude::*;
+use serde_json::json;
+
+/// The records of `tests/fixtures/pretty.json`.
+const PRETTY_RECORDS: &str = "{\"id\": 1,\"tags\": [\"a\",\"b\"]}\n{\"id\": 2,\"tags\": []}\n";
+
+/// Returns the path of a file in `tests/fixtures`.
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Returns a command that runs the binary with no arguments.
+fn converter() -> Command {
+    Command::cargo_bin("jsonl_converter").unwrap()
+}
+
+/// Returns a command that runs the binary with `args`, after the path of
+/// `fixture` in `tests/fixtures`.
+fn run_fixture(fixture_name: &str, args: &[&str]) -> Command {
+    let mut cmd = converter();
+    cmd.arg(fixture(fixture_name)).args(args);
+    cmd
+}
+
+/// Returns a command that runs the binary with `args`, reading `input` from
+/// stdin.
+fn run_stdin(input: impl Into<Vec<u8>>, args: &[&str]) -> Command {
+    let mut cmd = converter();
+    cmd.arg("-").args(args).write_stdin(input);
+    cmd
+}
+
+/// Returns the stdout of a command that has been asserted on.
+fn stdout_of(assert: &assert_cmd::assert::Assert) -> String {
+    String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+}
+
+/// Checks that each line of `output` is valid JSON.
+fn assert_lines_are_json(output: &str) {
+    for line in output.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+}
+
+#[test]
+fn test_converts_pretty_printed_file() {
+    run_fixture("pretty.json", &[])
+        .assert()
+        .success()
+        .stdout(PRETTY_RECORDS)
+        .stderr("");
+}
+
+#[test]
+fn test_converts_messy_file() {
+    run_fixture("messy.json", &["--messy"])
+        .assert()
+        .success()
+        .stdout("{\"id\": 1, \"tags\": [\"a\", \"b\"]}\n{\"id\": 2,\"tags\": []}\n")
+        .stderr("");
+}
+
+#[test]
+fn test_progress_records_reports_the_final_count_to_stderr() {
+    let assert = run_fixture("pretty.json", &["--progress-records"])
+        .assert()
+        .success()
+        .stdout(PRETTY_RECORDS);
+    let progress = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let last = progress.lines().last().unwrap();
+    assert!(last.starts_with("Processed 2 records in "), "{}", progress);
+}
+
+#[test]
+fn test_count_prints_the_number_of_records_to_stderr() {
+    for args in [&["--count"][..], &["--count", "--messy"]] {
+        run_fixture("pretty.json", args)
+            .assert()
+            .success()
+            .stdout(PRETTY_RECORDS)
+            .stderr("2\n");
+
+        run_fixture("empty_array.json", args)
+            .assert()
+            .success()
+            .stdout("")
+            .stderr("0\n");
+    }
+}
+
+#[test]
+fn test_columns_tallies_top_level_keys() {
+    for args in [&["--columns"][..], &["--columns", "--messy"]] {
+        run_fixture("heterogeneous.json", args)
+            .assert()
+            .success()
+            .stdout("id\t4\nname\t4\ntags\t2\nmeta\t1\nnote\t1\nzip\t1\n");
+    }
+}
+
+#[test]
+fn test_repair_salvages_defective_records() {
+    run_fixture("defects.json", &["--strict"])
+        .assert()
+        .failure();
+
+    for args in [
+        &["--repair", "--strict"][..],
+        &["--repair", "--strict", "--messy"],
+    ] {
+        let assert = run_fixture("defects.json", args).assert().success().stdout(
+            predicate::str::starts_with("{\"id\": 1, \"tags\": [\"a\", \"b\"], \"score\": null}\n"),
+        );
+        let stdout = stdout_of(&assert);
+        assert_eq!(stdout.lines().count(), 3);
+        assert_lines_are_json(&stdout);
+    }
+}
+
+#[test]
+fn test_diff_reports_elements_that_differ() {
+    run_fixture("pretty.json", &["--diff", &fixture("messy.json")])
+        .assert()
+        .success()
+        .stdout("");
+
+    let assert = run_fixture("pretty.json", &["--diff", &fixture("heterogeneous.json")])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "{\"index\":0,\"change\":\"modified\",\"old\":{\"id\":1,",
+        ));
+    assert_lines_are_json(&stdout_of(&assert));
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn test_format_msgpack_writes_messagepack() {
+    let mut expected: Vec<u8> = Vec::new();
+    expected.extend(b"\x82\xa2id\x01\xa4tags\x92\xa1a\xa1b");
+    expected.extend(b"\x82\xa2id\x02\xa4tags\x90");
+    run_fixture("pretty.json", &["--format", "msgpack"])
+        .assert()
+        .success()
+        .stdout(expected.clone());
+
+    let mut framed = vec![15];
+    framed.extend(&expected[..15]);
+    framed.push(11);
+    framed.extend(&expected[15..]);
+    run_fixture(
+        "messy.json",
+        &[
+            "--messy",
+            "--format",
+            "msgpack",
+            "--length-prefixed",
+            "--varint",
+        ],
+    )
+    .assert()
+    .success()
+    .stdout(framed);
+}
+
+#[test]
+#[cfg(feature = "msgpack")]
+fn test_format_msgpack_retains_key_order() {
+    converter()
+        .args(["-", "--format", "msgpack", "--retain-key-order"])
+        .write_stdin("[{\"b\": 1, \"a\": 2}]")
+        .assert()
+        .success()
+        .stdout(&b"\x82\xa1b\x01\xa1a\x02"[..]);
+}
+
+#[test]
+fn test_json5_converts_to_strict_jsonl() {
+    let assert = run_fixture("config.json5", &["--messy", "--json5"])
+        .assert()
+        .success()
+        .stdout(
+            "{\"name\": \"api\",\"port\": 8080,\"hosts\": [\"a.example.com\", \"b.example.com\"],\"note\": \"says \\\"hi\\\" // not a comment\"}\n\
+             {\"$id\": 2,\"_ratio\": null,\"quoted\": {\"nested\": true}}\n",
+        );
+    assert_lines_are_json(&stdout_of(&assert));
+}
+
+#[test]
+fn test_json5_spec_edge_cases() {
+    let assert = run_fixture("spec.json5", &["--messy", "--json5"])
+        .assert()
+        .success()
+        .stdout(
+            "{\"hex\": 3735928559, \"big\": 9444732965739290427391, \"neg\": -16}\n\
+             {\"lead\": 0.8675309, \"trail\": 8675309, \"plus\": 1, \"exp\": 1.5e-3}\n\
+             {\"inf\": null, \"neg_inf\": null, \"plus_inf\": null, \"nan\": null}\n\
+             {\"text\": \"Look, Mom! No \\\\n's! \\u0041\\u000b\"}\n",
+        );
+    assert_lines_are_json(&stdout_of(&assert));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reads_from_file_descriptor() {
+    // `-`, or no input at all when stdin is piped, reads from stdin too.
+    let cases: [&[&str]; 5] = [
+        &["--fd", "0"],
+        &["--fd", "0", "--messy"],
+        &["-"],
+        &["-", "--messy"],
+        &[],
+    ];
+    for args in cases {
+        converter()
+            .args(args)
+            .write_stdin("[\n  {\"a\": 1},\n  {\"b\": 2}\n]\n")
+            .assert()
+            .success()
+            .stdout("{\"a\": 1}\n{\"b\": 2}\n");
+    }
+}
+
+#[test]
+fn test_envelope_source_of_stdin_is_a_dash() {
+    converter()
+        .args(["-", "--envelope", "--envelope-fields", "source,index"])
+        .write_stdin("[{\"a\": 1}]")
+        .assert()
+        .success()
+        .stdout("{\"source\":\"-\",\"index\":0,\"data\":{\"a\": 1}}\n");
+}
+
+#[test]
+fn test_output_to_stderr() {
+    run_fixture("pretty.json", &["--output", "/dev/stderr"])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(PRETTY_RECORDS);
+}
+
+#[test]
+fn test_output_to_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.jsonl");
+    run_fixture("pretty.json", &["--output", path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(std::fs::read_to_string(path).unwrap(), PRETTY_RECORDS);
+}
+
+#[test]
+fn test_tee_writes_the_same_output_to_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("copy.jsonl");
+    run_fixture("pretty.json", &["--tee", path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(PRETTY_RECORDS);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), PRETTY_RECORDS);
+
+    let missing = dir.path().join("missing").join("copy.jsonl");
+    run_fixture("pretty.json", &["--tee", missing.to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::starts_with(
+            "Error: Could not open the output `",
+        ));
+}
+
+#[test]
+fn test_in_place_replaces_the_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    std::fs::write(&path, "[\n  {\"a\": 1},\n  {\"b\": 2}\n]\n").unwrap();
+    converter()
+        .args([path.to_str().unwrap(), "--in-place"])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "{\"a\": 1}\n{\"b\": 2}\n"
+    );
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_in_place_failure_leaves_the_input_intact() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    for (contents, args) in [
+        ("[\n  {\"a\": 1},\n  {\"b\": .5}\n]\n", ["--strict"]),
+        ("42\n", ["--messy"]),
+    ] {
+        std::fs::write(&path, contents).unwrap();
+        converter()
+            .args([path.to_str().unwrap(), "--in-place"])
+            .args(args)
+            .assert()
+            .failure();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
+
+#[test]
+fn test_index_points_at_each_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let index_path = dir.path().join("out.jsonl.idx");
+    let assert = run_fixture("pretty.json", &["--index", index_path.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let stdout = stdout_of(&assert);
+    let mut index = File::open(&index_path).unwrap();
+    let (offset, len) = read_entry(&mut index, 1).unwrap();
+    let record = &stdout[offset as usize..(offset + len) as usize];
+    assert_eq!(record, "{\"id\": 2,\"tags\": []}\n");
+    assert!(read_entry(&mut index, 2).is_err());
+}
+
+#[test]
+fn test_order_by_sorts_records_before_indexing() {
+    let dir = tempfile::tempdir().unwrap();
+    let index_path = dir.path().join("out.jsonl.idx");
+    let assert = run_fixture(
+        "pretty.json",
+        &[
+            "--order-by",
+            "id",
+            "--descending",
+            "--index",
+            index_path.to_str().unwrap(),
+        ],
+    )
+    .assert()
+    .success()
+    .stdout("{\"id\": 2,\"tags\": []}\n{\"id\": 1,\"tags\": [\"a\",\"b\"]}\n");
+
+    let stdout = stdout_of(&assert);
+    let mut index = File::open(&index_path).unwrap();
+    let (offset, len) = read_entry(&mut index, 1).unwrap();
+    let record = &stdout[offset as usize..(offset + len) as usize];
+    assert_eq!(record, "{\"id\": 1,\"tags\": [\"a\",\"b\"]}\n");
+}
+
+#[test]
+fn test_check_jsonl_accepts_valid_file() {
+    run_fixture("valid.jsonl", &["--check-jsonl", "--report-json"])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(predicate::str::starts_with("{\"records\":2,"));
+}
+
+#[test]
+fn test_check_jsonl_reports_first_invalid_line() {
+    run_fixture("invalid.jsonl", &["--check-jsonl"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::starts_with(
+            "Error: Line 2 is not valid JSON: ",
+        ));
+}
+
+#[test]
+fn test_check_jsonl_reports_a_blank_line_by_number() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blank.jsonl");
+    std::fs::write(&path, "{\"a\": 1}\n\n{\"b\": 2}\n").unwrap();
+    converter()
+        .args([path.to_str().unwrap(), "--check-jsonl"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("Error: Line 2 is empty, but every line of JSONL must hold a record.\n");
+}
+
+#[test]
+fn test_missing_file_is_an_error() {
+    for args in [&[][..], &["--messy"][..]] {
+        run_fixture("missing.json", args)
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr(predicate::str::starts_with(
+                "Error: No such file or directory",
+            ));
+    }
+}
+
+#[test]
+fn test_empty_file_is_an_error() {
+    for args in [&[][..], &["--messy"][..]] {
+        run_fixture("empty.json", args)
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr("Error: The file is empty. It must contain a JSON array.\n");
+    }
+}
+
+#[test]
+fn test_invalid_arguments_are_reported_with_exit_code_2() {
+    run_fixture("pretty.json", &["--help"])
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr("Error: Unknown argument: --help\n");
+}
+
+#[test]
+fn test_file_not_starting_with_an_array_or_object_is_rejected() {
+    for args in [&[][..], &["--messy"][..]] {
+        run_fixture("scalar.json", args)
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(
+                predicate::str::contains("The input must start with a '[' or a '{', not '4'.")
+                    .and(predicate::str::contains("panicked").not()),
+            );
+    }
+}
+
+#[test]
+fn test_file_of_a_single_object_is_one_record() {
+    for args in [&[][..], &["--messy"][..]] {
+        run_fixture("object.json", args)
+            .assert()
+            .success()
+            .stdout("{\"id\": 1}\n")
+            .stderr("");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reader_closing_stdout_early_exits_successfully() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.json");
+    let options = GeneratorOptions {
+        records: 20_000,
+        ..GeneratorOptions::default()
+    };
+    generate(&mut File::create(&path).unwrap(), &options).unwrap();
+
+    for extra_args in [&[][..], &["--messy"][..]] {
+        // The output is read while the binary runs, so this spawns it as a
+        // plain `std::process::Command` rather than asserting on it whole.
+        let mut child = std::process::Command::cargo_bin("jsonl_converter")
+            .unwrap()
+            .arg(&path)
+            .args(extra_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut first_line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut first_line)
+            .unwrap();
+        assert!(first_line.starts_with("{\"id\": 0,") || first_line.starts_with("{\"id\":0,"));
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    }
+}
+
+#[test]
+fn test_reverse_round_trips_a_converted_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let jsonl = dir.path().join("pretty.jsonl");
+    run_fixture("pretty.json", &["--output", jsonl.to_str().unwrap()])
+        .assert()
+        .success();
+
+    converter()
+        .args([jsonl.to_str().unwrap(), "--reverse", "--pretty"])
+        .assert()
+        .success()
+        .stdout(
+            "[\n  {\n    \"id\": 1,\n    \"tags\": [\n      \"a\",\n      \"b\"\n    ]\n  },\n  {\n    \"id\": 2,\n    \"tags\": []\n  }\n]\n",
+        );
+}
+
+#[test]
+fn test_reverse_writes_the_output_eol() {
+    run_fixture("valid.jsonl", &["--reverse", "--output-eol", "crlf"])
+        .assert()
+        .success()
+        .stdout("[{\"id\":1,\"tags\":[\"a\",\"b\"]},{\"id\":2,\"tags\":[]}]\r\n");
+
+    let assert = run_fixture(
+        "valid.jsonl",
+        &["--reverse", "--pretty", "--output-eol", "crlf"],
+    )
+    .assert()
+    .success();
+    let output = stdout_of(&assert);
+    assert!(output.starts_with("[\r\n  {\r\n    \"id\": 1,\r\n"));
+    assert!(output.ends_with("\r\n  }\r\n]\r\n"));
+    assert!(!output.replace("\r\n", "").contains('\n'));
+}
+
+#[test]
+fn test_prefix_and_suffix_wrap_each_record() {
+    run_fixture(
+        "pretty.json",
+        &[
+            "--prefix",
+            "INSERT INTO t VALUES('",
+            "--suffix",
+            "');",
+            "--chunk-records",
+            "1",
+        ],
+    )
+    .assert()
+    .success()
+    .stdout(
+        "INSERT INTO t VALUES('{\"id\": 1,\"tags\": [\"a\",\"b\"]}');\n\n\
+         INSERT INTO t VALUES('{\"id\": 2,\"tags\": []}');\n",
+    );
+}
+
+#[test]
+fn test_malformed_elements_are_written_as_error_records() {
+    for args in [&[][..], &["--messy"][..]] {
+        let mut cmd = run_fixture("broken.json", &["--emit-errors-as-records"]);
+        let assert = cmd.args(args).assert().success();
+        let output = stdout_of(&assert);
+        assert_lines_are_json(&output);
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let ids: Vec<_> = records.iter().map(|record| record["id"].clone()).collect();
+        assert_eq!(
+            ids[..5],
+            [json!(1), json!(null), json!(3), json!(null), json!(5)]
+        );
+        let errors = [
+            (
+                1,
+                "Expected `]` but found `}` at byte offset 39.",
+                "{\"id\": 2, \"tags\": [\"a\"}",
+            ),
+            (
+                3,
+                "A string contains the control character U+0009 at byte offset 79",
+                "{\"id\": 4, \"note\": \"tab\there\"}",
+            ),
+            (
+                5,
+                "The input ended before the record was closed",
+                "{\"id\": 6, \"cut",
+            ),
+        ];
+        for (line, message, raw) in errors {
+            let error = records[line]["_error"].as_str().unwrap();
+            assert!(error.starts_with(message), "{}", error);
+            assert_eq!(records[line]["_raw"], raw);
+        }
+        assert_eq!(records.len(), 6);
+    }
+}
+
+#[test]
+fn test_malformed_elements_stop_the_conversion_by_default() {
+    run_fixture("broken.json", &[])
+        .assert()
+        .failure()
+        .stdout("{\"id\": 1}\n")
+        .stderr(predicate::str::contains("Expected `]` but found `}`"));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_input_is_decompressed_in_both_modes() {
+    for args in [&[][..], &["--messy"][..]] {
+        let expected = run_fixture("pretty.json", args).assert().success();
+        run_fixture("pretty.json.gz", args)
+            .assert()
+            .success()
+            .stdout(stdout_of(&expected));
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn test_gzip_input_without_the_feature_is_rejected() {
+    run_fixture("pretty.json.gz", &[])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The input must start with a '[' or a '{', not '\\u{1f}'. The input looks \
+             compressed with gzip, which can only be read when built with the `gzip` feature.",
+        ));
+}
+
+#[test]
+fn test_concat_converts_values_that_follow_the_root() {
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin("{\"a\":1}{\"b\":2}\n", &[&["--concat"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\":1}\n{\"b\":2}\n");
+        run_stdin("{\"a\":1}{\"b\":2}\n", mode)
+            .assert()
+            .failure()
+            .stdout("{\"a\":1}\n")
+            .stderr(predicate::str::contains("after the root object was closed"));
+    }
+}
+
+#[test]
+fn test_output_eol_replaces_the_line_endings_of_the_input() {
+    let input = "[\r\n  {\"a\": 1},\n  {\r\n    \"b\": 2\r\n  }\r\n]\r\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, mode)
+            .assert()
+            .success()
+            .stdout("{\"a\": 1}\n{\"b\": 2}\n");
+        run_stdin(input, &[&["--output-eol", "crlf"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\": 1}\r\n{\"b\": 2}\r\n");
+    }
+}
+
+#[test]
+fn test_no_final_eol_only_separates_records() {
+    run_fixture("pretty.json", &["--no-final-eol"])
+        .assert()
+        .success()
+        .stdout(PRETTY_RECORDS.trim_end());
+}
+
+#[test]
+fn test_preserve_whitespace_only_removes_line_breaks() {
+    let input = "[\n  {\n    \"a\":  1,\n    \"b\": \"x  y\"\n  },\r\n  { \"c\": 3 }\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--preserve-whitespace"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{    \"a\":  1,    \"b\": \"x  y\"  }\n{ \"c\": 3 }\n");
+    }
+}
+
+#[test]
+fn test_minify_removes_whitespace_outside_strings() {
+    let input = "[\n  { \"a b\" :  1,\n    \"c\": [ \"x  y\" ] },\n  [ 2 ]\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--minify"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a b\":1,\"c\":[\"x  y\"]}\n[2]\n");
+    }
+}
+
+#[test]
+fn test_keys_keep_their_order() {
+    let input = "[\n  {\"z\": 1, \"a\": {\"y\": 2, \"b\": 3}, \"m\": 4}\n]\n";
+    for args in [
+        &[][..],
+        &["--strict", "--normalize-unicode", "nfc", "--normalize-keys"],
+    ] {
+        run_stdin(input, args)
+            .assert()
+            .success()
+            .stdout("{\"z\": 1, \"a\": {\"y\": 2, \"b\": 3}, \"m\": 4}\n");
+    }
+}
+
+#[test]
+fn test_strict_utf8_rejects_invalid_bytes() {
+    let input = &b"[\n  {\"a\": \"caf\xC3\xA9\"},\n  {\"b\": \"caf\xE9\"}\n]\n"[..];
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--strict-utf8"], mode].concat())
+            .assert()
+            .failure()
+            .stdout("{\"a\": \"café\"}\n")
+            .stderr(predicate::str::contains("Invalid UTF-8 at byte offset 32."));
+        run_stdin(input, mode)
+            .assert()
+            .success()
+            .stdout("{\"a\": \"café\"}\n{\"b\": \"caf\u{FFFD}\"}\n");
+    }
+}
+
+#[test]
+fn test_strict_stops_at_an_invalid_record() {
+    let input = "[\n  {\"a\": 1e3},\n  {\"b\": .5},\n  {\"c\": 3}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--strict"], mode].concat())
+            .assert()
+            .failure()
+            .stdout("{\"a\": 1e3}\n")
+            .stderr(predicate::str::contains(
+                "Record 2 is not valid JSON: `.5` is not a valid JSON number.",
+            ));
+    }
+}
+
+#[test]
+fn test_flatten_with_a_separator() {
+    let input = "[\n  {\n    \"a\": {\n      \"b\": 1\n    },\n    \"c\": [\n      2\n    ]\n  },\n  {\"d\": 3}\n]\n";
+    run_stdin(input, &["--flatten", "--flatten-separator", "/"])
+        .assert()
+        .success()
+        .stdout("{\"a/b\":1,\"c/0\":2}\n{\"d\":3}\n");
+}
+
+#[test]
+fn test_canonical_writes_equal_inputs_the_same() {
+    let pretty =
+        "[\n  {\n    \"name\": \" Ada \",\n    \"id\": 1.0,\n    \"tags\": [\"\\u0061\"]\n  }\n]\n";
+    let compact = "[\n{\"id\":1,\"tags\":[\"a\"],\"name\":\"Ada\"}\n]\n";
+    for input in [pretty, compact] {
+        run_stdin(input, &["--canonical", "--trim-strings"])
+            .assert()
+            .success()
+            .stdout("{\"id\":1,\"name\":\"Ada\",\"tags\":[\"a\"]}\n");
+    }
+}
+
+#[test]
+fn test_envelope_wraps_each_record_with_its_source() {
+    let assert = run_fixture("pretty.json", &["--envelope"])
+        .assert()
+        .success();
+    let output = stdout_of(&assert);
+    let records: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(record["source"], fixture("pretty.json"));
+        assert_eq!(record["index"], i);
+        assert_eq!(record["ts"].as_str().unwrap().len(), 20);
+        assert_eq!(record["data"]["id"], i + 1);
+    }
+}
+
+#[test]
+fn test_single_quotes_are_converted_to_json() {
+    let input = "[\n{'a': 'x]', \"b\": \"it's\"},\n{'c': ['say \"hi\" [', 'd']}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--single-quotes", "--strict"], mode].concat())
+            .assert()
+            .success()
+            .stdout(
+                "{\"a\": \"x]\", \"b\": \"it's\"}\n\
+                 {\"c\": [\"say \\\"hi\\\" [\", \"d\"]}\n",
+            );
+    }
+}
+
+#[test]
+fn test_strip_jsonp_removes_the_padding() {
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(
+            "window.cb ([\n  {\"a\":1},\n  [2]\n]);\n",
+            &[&["--strip-jsonp"], mode].concat(),
+        )
+        .assert()
+        .success()
+        .stdout("{\"a\":1}\n[2]\n");
+
+        for (input, message) in [
+            ("cb([\n  [1]\n]\n", "not closed"),
+            ("cb([\n  [1]\n]);x\n", "Unexpected 'x'"),
+            ("cb [\n  [1]\n]\n", "Expected `(`"),
+        ] {
+            run_stdin(input, &[&["--strip-jsonp"], mode].concat())
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains(message));
+        }
+    }
+}
+
+#[test]
+fn test_map_values_converts_the_members_of_the_root_object() {
+    let input =
+        "{\n  \"id1\": {\n    \"a\": 1\n  },\n  \"id2\": {\n  },\n  \"id3\": [\n    2\n  ]\n}\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--map-values"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\": 1}\n{}\n[2]\n");
+
+        // The array cannot take its key, so converting stops at it.
+        run_stdin(
+            input,
+            &[&["--map-values", "--inject-key", "_key"], mode].concat(),
+        )
+        .assert()
+        .failure()
+        .stdout("{\"_key\":\"id1\",\"a\": 1}\n{\"_key\":\"id2\"}\n")
+        .stderr(predicate::str::contains(
+            "The value of \"id3\" is not an object",
+        ));
+    }
+}
+
+#[test]
+fn test_max_depth_limits_the_nesting_of_records() {
+    let input = "[\n{\"a\": [1]},\n{\"b\": [[2]]}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--max-depth", "2"], mode].concat())
+            .assert()
+            .failure()
+            .stdout("{\"a\": [1]}\n")
+            .stderr(predicate::str::contains(
+                "Objects and arrays are nested deeper than 2, the limit set with --max-depth, \
+                 at byte offset 21.",
+            ));
+        run_stdin(input, &[&["--max-depth", "3"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\": [1]}\n{\"b\": [[2]]}\n");
+    }
+}
+
+#[test]
+fn test_newline_in_values() {
+    let input = "[{\"a\": \"b\n c\"}, [\"d\ne\"]]";
+    for (policy, expected) in [
+        ("strip", "{\"a\": \"bc\"}\n[\"de\"]\n"),
+        ("escape", "{\"a\": \"b\\n c\"}\n[\"d\\ne\"]\n"),
+    ] {
+        run_stdin(input, &["--messy", "--newline-in-values", policy])
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn test_control_chars_are_rejected_unless_allowed_or_escaped() {
+    // The tab before the record is whitespace, so is always allowed.
+    let input = "[\n\t{\"a\": \"b\tc\"},\n\t[1]\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, mode)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("at byte offset 11"));
+        run_stdin(input, &[&["--allow-control-chars"], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\": \"b\tc\"}\n[1]\n");
+        run_stdin(
+            input,
+            &[
+                &[
+                    "--allow-control-chars",
+                    "--escape-control-chars",
+                    "--strict",
+                ],
+                mode,
+            ]
+            .concat(),
+        )
+        .assert()
+        .success()
+        .stdout("{\"a\": \"b\\tc\"}\n[1]\n");
+    }
+}
+
+#[test]
+fn test_fields_required_stops_at_a_record_missing_them() {
+    let input = "[\n{\"id\": 1, \"name\": \"a\"},\n{\"id\": 2},\n{}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--fields-required", "id,name"], mode].concat())
+            .assert()
+            .failure()
+            .stdout("{\"id\": 1, \"name\": \"a\"}\n")
+            .stderr(predicate::str::contains("`name`"));
+    }
+}
+
+#[test]
+fn test_head_bytes_stops_reading_at_the_limit() {
+    let input = "[\n  {\"a\": 1},\n  {\"b\": 2},\n  {\"c\": 3}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        let limit = input.len().to_string();
+        run_stdin(input, &[&["--head-bytes", limit.as_str()], mode].concat())
+            .assert()
+            .success()
+            .stdout("{\"a\": 1}\n{\"b\": 2}\n{\"c\": 3}\n");
+
+        // The limit falls within the third record.
+        run_stdin(input, &[&["--head-bytes", "30"], mode].concat())
+            .assert()
+            .failure()
+            .stdout("{\"a\": 1}\n{\"b\": 2}\n")
+            .stderr(predicate::str::contains(
+                "Stopped reading after 30 bytes, the limit set with --head-bytes",
+            ));
+    }
+}
+
+#[test]
+fn test_long_line_suggests_messy() {
+    let input = format!("[{}]", vec!["{\"a\": [1, 2, 3]}"; 1000].join(","));
+    run_stdin(input.clone(), &["--max-line-bytes", "4096"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "The line at byte offset 0 is longer than 4096 bytes. Use --messy to convert files \
+             that are not split into lines, or --max-line-bytes to allow longer lines.",
+        ));
+    let assert = run_stdin(input, &["--messy"]).assert().success();
+    assert_eq!(stdout_of(&assert).lines().count(), 1000);
+}
+
+#[test]
+fn test_with_offset_points_at_the_start_of_each_record() {
+    let input = "[\n  {\"a\": 1},\n  {\"b\": 2},\n  {\"c\": 3}\n]\n";
+    for mode in [&[][..], &["--messy"][..]] {
+        run_stdin(input, &[&["--with-offset"], mode].concat())
+            .assert()
+            .success()
+            .stdout("4\t{\"a\": 1}\n16\t{\"b\": 2}\n28\t{\"c\": 3}\n");
+    }
+}